@@ -0,0 +1,175 @@
+use crate::duration::format_duration;
+use crate::hours::HoursProfile;
+use crate::period::Period;
+use crate::session::Session;
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate, Timelike};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Anomaly {
+    LongSession {
+        task: String,
+        start: DateTime<FixedOffset>,
+        duration: Duration,
+    },
+    NightStart {
+        task: String,
+        start: DateTime<FixedOffset>,
+    },
+    EmptyWorkday(NaiveDate),
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Anomaly::LongSession {
+                task,
+                start,
+                duration,
+            } => write!(
+                f,
+                "{} '{}' ran for {}",
+                format_time(start),
+                task,
+                format_duration(*duration)
+            ),
+            Anomaly::NightStart { task, start } => {
+                write!(f, "{} '{}' started at night", format_time(start), task)
+            }
+            Anomaly::EmptyWorkday(date) => write!(f, "{} no time tracked on a workday", date),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AnomalyRules {
+    pub max_session: Duration,
+    // この時刻範囲 [start, end) に始まったセッションを疑わしいとみなす
+    pub night_hours: (u32, u32),
+    // 所定時間のある曜日に何も記録が無ければ疑わしいとみなす
+    pub hours: HoursProfile,
+}
+
+impl Default for AnomalyRules {
+    fn default() -> Self {
+        AnomalyRules {
+            max_session: Duration::hours(12),
+            night_hours: (0, 5),
+            hours: HoursProfile::default(),
+        }
+    }
+}
+
+pub fn find_anomalies(
+    sessions: &[Session],
+    period: &Period,
+    rules: &AnomalyRules,
+    now: DateTime<FixedOffset>,
+) -> Vec<Anomaly> {
+    let (from, to) = (period.start_time(), period.end_time());
+    let mut anomalies = Vec::new();
+
+    for session in sessions {
+        if session.overlap(from, to, now).is_zero() {
+            continue;
+        }
+        let duration = session.end_or(now) - session.start;
+        if duration > rules.max_session {
+            anomalies.push(Anomaly::LongSession {
+                task: session.task.clone(),
+                start: session.start,
+                duration,
+            });
+        }
+        let hour = session.start.with_timezone(&Local).hour();
+        if hour >= rules.night_hours.0 && hour < rules.night_hours.1 {
+            anomalies.push(Anomaly::NightStart {
+                task: session.task.clone(),
+                start: session.start,
+            });
+        }
+    }
+
+    // 当日はまだ終わっていないので対象外
+    let today = now.with_timezone(&Local).date_naive();
+    for day in period
+        .days()
+        .filter(|d| *d < today && rules.hours.is_workday(*d))
+    {
+        let day_period = Period {
+            start: day,
+            end: day,
+        };
+        let (from, to) = (day_period.start_time(), day_period.end_time());
+        if sessions.iter().all(|s| s.overlap(from, to, now).is_zero()) {
+            anomalies.push(Anomaly::EmptyWorkday(day));
+        }
+    }
+
+    anomalies
+}
+
+fn format_time(time: &DateTime<FixedOffset>) -> String {
+    time.with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::{local_midnight, parse_date};
+
+    fn local(date: &str, hour: i64) -> DateTime<FixedOffset> {
+        local_midnight(parse_date(date).unwrap()) + Duration::hours(hour)
+    }
+
+    fn session(task: &str, start: DateTime<FixedOffset>, hours: i64) -> Session {
+        Session::new(task, start, Some(start + Duration::hours(hours)))
+    }
+
+    #[test]
+    fn test_find_long_and_night_sessions() {
+        let period = Period::parse("2024-05-01").unwrap();
+        let now = local("2024-05-01", 23);
+        let sessions = vec![
+            session("long", local("2024-05-01", 6), 13),
+            session("night", local("2024-05-01", 3), 1),
+            session("normal", local("2024-05-01", 20), 1),
+        ];
+        let anomalies = find_anomalies(&sessions, &period, &AnomalyRules::default(), now);
+        assert_eq!(anomalies.len(), 2);
+        assert!(matches!(&anomalies[0], Anomaly::LongSession { task, .. } if task == "long"));
+        assert!(matches!(&anomalies[1], Anomaly::NightStart { task, .. } if task == "night"));
+    }
+
+    #[test]
+    fn test_find_empty_workdays() {
+        // 2024-05-03 (金) から 2024-05-07 (火) まで
+        let period = Period::new(
+            parse_date("2024-05-03").unwrap(),
+            parse_date("2024-05-07").unwrap(),
+        )
+        .unwrap();
+        let now = local("2024-05-07", 12);
+        let sessions = vec![session("a", local("2024-05-06", 9), 8)];
+        let anomalies = find_anomalies(&sessions, &period, &AnomalyRules::default(), now);
+        assert_eq!(
+            anomalies,
+            vec![Anomaly::EmptyWorkday(parse_date("2024-05-03").unwrap())]
+        );
+    }
+
+    #[test]
+    fn test_open_session_counts_until_now() {
+        let period = Period::parse("2024-05-01").unwrap();
+        let start = local("2024-05-01", 8);
+        let sessions = vec![Session::new("forgotten", start, None)];
+        let rules = AnomalyRules {
+            max_session: Duration::hours(10),
+            ..Default::default()
+        };
+        let anomalies = find_anomalies(&sessions, &period, &rules, start + Duration::hours(11));
+        assert_eq!(anomalies.len(), 1);
+    }
+}
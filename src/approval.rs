@@ -0,0 +1,215 @@
+use crate::duration::format_duration;
+use crate::period::Period;
+use crate::record::{format_timestamp, Event, Record};
+use crate::report::total_between;
+use crate::session::Session;
+use chrono::{DateTime, FixedOffset, Local};
+
+pub const REVIEW_FIELD: &str = "review";
+pub const REVIEWER_FIELD: &str = "reviewer";
+pub const REVIEWED_AT_FIELD: &str = "reviewed_at";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Review {
+    Approved,
+    Rejected,
+}
+
+impl Review {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Review::Approved => "approved",
+            Review::Rejected => "rejected",
+        }
+    }
+}
+
+// "3" / "3-7" / "3,5,8-10" のような ID の指定。ID は 1 から数える。
+pub fn parse_id_range(s: &str) -> Result<Vec<usize>, String> {
+    let invalid = || format!("Invalid id range '{}'.", s);
+    let mut ids = Vec::new();
+    for part in s.split(',') {
+        let (from, to) = part.split_once('-').unwrap_or((part, part));
+        let from: usize = from.trim().parse().map_err(|_| invalid())?;
+        let to: usize = to.trim().parse().map_err(|_| invalid())?;
+        if from == 0 || to < from {
+            return Err(invalid());
+        }
+        ids.extend(from..=to);
+    }
+    ids.sort_unstable();
+    ids.dedup();
+    Ok(ids)
+}
+
+// 記録ファイル内の n 番目の start が ID n のエントリ
+pub fn entry_indices(records: &[Record]) -> Vec<usize> {
+    records
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| r.event == Event::Start)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+// 指定した ID の start に判定・承認者・日時を書き込む。以前の判定は置き換える。
+pub fn annotate(
+    records: &mut [Record],
+    ids: &[usize],
+    review: Review,
+    reviewer: &str,
+    now: DateTime<FixedOffset>,
+) -> Result<(), String> {
+    let entries = entry_indices(records);
+    if let Some(id) = ids.iter().find(|id| **id > entries.len()) {
+        return Err(format!(
+            "No entry with id {} ({} entries recorded).",
+            id,
+            entries.len()
+        ));
+    }
+    for id in ids {
+        let record = &mut records[entries[id - 1]];
+        record.fields.retain(|(key, _)| {
+            ![REVIEW_FIELD, REVIEWER_FIELD, REVIEWED_AT_FIELD].contains(&key.as_str())
+        });
+        record
+            .fields
+            .push((REVIEW_FIELD.to_string(), review.as_str().to_string()));
+        record
+            .fields
+            .push((REVIEWER_FIELD.to_string(), reviewer.to_string()));
+        record
+            .fields
+            .push((REVIEWED_AT_FIELD.to_string(), format_timestamp(now)));
+    }
+    Ok(())
+}
+
+// approve --list の 1 行。ID・時刻・タスク・判定を並べる。
+pub fn describe_entry(id: usize, session: &Session) -> String {
+    let start = session.start.with_timezone(&Local);
+    let stop = match session.stop {
+        Some(stop) => stop.with_timezone(&Local).format("%H:%M").to_string(),
+        None => "(running)".to_string(),
+    };
+    let mut line = format!(
+        "{:>5}  {} - {}  {}",
+        id,
+        start.format("%Y-%m-%d %H:%M"),
+        stop,
+        session.task
+    );
+    if let Some(review) = session.field(REVIEW_FIELD) {
+        line += &format!("  [{}", review);
+        if let Some(reviewer) = session.field(REVIEWER_FIELD) {
+            line += &format!(" by {}", reviewer);
+        }
+        line += "]";
+    }
+    line
+}
+
+// 期間内に判定済みのエントリがあれば、承認・却下・未判定の時間を並べる
+pub fn render_summary(sessions: &[Session], period: &Period, now: DateTime<FixedOffset>) -> String {
+    let (from, to) = (period.start_time(), period.end_time());
+    let in_period: Vec<&Session> = sessions
+        .iter()
+        .filter(|s| !s.overlap(from, to, now).is_zero())
+        .collect();
+    if !in_period.iter().any(|s| s.field(REVIEW_FIELD).is_some()) {
+        return String::new();
+    }
+    let total_of = |review: Option<&str>| {
+        let matching = in_period
+            .iter()
+            .copied()
+            .filter(|s| s.field(REVIEW_FIELD) == review);
+        total_between(matching, from, to, now)
+    };
+    format!(
+        "\nReview:\n  approved  {:>8}\n  rejected  {:>8}\n  pending   {:>8}\n",
+        format_duration(total_of(Some(Review::Approved.as_str()))),
+        format_duration(total_of(Some(Review::Rejected.as_str()))),
+        format_duration(total_of(None))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::parse_date;
+    use crate::record::parse_records;
+    use crate::session::pair_sessions;
+
+    fn ts(s: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(s).unwrap()
+    }
+
+    fn records() -> Vec<Record> {
+        parse_records(
+            "2024-05-01T09:00:00+09:00\tstart\ta\n\
+             2024-05-01T09:30:00+09:00\tlap\tnote\n\
+             2024-05-01T10:00:00+09:00\tstop\t\n\
+             2024-05-01T10:00:00+09:00\tstart\tb\n\
+             2024-05-01T10:30:00+09:00\tstart\tc\n\
+             2024-05-01T11:00:00+09:00\tstop\t\n",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_id_range() {
+        assert_eq!(parse_id_range("3").unwrap(), vec![3]);
+        assert_eq!(parse_id_range("5,3-4,4").unwrap(), vec![3, 4, 5]);
+        assert!(parse_id_range("0").is_err());
+        assert!(parse_id_range("4-3").is_err());
+        assert_eq!(
+            parse_id_range("a-b").unwrap_err(),
+            "Invalid id range 'a-b'."
+        );
+    }
+
+    #[test]
+    fn test_annotate() {
+        let mut records = records();
+        let now = ts("2024-05-02T09:00:00+09:00");
+        annotate(&mut records, &[1, 3], Review::Approved, "lead", now).unwrap();
+        annotate(&mut records, &[3], Review::Rejected, "lead", now).unwrap();
+        assert_eq!(records[0].fields.len(), 3);
+        assert_eq!(
+            crate::record::field(&records[0].fields, REVIEW_FIELD),
+            Some("approved")
+        );
+        assert!(records[3].fields.is_empty());
+        assert_eq!(
+            crate::record::field(&records[4].fields, REVIEW_FIELD),
+            Some("rejected")
+        );
+        assert_eq!(records[4].fields.len(), 3);
+        assert_eq!(
+            annotate(&mut records, &[4], Review::Approved, "lead", now).unwrap_err(),
+            "No entry with id 4 (3 entries recorded)."
+        );
+    }
+
+    #[test]
+    fn test_render_summary() {
+        let mut records = records();
+        let now = ts("2024-05-02T09:00:00+09:00");
+        let period = Period::new(
+            parse_date("2024-05-01").unwrap(),
+            parse_date("2024-05-01").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(render_summary(&pair_sessions(&records), &period, now), "");
+
+        annotate(&mut records, &[1], Review::Approved, "lead", now).unwrap();
+        annotate(&mut records, &[2], Review::Rejected, "lead", now).unwrap();
+        let summary = render_summary(&pair_sessions(&records), &period, now);
+        assert_eq!(
+            summary,
+            "\nReview:\n  approved     1h00m\n  rejected       30m\n  pending        30m\n"
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use crate::config::Config;
+use crate::period::to_local;
+use crate::record::{last_event, Event, Record};
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveTime};
+
+pub const AUTO_STOPPED_TAG: &str = "auto-stopped";
+
+// max_session と auto_stop_at のうち早い方を過ぎて開いたままのセッションがあれば、
+// その時刻で閉じる stop を返す
+pub fn auto_stop_record(
+    records: &[Record],
+    config: &Config,
+    now: DateTime<FixedOffset>,
+) -> Option<Record> {
+    let start = last_event(records).filter(|r| r.event == Event::Start)?;
+    let by_length = config.max_session.map(|max| start.timestamp + max);
+    let by_clock = config
+        .auto_stop_at
+        .map(|time| next_occurrence(start.timestamp, time));
+    let limit = by_length.into_iter().chain(by_clock).min()?;
+    if limit >= now {
+        return None;
+    }
+    Some(Record::new(limit, Event::Stop, "").with_tag(AUTO_STOPPED_TAG))
+}
+
+// start より後で最初に来る time の時刻
+fn next_occurrence(start: DateTime<FixedOffset>, time: NaiveTime) -> DateTime<FixedOffset> {
+    let date = start.with_timezone(&Local).date_naive();
+    let same_day = to_local(date.and_time(time));
+    if same_day > start {
+        same_day
+    } else {
+        to_local((date + Duration::days(1)).and_time(time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::{local_midnight, parse_date};
+
+    fn ts(s: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(s).unwrap()
+    }
+
+    fn local(date: &str, hour: i64) -> DateTime<FixedOffset> {
+        local_midnight(parse_date(date).unwrap()) + Duration::hours(hour)
+    }
+
+    fn config(max_session: Option<Duration>) -> Config {
+        Config {
+            max_session,
+            ..Default::default()
+        }
+    }
+
+    fn clock_config(hour: u32) -> Config {
+        Config {
+            auto_stop_at: NaiveTime::from_hms_opt(hour, 0, 0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_auto_stop_at_cap() {
+        let records = vec![Record::new(
+            ts("2024-05-01T09:00:00+09:00"),
+            Event::Start,
+            "a",
+        )];
+        let now = ts("2024-05-02T09:00:00+09:00");
+        let stop = auto_stop_record(&records, &config(Some(Duration::hours(10))), now).unwrap();
+        assert_eq!(stop.timestamp, ts("2024-05-01T19:00:00+09:00"));
+        assert_eq!(stop.event, Event::Stop);
+        assert_eq!(stop.tags, vec![AUTO_STOPPED_TAG]);
+    }
+
+    #[test]
+    fn test_no_auto_stop_within_cap() {
+        let records = vec![Record::new(
+            ts("2024-05-01T09:00:00+09:00"),
+            Event::Start,
+            "a",
+        )];
+        let now = ts("2024-05-01T18:00:00+09:00");
+        assert!(auto_stop_record(&records, &config(Some(Duration::hours(10))), now).is_none());
+    }
+
+    #[test]
+    fn test_no_auto_stop_without_config() {
+        let records = vec![Record::new(
+            ts("2024-05-01T09:00:00+09:00"),
+            Event::Start,
+            "a",
+        )];
+        let now = ts("2024-05-03T09:00:00+09:00");
+        assert!(auto_stop_record(&records, &config(None), now).is_none());
+    }
+
+    #[test]
+    fn test_auto_stop_at_clock_time() {
+        let records = vec![Record::new(local("2024-05-01", 9), Event::Start, "a")];
+        let stop = auto_stop_record(&records, &clock_config(19), local("2024-05-02", 8)).unwrap();
+        assert_eq!(stop.timestamp, local("2024-05-01", 19));
+        assert!(auto_stop_record(&records, &clock_config(19), local("2024-05-01", 18)).is_none());
+    }
+
+    #[test]
+    fn test_auto_stop_at_next_day_when_started_late() {
+        let records = vec![Record::new(local("2024-05-01", 20), Event::Start, "a")];
+        let stop = auto_stop_record(&records, &clock_config(19), local("2024-05-03", 8)).unwrap();
+        assert_eq!(stop.timestamp, local("2024-05-02", 19));
+    }
+
+    #[test]
+    fn test_auto_stop_uses_earliest_limit() {
+        let records = vec![Record::new(local("2024-05-01", 9), Event::Start, "a")];
+        let config = Config {
+            max_session: Some(Duration::hours(8)),
+            auto_stop_at: NaiveTime::from_hms_opt(19, 0, 0),
+            ..Default::default()
+        };
+        let stop = auto_stop_record(&records, &config, local("2024-05-02", 8)).unwrap();
+        assert_eq!(stop.timestamp, local("2024-05-01", 17));
+    }
+
+    #[test]
+    fn test_no_auto_stop_when_stopped() {
+        let records = vec![
+            Record::new(ts("2024-05-01T09:00:00+09:00"), Event::Start, "a"),
+            Record::new(ts("2024-05-01T10:00:00+09:00"), Event::Stop, ""),
+        ];
+        let now = ts("2024-05-03T09:00:00+09:00");
+        assert!(auto_stop_record(&records, &config(Some(Duration::hours(1))), now).is_none());
+    }
+}
@@ -0,0 +1,135 @@
+use crate::duration::{format_duration, hours};
+use crate::period::Period;
+use crate::report::total_between;
+use crate::session::Session;
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetPeriod {
+    Week,
+    #[default]
+    Month,
+}
+
+impl BudgetPeriod {
+    pub fn period_of(&self, date: NaiveDate) -> Period {
+        match self {
+            BudgetPeriod::Week => Period::week_of(date),
+            BudgetPeriod::Month => Period::month_of(date),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BudgetPeriod::Week => "weekly",
+            BudgetPeriod::Month => "monthly",
+        }
+    }
+}
+
+pub struct BudgetUsage {
+    pub project: String,
+    pub budget: Duration,
+    pub period: BudgetPeriod,
+    pub used: Duration,
+}
+
+impl BudgetUsage {
+    // date を含む予算期間に project で使った時間
+    pub fn new(
+        sessions: &[Session],
+        project: &str,
+        budget: Duration,
+        period: BudgetPeriod,
+        date: NaiveDate,
+        now: DateTime<FixedOffset>,
+    ) -> BudgetUsage {
+        let range = period.period_of(date);
+        let matching = sessions.iter().filter(|s| s.project() == Some(project));
+        BudgetUsage {
+            project: project.to_string(),
+            budget,
+            period,
+            used: total_between(matching, range.start_time(), range.end_time(), now),
+        }
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.used >= self.budget
+    }
+
+    pub fn warning(&self) -> String {
+        format!(
+            "Warning: '{}' has used {} of its {} {} budget.",
+            self.project,
+            format_duration(self.used),
+            self.period.name(),
+            format_duration(self.budget)
+        )
+    }
+}
+
+pub fn render(usages: &[BudgetUsage]) -> String {
+    if usages.is_empty() {
+        return String::new();
+    }
+    let width = usages
+        .iter()
+        .map(|u| u.project.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut output = "\nBudgets:\n".to_string();
+    for usage in usages {
+        let share = hours(usage.used) / hours(usage.budget) * 100.0;
+        output += &format!(
+            "{:<width$}  {:>8} / {:>8}  {:>4.0}%  {}{}\n",
+            usage.project,
+            format_duration(usage.used),
+            format_duration(usage.budget),
+            share,
+            usage.period.name(),
+            if usage.used > usage.budget { "  !" } else { "" }
+        );
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::{parse_date, to_local};
+
+    fn session(task: &str, date: &str, hours: i64) -> Session {
+        let start = to_local(parse_date(date).unwrap().and_hms_opt(9, 0, 0).unwrap());
+        Session::new(task, start, Some(start + Duration::hours(hours)))
+    }
+
+    #[test]
+    fn test_budget_usage() {
+        let sessions = vec![
+            session("clientA:fix", "2024-04-30", 8),
+            session("clientA:fix", "2024-05-02", 6),
+            session("clientA:review", "2024-05-06", 4),
+            session("clientB:fix", "2024-05-02", 3),
+        ];
+        let date = parse_date("2024-05-03").unwrap();
+        let now = to_local(date.and_hms_opt(18, 0, 0).unwrap());
+        let budget = Duration::hours(10);
+        let week = BudgetUsage::new(&sessions, "clientA", budget, BudgetPeriod::Week, date, now);
+        assert_eq!(week.used, Duration::hours(14));
+        assert!(week.is_exhausted());
+        let month = BudgetUsage::new(&sessions, "clientA", budget, BudgetPeriod::Month, date, now);
+        assert_eq!(month.used, Duration::hours(10));
+        assert!(month.is_exhausted());
+        assert_eq!(
+            month.warning(),
+            "Warning: 'clientA' has used 10h00m of its monthly 10h00m budget."
+        );
+
+        let output = render(&[week, month]);
+        assert!(output.contains("clientA    14h00m /   10h00m   140%  weekly  !\n"));
+        assert!(output.contains("clientA    10h00m /   10h00m   100%  monthly\n"));
+    }
+}
@@ -0,0 +1,74 @@
+use crate::record::hash_text;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+// report の出力を "<記録ファイル>.cache" に保存して使い回す。
+// 読んだファイルの長さと更新時刻、引数、今日の日付から作ったキーが変われば作り直す。
+pub fn cache_path(file_path: &str) -> String {
+    format!("{}.cache", file_path)
+}
+
+// 無いファイルは "-" として、キーに含める
+pub fn key<'a>(files: impl Iterator<Item = &'a Path>, inputs: &[&str]) -> String {
+    let mut key = String::new();
+    for file in files {
+        let stamp = fs::metadata(file).ok().map(|metadata| {
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |since| since.as_nanos());
+            format!("{} {}", metadata.len(), modified)
+        });
+        key += &format!("{}\0{}\0", file.display(), stamp.as_deref().unwrap_or("-"));
+    }
+    key += &inputs.join("\0");
+    hash_text(&key)
+}
+
+pub fn load(file_path: &str, key: &str) -> Option<String> {
+    let content = fs::read_to_string(cache_path(file_path)).ok()?;
+    let (header, output) = content.split_once('\n')?;
+    (header.strip_prefix("# ") == Some(key)).then(|| output.to_string())
+}
+
+pub fn store(file_path: &str, key: &str, output: &str) -> Result<(), String> {
+    fs::write(cache_path(file_path), format!("# {}\n{}", key, output)).map_err(|e| e.to_string())
+}
+
+// 記録ファイルを書き換えたら消す
+pub fn invalidate(file_path: &str) -> Result<(), String> {
+    match fs::remove_file(cache_path(file_path)) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.to_string()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_round_trip() {
+        let file_path = "test_cache_record.txt";
+        fs::write(file_path, "2024-05-01T09:00:00+09:00\tstart\ta\n").unwrap();
+        let key_of = |inputs: &[&str]| key([Path::new(file_path)].into_iter(), inputs);
+        let key = key_of(&["report", "2024-05-01"]);
+        assert_ne!(key, key_of(&["report", "2024-05-02"]));
+
+        assert_eq!(load(file_path, &key), None);
+        store(file_path, &key, "a  1h00m\n").unwrap();
+        assert_eq!(load(file_path, &key).as_deref(), Some("a  1h00m\n"));
+
+        // 記録ファイルの長さが変わればキーも変わる
+        fs::write(file_path, "2024-05-01T09:00:00+09:00\tstart\tab\n").unwrap();
+        assert_eq!(load(file_path, &key_of(&["report", "2024-05-01"])), None);
+
+        invalidate(file_path).unwrap();
+        invalidate(file_path).unwrap();
+        assert!(fs::metadata(cache_path(file_path)).is_err());
+        fs::remove_file(file_path).unwrap();
+    }
+}
@@ -0,0 +1,127 @@
+use crate::config::deserialize_required_duration;
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use std::net::UdpSocket;
+use std::time;
+
+// NTP は 1900 年、Unix 時刻は 1970 年から数える
+const NTP_UNIX_OFFSET: i64 = 2_208_988_800;
+const PACKET_SIZE: usize = 48;
+const NTP_PORT: u16 = 123;
+const DEFAULT_SERVER: &str = "pool.ntp.org";
+pub const TIMEOUT: time::Duration = time::Duration::from_secs(3);
+const INVALID_RESPONSE_MSG: &str = "NTP サーバーの応答を読めません。";
+
+// [clock] の設定。verify なら時刻を記録するコマンドの前に NTP サーバーと比べる。
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClockCheck {
+    pub verify: bool,
+    pub server: String,
+    // これより大きくずれていたら警告する
+    #[serde(deserialize_with = "deserialize_required_duration")]
+    pub max_skew: Duration,
+}
+
+impl Default for ClockCheck {
+    fn default() -> ClockCheck {
+        ClockCheck {
+            verify: false,
+            server: DEFAULT_SERVER.to_string(),
+            max_skew: Duration::seconds(2),
+        }
+    }
+}
+
+// SNTP の問い合わせ。LI = 0, VN = 4, Mode = 3 (client)。
+pub fn request() -> [u8; PACKET_SIZE] {
+    let mut packet = [0; PACKET_SIZE];
+    packet[0] = 0x23;
+    packet
+}
+
+// 応答 (Mode = 4) の送信時刻 (transmit timestamp)
+pub fn transmit_time(packet: &[u8]) -> Result<DateTime<Utc>, String> {
+    if packet.len() < PACKET_SIZE || packet[0] & 0x07 != 4 {
+        return Err(INVALID_RESPONSE_MSG.to_string());
+    }
+    let word =
+        |i: usize| u32::from_be_bytes([packet[i], packet[i + 1], packet[i + 2], packet[i + 3]]);
+    let (seconds, fraction) = (word(40), word(44));
+    // 0 は同期していないサーバー
+    if seconds == 0 {
+        return Err(INVALID_RESPONSE_MSG.to_string());
+    }
+    let nanos = ((u64::from(fraction) * 1_000_000_000) >> 32) as u32;
+    DateTime::from_timestamp(i64::from(seconds) - NTP_UNIX_OFFSET, nanos)
+        .ok_or_else(|| INVALID_RESPONSE_MSG.to_string())
+}
+
+// このマシンの時計がサーバーより進んでいる量 (遅れていれば負)。往復の半分を片道とみなす。
+pub fn skew(server: &str, timeout: time::Duration) -> Result<Duration, String> {
+    let address = match server.contains(':') {
+        true => server.to_string(),
+        false => format!("{}:{}", server, NTP_PORT),
+    };
+    let error = |e: std::io::Error| format!("{}: {}", address, e);
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(error)?;
+    socket.set_read_timeout(Some(timeout)).map_err(error)?;
+    socket.connect(&address).map_err(error)?;
+
+    let sent = Utc::now();
+    socket.send(&request()).map_err(error)?;
+    let mut buffer = [0; PACKET_SIZE];
+    let length = socket.recv(&mut buffer).map_err(error)?;
+    let received = Utc::now();
+    let server_time = transmit_time(&buffer[..length])?;
+    Ok(sent + (received - sent) / 2 - server_time)
+}
+
+pub fn format_skew(skew: Duration) -> String {
+    format!("{:+.1}s", skew.num_milliseconds() as f64 / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn response(time: DateTime<Utc>) -> [u8; PACKET_SIZE] {
+        let mut packet = [0; PACKET_SIZE];
+        packet[0] = 0x24;
+        let seconds = (time.timestamp() + NTP_UNIX_OFFSET) as u32;
+        let fraction = ((u64::from(time.timestamp_subsec_nanos()) << 32) / 1_000_000_000) as u32;
+        packet[40..44].copy_from_slice(&seconds.to_be_bytes());
+        packet[44..48].copy_from_slice(&fraction.to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn test_transmit_time() {
+        let time = DateTime::parse_from_rfc3339("2024-05-01T00:00:00.5Z")
+            .unwrap()
+            .to_utc();
+        let parsed = transmit_time(&response(time)).unwrap();
+        assert!((parsed - time).abs() < Duration::microseconds(1));
+        assert!(transmit_time(&request()).is_err());
+        assert!(transmit_time(&[0x24; 10]).is_err());
+        assert_eq!(format_skew(Duration::milliseconds(-2500)), "-2.5s");
+    }
+
+    #[test]
+    fn test_skew_against_local_server() {
+        // 1 分遅れた時刻を返すサーバー
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = server.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let mut buffer = [0; PACKET_SIZE];
+            let (_, client) = server.recv_from(&mut buffer).unwrap();
+            assert_eq!(buffer, request());
+            let reply = response(Utc::now() - Duration::minutes(1));
+            server.send_to(&reply, client).unwrap();
+        });
+        let skew = skew(&address, TIMEOUT).unwrap();
+        handle.join().unwrap();
+        assert!((skew - Duration::minutes(1)).abs() < Duration::seconds(1));
+    }
+}
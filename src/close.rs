@@ -0,0 +1,130 @@
+use crate::export::{parse_fields, to_csv, to_jsonl, Field, Locale, DEFAULT_FIELDS};
+use crate::hours::HoursProfile;
+use crate::ledger::{to_timeclock, to_timedot};
+use crate::period::Period;
+use crate::report::daily_totals;
+use crate::session::Session;
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use serde::Deserialize;
+
+// close_exports が空のときに書き出すもの
+pub const DEFAULT_EXPORTS: [CloseExport; 2] = [CloseExport::Sessions, CloseExport::Billing];
+
+const BILLING_FIELDS: &str = "start,end,duration,project,task,note,rate,amount";
+
+// 月の締めで出力ディレクトリに書き出すファイル
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CloseExport {
+    // 全セッションの CSV
+    Sessions,
+    // 請求対象 (billable) のセッションだけを単価・金額付きで並べた CSV
+    Billing,
+    Jsonl,
+    Timeclock,
+    Timedot,
+}
+
+impl CloseExport {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            CloseExport::Sessions => "sessions.csv",
+            CloseExport::Billing => "billing.csv",
+            CloseExport::Jsonl => "sessions.jsonl",
+            CloseExport::Timeclock => "sessions.timeclock",
+            CloseExport::Timedot => "sessions.timedot",
+        }
+    }
+
+    pub fn render(
+        &self,
+        sessions: &[Session],
+        rate_of: impl Fn(&Session) -> Option<f64>,
+        now: DateTime<FixedOffset>,
+    ) -> String {
+        match self {
+            CloseExport::Sessions => {
+                to_csv(sessions, &DEFAULT_FIELDS, Locale::Default, rate_of, now)
+            }
+            CloseExport::Billing => {
+                let billable: Vec<Session> =
+                    sessions.iter().filter(|s| s.billable()).cloned().collect();
+                let fields: Vec<Field> = parse_fields(BILLING_FIELDS).unwrap();
+                to_csv(&billable, &fields, Locale::Default, rate_of, now)
+            }
+            CloseExport::Jsonl => to_jsonl(sessions, &DEFAULT_FIELDS, rate_of, now),
+            CloseExport::Timeclock => to_timeclock(sessions),
+            CloseExport::Timedot => to_timedot(sessions, now),
+        }
+    }
+}
+
+// 期間内の今日までの所定時間のある日で、何も記録の無い日
+pub fn untracked_workdays(
+    sessions: &[Session],
+    period: &Period,
+    hours: &HoursProfile,
+    today: NaiveDate,
+    now: DateTime<FixedOffset>,
+) -> Vec<NaiveDate> {
+    daily_totals(sessions, period, now)
+        .into_iter()
+        .filter(|(day, total)| *day <= today && hours.is_workday(*day) && total.is_zero())
+        .map(|(day, _)| day)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::{parse_date, to_local};
+    use crate::session::BILLABLE_FIELD;
+    use chrono::Duration;
+
+    fn session(task: &str, date: &str, billable: bool) -> Session {
+        let start = to_local(parse_date(date).unwrap().and_hms_opt(9, 0, 0).unwrap());
+        let mut session = Session::new(task, start, Some(start + Duration::hours(2)));
+        if billable {
+            session
+                .fields
+                .push((BILLABLE_FIELD.to_string(), "true".to_string()));
+        }
+        session
+    }
+
+    #[test]
+    fn test_untracked_workdays() {
+        let sessions = vec![
+            session("a", "2024-05-01", false),
+            session("a", "2024-05-03", false),
+        ];
+        let period = Period::parse("2024-05").unwrap();
+        let today = parse_date("2024-05-07").unwrap();
+        let now = to_local(today.and_hms_opt(12, 0, 0).unwrap());
+        let days: Vec<String> =
+            untracked_workdays(&sessions, &period, &HoursProfile::default(), today, now)
+                .iter()
+                .map(|d| d.to_string())
+                .collect();
+        assert_eq!(days, vec!["2024-05-02", "2024-05-06", "2024-05-07"]);
+    }
+
+    #[test]
+    fn test_billing_export() {
+        let sessions = vec![
+            session("clientA:fix", "2024-05-01", true),
+            session("internal:meeting", "2024-05-01", false),
+        ];
+        let now = to_local(
+            parse_date("2024-06-01")
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+        );
+        let csv = CloseExport::Billing.render(&sessions, |_| Some(50.0), now);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], BILLING_FIELDS);
+        assert!(lines[1].ends_with(",2,clientA,fix,,50,100"));
+    }
+}
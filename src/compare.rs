@@ -0,0 +1,128 @@
+use crate::duration::{format_delta, format_duration};
+use crate::period::Period;
+use crate::report::{sum, totals, GroupBy};
+use crate::session::Session;
+use chrono::{DateTime, Duration, FixedOffset};
+
+pub struct CompareRow {
+    pub key: String,
+    pub a: Duration,
+    pub b: Duration,
+}
+
+impl CompareRow {
+    pub fn delta(&self) -> Duration {
+        self.b - self.a
+    }
+}
+
+pub fn compare(
+    sessions: &[Session],
+    a: &Period,
+    b: &Period,
+    group_by: GroupBy,
+    now: DateTime<FixedOffset>,
+) -> Vec<CompareRow> {
+    let mut rows: Vec<CompareRow> = totals(sessions, a, group_by.clone(), now)
+        .into_iter()
+        .map(|(key, a)| CompareRow {
+            key,
+            a,
+            b: Duration::zero(),
+        })
+        .collect();
+
+    for (key, b) in totals(sessions, b, group_by, now) {
+        match rows.iter_mut().find(|row| row.key == key) {
+            Some(row) => row.b = b,
+            None => rows.push(CompareRow {
+                key,
+                a: Duration::zero(),
+                b,
+            }),
+        }
+    }
+
+    rows.sort_by(|x, y| {
+        (y.a + y.b)
+            .cmp(&(x.a + x.b))
+            .then_with(|| x.key.cmp(&y.key))
+    });
+    rows
+}
+
+pub fn render_compare(rows: &[CompareRow], label_a: &str, label_b: &str) -> String {
+    let total = CompareRow {
+        key: "Total".to_string(),
+        a: sum(rows.iter().map(|row| row.a)),
+        b: sum(rows.iter().map(|row| row.b)),
+    };
+    let width = rows
+        .iter()
+        .map(|row| row.key.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(total.key.len());
+    let col = label_a.len().max(label_b.len()).max(8);
+
+    let mut output = format!(
+        "{:<width$}  {:>col$}  {:>col$}  {:>8}\n",
+        "", label_a, label_b, "Delta"
+    );
+    for row in rows.iter().chain(std::iter::once(&total)) {
+        output += &format!(
+            "{:<width$}  {:>col$}  {:>col$}  {:>8}\n",
+            row.key,
+            format_duration(row.a),
+            format_duration(row.b),
+            format_delta(row.delta())
+        );
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts(s: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(s).unwrap()
+    }
+
+    fn session(task: &str, start: &str, stop: &str) -> Session {
+        Session::new(task, ts(start), Some(ts(stop)))
+    }
+
+    #[test]
+    fn test_compare_months() {
+        let now = ts("2030-01-01T00:00:00Z");
+        let sessions = vec![
+            session("x:a", "2024-04-10T03:00:00Z", "2024-04-10T05:00:00Z"),
+            session("x:b", "2024-05-10T03:00:00Z", "2024-05-10T04:00:00Z"),
+            session("y:c", "2024-05-11T03:00:00Z", "2024-05-11T06:00:00Z"),
+        ];
+        let a = Period::parse("2024-04").unwrap();
+        let b = Period::parse("2024-05").unwrap();
+
+        let rows = compare(&sessions, &a, &b, GroupBy::Project, now);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].key, "x");
+        assert_eq!(rows[0].delta(), Duration::hours(-1));
+        assert_eq!(rows[1].key, "y");
+        assert_eq!(rows[1].a, Duration::zero());
+        assert_eq!(rows[1].delta(), Duration::hours(3));
+    }
+
+    #[test]
+    fn test_render_compare_includes_total() {
+        let rows = vec![CompareRow {
+            key: "a".to_string(),
+            a: Duration::hours(2),
+            b: Duration::minutes(30),
+        }];
+        let output = render_compare(&rows, "2024-04", "2024-05");
+        let last = output.lines().last().unwrap();
+        assert!(last.starts_with("Total"));
+        assert!(last.ends_with("-1h30m"));
+    }
+}
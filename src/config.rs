@@ -0,0 +1,747 @@
+use crate::budget::BudgetPeriod;
+use crate::clock::ClockCheck;
+use crate::close::CloseExport;
+use crate::duration::parse_duration;
+use crate::export::Locale;
+use crate::fiscal::FiscalCalendar;
+use crate::hours::HoursProfile;
+use crate::note::NoteOnStop;
+use crate::period::parse_time;
+use crate::policy::NamingPolicy;
+use crate::pomodoro::PomodoroConfig;
+use crate::record::Precision;
+use crate::recurring::RecurringEntry;
+use crate::reference::ReferenceRule;
+use crate::rounding::RoundingConfig;
+use crate::session::project_of;
+use crate::sources::Source;
+use crate::timezone::TimeZone;
+use crate::untracked::Workday;
+use crate::watch::WatchConfig;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveTime, Weekday};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+pub const CONFIG_ENV: &str = "WORKING_TIME_RECORDER_CONFIG";
+pub const DEFAULT_TIME_FORMAT: &str = "%H:%M";
+
+// config show で表示するキーと既定値。None は既定では未設定。
+const KEYS: &[(&str, Option<&str>)] = &[
+    ("max_session", None),
+    ("merge_gap", None),
+    ("auto_stop_at", None),
+    ("recurring", Some("[]")),
+    ("timestamp_precision", Some("\"seconds\"")),
+    ("record_host", Some("false")),
+    ("device_name", None),
+    ("retention", None),
+    ("history_limit", None),
+    ("naming", Some("{}")),
+    ("allowed_projects", Some("[]")),
+    ("projects_file", None),
+    ("required_tags", Some("[]")),
+    ("references", Some("[]")),
+    ("rounding", Some("{}")),
+    (
+        "fiscal",
+        Some("{ year_start_month = 1, month_start_day = 1 }"),
+    ),
+    ("sources", Some("[]")),
+    ("projects", Some("{}")),
+    ("workday", Some("\"09:00-18:00\"")),
+    (
+        "hours",
+        Some("{ mon = \"8h\", tue = \"8h\", wed = \"8h\", thu = \"8h\", fri = \"8h\", sat = \"0\", sun = \"0\" }"),
+    ),
+    ("note_on_stop", Some("\"never\"")),
+    ("close_exports", Some("[\"sessions\", \"billing\"]")),
+    ("locale", None),
+    ("timezone", None),
+    ("time_format", Some("\"%H:%M\"")),
+    (
+        "clock",
+        Some("{ verify = false, server = \"pool.ntp.org\", max_skew = \"2s\" }"),
+    ),
+    (
+        "pomodoro",
+        Some("{ short_break = \"5m\", long_break = \"15m\", long_break_every = 4 }"),
+    ),
+    (
+        "watch",
+        Some("{ threshold = \"10h\", interval = \"1m\", auto_stop = false }"),
+    ),
+    ("aliases", Some("{}")),
+];
+
+// init で書き出す設定ファイル。設定はすべてコメントにしてあり、既定値のまま動く。
+pub const DEFAULT_CONTENT: &str = "\
+# working-time-recorder configuration.
+# Uncomment a line to change a setting. The WORKING_TIME_RECORD environment
+# variable and command-line flags take precedence over this file.
+
+# Close sessions that run longer than this.
+# max_session = \"10h\"
+
+# Continue the previous session when the same task starts again within this gap.
+# merge_gap = \"5m\"
+
+# Only read this far back in report, log, stats, timeline and digest unless
+# --all-history is given or the requested period starts earlier.
+# history_limit = \"540d\"
+
+# Store timestamps in whole seconds or minutes.
+# timestamp_precision = \"seconds\"
+
+# Locale for `export csv` and for weekday and month names in reports
+# (en, de, fr or ja).
+# locale = \"de\"
+
+# How times of day are shown in log, report --laps, status and other output
+# (a strftime format).
+# time_format = \"%I:%M %p\"
+
+# Time zone for new timestamps and reports: UTC or an IANA name (default: the system's).
+# timezone = \"UTC\"
+
+# Working hours for `untracked`. The end is also offered as a stop time for
+# a session left running overnight.
+# workday = \"09:00-18:00\"
+
+# Round session lengths in reports.
+# [rounding.report]
+# interval = \"15m\"
+# mode = \"up\"
+
+# Target hours per weekday (mon..sun); days with 0 are days off.
+# [hours]
+# fri = \"6h\"
+
+# Shortcuts for subcommands, e.g. `w` runs `report --week`.
+# [aliases]
+# w = \"report --week\"
+
+# Warn before recording when the system clock is off from an NTP server.
+# [clock]
+# verify = true
+# server = \"pool.ntp.org\"
+# max_skew = \"2s\"
+
+# Copy references in task names into fields when recording, e.g. jira=PROJ-12.
+# [[references]]
+# field = \"jira\"
+# pattern = '\\b[A-Z][A-Z0-9]+-\\d+\\b'
+
+# Breaks announced after `start --pomodoro`; every 4th break is the long one.
+# [pomodoro]
+# short_break = \"5m\"
+# long_break = \"15m\"
+# long_break_every = 4
+
+# Notify from `watch` when a session runs longer than the threshold.
+# [watch]
+# threshold = \"10h\"
+# interval = \"1m\"
+# auto_stop = true
+
+# A record file per project, chosen with `-p clientA`; `report --all-projects`
+# merges them.
+# [projects.clientA]
+# file = \"clientA.txt\"
+
+# Record files to read. The primary one is written to and is the default.
+# [[sources]]
+# name = \"work\"
+# path = \"working_time_record.txt\"
+# primary = true
+";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub max_session: Option<Duration>,
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub merge_gap: Option<Duration>,
+    #[serde(deserialize_with = "deserialize_time")]
+    pub auto_stop_at: Option<NaiveTime>,
+    pub recurring: Vec<RecurringEntry>,
+    pub timestamp_precision: Precision,
+    pub record_host: bool,
+    pub device_name: Option<String>,
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub retention: Option<Duration>,
+    // report や log が既定でさかのぼる長さ。--all-history で全部読む。
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub history_limit: Option<Duration>,
+    pub naming: NamingPolicy,
+    pub allowed_projects: Vec<String>,
+    pub projects_file: Option<PathBuf>,
+    pub required_tags: Vec<String>,
+    // タスク名からチケット番号などを取り出してフィールドに書く規則
+    pub references: Vec<ReferenceRule>,
+    pub rounding: RoundingConfig,
+    pub fiscal: FiscalCalendar,
+    pub sources: Vec<Source>,
+    pub projects: BTreeMap<String, ProjectSettings>,
+    pub workday: Workday,
+    // 曜日ごとの所定時間。勤務日の判定と report --balance に使う。
+    pub hours: HoursProfile,
+    pub note_on_stop: NoteOnStop,
+    // close で書き出すファイル。空なら sessions と billing。
+    pub close_exports: Vec<CloseExport>,
+    // export csv の既定の書式と、レポートの曜日・月の名前の地域設定
+    pub locale: Option<Locale>,
+    // 記録と表示のタイムゾーン。未設定ならシステムのもの。
+    pub timezone: Option<TimeZone>,
+    // 出力で時刻を書く書式 (strftime)。未設定なら DEFAULT_TIME_FORMAT。
+    #[serde(deserialize_with = "deserialize_time_format")]
+    pub time_format: Option<String>,
+    pub clock: ClockCheck,
+    pub pomodoro: PomodoroConfig,
+    pub watch: WatchConfig,
+    // サブコマンドの別名。値は空白で区切ったサブコマンドと引数。
+    pub aliases: BTreeMap<String, String>,
+}
+
+// [projects.<name>] のプロジェクトごとの設定
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectSettings {
+    pub billable: Option<bool>,
+    // 1 時間あたりの単価
+    pub rate: Option<f64>,
+    // budget_period ごとに使える時間
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub budget: Option<Duration>,
+    pub budget_period: BudgetPeriod,
+    // -p で選ぶ、このプロジェクト専用の記録ファイル
+    pub file: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn load() -> Result<Config, String> {
+        match config_path() {
+            Some(path) => Config::load_from(&path),
+            None => Ok(Config::default()),
+        }
+    }
+
+    pub fn load_from(path: &PathBuf) -> Result<Config, String> {
+        let mut config = match fs::read_to_string(path) {
+            Ok(content) => Config::parse(&content)
+                .map_err(|e| format!("Invalid config '{}': {}", path.display(), e))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(e.to_string()),
+        };
+
+        // 相対パスは設定ファイルの場所から解決する。projects_file は 1 行に 1 プロジェクト。
+        let config_dir = path.parent().unwrap_or(Path::new(""));
+        for source in &mut config.sources {
+            source.path = config_dir.join(&source.path);
+        }
+        for settings in config.projects.values_mut() {
+            if let Some(file) = &mut settings.file {
+                *file = config_dir.join(&*file);
+            }
+        }
+        if let Some(projects_file) = &config.projects_file {
+            let projects_file = config_dir.join(projects_file);
+            let content = fs::read_to_string(&projects_file)
+                .map_err(|e| format!("{}: {}", projects_file.display(), e))?;
+            config.allowed_projects.extend(
+                content
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(str::to_string),
+            );
+        }
+        Ok(config)
+    }
+
+    pub fn parse(content: &str) -> Result<Config, String> {
+        toml::from_str(content).map_err(|e| e.message().to_string())
+    }
+
+    // 記録する時刻。精度で切り捨ててから、storage の丸めを適用する。
+    pub fn timestamp(&self, time: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        let time = self.timestamp_precision.truncate(time);
+        match &self.rounding.storage {
+            Some(rounding) => rounding.round_time(time),
+            None => time,
+        }
+    }
+
+    // タスクのプロジェクトの設定にある billable の既定値
+    pub fn billable_default(&self, task: &str) -> Option<bool> {
+        let project = project_of(task)?;
+        self.projects.get(project)?.billable
+    }
+
+    pub fn rate(&self, task: &str) -> Option<f64> {
+        self.projects.get(project_of(task)?)?.rate
+    }
+
+    pub fn budget(&self, project: &str) -> Option<(Duration, BudgetPeriod)> {
+        let settings = self.projects.get(project)?;
+        Some((settings.budget?, settings.budget_period))
+    }
+
+    // -p で選んだプロジェクトの記録ファイル
+    pub fn project_file(&self, project: &str) -> Result<&Path, String> {
+        self.projects
+            .get(project)
+            .and_then(|settings| settings.file.as_deref())
+            .ok_or(format!(
+                "Project '{}' has no record file in the config.",
+                project
+            ))
+    }
+
+    // file のあるプロジェクトの (名前, 記録ファイル)。report --all-projects で合わせる。
+    pub fn project_files(&self) -> Vec<(&str, &Path)> {
+        self.projects
+            .iter()
+            .filter_map(|(name, settings)| Some((name.as_str(), settings.file.as_deref()?)))
+            .collect()
+    }
+
+    // args[1] が別名なら展開した引数。後ろの引数はそのまま続け、展開したものより優先する。
+    pub fn expand_alias(&self, args: &[String]) -> Option<Vec<String>> {
+        let expansion = self.aliases.get(args.get(1)?)?;
+        let mut expanded = vec![args[0].clone()];
+        expanded.extend(expansion.split_whitespace().map(str::to_string));
+        expanded.extend(args[2..].iter().cloned());
+        Some(expanded)
+    }
+
+    pub fn time_format(&self) -> &str {
+        self.time_format.as_deref().unwrap_or(DEFAULT_TIME_FORMAT)
+    }
+
+    // 時刻をローカル時刻にして time_format で書く
+    pub fn format_time(&self, time: DateTime<FixedOffset>) -> String {
+        time.with_timezone(&Local)
+            .format(self.time_format())
+            .to_string()
+    }
+
+    // 日付と時刻をローカル時刻にして書く
+    pub fn format_datetime(&self, time: DateTime<FixedOffset>) -> String {
+        format!(
+            "{} {}",
+            time.with_timezone(&Local).format("%Y-%m-%d"),
+            self.format_time(time)
+        )
+    }
+
+    // record_host が有効なら、device_name かホスト名を返す
+    pub fn host(&self) -> Option<String> {
+        if !self.record_host {
+            return None;
+        }
+        self.device_name
+            .clone()
+            .or_else(|| gethostname::gethostname().into_string().ok())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Setting {
+    pub key: &'static str,
+    pub value: Option<String>,
+    pub source: &'static str,
+}
+
+// 設定ファイルの内容から、各キーの値とその出どころを返す
+pub fn describe(content: &str) -> Result<Vec<Setting>, String> {
+    let table: toml::Table = toml::from_str(content).map_err(|e| e.message().to_string())?;
+    Ok(KEYS
+        .iter()
+        .map(|(key, default)| match table.get(*key) {
+            Some(value) => Setting {
+                key,
+                value: Some(value.to_string()),
+                source: "config file",
+            },
+            None => Setting {
+                key,
+                value: default.map(str::to_string),
+                source: "default",
+            },
+        })
+        .collect())
+}
+
+// "rounding.report.interval" のようなドット区切りのキーに値を設定した内容を返す。
+// コメントや書式はそのまま残し、設定として不正になる変更は拒否する。
+pub fn set_value(content: &str, key: &str, value: &str) -> Result<String, String> {
+    let mut document: toml_edit::DocumentMut = content.parse().map_err(|e| format!("{}", e))?;
+    // TOML の値として読めなければ文字列として扱う ("10h" など)
+    let value = value
+        .parse::<toml_edit::Value>()
+        .unwrap_or_else(|_| toml_edit::Value::from(value));
+
+    let mut segments: Vec<&str> = key.split('.').collect();
+    let last = segments.pop().filter(|s| !s.is_empty());
+    let last = last.ok_or_else(|| format!("Invalid key '{}'.", key))?;
+    let mut table = document.as_table_mut() as &mut dyn toml_edit::TableLike;
+    for segment in segments {
+        table = table
+            .entry(segment)
+            .or_insert_with(|| {
+                let mut table = toml_edit::Table::new();
+                table.set_implicit(true);
+                toml_edit::Item::Table(table)
+            })
+            .as_table_like_mut()
+            .ok_or_else(|| format!("'{}' is not a table.", segment))?;
+    }
+    // 既存のキーは置き換えず値だけ差し替えて、キーの前のコメントを残す
+    match table.get_mut(last) {
+        Some(item) => *item = toml_edit::Item::Value(value),
+        None => {
+            table.insert(last, toml_edit::Item::Value(value));
+        }
+    }
+
+    let content = document.to_string();
+    Config::parse(&content).map_err(|e| format!("Invalid value for '{}': {}", key, e))?;
+    Ok(content)
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    match env::var(CONFIG_ENV) {
+        Ok(path) => Some(PathBuf::from(path)),
+        Err(_) => {
+            dirs::config_dir().map(|dir| dir.join("working-time-recorder").join("config.toml"))
+        }
+    }
+}
+
+pub(crate) fn deserialize_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_required_duration(deserializer).map(Some)
+}
+
+pub(crate) fn deserialize_required_duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    parse_duration(&value).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_time<'de, D>(deserializer: D) -> Result<Option<NaiveTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_required_time(deserializer).map(Some)
+}
+
+pub(crate) fn deserialize_required_time<'de, D>(deserializer: D) -> Result<NaiveTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    parse_time(&value).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_time_format<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    if StrftimeItems::new(&value).any(|item| item == Item::Error) {
+        return Err(serde::de::Error::custom(format!(
+            "Invalid time format '{}'.",
+            value
+        )));
+    }
+    Ok(Some(value))
+}
+
+pub(crate) fn deserialize_weekdays<'de, D>(deserializer: D) -> Result<Vec<Weekday>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|day| {
+            day.parse()
+                .map_err(|_| serde::de::Error::custom(format!("invalid weekday '{}'", day)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_empty_config() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(config.max_session, None);
+    }
+
+    #[test]
+    fn test_parse_max_session() {
+        let config = Config::parse("max_session = \"10h\"").unwrap();
+        assert_eq!(config.max_session, Some(Duration::hours(10)));
+    }
+
+    #[test]
+    fn test_parse_auto_stop_at() {
+        let config = Config::parse("auto_stop_at = \"19:00\"").unwrap();
+        assert_eq!(config.auto_stop_at, NaiveTime::from_hms_opt(19, 0, 0));
+        assert!(Config::parse("auto_stop_at = \"7pm\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_recurring_entries() {
+        let config = Config::parse(
+            r#"
+            [[recurring]]
+            task = "standup"
+            start = "09:30"
+            end = "09:45"
+
+            [[recurring]]
+            task = "retro"
+            start = "16:00"
+            end = "17:00"
+            days = ["fri"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.recurring.len(), 2);
+        assert_eq!(config.recurring[0].days.len(), 5);
+        assert_eq!(config.recurring[1].days, vec![Weekday::Fri]);
+    }
+
+    #[test]
+    fn test_parse_timestamp_precision() {
+        let config = Config::parse("timestamp_precision = \"millis\"").unwrap();
+        assert_eq!(config.timestamp_precision, Precision::Millis);
+        assert_eq!(Config::default().timestamp_precision, Precision::Seconds);
+        assert!(Config::parse("timestamp_precision = \"hours\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_rounding() {
+        let config = Config::parse(
+            r#"
+            [rounding.storage]
+            interval = "15m"
+            mode = "down"
+
+            [rounding.report]
+            interval = "6m"
+            "#,
+        )
+        .unwrap();
+        let time = DateTime::parse_from_rfc3339("2024-05-01T09:14:30.5+09:00").unwrap();
+        assert_eq!(
+            config.timestamp(time).to_rfc3339(),
+            "2024-05-01T09:00:00+09:00"
+        );
+        assert_eq!(
+            config.rounding.report.unwrap().interval,
+            Duration::minutes(6)
+        );
+        assert!(Config::parse("[rounding.report]\nmode = \"up\"").is_err());
+    }
+
+    #[test]
+    fn test_key_defaults_are_valid() {
+        for (key, default) in KEYS {
+            if let Some(default) = default {
+                assert!(Config::parse(&format!("{} = {}", key, default)).is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn test_default_content_is_valid() {
+        assert!(Config::parse(DEFAULT_CONTENT).is_ok());
+        // コメントを外しても設定として読める
+        let uncommented: String = DEFAULT_CONTENT
+            .lines()
+            .map(|line| match line.strip_prefix("# ") {
+                Some(setting) if setting.starts_with('[') || setting.contains(" = ") => setting,
+                _ => line,
+            })
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let config = Config::parse(&uncommented).unwrap();
+        assert_eq!(config.locale, Some(Locale::De));
+        assert_eq!(config.timezone, Some(TimeZone::utc()));
+        assert_eq!(config.time_format(), "%I:%M %p");
+        assert_eq!(config.history_limit, Some(Duration::days(540)));
+        assert!(config.clock.verify);
+        assert_eq!(config.aliases["w"], "report --week");
+    }
+
+    #[test]
+    fn test_time_format() {
+        let time = DateTime::parse_from_rfc3339("2024-05-01T21:05:00+09:00").unwrap();
+        let local = time.with_timezone(&Local);
+        assert_eq!(
+            Config::default().format_time(time),
+            local.format("%H:%M").to_string()
+        );
+        let config = Config::parse("time_format = \"%I:%M %p\"").unwrap();
+        assert_eq!(
+            config.format_time(time),
+            local.format("%I:%M %p").to_string()
+        );
+        assert_eq!(
+            config.format_datetime(time),
+            local.format("%Y-%m-%d %I:%M %p").to_string()
+        );
+        assert!(Config::parse("time_format = \"%Q\"").is_err());
+    }
+
+    #[test]
+    fn test_expand_alias() {
+        let config = Config::parse("[aliases]\nw = \"report --week\"").unwrap();
+        let args = |args: &[&str]| -> Vec<String> { args.iter().map(|a| a.to_string()).collect() };
+        assert_eq!(
+            config.expand_alias(&args(&["wtr", "w", "--laps"])),
+            Some(args(&["wtr", "report", "--week", "--laps"]))
+        );
+        assert_eq!(config.expand_alias(&args(&["wtr", "report"])), None);
+    }
+
+    #[test]
+    fn test_describe() {
+        let described = describe("max_session = \"10h\"").unwrap();
+        let setting = |key| described.iter().find(|s| s.key == key).unwrap();
+        assert_eq!(setting("max_session").value.as_deref(), Some("\"10h\""));
+        assert_eq!(setting("max_session").source, "config file");
+        assert_eq!(setting("retention").value, None);
+        assert_eq!(setting("record_host").value.as_deref(), Some("false"));
+        assert_eq!(setting("record_host").source, "default");
+    }
+
+    #[test]
+    fn test_set_value_keeps_comments() {
+        let content = "# my settings\nmax_session = \"10h\"\n";
+        let updated = set_value(content, "max_session", "8h").unwrap();
+        assert_eq!(updated, "# my settings\nmax_session = \"8h\"\n");
+        let updated = set_value(&updated, "record_host", "true").unwrap();
+        assert!(Config::parse(&updated).unwrap().record_host);
+    }
+
+    #[test]
+    fn test_set_nested_value() {
+        let updated = set_value("", "rounding.report.interval", "6m").unwrap();
+        let config = Config::parse(&updated).unwrap();
+        assert_eq!(
+            config.rounding.report.unwrap().interval,
+            Duration::minutes(6)
+        );
+    }
+
+    #[test]
+    fn test_set_invalid_value() {
+        assert!(set_value("", "max_session", "ten hours").is_err());
+        assert!(set_value("", "unknown_key", "1").is_err());
+        assert!(set_value("", "", "1").is_err());
+    }
+
+    #[test]
+    fn test_billable_default() {
+        let config = Config::parse(
+            "[projects.clientA]\nbillable = true\n[projects.internal]\nbillable = false\n",
+        )
+        .unwrap();
+        assert_eq!(config.billable_default("clientA:fix"), Some(true));
+        assert_eq!(config.billable_default("internal:meeting"), Some(false));
+        assert_eq!(config.billable_default("clientB:fix"), None);
+        assert_eq!(config.billable_default("lunch"), None);
+        assert_eq!(config.rate("clientA:fix"), None);
+    }
+
+    #[test]
+    fn test_budget() {
+        let config = Config::parse(
+            "[projects.clientA]\nbudget = \"40h\"\n[projects.clientB]\nbudget = \"8h\"\nbudget_period = \"week\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.budget("clientA"),
+            Some((Duration::hours(40), BudgetPeriod::Month))
+        );
+        assert_eq!(
+            config.budget("clientB"),
+            Some((Duration::hours(8), BudgetPeriod::Week))
+        );
+        assert_eq!(config.budget("clientC"), None);
+        assert!(Config::parse("[projects.clientA]\nbudget = \"lots\"\n").is_err());
+    }
+
+    #[test]
+    fn test_project_file() {
+        let dir = env::temp_dir().join("wtr_test_project_file");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("config.toml"),
+            "[projects.clientA]\nfile = \"clientA.txt\"\n[projects.clientB]\nbillable = true\n",
+        )
+        .unwrap();
+        let config = Config::load_from(&dir.join("config.toml")).unwrap();
+        let file = dir.join("clientA.txt");
+        assert_eq!(config.project_file("clientA"), Ok(file.as_path()));
+        assert!(config.project_file("clientB").is_err());
+        assert!(config.project_file("clientC").is_err());
+        assert_eq!(config.project_files(), vec![("clientA", file.as_path())]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_host() {
+        assert_eq!(Config::default().host(), None);
+        let config = Config::parse("record_host = true\ndevice_name = \"laptop\"").unwrap();
+        assert_eq!(config.host(), Some("laptop".to_string()));
+        let config = Config::parse("record_host = true").unwrap();
+        assert!(config.host().is_some());
+    }
+
+    #[test]
+    fn test_load_projects_file() {
+        let dir = env::temp_dir().join("wtr_test_load_projects_file");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("projects.txt"), "clientB\n# comment\n\nclientC\n").unwrap();
+        fs::write(
+            dir.join("config.toml"),
+            "allowed_projects = [\"clientA\"]\nprojects_file = \"projects.txt\"\n",
+        )
+        .unwrap();
+        let config = Config::load_from(&dir.join("config.toml")).unwrap();
+        assert_eq!(
+            config.allowed_projects,
+            vec!["clientA", "clientB", "clientC"]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_invalid_config() {
+        assert!(Config::parse("max_session = \"ten hours\"").is_err());
+        assert!(Config::parse("unknown_key = 1").is_err());
+    }
+
+    #[test]
+    fn test_load_missing_config() {
+        let config = Config::load_from(&PathBuf::from("no_such_config.toml")).unwrap();
+        assert_eq!(config.max_session, None);
+    }
+}
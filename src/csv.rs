@@ -0,0 +1,112 @@
+// 最小限の CSV/TSV 読み書き。引用符で囲んだ区切り文字・改行と、"" による引用符のエスケープに対応する。
+
+const DELIMITER_CANDIDATES: [char; 3] = [',', ';', '\t'];
+
+pub fn parse(content: &str, delimiter: char) -> Result<Vec<Vec<String>>, String> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = content.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => quoted = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => quoted = true,
+            '\r' => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ if c == delimiter => row.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("Unterminated quoted field.".to_string());
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows.retain(|row| row.iter().any(|field| !field.trim().is_empty()));
+    Ok(rows)
+}
+
+// 区切り文字・引用符・改行を含むものだけ引用符で囲んで 1 行にする
+pub fn write_row(fields: &[String], delimiter: char) -> String {
+    let quoted: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            if field.contains([delimiter, '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        })
+        .collect();
+    quoted.join(&delimiter.to_string()) + "\n"
+}
+
+// 先頭行に最も多く現れる候補を区切り文字とみなす
+pub fn detect_delimiter(content: &str) -> char {
+    let header = content.lines().next().unwrap_or_default();
+    DELIMITER_CANDIDATES
+        .iter()
+        .copied()
+        .max_by_key(|c| header.matches(*c).count())
+        .filter(|c| header.contains(*c))
+        .unwrap_or(',')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_row_round_trip() {
+        let row: Vec<String> = vec!["a".into(), "b,c".into(), "say \"hi\"".into(), "".into()];
+        let line = write_row(&row, ',');
+        assert_eq!(line, "a,\"b,c\",\"say \"\"hi\"\"\",\n");
+        assert_eq!(parse(&line, ',').unwrap(), vec![row]);
+    }
+
+    #[test]
+    fn test_parse_quoted_fields() {
+        let rows = parse("a,\"b,c\",\"say \"\"hi\"\"\"\r\n1,\"two\nlines\",3\n", ',').unwrap();
+        assert_eq!(
+            rows,
+            vec![vec!["a", "b,c", "say \"hi\""], vec!["1", "two\nlines", "3"]]
+        );
+    }
+
+    #[test]
+    fn test_parse_strips_bom_and_blank_lines() {
+        let rows = parse("\u{feff}task;start\n\nx;y", ';').unwrap();
+        assert_eq!(rows, vec![vec!["task", "start"], vec!["x", "y"]]);
+    }
+
+    #[test]
+    fn test_parse_unterminated_quote() {
+        assert!(parse("\"abc\n", ',').is_err());
+    }
+
+    #[test]
+    fn test_detect_delimiter() {
+        assert_eq!(detect_delimiter("task;start;end\n"), ';');
+        assert_eq!(detect_delimiter("task\tstart\tend\n"), '\t');
+        assert_eq!(detect_delimiter("task,start\n"), ',');
+        assert_eq!(detect_delimiter("task\n"), ',');
+    }
+}
@@ -0,0 +1,114 @@
+use crate::hours::HoursProfile;
+use crate::period::to_local;
+use crate::record::{Event, Record};
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+
+const DEMO_TASKS: [&str; 8] = [
+    "acme:api-design",
+    "acme:bugfix",
+    "acme:code-review",
+    "globex:reporting",
+    "globex:migration",
+    "internal:meetings",
+    "internal:email",
+    "internal:learning",
+];
+
+// 再現性のある擬似乱数 (xorshift64*)
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    // [low, high) の整数
+    pub fn range(&mut self, low: i64, high: i64) -> i64 {
+        low + (self.next_u64() % (high - low) as u64) as i64
+    }
+}
+
+// end までの days 日分 (平日のみ) の作業記録を生成する
+pub fn generate(days: u32, end: NaiveDate, rng: &mut Rng) -> Vec<Record> {
+    let mut records = Vec::new();
+    let first = end - Duration::days(days as i64 - 1);
+
+    for day in first
+        .iter_days()
+        .take(days as usize)
+        .filter(|d| HoursProfile::default().is_workday(*d))
+    {
+        let at = |h: u32, m: u32| day.and_time(NaiveTime::from_hms_opt(h, m, 0).unwrap());
+        let mut time = at(9, 0) + Duration::minutes(rng.range(-15, 30));
+        let lunch = at(12, 0) + Duration::minutes(rng.range(0, 30));
+        let end_of_day = at(17, 30) + Duration::minutes(rng.range(0, 60));
+        let mut had_lunch = false;
+
+        while time < end_of_day {
+            let task = DEMO_TASKS[rng.range(0, DEMO_TASKS.len() as i64) as usize];
+            let mut stop = time + Duration::minutes(rng.range(6, 30) * 5);
+            if !had_lunch && stop > lunch {
+                stop = lunch.max(time + Duration::minutes(15));
+            }
+            let stop = stop.min(end_of_day);
+            records.push(record(time, Event::Start, task));
+            records.push(record(stop, Event::Stop, ""));
+
+            time = stop + Duration::minutes(rng.range(0, 3) * 5);
+            if !had_lunch && time >= lunch {
+                time += Duration::minutes(rng.range(40, 70));
+                had_lunch = true;
+            }
+        }
+    }
+
+    records
+}
+
+fn record(time: NaiveDateTime, event: Event, task: &str) -> Record {
+    Record::new(to_local(time), event, task)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::parse_date;
+    use crate::session::pair_sessions;
+
+    #[test]
+    fn test_rng_is_deterministic() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        assert_eq!(a.next_u64(), b.next_u64());
+        assert!((0..100).map(|_| a.range(3, 7)).all(|n| (3..7).contains(&n)));
+    }
+
+    #[test]
+    fn test_generate_workdays_only() {
+        // 2024-05-06 (月) から 2024-05-12 (日) まで
+        let end = parse_date("2024-05-12").unwrap();
+        let records = generate(7, end, &mut Rng::new(1));
+        let days: std::collections::BTreeSet<_> =
+            records.iter().map(|r| r.timestamp.date_naive()).collect();
+        assert_eq!(days.len(), 5);
+        assert!(days.iter().all(|d| HoursProfile::default().is_workday(*d)));
+    }
+
+    #[test]
+    fn test_generate_well_formed_sessions() {
+        let end = parse_date("2024-05-31").unwrap();
+        let records = generate(30, end, &mut Rng::new(7));
+        assert!(records.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+
+        let sessions = pair_sessions(&records);
+        assert_eq!(sessions.len() * 2, records.len());
+        assert!(sessions.iter().all(|s| s.stop.unwrap() > s.start));
+    }
+}
@@ -0,0 +1,185 @@
+use crate::anomaly::{find_anomalies, AnomalyRules};
+use crate::duration::{format_delta, format_duration, hours};
+use crate::export::Locale;
+use crate::goal;
+use crate::names;
+use crate::period::Period;
+use crate::report::{daily_totals, sum, totals, GroupBy};
+use crate::session::Session;
+use chrono::{DateTime, FixedOffset};
+
+const TOP_TASKS: usize = 5;
+
+// メールや社内ページに貼れるよう、スタイルを埋め込んだ 1 ファイルの HTML にする
+pub fn render_html(
+    sessions: &[Session],
+    period: &Period,
+    rules: &AnomalyRules,
+    locale: Locale,
+    now: DateTime<FixedOffset>,
+) -> String {
+    let projects = totals(sessions, period, GroupBy::Project, now);
+    let tasks = totals(sessions, period, GroupBy::Task, now);
+    let daily = daily_totals(sessions, period, now);
+    let total = sum(projects.iter().map(|(_, d)| *d));
+    let max_day = daily.iter().map(|(_, d)| hours(*d)).fold(0.0, f64::max);
+    let title = format!("Working time {} – {}", period.start, period.end);
+
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>\n\
+         body {{ font-family: sans-serif; color: #222; max-width: 40em; }}\n\
+         table {{ border-collapse: collapse; margin-bottom: 1.5em; }}\n\
+         td, th {{ padding: 2px 12px 2px 0; text-align: left; }}\n\
+         td.num {{ text-align: right; }}\n\
+         .bar {{ background: #4a7bd0; height: 10px; }}\n\
+         .anomaly {{ color: #b00; }}\n\
+         </style>\n</head>\n<body>\n<h1>{}</h1>\n<p>Total <strong>{}</strong></p>\n",
+        escape(&title),
+        escape(&title),
+        format_duration(total)
+    );
+
+    html += "<h2>Projects</h2>\n<table>\n";
+    for (project, duration) in &projects {
+        html += &row(project, *duration);
+    }
+    html += "</table>\n";
+
+    html += "<h2>Top tasks</h2>\n<table>\n";
+    for (task, duration) in tasks.iter().take(TOP_TASKS) {
+        html += &row(task, *duration);
+    }
+    html += "</table>\n";
+
+    html += "<h2>Days</h2>\n<table>\n";
+    for (day, duration) in &daily {
+        let width = if max_day > 0.0 {
+            (hours(*duration) / max_day * 100.0).round()
+        } else {
+            0.0
+        };
+        html += &format!(
+            "<tr><td>{}</td><td class=\"num\">{}</td>\
+             <td style=\"width: 10em\"><div class=\"bar\" style=\"width: {}%\"></div></td></tr>\n",
+            names::short_date(*day, locale),
+            format_duration(*duration),
+            width
+        );
+    }
+    html += "</table>\n";
+
+    // 所定時間 (rules.hours) に対する週ごとの実績。今日より後の日は数えない。
+    let days = goal::daily(sessions, period, &rules.hours, now.date_naive(), now);
+    let weeks = goal::weekly(&days);
+    if !weeks.is_empty() {
+        html += "<h2>Goal progress</h2>\n<table>\n\
+                 <tr><th>Week of</th><th>Actual</th><th>Target</th><th>Delta</th><th></th></tr>\n";
+        for (week, attainment) in &weeks {
+            html += &format!(
+                "<tr><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td>\
+                 <td class=\"num\">{}</td><td>{}</td></tr>\n",
+                names::short_date(*week, locale),
+                format_duration(attainment.actual),
+                format_duration(attainment.target),
+                format_delta(attainment.delta()),
+                attainment.status().as_str()
+            );
+        }
+        html += "</table>\n";
+    }
+
+    let anomalies = find_anomalies(sessions, period, rules, now);
+    if !anomalies.is_empty() {
+        html += "<h2>Anomalies</h2>\n<ul>\n";
+        for anomaly in &anomalies {
+            html += &format!(
+                "<li class=\"anomaly\">{}</li>\n",
+                escape(&anomaly.to_string())
+            );
+        }
+        html += "</ul>\n";
+    }
+    html + "</body>\n</html>\n"
+}
+
+fn row(label: &str, duration: chrono::Duration) -> String {
+    format!(
+        "<tr><td>{}</td><td class=\"num\">{}</td></tr>\n",
+        escape(label),
+        format_duration(duration)
+    )
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts(s: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(s).unwrap()
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("<a & \"b\">"), "&lt;a &amp; &quot;b&quot;&gt;");
+    }
+
+    #[test]
+    fn test_render_html() {
+        let sessions = vec![
+            Session::new(
+                "clientA:<fix>",
+                ts("2024-05-01T09:00:00Z"),
+                Some(ts("2024-05-01T11:00:00Z")),
+            ),
+            Session::new(
+                "mail",
+                ts("2024-05-02T09:00:00Z"),
+                Some(ts("2024-05-02T09:30:00Z")),
+            ),
+        ];
+        let period = Period::parse("2024-W18").unwrap();
+        let html = render_html(
+            &sessions,
+            &period,
+            &AnomalyRules::default(),
+            Locale::Default,
+            ts("2030-01-01T00:00:00Z"),
+        );
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<p>Total <strong>2h30m</strong></p>"));
+        assert!(html.contains("<tr><td>clientA:&lt;fix&gt;</td><td class=\"num\">2h00m</td></tr>"));
+        assert!(html.contains("style=\"width: 100%\""));
+        assert!(html.trim_end().ends_with("</html>"));
+    }
+
+    #[test]
+    fn test_render_html_goal_progress() {
+        let sessions = vec![Session::new(
+            "a",
+            ts("2024-04-30T00:00:00Z"),
+            Some(ts("2024-04-30T07:00:00Z")),
+        )];
+        let period = Period::parse("2024-W18").unwrap();
+        // 平日 8h の既定で、火曜までの目標 16h に対して 7h
+        let html = render_html(
+            &sessions,
+            &period,
+            &AnomalyRules::default(),
+            Locale::Default,
+            ts("2024-04-30T12:00:00Z"),
+        );
+        assert!(html.contains("<h2>Goal progress</h2>"));
+        assert!(html.contains(
+            "<td class=\"num\">7h00m</td><td class=\"num\">16h00m</td>\
+             <td class=\"num\">-9h00m</td><td>behind</td>"
+        ));
+    }
+}
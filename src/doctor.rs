@@ -0,0 +1,280 @@
+use crate::record::{format_timestamp, sort_key, Event, Record, RecordFormat, Rejected};
+use chrono::{DateTime, Duration, FixedOffset};
+
+// 記録ファイルの問題。line は 1 始まりの行番号。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub line: usize,
+    pub message: String,
+}
+
+// 読めたレコードと、その前に並ぶコメント。並べ替えるときは一緒に動かす。
+struct Entry<'a> {
+    prefix: Vec<&'a str>,
+    line: usize,
+    text: &'a str,
+    record: Record,
+}
+
+struct Parsed<'a> {
+    entries: Vec<Entry<'a>>,
+    // 最後のレコードより後のコメント
+    trailer: Vec<&'a str>,
+    empty_lines: Vec<usize>,
+    unreadable: Vec<Rejected>,
+}
+
+fn parse(content: &str) -> Parsed<'_> {
+    let mut parsed = Parsed {
+        entries: Vec::new(),
+        trailer: Vec::new(),
+        empty_lines: Vec::new(),
+        unreadable: Vec::new(),
+    };
+    for (i, text) in content.lines().enumerate() {
+        if text.trim().is_empty() {
+            parsed.empty_lines.push(i + 1);
+            continue;
+        }
+        if text.starts_with('#') {
+            parsed.trailer.push(text);
+            continue;
+        }
+        match Record::parse(text) {
+            Ok(record) => parsed.entries.push(Entry {
+                prefix: std::mem::take(&mut parsed.trailer),
+                line: i + 1,
+                text,
+                record,
+            }),
+            Err(reason) => parsed.unreadable.push(Rejected {
+                line: i + 1,
+                text: text.to_string(),
+                reason,
+            }),
+        }
+    }
+    parsed
+}
+
+// 時刻順に並べたうえで、計測中でないときの stop と lap の位置 (entries の添字)
+fn orphans(entries: &[&Entry]) -> Vec<usize> {
+    let mut running = false;
+    let mut orphans = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        match entry.record.event {
+            Event::Start => running = true,
+            Event::Stop if running => running = false,
+            Event::Lap if running => {}
+            _ => orphans.push(i),
+        }
+    }
+    orphans
+}
+
+// 直前のレコードより前の時刻のレコード。(その行, 直前の行)
+fn out_of_order(entries: &[Entry]) -> Vec<(usize, usize)> {
+    entries
+        .windows(2)
+        .filter(|pair| sort_key(&pair[1].record) < sort_key(&pair[0].record))
+        .map(|pair| (pair[1].line, pair[0].line))
+        .collect()
+}
+
+fn sorted<'a, 'b>(entries: &'b [Entry<'a>]) -> Vec<&'b Entry<'a>> {
+    let mut sorted: Vec<&Entry> = entries.iter().collect();
+    sorted.sort_by_key(|entry| sort_key(&entry.record));
+    sorted
+}
+
+// 計測中のまま残っている start
+fn open_start<'b>(entries: &[&'b Entry]) -> Option<&'b Entry<'b>> {
+    entries
+        .iter()
+        .rev()
+        .find(|e| e.record.event != Event::Lap)
+        .filter(|e| e.record.event == Event::Start)
+        .copied()
+}
+
+// 問題を行番号順に返す。stale より長く開いたままのセッションも問題にする。
+pub fn diagnose(content: &str, stale: Duration, now: DateTime<FixedOffset>) -> Vec<Issue> {
+    let parsed = parse(content);
+    let mut issues: Vec<Issue> = Vec::new();
+    let mut issue = |line: usize, message: String| issues.push(Issue { line, message });
+
+    for line in &parsed.empty_lines {
+        issue(*line, "empty line".to_string());
+    }
+    for entry in &parsed.unreadable {
+        issue(entry.line, format!("cannot be read: {}", entry.reason));
+    }
+    for (earlier, previous) in out_of_order(&parsed.entries) {
+        issue(earlier, format!("is earlier than line {}", previous));
+    }
+    let sorted = sorted(&parsed.entries);
+    for i in orphans(&sorted) {
+        let message = match sorted[i].record.event {
+            Event::Stop => "stop without a running session",
+            _ => "lap without a running session",
+        };
+        issue(sorted[i].line, message.to_string());
+    }
+    if let Some(start) = open_start(&sorted) {
+        if now - start.record.timestamp > stale {
+            issue(
+                start.line,
+                format!(
+                    "'{}' has been running since {}",
+                    start.record.task,
+                    format_timestamp(start.record.timestamp)
+                ),
+            );
+        }
+    }
+    issues.sort_by_key(|issue| issue.line);
+    issues
+}
+
+// repair の結果
+#[derive(Debug, PartialEq)]
+pub struct Repaired {
+    pub content: String,
+    // 直した問題の数。diagnose が挙げるものと同じ数え方。
+    pub fixes: usize,
+    // 記録から外した読めない行。read_records と同じく .rejected に移す。
+    pub rejected: Vec<Rejected>,
+}
+
+// 安全に直せるものを直す。空行を消し、時刻順に並べ、計測中でないときの stop と lap を消し、
+// 読めない行は外す。close_at があれば開いたままのセッションを閉じる。
+// コメントは後ろのレコードと一緒に動かして残す。
+pub fn repair(content: &str, close_at: Option<DateTime<FixedOffset>>) -> Result<Repaired, String> {
+    let parsed = parse(content);
+    let sorted = sorted(&parsed.entries);
+    let orphans = orphans(&sorted);
+    let mut fixes = parsed.empty_lines.len()
+        + parsed.unreadable.len()
+        + out_of_order(&parsed.entries).len()
+        + orphans.len();
+
+    let mut lines: Vec<&str> = Vec::new();
+    for (i, entry) in sorted.iter().enumerate() {
+        lines.extend(&entry.prefix);
+        if !orphans.contains(&i) {
+            lines.push(entry.text);
+        }
+    }
+    lines.extend(&parsed.trailer);
+    let mut repaired: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+
+    if let (Some(close_at), Some(_)) = (close_at, open_start(&sorted)) {
+        let last = sorted.last().unwrap().record.timestamp;
+        if close_at < last {
+            return Err(format!(
+                "{} is before the last record ({}).",
+                format_timestamp(close_at),
+                format_timestamp(last)
+            ));
+        }
+        let stop = Record::new(close_at, Event::Stop, "");
+        repaired += &stop.to_line_as(RecordFormat::detect(content));
+        fixes += 1;
+    }
+    Ok(Repaired {
+        content: repaired,
+        fixes,
+        rejected: parsed.unreadable,
+    })
+}
+
+// "1 problem" / "2 problems"
+pub fn problems(count: usize) -> String {
+    match count {
+        1 => "1 problem".to_string(),
+        _ => format!("{} problems", count),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT: &str = "2024-05-01T09:00:00+09:00\tstart\ta\n\
+                           \n\
+                           # note\n\
+                           2024-05-01T08:00:00+09:00\tstop\t\n\
+                           2024-05-01T10:00:00+09:00\tstop\t\n\
+                           broken\n\
+                           2024-05-01T11:00:00+09:00\tstart\tb\n";
+
+    fn ts(s: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(s).unwrap()
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let now = ts("2024-05-02T12:00:00+09:00");
+        let issues = diagnose(CONTENT, Duration::hours(16), now);
+        let lines: Vec<(usize, &str)> = issues
+            .iter()
+            .map(|i| (i.line, i.message.as_str()))
+            .collect();
+        assert_eq!(lines[0], (2, "empty line"));
+        assert_eq!(lines[1], (4, "is earlier than line 1"));
+        assert_eq!(lines[2], (4, "stop without a running session"));
+        assert_eq!(lines[3].0, 6);
+        assert!(lines[3].1.starts_with("cannot be read: "));
+        assert_eq!(
+            lines[4],
+            (7, "'b' has been running since 2024-05-01T11:00:00+09:00")
+        );
+        assert_eq!(lines.len(), 5);
+        assert_eq!(diagnose(CONTENT, Duration::hours(48), now).len(), 4);
+    }
+
+    #[test]
+    fn test_repair() {
+        let repaired = repair(CONTENT, None).unwrap();
+        assert_eq!(
+            repaired.content,
+            "# note\n\
+             2024-05-01T09:00:00+09:00\tstart\ta\n\
+             2024-05-01T10:00:00+09:00\tstop\t\n\
+             2024-05-01T11:00:00+09:00\tstart\tb\n"
+        );
+        // 空行、読めない行、前後した行、start の前の stop がひとつずつ
+        assert_eq!(repaired.fixes, 4);
+        assert_eq!(repaired.rejected.len(), 1);
+        assert_eq!(repaired.rejected[0].line, 6);
+        assert_eq!(repaired.rejected[0].text, "broken");
+        assert!(diagnose(
+            &repaired.content,
+            Duration::hours(16),
+            ts("2024-05-01T12:00:00+09:00")
+        )
+        .is_empty());
+
+        let closed = repair(&repaired.content, Some(ts("2024-05-01T12:00:00+09:00")))
+            .unwrap()
+            .content;
+        assert!(closed.ends_with("2024-05-01T12:00:00+09:00\tstop\t\n"));
+        assert!(repair(&repaired.content, Some(ts("2024-05-01T10:30:00+09:00"))).is_err());
+        // 直すものが無ければそのまま
+        let unchanged = repair(&closed, None).unwrap();
+        assert_eq!((unchanged.content, unchanged.fixes), (closed, 0));
+    }
+
+    #[test]
+    fn test_repair_counts_misplaced_records() {
+        // 1 行だけ前後していれば、ほかの行がずれても 1 つと数える
+        let mut content = String::from("2024-05-01T00:30:00+09:00\tstart\tlate\n");
+        for hour in 1..10 {
+            content += &format!("2024-05-01T{:02}:00:00+09:00\tlap\t\n", hour);
+        }
+        content += "2024-05-01T00:45:00+09:00\tlap\t\n";
+        assert_eq!(repair(&content, None).unwrap().fixes, 1);
+        assert_eq!(problems(1), "1 problem");
+        assert_eq!(problems(2), "2 problems");
+    }
+}
@@ -0,0 +1,150 @@
+use crate::record::Record;
+use chrono::{DateTime, Duration, FixedOffset, LocalResult, NaiveDateTime, Offset, TimeZone};
+
+// 壁時計の時刻を tz の時刻にする。
+// 夏時間の切り替えで 2 回ある時刻は早い方を取り、存在しない時刻は切り替え前の時差で読んで後ろへずらす。
+pub fn resolve_local<Tz: TimeZone>(tz: &Tz, naive: NaiveDateTime) -> DateTime<FixedOffset> {
+    match tz.from_local_datetime(&naive) {
+        LocalResult::Single(time) => time.fixed_offset(),
+        LocalResult::Ambiguous(earliest, _) => earliest.fixed_offset(),
+        LocalResult::None => {
+            let before = tz
+                .offset_from_local_datetime(&(naive - Duration::days(1)))
+                .earliest()
+                .map_or(0, |offset| offset.fix().local_minus_utc());
+            tz.from_utc_datetime(&(naive - Duration::seconds(before as i64)))
+                .fixed_offset()
+        }
+    }
+}
+
+// 夏時間の終わりで 2 回現れる壁時計の時刻に記録されたもの。
+// 時差も記録しているので計算は正しいが、手で直すときに取り違えやすい。
+pub fn ambiguous_times<Tz: TimeZone>(content: &str, tz: &Tz) -> Vec<(usize, String)> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| Record::parse(line).ok().map(|r| (i + 1, r)))
+        .filter(|(_, record)| {
+            matches!(
+                tz.offset_from_local_datetime(&record.timestamp.naive_local()),
+                LocalResult::Ambiguous(..)
+            )
+        })
+        .map(|(line, record)| {
+            (
+                line,
+                format!(
+                    "local time {} occurs twice (DST change); the recorded offset {} decides which.",
+                    record.timestamp.naive_local().format("%Y-%m-%d %H:%M"),
+                    record.timestamp.offset()
+                ),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, Utc};
+
+    // 2024-03-31 01:00Z から 2024-10-27 01:00Z まで +02:00、それ以外は +01:00 のタイムゾーン
+    #[derive(Debug, Clone)]
+    struct Cet;
+
+    impl Cet {
+        fn offset_at(utc: &NaiveDateTime) -> FixedOffset {
+            let spring = naive("2024-03-31 01:00");
+            let autumn = naive("2024-10-27 01:00");
+            let hours = if spring <= *utc && *utc < autumn {
+                2
+            } else {
+                1
+            };
+            FixedOffset::east_opt(hours * 3600).unwrap()
+        }
+    }
+
+    impl TimeZone for Cet {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Cet {
+            Cet
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            let candidates: Vec<FixedOffset> = [2, 1]
+                .into_iter()
+                .map(|hours| FixedOffset::east_opt(hours * 3600).unwrap())
+                .filter(|offset| Cet::offset_at(&(*local - *offset)) == *offset)
+                .collect();
+            match candidates[..] {
+                [offset] => LocalResult::Single(offset),
+                [earliest, latest] => LocalResult::Ambiguous(earliest, latest),
+                _ => LocalResult::None,
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            Cet::offset_at(&utc.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            Cet::offset_at(utc)
+        }
+    }
+
+    fn naive(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn resolve(s: &str) -> String {
+        resolve_local(&Cet, naive(s)).to_rfc3339()
+    }
+
+    #[test]
+    fn test_resolve_local() {
+        assert_eq!(resolve("2024-05-01 09:00"), "2024-05-01T09:00:00+02:00");
+        // 02:30 は存在しないので 03:30 にずらす
+        assert_eq!(resolve("2024-03-31 02:30"), "2024-03-31T03:30:00+02:00");
+        // 02:30 は 2 回あるので早い方
+        assert_eq!(resolve("2024-10-27 02:30"), "2024-10-27T02:30:00+02:00");
+    }
+
+    #[test]
+    fn test_durations_across_dst_use_instants() {
+        let start = resolve_local(&Cet, naive("2024-10-26 22:00"));
+        let stop = resolve_local(&Cet, naive("2024-10-27 06:00"));
+        assert_eq!(stop - start, Duration::hours(9));
+        let start = resolve_local(&Cet, naive("2024-03-30 22:00"));
+        let stop = resolve_local(&Cet, naive("2024-03-31 06:00"));
+        assert_eq!(stop - start, Duration::hours(7));
+        assert_eq!(
+            stop.with_timezone(&Utc) - start.with_timezone(&Utc),
+            Duration::hours(7)
+        );
+    }
+
+    #[test]
+    fn test_ambiguous_times() {
+        let content = "2024-10-27T02:30:00+02:00\tstart\ta\n\
+                       2024-10-27T02:30:00+01:00\tstop\t\n\
+                       2024-10-27T03:30:00+01:00\tstart\tb\n";
+        let warnings = ambiguous_times(content, &Cet);
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(
+            warnings[1],
+            (
+                2,
+                "local time 2024-10-27 02:30 occurs twice (DST change); \
+                 the recorded offset +01:00 decides which."
+                    .to_string()
+            )
+        );
+    }
+}
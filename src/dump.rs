@@ -0,0 +1,292 @@
+use crate::json::{self, Value};
+use crate::period::parse_date;
+use crate::plan::Plan;
+use crate::record::{format_timestamp, Event, Record};
+use crate::state::{Lock, State};
+use chrono::{DateTime, FixedOffset};
+
+const FORMAT_NAME: &str = "working-time-recorder";
+const FORMAT_VERSION: f64 = 1.0;
+
+// 記録ファイル・状態ファイル・設定ファイルをまとめたもの。dump/load の単位。
+#[derive(Debug, Default, PartialEq)]
+pub struct Store {
+    pub content: String,
+    pub state: State,
+    pub config: Option<String>,
+}
+
+// 記録はレコードごとのオブジェクトに、# で始まるコメント行は {"comment": ...} にする
+pub fn dump(store: &Store, now: DateTime<FixedOffset>) -> Result<String, String> {
+    let mut entries = Vec::new();
+    for (i, line) in store.content.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = match line.strip_prefix('#') {
+            Some(comment) => Value::Object(vec![("comment".to_string(), comment.into())]),
+            None => {
+                record_to_json(&Record::parse(line).map_err(|e| format!("line {}: {}", i + 1, e))?)
+            }
+        };
+        entries.push(entry);
+    }
+    let document = Value::Object(vec![
+        ("format".to_string(), FORMAT_NAME.into()),
+        ("version".to_string(), Value::Number(FORMAT_VERSION)),
+        ("dumped_at".to_string(), format_timestamp(now).into()),
+        ("records".to_string(), Value::Array(entries)),
+        ("state".to_string(), state_to_json(&store.state)),
+        (
+            "config".to_string(),
+            store.config.as_deref().map_or(Value::Null, Value::from),
+        ),
+    ]);
+    Ok(document.to_pretty() + "\n")
+}
+
+pub fn load(content: &str) -> Result<Store, String> {
+    let document = json::parse(content)?;
+    if document.get("format").and_then(Value::as_str) != Some(FORMAT_NAME) {
+        return Err("Not a working-time-recorder dump.".to_string());
+    }
+    let version = document.get("version").and_then(Value::as_f64);
+    if version != Some(FORMAT_VERSION) {
+        return Err(format!(
+            "Unsupported dump version {}.",
+            version.map_or("(missing)".to_string(), |v| v.to_string())
+        ));
+    }
+
+    let mut store = Store::default();
+    let entries = document
+        .get("records")
+        .and_then(Value::as_array)
+        .ok_or("records: expected an array.")?;
+    for (i, entry) in entries.iter().enumerate() {
+        let line = match entry.get("comment") {
+            Some(comment) => {
+                let comment = comment
+                    .as_str()
+                    .ok_or(format!("records[{}]: invalid comment.", i))?;
+                format!("#{}\n", comment)
+            }
+            None => record_from_json(entry)
+                .map_err(|e| format!("records[{}]: {}", i, e))?
+                .to_line(),
+        };
+        store.content += &line;
+    }
+    if let Some(state) = document.get("state") {
+        store.state = state_from_json(state).map_err(|e| format!("state: {}", e))?;
+    }
+    store.config = match document.get("config") {
+        None | Some(Value::Null) => None,
+        Some(config) => Some(
+            config
+                .as_str()
+                .ok_or("config: expected a string.")?
+                .to_string(),
+        ),
+    };
+    Ok(store)
+}
+
+fn record_to_json(record: &Record) -> Value {
+    Value::Object(vec![
+        (
+            "timestamp".to_string(),
+            format_timestamp(record.timestamp).into(),
+        ),
+        ("event".to_string(), record.event.as_str().into()),
+        ("task".to_string(), record.task.as_str().into()),
+        (
+            "tags".to_string(),
+            Value::Array(record.tags.iter().map(|t| t.as_str().into()).collect()),
+        ),
+        (
+            "fields".to_string(),
+            Value::Object(
+                record
+                    .fields
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.as_str().into()))
+                    .collect(),
+            ),
+        ),
+    ])
+}
+
+fn record_from_json(value: &Value) -> Result<Record, String> {
+    let timestamp = parse_timestamp(string(value, "timestamp")?)?;
+    let event = string(value, "event")?;
+    let event = Event::parse(event).ok_or(format!("Invalid event '{}'.", event))?;
+    let mut record = Record::new(timestamp, event, string(value, "task")?);
+    for tag in value
+        .get("tags")
+        .and_then(Value::as_array)
+        .unwrap_or_default()
+    {
+        record = record.with_tag(tag.as_str().ok_or("tags: expected strings.")?);
+    }
+    for (key, field) in value
+        .get("fields")
+        .and_then(Value::as_object)
+        .unwrap_or_default()
+    {
+        record = record.with_field(key, field.as_str().ok_or("fields: expected strings.")?);
+    }
+    // 記録ファイルの 1 行として読み戻せないものは受け付けない
+    Record::parse(record.to_line().trim_end_matches('\n'))
+        .ok()
+        .filter(|parsed| *parsed == record)
+        .ok_or_else(|| "contains tabs, newlines or malformed tags/fields.".to_string())
+}
+
+fn state_to_json(state: &State) -> Value {
+    let locks = state
+        .locks
+        .iter()
+        .map(|lock| {
+            Value::Object(vec![
+                ("period".to_string(), lock.period.as_str().into()),
+                ("start".to_string(), lock.start.to_string().into()),
+                ("end".to_string(), lock.end.to_string().into()),
+                (
+                    "submitted_at".to_string(),
+                    format_timestamp(lock.submitted_at).into(),
+                ),
+            ])
+        })
+        .collect();
+    let plans = state
+        .plans
+        .iter()
+        .map(|plan| {
+            Value::Object(vec![
+                ("task".to_string(), plan.task.as_str().into()),
+                ("start".to_string(), format_timestamp(plan.start).into()),
+                ("end".to_string(), format_timestamp(plan.end).into()),
+            ])
+        })
+        .collect();
+    Value::Object(vec![
+        ("locks".to_string(), Value::Array(locks)),
+        ("plans".to_string(), Value::Array(plans)),
+    ])
+}
+
+fn state_from_json(value: &Value) -> Result<State, String> {
+    let mut state = State::default();
+    for lock in value
+        .get("locks")
+        .and_then(Value::as_array)
+        .unwrap_or_default()
+    {
+        state.locks.push(Lock {
+            period: string(lock, "period")?.to_string(),
+            start: parse_date(string(lock, "start")?)?,
+            end: parse_date(string(lock, "end")?)?,
+            submitted_at: parse_timestamp(string(lock, "submitted_at")?)?,
+        });
+    }
+    for plan in value
+        .get("plans")
+        .and_then(Value::as_array)
+        .unwrap_or_default()
+    {
+        state.plans.push(Plan {
+            task: string(plan, "task")?.to_string(),
+            start: parse_timestamp(string(plan, "start")?)?,
+            end: parse_timestamp(string(plan, "end")?)?,
+        });
+    }
+    Ok(state)
+}
+
+fn string<'a>(value: &'a Value, key: &str) -> Result<&'a str, String> {
+    value
+        .get(key)
+        .and_then(Value::as_str)
+        .ok_or(format!("{}: expected a string.", key))
+}
+
+fn parse_timestamp(s: &str) -> Result<DateTime<FixedOffset>, String> {
+    DateTime::parse_from_rfc3339(s).map_err(|e| format!("Invalid timestamp '{}': {}", s, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339("2024-06-01T12:00:00+09:00").unwrap()
+    }
+
+    fn store() -> Store {
+        let mut state = State::default();
+        state.lock(
+            "2024-05",
+            &crate::period::Period::parse("2024-05").unwrap(),
+            now(),
+        );
+        state.plans.push(
+            Plan::parse(
+                "09:00-11:00",
+                "spec review",
+                parse_date("2024-05-01").unwrap(),
+            )
+            .unwrap(),
+        );
+        Store {
+            content: "# 2024-05 submitted on 2024-06-01T12:00:00+09:00\n\
+                      2024-05-01T09:00:00+09:00\tstart\tclientA:fix\t+urgent\thost=laptop\n\
+                      2024-05-01T09:30:00.250+09:00\tlap\trepro \"found\"\n\
+                      2024-05-01T10:00:00+09:00\tstop\t\n"
+                .to_string(),
+            state,
+            config: Some("merge_gap = \"2m\"\n".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let store = store();
+        let dumped = dump(&store, now()).unwrap();
+        assert!(dumped.contains("\"dumped_at\": \"2024-06-01T12:00:00+09:00\""));
+        assert_eq!(load(&dumped).unwrap(), store);
+    }
+
+    #[test]
+    fn test_dump_reports_broken_line() {
+        let store = Store {
+            content: "2024-05-01T09:00:00+09:00\tstart\ta\nbroken\n".to_string(),
+            ..Default::default()
+        };
+        assert!(dump(&store, now()).unwrap_err().starts_with("line 2: "));
+    }
+
+    #[test]
+    fn test_load_rejects_unknown_documents() {
+        assert_eq!(
+            load("{\"records\": []}").unwrap_err(),
+            "Not a working-time-recorder dump."
+        );
+        assert_eq!(
+            load("{\"format\": \"working-time-recorder\", \"version\": 2}").unwrap_err(),
+            "Unsupported dump version 2."
+        );
+    }
+
+    #[test]
+    fn test_load_rejects_tabs_in_task() {
+        let document = r#"{"format": "working-time-recorder", "version": 1, "records": [
+            {"timestamp": "2024-05-01T09:00:00+09:00", "event": "start", "task": "a\tb"}
+        ]}"#;
+        assert_eq!(
+            load(document).unwrap_err(),
+            "records[0]: contains tabs, newlines or malformed tags/fields."
+        );
+    }
+}
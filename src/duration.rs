@@ -0,0 +1,96 @@
+use chrono::Duration;
+
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.num_minutes().max(0);
+    let (hours, minutes) = (minutes / 60, minutes % 60);
+    if hours > 0 {
+        format!("{}h{:02}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+pub fn format_delta(duration: Duration) -> String {
+    if duration < Duration::zero() {
+        format!("-{}", format_duration(-duration))
+    } else {
+        format!("+{}", format_duration(duration))
+    }
+}
+
+// "1h30m" / "90m" / "2d" / "3y" のような表記を解釈する (1y = 365d)
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid duration '{}'.", s);
+    let mut total = Duration::zero();
+    let mut number = String::new();
+
+    for c in s.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let value: i64 = number.parse().map_err(|_| invalid())?;
+        total += match c {
+            'y' => Duration::days(value * 365),
+            'w' => Duration::weeks(value),
+            'd' => Duration::days(value),
+            'h' => Duration::hours(value),
+            'm' => Duration::minutes(value),
+            's' => Duration::seconds(value),
+            _ => return Err(invalid()),
+        };
+        number.clear();
+    }
+
+    if !number.is_empty() || s.trim().is_empty() {
+        return Err(invalid());
+    }
+    Ok(total)
+}
+
+pub fn hours(duration: Duration) -> f64 {
+    duration.num_seconds() as f64 / 3600.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::minutes(130)), "2h10m");
+        assert_eq!(format_duration(Duration::minutes(45)), "45m");
+        assert_eq!(format_duration(Duration::hours(3)), "3h00m");
+        assert_eq!(format_duration(Duration::seconds(59)), "0m");
+    }
+
+    #[test]
+    fn test_format_delta() {
+        assert_eq!(format_delta(Duration::minutes(-130)), "-2h10m");
+        assert_eq!(format_delta(Duration::minutes(5)), "+5m");
+        assert_eq!(format_delta(Duration::zero()), "+0m");
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("12h").unwrap(), Duration::hours(12));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::minutes(90));
+        assert_eq!(parse_duration("2d").unwrap(), Duration::days(2));
+        assert_eq!(parse_duration("2w").unwrap(), Duration::days(14));
+        assert_eq!(parse_duration("3y").unwrap(), Duration::days(3 * 365));
+        assert_eq!(parse_duration("0m").unwrap(), Duration::zero());
+    }
+
+    #[test]
+    fn test_parse_invalid_duration() {
+        assert_eq!(parse_duration("12").unwrap_err(), "Invalid duration '12'.");
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("1x").is_err());
+    }
+
+    #[test]
+    fn test_hours() {
+        assert_eq!(hours(Duration::minutes(90)), 1.5);
+    }
+}
@@ -0,0 +1,103 @@
+use std::error::Error;
+use std::fmt;
+
+pub const TASK_NAME_NOT_PROVIDED_MSG: &str = "タスク名が提供されていません。";
+pub const NOT_RUNNING_MSG: &str = "計測中のタスクがありません。";
+pub const INVALID_PATH_MSG: &str = "パスに使えない文字が含まれています。";
+pub const BUSY_MSG: &str = "記録ファイルを別のプロセスが使用中です (record file is busy)。";
+
+// RecordStore / Recorder が返すエラー。組み込む側が種類で分岐でき、main は種類ごとの終了コードで終わる。
+// ほかのモジュールの String のエラーは Other になる。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecorderError {
+    MissingTask,
+    NoOpenSession,
+    // 計測中のタスク名
+    AlreadyRunning(String),
+    Busy,
+    InvalidPath,
+    Io(String),
+    Parse(String),
+    InvalidTimestamp(String),
+    Other(String),
+}
+
+impl RecorderError {
+    // 1 は種類の決まっていないエラー、2 以降は種類ごと
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RecorderError::Other(_) => 1,
+            RecorderError::MissingTask => 2,
+            RecorderError::NoOpenSession => 3,
+            RecorderError::AlreadyRunning(_) => 4,
+            RecorderError::Busy => 5,
+            RecorderError::InvalidPath => 6,
+            RecorderError::Io(_) => 7,
+            RecorderError::Parse(_) => 8,
+            RecorderError::InvalidTimestamp(_) => 9,
+        }
+    }
+}
+
+impl fmt::Display for RecorderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecorderError::MissingTask => f.write_str(TASK_NAME_NOT_PROVIDED_MSG),
+            RecorderError::NoOpenSession => f.write_str(NOT_RUNNING_MSG),
+            RecorderError::AlreadyRunning(task) => write!(
+                f,
+                "'{}' is already running. Stop it first or use --switch.",
+                task
+            ),
+            RecorderError::Busy => f.write_str(BUSY_MSG),
+            RecorderError::InvalidPath => f.write_str(INVALID_PATH_MSG),
+            RecorderError::Io(message)
+            | RecorderError::Parse(message)
+            | RecorderError::InvalidTimestamp(message)
+            | RecorderError::Other(message) => f.write_str(message),
+        }
+    }
+}
+
+impl Error for RecorderError {}
+
+impl From<String> for RecorderError {
+    fn from(message: String) -> RecorderError {
+        RecorderError::Other(message)
+    }
+}
+
+impl From<&str> for RecorderError {
+    fn from(message: &str) -> RecorderError {
+        RecorderError::Other(message.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let errors = [
+            RecorderError::MissingTask,
+            RecorderError::NoOpenSession,
+            RecorderError::AlreadyRunning("a".to_string()),
+            RecorderError::Busy,
+            RecorderError::InvalidPath,
+            RecorderError::Io(String::new()),
+            RecorderError::Parse(String::new()),
+            RecorderError::InvalidTimestamp(String::new()),
+            RecorderError::Other(String::new()),
+        ];
+        let mut codes: Vec<i32> = errors.iter().map(RecorderError::exit_code).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), errors.len());
+        assert_eq!(
+            RecorderError::AlreadyRunning("a".to_string()).to_string(),
+            "'a' is already running. Stop it first or use --switch."
+        );
+        assert_eq!(RecorderError::NoOpenSession.to_string(), NOT_RUNNING_MSG);
+    }
+}
@@ -0,0 +1,200 @@
+use crate::autostop::AUTO_STOPPED_TAG;
+use crate::duration::format_duration;
+use crate::period::{parse_date, Period};
+use crate::report::{sum, GroupBy};
+use crate::rounding::{Rounding, RoundingMode};
+use crate::session::Session;
+use chrono::{DateTime, FixedOffset, Local, NaiveDate};
+
+// report --explain の対象。日付として読めれば日、それ以外は集計のキー。
+#[derive(Debug, PartialEq)]
+pub enum Target {
+    Key(String),
+    Day(NaiveDate),
+}
+
+impl Target {
+    pub fn parse(s: &str) -> Target {
+        match parse_date(s) {
+            Ok(date) => Target::Day(date),
+            Err(_) => Target::Key(s.to_string()),
+        }
+    }
+}
+
+// 対象の合計に寄与したセッションを、丸めと範囲での切り分けの過程とともに並べる。
+// report と同じく、丸めはセッション全体の長さにかけてから範囲で切り取る。
+pub fn explain(
+    sessions: &[Session],
+    period: &Period,
+    group_by: GroupBy,
+    target: &Target,
+    rounding: Option<&Rounding>,
+    now: DateTime<FixedOffset>,
+) -> String {
+    let (label, range) = match target {
+        Target::Key(key) => (format!("'{}'", key), *period),
+        Target::Day(date) => (
+            date.to_string(),
+            Period {
+                start: *date,
+                end: *date,
+            },
+        ),
+    };
+    let (from, to) = (range.start_time(), range.end_time());
+    let time = |t: DateTime<FixedOffset>| t.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+
+    let mut output = format!("{} in {} - {}\n", label, range.start, range.end);
+    let mut counted = Vec::new();
+    for session in sessions {
+        if let Target::Key(key) = target {
+            if group_by.key(session) != *key {
+                continue;
+            }
+        }
+        let raw = session.end_or(now) - session.start;
+        let mut rounded = session.clone();
+        if let Some(rounding) = rounding {
+            crate::rounding::round_sessions(std::slice::from_mut(&mut rounded), rounding);
+        }
+        let overlap = rounded.overlap(from, to, now);
+        if overlap.is_zero() {
+            continue;
+        }
+
+        output += &format!(
+            "  {} - {}  {:>8}  {}\n",
+            time(session.start),
+            time(session.end_or(now)),
+            format_duration(raw),
+            session.task
+        );
+        if session.stop.is_none() {
+            output += "    still running, counted until now\n";
+        }
+        if session.tags.iter().any(|tag| tag == AUTO_STOPPED_TAG) {
+            output += "    closed by auto-stop\n";
+        }
+        let length = rounded.end_or(now) - rounded.start;
+        if let (Some(rounding), Some(_)) = (rounding, session.stop) {
+            output += &format!(
+                "    rounded {} {}: {} -> {}\n",
+                mode_label(rounding.mode),
+                format_duration(rounding.interval),
+                format_duration(raw),
+                format_duration(length)
+            );
+        }
+        if overlap != length {
+            let splits: Vec<String> =
+                [(rounded.start < from, from), (rounded.end_or(now) > to, to)]
+                    .iter()
+                    .filter(|(split, _)| *split)
+                    .map(|(_, at)| time(*at).to_string())
+                    .collect();
+            output += &format!(
+                "    split at {}: counted {}\n",
+                splits.join(" and "),
+                format_duration(overlap)
+            );
+        }
+        counted.push(overlap);
+    }
+
+    if counted.is_empty() {
+        output += "  no tracked time\n";
+    }
+    output += &format!("Total  {}\n", format_duration(sum(counted.into_iter())));
+    output
+}
+
+fn mode_label(mode: RoundingMode) -> &'static str {
+    match mode {
+        RoundingMode::Nearest => "to nearest",
+        RoundingMode::Up => "up to",
+        RoundingMode::Down => "down to",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::to_local;
+    use chrono::Duration;
+
+    fn local(date: &str, hour: u32, minute: u32) -> DateTime<FixedOffset> {
+        to_local(
+            parse_date(date)
+                .unwrap()
+                .and_hms_opt(hour, minute, 0)
+                .unwrap(),
+        )
+    }
+
+    fn sessions() -> Vec<Session> {
+        vec![
+            Session::new(
+                "fix",
+                local("2024-05-01", 9, 0),
+                Some(local("2024-05-01", 10, 7)),
+            ),
+            Session::new(
+                "fix",
+                local("2024-05-31", 23, 0),
+                Some(local("2024-06-01", 1, 0)),
+            ),
+            Session::new(
+                "other",
+                local("2024-05-01", 11, 0),
+                Some(local("2024-05-01", 12, 0)),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_target_parse() {
+        assert_eq!(
+            Target::parse("2024-05-01"),
+            Target::Day(parse_date("2024-05-01").unwrap())
+        );
+        assert_eq!(Target::parse("fix"), Target::Key("fix".to_string()));
+    }
+
+    #[test]
+    fn test_explain_key_with_rounding_and_split() {
+        let now = local("2030-01-01", 0, 0);
+        let rounding = Rounding {
+            interval: Duration::minutes(15),
+            mode: RoundingMode::Up,
+        };
+        let output = explain(
+            &sessions(),
+            &Period::parse("2024-05").unwrap(),
+            GroupBy::Task,
+            &Target::parse("fix"),
+            Some(&rounding),
+            now,
+        );
+        assert!(output.starts_with("'fix' in 2024-05-01 - 2024-05-31\n"));
+        assert!(output.contains("rounded up to 15m: 1h07m -> 1h15m\n"));
+        assert!(output.contains("split at 2024-06-01 00:00: counted 1h00m\n"));
+        assert!(!output.contains("other"));
+        assert!(output.ends_with("Total  2h15m\n"));
+    }
+
+    #[test]
+    fn test_explain_day() {
+        let now = local("2030-01-01", 0, 0);
+        let output = explain(
+            &sessions(),
+            &Period::parse("2024-05").unwrap(),
+            GroupBy::Task,
+            &Target::parse("2024-05-01"),
+            None,
+            now,
+        );
+        assert!(output.contains("other"));
+        assert!(output.ends_with("Total  2h07m\n"));
+    }
+}
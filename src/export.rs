@@ -0,0 +1,717 @@
+use crate::autostop::AUTO_STOPPED_TAG;
+use crate::csv;
+use crate::duration::hours;
+use crate::interval::Interval;
+use crate::json::Value;
+use crate::pdf;
+use crate::period::Period;
+use crate::record::{format_timestamp, Event, Record};
+use crate::recurring::RECURRING_TAG;
+use crate::report::GroupBy;
+use crate::session::{Session, NOTE_FIELD};
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate};
+use serde::Deserialize;
+
+// export pdf の余白・文字の大きさ・行の高さ (pt)
+const PDF_MARGIN: f64 = 40.0;
+const PDF_FONT_SIZE: f64 = 9.0;
+const PDF_ROW_HEIGHT: f64 = 13.0;
+
+// ツール自身が付けるタグは情報を含まないのでそのまま残す
+const SYSTEM_TAGS: [&str; 2] = [AUTO_STOPPED_TAG, RECURRING_TAG];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    // 記録ファイルそのままの TSV
+    Records,
+    // セッションごとに 1 行
+    Csv,
+    // セッションの配列 1 つ
+    Json,
+    Jsonl,
+    // hledger の時間記録形式
+    Timeclock,
+    Timedot,
+    // 日を列、プロジェクトを行に並べた時間の表
+    Grid,
+    // Grid を印刷用の勤務表にしたもの
+    Pdf,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Format> {
+        match s {
+            "csv" => Some(Format::Csv),
+            "json" => Some(Format::Json),
+            "jsonl" => Some(Format::Jsonl),
+            "timeclock" => Some(Format::Timeclock),
+            "timedot" => Some(Format::Timedot),
+            "grid" => Some(Format::Grid),
+            "pdf" => Some(Format::Pdf),
+            _ => None,
+        }
+    }
+}
+
+// 表計算ソフトの地域設定に合わせた CSV の書式。
+// Default 以外は日時を "2024-05-01 09:00:00" のような現地時刻で書き、
+// Excel が文字コードを判別できるよう BOM を付ける。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Locale {
+    #[default]
+    Default,
+    En,
+    De,
+    Fr,
+    Ja,
+}
+
+impl Locale {
+    pub fn parse(s: &str) -> Result<Locale, String> {
+        match s {
+            "en" => Ok(Locale::En),
+            "de" => Ok(Locale::De),
+            "fr" => Ok(Locale::Fr),
+            "ja" => Ok(Locale::Ja),
+            _ => Err(format!("Invalid locale '{}'. Use en, de, fr or ja.", s)),
+        }
+    }
+
+    // 小数点がコンマの地域では、区切り文字にセミコロンを使う
+    fn delimiter(&self) -> char {
+        match self {
+            Locale::De | Locale::Fr => ';',
+            _ => ',',
+        }
+    }
+
+    fn decimal_separator(&self) -> char {
+        match self {
+            Locale::De | Locale::Fr => ',',
+            _ => '.',
+        }
+    }
+
+    fn datetime_format(&self) -> Option<&'static str> {
+        match self {
+            Locale::Default => None,
+            Locale::En => Some("%Y-%m-%d %H:%M:%S"),
+            Locale::De => Some("%d.%m.%Y %H:%M:%S"),
+            Locale::Fr => Some("%d/%m/%Y %H:%M:%S"),
+            Locale::Ja => Some("%Y/%m/%d %H:%M:%S"),
+        }
+    }
+
+    fn format_time(&self, time: DateTime<FixedOffset>) -> String {
+        match self.datetime_format() {
+            Some(format) => time.with_timezone(&Local).format(format).to_string(),
+            None => format_timestamp(time),
+        }
+    }
+
+    fn format_number(&self, n: f64) -> String {
+        n.to_string()
+            .replace('.', &self.decimal_separator().to_string())
+    }
+}
+
+impl TryFrom<String> for Locale {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Locale, String> {
+        Locale::parse(&s)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Field {
+    Start,
+    End,
+    Duration,
+    Project,
+    Task,
+    Tags,
+    Note,
+    Rate,
+    Amount,
+    // "field:ticket" のように指定した、記録の任意のフィールド
+    Custom(String),
+}
+
+const CUSTOM_FIELD_PREFIX: &str = "field:";
+
+pub const DEFAULT_FIELDS: [Field; 7] = [
+    Field::Start,
+    Field::End,
+    Field::Duration,
+    Field::Project,
+    Field::Task,
+    Field::Tags,
+    Field::Note,
+];
+
+impl Field {
+    fn parse(s: &str) -> Result<Field, String> {
+        match s {
+            "start" => Ok(Field::Start),
+            "end" => Ok(Field::End),
+            "duration" => Ok(Field::Duration),
+            "project" => Ok(Field::Project),
+            "task" => Ok(Field::Task),
+            "tags" => Ok(Field::Tags),
+            "note" => Ok(Field::Note),
+            "rate" => Ok(Field::Rate),
+            "amount" => Ok(Field::Amount),
+            _ => match s.strip_prefix(CUSTOM_FIELD_PREFIX) {
+                Some(key) if !key.is_empty() => Ok(Field::Custom(key.to_string())),
+                _ => Err(format!(
+                "Invalid field '{}'. Use start, end, duration, project, task, tags, note, rate, amount or field:<key>.",
+                    s
+                )),
+            },
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Field::Start => "start",
+            Field::End => "end",
+            Field::Duration => "duration",
+            Field::Project => "project",
+            Field::Task => "task",
+            Field::Tags => "tags",
+            Field::Note => "note",
+            Field::Rate => "rate",
+            Field::Amount => "amount",
+            Field::Custom(key) => key,
+        }
+    }
+
+    // duration は時間単位。amount は請求対象 (billable) のときだけ単価 × 時間。
+    fn value(&self, session: &Session, rate: Option<f64>, now: DateTime<FixedOffset>) -> Value {
+        let duration = hours(session.end_or(now) - session.start);
+        match self {
+            Field::Start => format_timestamp(session.start).into(),
+            Field::End => session
+                .stop
+                .map_or(Value::Null, |t| format_timestamp(t).into()),
+            Field::Duration => Value::Number(round_cents(duration)),
+            Field::Project => session.project().map_or(Value::Null, Value::from),
+            Field::Task => session
+                .task
+                .split_once(':')
+                .map_or(session.task.as_str(), |(_, task)| task)
+                .into(),
+            Field::Tags => Value::Array(session.tags.iter().map(|t| t.as_str().into()).collect()),
+            Field::Note => session.field(NOTE_FIELD).map_or(Value::Null, Value::from),
+            Field::Rate => rate.map_or(Value::Null, Value::Number),
+            Field::Amount => match rate {
+                Some(rate) if session.billable() => Value::Number(round_cents(rate * duration)),
+                Some(_) => Value::Number(0.0),
+                None => Value::Null,
+            },
+            Field::Custom(key) => session.field(key).map_or(Value::Null, Value::from),
+        }
+    }
+}
+
+pub fn parse_fields(s: &str) -> Result<Vec<Field>, String> {
+    s.split(',').map(|name| Field::parse(name.trim())).collect()
+}
+
+fn round_cents(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+// 見出し行付きの CSV。タグは空白区切り、値の無いものは空欄にする。
+pub fn to_csv(
+    sessions: &[Session],
+    fields: &[Field],
+    locale: Locale,
+    rate_of: impl Fn(&Session) -> Option<f64>,
+    now: DateTime<FixedOffset>,
+) -> String {
+    let delimiter = locale.delimiter();
+    let header: Vec<String> = fields.iter().map(|f| f.name().to_string()).collect();
+    let mut output = match locale {
+        Locale::Default => String::new(),
+        _ => "\u{feff}".to_string(),
+    };
+    output += &csv::write_row(&header, delimiter);
+    for session in sessions {
+        let row: Vec<String> = fields
+            .iter()
+            .map(
+                |field| match (field, field.value(session, rate_of(session), now)) {
+                    (Field::Start, _) => locale.format_time(session.start),
+                    (Field::End, _) => session
+                        .stop
+                        .map_or(String::new(), |t| locale.format_time(t)),
+                    (_, Value::Null) => String::new(),
+                    (_, Value::String(s)) => s,
+                    (_, Value::Number(n)) => locale.format_number(n),
+                    (_, Value::Array(items)) => items
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    (_, other) => other.to_compact(),
+                },
+            )
+            .collect();
+        output += &csv::write_row(&row, delimiter);
+    }
+    output
+}
+
+// 1 行に 1 セッションの JSON オブジェクト
+pub fn to_jsonl(
+    sessions: &[Session],
+    fields: &[Field],
+    rate_of: impl Fn(&Session) -> Option<f64>,
+    now: DateTime<FixedOffset>,
+) -> String {
+    sessions
+        .iter()
+        .map(|session| session_object(session, fields, &rate_of, now).to_compact() + "\n")
+        .collect()
+}
+
+// セッションの JSON 配列を字下げして書く
+pub fn to_json(
+    sessions: &[Session],
+    fields: &[Field],
+    rate_of: impl Fn(&Session) -> Option<f64>,
+    now: DateTime<FixedOffset>,
+) -> String {
+    let sessions = sessions
+        .iter()
+        .map(|session| session_object(session, fields, &rate_of, now))
+        .collect();
+    Value::Array(sessions).to_pretty() + "\n"
+}
+
+fn session_object(
+    session: &Session,
+    fields: &[Field],
+    rate_of: impl Fn(&Session) -> Option<f64>,
+    now: DateTime<FixedOffset>,
+) -> Value {
+    let members = fields
+        .iter()
+        .map(|field| {
+            let value = field.value(session, rate_of(session), now);
+            (field.name().to_string(), value)
+        })
+        .collect();
+    Value::Object(members)
+}
+
+// 勤怠システムにそのまま打ち込める、日 × プロジェクトの時間の表
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grid {
+    pub days: Vec<NaiveDate>,
+    // プロジェクト名の順に並べ、セルは days と同じ並び
+    pub rows: Vec<(String, Vec<Duration>)>,
+}
+
+impl Grid {
+    pub fn new(sessions: &[Session], period: &Period, now: DateTime<FixedOffset>) -> Grid {
+        let days: Vec<NaiveDate> = period.days().collect();
+        let mut rows: Vec<(String, Vec<Duration>)> = Vec::new();
+        for session in sessions {
+            let interval = session.interval(now);
+            let project = GroupBy::Project.key(session);
+            for (column, day) in days.iter().enumerate() {
+                let overlap = interval.overlap(&Interval::day(*day));
+                if overlap.is_zero() {
+                    continue;
+                }
+                let index = match rows.iter().position(|(p, _)| *p == project) {
+                    Some(index) => index,
+                    None => {
+                        rows.push((project.clone(), vec![Duration::zero(); days.len()]));
+                        rows.len() - 1
+                    }
+                };
+                rows[index].1[column] += overlap;
+            }
+        }
+        rows.sort_by(|a, b| a.0.cmp(&b.0));
+        Grid { days, rows }
+    }
+
+    fn day_total(&self, column: usize) -> Duration {
+        self.rows
+            .iter()
+            .fold(Duration::zero(), |acc, (_, cells)| acc + cells[column])
+    }
+
+    // 見出し・各行・合計行を、末尾に行の合計を付けて並べる。セルは時間数。
+    fn table(&self, number: impl Fn(f64) -> String) -> Vec<Vec<String>> {
+        let cell = |duration: Duration| number(round_cents(hours(duration)));
+        let mut header = vec!["project".to_string()];
+        header.extend(self.days.iter().map(|day| day.to_string()));
+        header.push("total".to_string());
+        let mut table = vec![header];
+        for (project, cells) in &self.rows {
+            let mut row = vec![project.clone()];
+            row.extend(cells.iter().map(|d| cell(*d)));
+            row.push(cell(cells.iter().fold(Duration::zero(), |acc, d| acc + *d)));
+            table.push(row);
+        }
+        let totals: Vec<Duration> = (0..self.days.len()).map(|c| self.day_total(c)).collect();
+        let mut row = vec!["total".to_string()];
+        row.extend(totals.iter().map(|d| cell(*d)));
+        row.push(cell(
+            totals.iter().fold(Duration::zero(), |acc, d| acc + *d),
+        ));
+        table.push(row);
+        table
+    }
+
+    pub fn to_csv(&self, locale: Locale) -> String {
+        let mut output = match locale {
+            Locale::Default => String::new(),
+            _ => "\u{feff}".to_string(),
+        };
+        for row in self.table(|n| locale.format_number(n)) {
+            output += &csv::write_row(&row, locale.delimiter());
+        }
+        output
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let table = self.table(|n| n.to_string());
+        let line = |row: &[String]| format!("| {} |\n", row.join(" | "));
+        let mut output = line(&table[0]);
+        let mut rule = vec!["---".to_string()];
+        rule.extend(table[0][1..].iter().map(|_| "---:".to_string()));
+        output += &line(&rule);
+        for row in &table[1..] {
+            output += &line(row);
+        }
+        output
+    }
+
+    // 印刷して署名する勤務表。日を行、プロジェクトを列に入れ替え、最後に署名欄を付ける。
+    // Helvetica で書けず '?' になった文字列も返す。
+    pub fn to_pdf(&self, title: &str) -> (String, Vec<String>) {
+        let table = self.table(|n| match n == 0.0 {
+            true => String::new(),
+            false => format!("{:.2}", n),
+        });
+        let mut rows: Vec<Vec<String>> = (0..table[0].len())
+            .map(|column| table.iter().map(|row| row[column].clone()).collect())
+            .collect();
+        rows[0][0] = "date".to_string();
+        for (row, day) in rows[1..].iter_mut().zip(&self.days) {
+            row[0] = day.format("%Y-%m-%d %a").to_string();
+        }
+        let mut unsupported: Vec<String> = Vec::new();
+        for text in std::iter::once(title).chain(rows.iter().flatten().map(String::as_str)) {
+            if pdf::unsupported(text) && !unsupported.iter().any(|t| t == text) {
+                unsupported.push(text.to_string());
+            }
+        }
+
+        let (left, right) = (PDF_MARGIN, pdf::PAGE_WIDTH - PDF_MARGIN);
+        let first_width = 90.0;
+        let width = (right - left - first_width) / (rows[0].len() - 1) as f64;
+        let draw_row = |page: &mut pdf::Page, y: f64, row: &[String], font: pdf::Font| {
+            page.text(left + 2.0, y, font, PDF_FONT_SIZE, &row[0]);
+            for (i, cell) in row[1..].iter().enumerate() {
+                let x = left + first_width + width * (i + 1) as f64 - 2.0;
+                let cell = pdf::fit(cell, width - 4.0, PDF_FONT_SIZE);
+                page.text_right(x, y, font, PDF_FONT_SIZE, &cell);
+            }
+        };
+
+        let draw_header = |page: &mut pdf::Page, y: f64| {
+            draw_row(page, y, &rows[0], pdf::Font::Bold);
+            page.line(left, y - 4.0, right, y - 4.0);
+            y - PDF_ROW_HEIGHT - 2.0
+        };
+
+        let mut pages = Vec::new();
+        let mut page = pdf::Page::new();
+        let top = pdf::PAGE_HEIGHT - PDF_MARGIN;
+        page.text(left, top - 14.0, pdf::Font::Bold, 14.0, title);
+        let mut y = draw_header(&mut page, top - 40.0);
+        let (days, total) = (&rows[1..rows.len() - 1], &rows[rows.len() - 1]);
+        for row in days {
+            if y < PDF_MARGIN + PDF_ROW_HEIGHT {
+                pages.push(std::mem::take(&mut page));
+                y = draw_header(&mut page, top);
+            }
+            draw_row(&mut page, y, row, pdf::Font::Regular);
+            y -= PDF_ROW_HEIGHT;
+        }
+        let rule = y + PDF_ROW_HEIGHT - 4.0;
+        page.line(left, rule, right, rule);
+        draw_row(&mut page, y, total, pdf::Font::Bold);
+
+        // 署名欄。入りきらなければ次のページに書く
+        if y < PDF_MARGIN + 110.0 {
+            pages.push(std::mem::take(&mut page));
+            y = top;
+        }
+        y -= 50.0;
+        for (i, label) in ["Employee", "Approved by"].iter().enumerate() {
+            let x = left + i as f64 * (right - left) / 2.0;
+            let end = x + (right - left) / 2.0 - 20.0;
+            page.line(x, y, end, y);
+            page.text(x, y - 12.0, pdf::Font::Regular, PDF_FONT_SIZE, label);
+            page.line(x, y - 40.0, end, y - 40.0);
+            page.text(x, y - 52.0, pdf::Font::Regular, PDF_FONT_SIZE, "Date");
+        }
+        pages.push(page);
+        (pdf::render(&pages), unsupported)
+    }
+}
+
+// 時刻と構造はそのままに、タスク名・タグ・フィールド値を安定した仮名に置き換える
+pub fn anonymize(record: &Record, salt: &str) -> Record {
+    let mut anonymized = record.clone();
+    anonymized.task = match record.task.split_once(':') {
+        Some((project, task)) => format!(
+            "{}:{}",
+            pseudonym("project", project, salt),
+            pseudonym("task", task, salt)
+        ),
+        None if record.task.is_empty() => String::new(),
+        None if record.event == Event::Lap => pseudonym("note", &record.task, salt),
+        None => pseudonym("task", &record.task, salt),
+    };
+    anonymized.tags = record
+        .tags
+        .iter()
+        .map(|tag| {
+            if SYSTEM_TAGS.contains(&tag.as_str()) {
+                tag.clone()
+            } else {
+                pseudonym("tag", tag, salt)
+            }
+        })
+        .collect();
+    anonymized.fields = record
+        .fields
+        .iter()
+        .map(|(key, value)| (key.clone(), pseudonym(key, value, salt)))
+        .collect();
+    anonymized
+}
+
+pub fn pseudonym(prefix: &str, value: &str, salt: &str) -> String {
+    format!("{}-{:08x}", prefix, fnv1a(salt, value) as u32)
+}
+
+// 実行環境やバージョンによらず同じ値になるハッシュ (FNV-1a)
+pub fn fnv1a(salt: &str, value: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in salt.bytes().chain([0]).chain(value.bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(line: &str) -> Record {
+        Record::parse(line).unwrap()
+    }
+
+    #[test]
+    fn test_pseudonym_is_stable() {
+        assert_eq!(pseudonym("task", "a", ""), pseudonym("task", "a", ""));
+        assert_ne!(pseudonym("task", "a", ""), pseudonym("task", "b", ""));
+        assert_ne!(pseudonym("task", "a", ""), pseudonym("task", "a", "salt"));
+        assert_eq!(fnv1a("", ""), 0xaf63bd4c8601b7df);
+    }
+
+    #[test]
+    fn test_anonymize_keeps_structure() {
+        let original = record(
+            "2024-05-01T09:00:00+09:00\tstart\tclientA:fix\t+urgent\t+recurring\thost=laptop",
+        );
+        let anonymized = anonymize(&original, "");
+        assert_eq!(anonymized.timestamp, original.timestamp);
+        assert_eq!(anonymized.event, original.event);
+        let (project, task) = anonymized.task.split_once(':').unwrap();
+        assert_eq!(project, pseudonym("project", "clientA", ""));
+        assert_eq!(task, pseudonym("task", "fix", ""));
+        assert_eq!(anonymized.tags[0], pseudonym("tag", "urgent", ""));
+        assert_eq!(anonymized.tags[1], RECURRING_TAG);
+        assert_eq!(anonymized.fields[0].0, "host");
+        assert_ne!(anonymized.fields[0].1, "laptop");
+    }
+
+    #[test]
+    fn test_anonymize_same_project_same_pseudonym() {
+        let a = anonymize(&record("2024-05-01T09:00:00+09:00\tstart\tclientA:x"), "");
+        let b = anonymize(&record("2024-05-01T10:00:00+09:00\tstart\tclientA:y"), "");
+        assert_eq!(a.task.split(':').next(), b.task.split(':').next());
+    }
+
+    fn sessions() -> Vec<Session> {
+        crate::session::pair_sessions(
+            &crate::record::parse_records(
+                "2024-05-01T09:00:00+09:00\tstart\tclientA:fix\t+urgent\t+bug\tbillable=true\n\
+             2024-05-01T10:30:00+09:00\tstop\t\tnote=done, \"mostly\"\n\
+             2024-05-01T11:00:00+09:00\tstart\tmail\n",
+            )
+            .unwrap(),
+        )
+    }
+
+    fn now() -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339("2024-05-01T11:15:00+09:00").unwrap()
+    }
+
+    #[test]
+    fn test_parse_fields() {
+        assert_eq!(
+            parse_fields("start, duration,amount").unwrap(),
+            vec![Field::Start, Field::Duration, Field::Amount]
+        );
+        assert_eq!(
+            parse_fields("field:ticket").unwrap(),
+            vec![Field::Custom("ticket".to_string())]
+        );
+        assert!(parse_fields("start,cost").is_err());
+        assert!(parse_fields("field:").is_err());
+    }
+
+    #[test]
+    fn test_to_csv() {
+        let fields = parse_fields("start,end,duration,project,task,tags,note,amount").unwrap();
+        let rate = |s: &Session| s.project().map(|_| 100.0);
+        assert_eq!(
+            to_csv(&sessions(), &fields, Locale::Default, rate, now()),
+            "start,end,duration,project,task,tags,note,amount\n\
+             2024-05-01T09:00:00+09:00,2024-05-01T10:30:00+09:00,1.5,clientA,fix,urgent bug,\"done, \"\"mostly\"\"\",150\n\
+             2024-05-01T11:00:00+09:00,,0.25,,mail,,,\n"
+        );
+    }
+
+    #[test]
+    fn test_to_csv_locale() {
+        let fields = parse_fields("start,duration,note").unwrap();
+        let start = |format: &str| {
+            sessions()[0]
+                .start
+                .with_timezone(&Local)
+                .format(format)
+                .to_string()
+        };
+        let csv = to_csv(&sessions(), &fields, Locale::De, |_| None, now());
+        assert_eq!(
+            csv.lines().take(2).collect::<Vec<_>>(),
+            vec![
+                "\u{feff}start;duration;note".to_string(),
+                format!(
+                    "{};1,5;\"done, \"\"mostly\"\"\"",
+                    start("%d.%m.%Y %H:%M:%S")
+                ),
+            ]
+        );
+        let csv = to_csv(&sessions(), &fields, Locale::Ja, |_| None, now());
+        assert!(csv.contains(&format!("{},1.5,", start("%Y/%m/%d %H:%M:%S"))));
+        assert!(Locale::parse("xx").is_err());
+    }
+
+    #[test]
+    fn test_to_jsonl() {
+        let fields = parse_fields("task,tags,rate,amount,field:billable").unwrap();
+        let rate = |_: &Session| Some(80.0);
+        assert_eq!(
+            to_jsonl(&sessions(), &fields, rate, now()),
+            "{\"task\":\"fix\",\"tags\":[\"urgent\",\"bug\"],\"rate\":80,\"amount\":120,\"billable\":\"true\"}\n\
+             {\"task\":\"mail\",\"tags\":[],\"rate\":80,\"amount\":0,\"billable\":null}\n"
+        );
+    }
+
+    #[test]
+    fn test_to_json() {
+        let fields = parse_fields("task,duration").unwrap();
+        assert_eq!(
+            to_json(&sessions(), &fields, |_| None, now()),
+            "[\n  {\n    \"task\": \"fix\",\n    \"duration\": 1.5\n  },\n  \
+             {\n    \"task\": \"mail\",\n    \"duration\": 0.25\n  }\n]\n"
+        );
+        assert_eq!(to_json(&[], &fields, |_| None, now()), "[]\n");
+    }
+
+    #[test]
+    fn test_anonymize_stop_record() {
+        let stop = record("2024-05-01T10:00:00+09:00\tstop\t");
+        assert_eq!(anonymize(&stop, "").task, "");
+    }
+
+    #[test]
+    fn test_grid() {
+        let local = |date: &str, hour: u32| {
+            crate::period::to_local(
+                crate::period::parse_date(date)
+                    .unwrap()
+                    .and_hms_opt(hour, 0, 0)
+                    .unwrap(),
+            )
+        };
+        let session = |task: &str, date: &str, from: u32, to: u32| {
+            Session::new(task, local(date, from), Some(local(date, to)))
+        };
+        let sessions = vec![
+            session("clientB:fix", "2024-05-06", 9, 12),
+            session("clientA:call", "2024-05-06", 13, 14),
+            session("clientA:call", "2024-05-07", 9, 10),
+            Session::new(
+                "mail",
+                local("2024-05-07", 20),
+                Some(local("2024-05-08", 1)),
+            ),
+        ];
+        let period = Period::new(
+            crate::period::parse_date("2024-05-06").unwrap(),
+            crate::period::parse_date("2024-05-08").unwrap(),
+        )
+        .unwrap();
+        let grid = Grid::new(&sessions, &period, local("2024-06-01", 0));
+        assert_eq!(
+            grid.to_csv(Locale::Default),
+            "project,2024-05-06,2024-05-07,2024-05-08,total\n\
+             (no project),0,4,1,5\n\
+             clientA,1,1,0,2\n\
+             clientB,3,0,0,3\n\
+             total,4,5,1,10\n"
+        );
+        let markdown = grid.to_markdown();
+        let lines: Vec<&str> = markdown.lines().collect();
+        assert_eq!(lines[1], "| --- | ---: | ---: | ---: | ---: |");
+        assert_eq!(lines[5], "| total | 4 | 5 | 1 | 10 |");
+
+        let (pdf, unsupported) = grid.to_pdf("Timesheet");
+        assert!(pdf.starts_with("%PDF-"));
+        assert!(unsupported.is_empty());
+        for text in [
+            "(Timesheet)",
+            "(2024-05-07 Tue)",
+            "(clientB)",
+            "(10.00)",
+            "(Approved by)",
+        ] {
+            assert!(pdf.contains(text), "{}", text);
+        }
+
+        // Helvetica で書けない名前は '?' になり、その名前を返す
+        let sessions = vec![session("作業:fix", "2024-05-06", 9, 12)];
+        let grid = Grid::new(&sessions, &period, local("2024-06-01", 0));
+        let (pdf, unsupported) = grid.to_pdf("Timesheet");
+        assert!(pdf.contains("(??)"));
+        assert_eq!(unsupported, vec!["作業".to_string()]);
+    }
+}
@@ -0,0 +1,168 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+// inotify が使えないときに大きさと更新時刻を確かめる間隔
+pub const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+// 記録ファイルが書き換わるのを待つ。
+// Linux では inotify で親ディレクトリを見張る。書き直しは一時ファイルからの rename なので、
+// ファイルそのものではなくディレクトリへの書き込みと移動を見る。
+// inotify が使えなければ POLL_INTERVAL ごとに確かめる。
+pub struct FileWatch {
+    path: PathBuf,
+    stamp: Option<(u64, Option<SystemTime>)>,
+    #[cfg(target_os = "linux")]
+    inotify: Option<inotify::Inotify>,
+}
+
+impl FileWatch {
+    pub fn new(path: &Path) -> FileWatch {
+        FileWatch {
+            path: path.to_path_buf(),
+            stamp: stamp(path),
+            #[cfg(target_os = "linux")]
+            inotify: inotify::Inotify::watch(match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            }),
+        }
+    }
+
+    // ファイルの大きさか更新時刻が変わるまで待つ。timeout を過ぎたら false。
+    pub fn wait(&mut self, timeout: Option<Duration>) -> bool {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let remaining = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+            if remaining.is_some_and(|remaining| remaining.is_zero()) {
+                return false;
+            }
+            self.block(remaining);
+            let current = stamp(&self.path);
+            if current != self.stamp {
+                self.stamp = current;
+                return true;
+            }
+        }
+    }
+
+    // ディレクトリで何か起きるか timeout まで待つ。同じディレクトリのほかのファイルでも起きる。
+    fn block(&mut self, timeout: Option<Duration>) {
+        #[cfg(target_os = "linux")]
+        if let Some(inotify) = &mut self.inotify {
+            inotify.wait(timeout);
+            return;
+        }
+        thread::sleep(timeout.map_or(POLL_INTERVAL, |t| t.min(POLL_INTERVAL)));
+    }
+}
+
+fn stamp(path: &Path) -> Option<(u64, Option<SystemTime>)> {
+    fs::metadata(path)
+        .ok()
+        .map(|m| (m.len(), m.modified().ok()))
+}
+
+// libc の inotify と poll を直接呼ぶ。std が libc をリンクしているので、crate は足さない。
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::ffi::CString;
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::os::raw::{c_char, c_int, c_short, c_ulong};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+    use std::time::Duration;
+
+    const IN_NONBLOCK: c_int = 0o4000;
+    const IN_CLOEXEC: c_int = 0o2000000;
+    const IN_MODIFY: u32 = 0x002;
+    const IN_CLOSE_WRITE: u32 = 0x008;
+    const IN_MOVED_TO: u32 = 0x080;
+    const IN_CREATE: u32 = 0x100;
+    const IN_DELETE: u32 = 0x200;
+    const POLLIN: c_short = 0x001;
+
+    #[repr(C)]
+    struct PollFd {
+        fd: c_int,
+        events: c_short,
+        revents: c_short,
+    }
+
+    extern "C" {
+        fn inotify_init1(flags: c_int) -> c_int;
+        fn inotify_add_watch(fd: c_int, path: *const c_char, mask: u32) -> c_int;
+        fn poll(fds: *mut PollFd, nfds: c_ulong, timeout: c_int) -> c_int;
+    }
+
+    pub struct Inotify {
+        file: File,
+    }
+
+    impl Inotify {
+        pub fn watch(dir: &Path) -> Option<Inotify> {
+            let dir = CString::new(dir.as_os_str().as_bytes()).ok()?;
+            // SAFETY: 引数は定数で、返った fd はすぐ File に持たせて閉じ忘れないようにする
+            let fd = unsafe { inotify_init1(IN_NONBLOCK | IN_CLOEXEC) };
+            if fd < 0 {
+                return None;
+            }
+            // SAFETY: fd は inotify_init1 が返したばかりで、ほかに持ち主はいない
+            let file = unsafe { File::from_raw_fd(fd) };
+            let mask = IN_MODIFY | IN_CLOSE_WRITE | IN_MOVED_TO | IN_CREATE | IN_DELETE;
+            // SAFETY: dir は NUL で終わる文字列で、呼び出しのあいだ生きている
+            if unsafe { inotify_add_watch(fd, dir.as_ptr(), mask) } < 0 {
+                return None;
+            }
+            Some(Inotify { file })
+        }
+
+        // イベントが届くか timeout が過ぎるまで待ち、届いたイベントは読み捨てる。
+        // シグナルで起こされたときも戻るので、呼ぶ側で確かめ直す。
+        pub fn wait(&mut self, timeout: Option<Duration>) {
+            let timeout = timeout.map_or(-1, |t| t.as_millis().min(c_int::MAX as u128) as c_int);
+            let mut fd = PollFd {
+                fd: self.file.as_raw_fd(),
+                events: POLLIN,
+                revents: 0,
+            };
+            // SAFETY: fd は 1 個の PollFd を指し、呼び出しのあいだ生きている
+            if unsafe { poll(&mut fd, 1, timeout) } <= 0 {
+                return;
+            }
+            let mut buffer = [0; 4096];
+            while matches!(self.file.read(&mut buffer), Ok(n) if n > 0) {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_watch() {
+        let test_file = "test_file_watch.txt";
+        fs::write(test_file, "").unwrap();
+        let mut watch = FileWatch::new(Path::new(test_file));
+        #[cfg(target_os = "linux")]
+        assert!(watch.inotify.is_some());
+        // 変わらなければ timeout で戻る
+        assert!(!watch.wait(Some(Duration::from_millis(50))));
+
+        // rename で書き直されても気づく
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::write("test_file_watch.txt.tmp", "stop\n").unwrap();
+            fs::rename("test_file_watch.txt.tmp", test_file).unwrap();
+        });
+        let started = Instant::now();
+        assert!(watch.wait(Some(Duration::from_secs(10))));
+        assert!(started.elapsed() < Duration::from_secs(10));
+        writer.join().unwrap();
+        fs::remove_file(test_file).unwrap();
+    }
+}
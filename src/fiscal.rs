@@ -0,0 +1,206 @@
+use crate::period::Period;
+use chrono::{Duration, NaiveDate, Weekday};
+use serde::{Deserialize, Deserializer};
+
+// 会計年度の区切り。FY2024 は year_start_month が属する 2024 年に始まる年度。
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FiscalCalendar {
+    #[serde(deserialize_with = "deserialize_month")]
+    pub year_start_month: u32,
+    // 1 以外なら、各月はこの日に始まり、翌月のこの日の前日に締める (月名は締める側の月)
+    #[serde(deserialize_with = "deserialize_day")]
+    pub month_start_day: u32,
+    // [4, 4, 5] のような週数のパターン。空ならカレンダーの月で区切る。
+    #[serde(deserialize_with = "deserialize_weeks")]
+    pub weeks: Vec<u32>,
+}
+
+impl Default for FiscalCalendar {
+    fn default() -> Self {
+        FiscalCalendar {
+            year_start_month: 1,
+            month_start_day: 1,
+            weeks: Vec::new(),
+        }
+    }
+}
+
+impl FiscalCalendar {
+    // FY2024 / FY2024-Q2 / FY2024-M05 の形式なら会計期間、それ以外は通常の期間として解釈する
+    pub fn parse_period(&self, s: &str) -> Result<Period, String> {
+        let Some(rest) = s.strip_prefix("FY") else {
+            return Period::parse(s);
+        };
+        let invalid = || format!("Invalid period '{}'.", s);
+        let (year, part) = match rest.split_once('-') {
+            Some((year, part)) => (year, Some(part)),
+            None => (rest, None),
+        };
+        let year: i32 = year.parse().map_err(|_| invalid())?;
+        let (first, last) = match part {
+            None => (1, 12),
+            Some(part) => {
+                let (unit, number) =
+                    part.split_at(part.find(|c: char| c.is_ascii_digit()).unwrap_or(0));
+                let number: u32 = number.parse().map_err(|_| invalid())?;
+                match unit {
+                    "Q" if (1..=4).contains(&number) => (number * 3 - 2, number * 3),
+                    "M" if (1..=12).contains(&number) => (number, number),
+                    _ => return Err(invalid()),
+                }
+            }
+        };
+        let start = self.month_start(year, first - 1).ok_or_else(invalid)?;
+        let end = self.month_start(year, last).ok_or_else(invalid)?;
+        Period::new(start, end - Duration::days(1))
+    }
+
+    // 会計年度 year の index 番目 (0 始まり) の月の初日。index 12 は翌年度の初日。
+    fn month_start(&self, year: i32, index: u32) -> Option<NaiveDate> {
+        if self.weeks.is_empty() {
+            let offset = if self.month_start_day > 1 { 1 } else { 0 };
+            let month = (year * 12 + self.year_start_month as i32 - 1) + index as i32 - offset;
+            return NaiveDate::from_ymd_opt(
+                month.div_euclid(12),
+                month.rem_euclid(12) as u32 + 1,
+                self.month_start_day,
+            );
+        }
+        if index == 12 {
+            return self.year_start_by_weeks(year + 1);
+        }
+        let weeks: u32 = (0..index).map(|i| self.weeks[i as usize % 3]).sum();
+        Some(self.year_start_by_weeks(year)? + Duration::weeks(weeks as i64))
+    }
+
+    // 週で区切る年度は、year_start_month の 1 日を含む週の月曜に始まる
+    fn year_start_by_weeks(&self, year: i32) -> Option<NaiveDate> {
+        let first = NaiveDate::from_ymd_opt(year, self.year_start_month, 1)?;
+        Some(first.week(Weekday::Mon).first_day())
+    }
+}
+
+fn deserialize_month<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let month = u32::deserialize(deserializer)?;
+    if !(1..=12).contains(&month) {
+        return Err(serde::de::Error::custom(format!("invalid month {}", month)));
+    }
+    Ok(month)
+}
+
+fn deserialize_day<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let day = u32::deserialize(deserializer)?;
+    if !(1..=28).contains(&day) {
+        return Err(serde::de::Error::custom(format!(
+            "invalid day {} (must be 1-28)",
+            day
+        )));
+    }
+    Ok(day)
+}
+
+fn deserialize_weeks<'de, D>(deserializer: D) -> Result<Vec<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let weeks = Vec::<u32>::deserialize(deserializer)?;
+    if weeks.len() != 3 || weeks.iter().sum::<u32>() != 13 {
+        return Err(serde::de::Error::custom(
+            "weeks must be three numbers adding up to 13, like [4, 4, 5]",
+        ));
+    }
+    Ok(weeks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::parse_date;
+
+    fn period(start: &str, end: &str) -> Period {
+        Period::new(parse_date(start).unwrap(), parse_date(end).unwrap()).unwrap()
+    }
+
+    fn calendar(content: &str) -> FiscalCalendar {
+        toml::from_str(content).unwrap()
+    }
+
+    #[test]
+    fn test_default_is_calendar_year() {
+        let fiscal = FiscalCalendar::default();
+        assert_eq!(
+            fiscal.parse_period("FY2024").unwrap(),
+            period("2024-01-01", "2024-12-31")
+        );
+        assert_eq!(
+            fiscal.parse_period("2024-05").unwrap(),
+            period("2024-05-01", "2024-05-31")
+        );
+    }
+
+    #[test]
+    fn test_year_start_month() {
+        let fiscal = calendar("year_start_month = 4");
+        assert_eq!(
+            fiscal.parse_period("FY2024").unwrap(),
+            period("2024-04-01", "2025-03-31")
+        );
+        assert_eq!(
+            fiscal.parse_period("FY2024-Q2").unwrap(),
+            period("2024-07-01", "2024-09-30")
+        );
+        assert_eq!(
+            fiscal.parse_period("FY2024-M12").unwrap(),
+            period("2025-03-01", "2025-03-31")
+        );
+    }
+
+    #[test]
+    fn test_month_start_day() {
+        let fiscal = calendar("year_start_month = 4\nmonth_start_day = 21");
+        assert_eq!(
+            fiscal.parse_period("FY2024-M01").unwrap(),
+            period("2024-03-21", "2024-04-20")
+        );
+        assert_eq!(
+            fiscal.parse_period("FY2024-Q2").unwrap(),
+            period("2024-06-21", "2024-09-20")
+        );
+    }
+
+    #[test]
+    fn test_four_four_five_weeks() {
+        let fiscal = calendar("weeks = [4, 4, 5]");
+        // 2024-01-01 は月曜
+        assert_eq!(
+            fiscal.parse_period("FY2024-M03").unwrap(),
+            period("2024-02-26", "2024-03-31")
+        );
+        assert_eq!(
+            fiscal.parse_period("FY2024-Q1").unwrap(),
+            period("2024-01-01", "2024-03-31")
+        );
+        // 2025-01-01 を含む週は 2024-12-30 に始まる
+        assert_eq!(
+            fiscal.parse_period("FY2024").unwrap(),
+            period("2024-01-01", "2024-12-29")
+        );
+    }
+
+    #[test]
+    fn test_invalid_fiscal_period() {
+        let fiscal = FiscalCalendar::default();
+        assert!(fiscal.parse_period("FY2024-Q5").is_err());
+        assert!(fiscal.parse_period("FY2024-M13").is_err());
+        assert!(fiscal.parse_period("FYX").is_err());
+        assert!(toml::from_str::<FiscalCalendar>("month_start_day = 31").is_err());
+        assert!(toml::from_str::<FiscalCalendar>("weeks = [4, 4]").is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use crate::duration::format_duration;
+use crate::export::Locale;
+use crate::hours::HoursProfile;
+use crate::names;
+use crate::period::Period;
+use crate::report::{totals, GroupBy};
+use crate::session::Session;
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate};
+
+pub struct ForecastRow {
+    pub project: String,
+    pub so_far: Duration,
+    pub forecast: Duration,
+    pub budget: Option<Duration>,
+}
+
+impl ForecastRow {
+    pub fn is_over_budget(&self) -> bool {
+        self.budget.is_some_and(|budget| self.forecast > budget)
+    }
+}
+
+pub struct Forecast {
+    pub month: Period,
+    pub elapsed_workdays: i32,
+    pub workdays: i32,
+    pub rows: Vec<ForecastRow>,
+}
+
+// 今日までの勤務日 1 日あたりの実績で、月末までの残りの勤務日を埋めた見込み。
+// 予算のあるプロジェクトは、まだ記録が無くても並べる。
+pub fn forecast(
+    sessions: &[Session],
+    today: NaiveDate,
+    budget_of: impl Fn(&str) -> Option<Duration>,
+    budgeted: &[&str],
+    hours: &HoursProfile,
+    now: DateTime<FixedOffset>,
+) -> Forecast {
+    let month = Period::month_of(today);
+    let workdays = month.days().filter(|d| hours.is_workday(*d)).count() as i32;
+    let elapsed_workdays = month
+        .days()
+        .filter(|d| *d <= today && hours.is_workday(*d))
+        .count() as i32;
+
+    let mut totals = totals(sessions, &month, GroupBy::Project, now);
+    for project in budgeted {
+        if !totals.iter().any(|(name, _)| name == project) {
+            totals.push((project.to_string(), Duration::zero()));
+        }
+    }
+    let rows = totals
+        .into_iter()
+        .map(|(project, so_far)| {
+            let forecast = match elapsed_workdays {
+                0 => so_far,
+                elapsed => so_far + so_far / elapsed * (workdays - elapsed),
+            };
+            ForecastRow {
+                budget: budget_of(&project),
+                project,
+                so_far,
+                forecast,
+            }
+        })
+        .collect();
+    Forecast {
+        month,
+        elapsed_workdays,
+        workdays,
+        rows,
+    }
+}
+
+pub fn render(forecast: &Forecast, locale: Locale) -> String {
+    let width = forecast
+        .rows
+        .iter()
+        .map(|row| row.project.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("Project".len());
+    let mut output = format!(
+        "Forecast for {} ({} of {} workdays elapsed)\n{:<width$}  {:>8}  {:>8}  {:>8}\n",
+        names::month_year(forecast.month.start, locale),
+        forecast.elapsed_workdays,
+        forecast.workdays,
+        "Project",
+        "So far",
+        "Forecast",
+        "Budget"
+    );
+    for row in &forecast.rows {
+        output += &format!(
+            "{:<width$}  {:>8}  {:>8}  {:>8}{}\n",
+            row.project,
+            format_duration(row.so_far),
+            format_duration(row.forecast),
+            row.budget.map_or("-".to_string(), format_duration),
+            if row.is_over_budget() { "  !" } else { "" }
+        );
+    }
+    let over = forecast.rows.iter().filter(|r| r.is_over_budget()).count();
+    if over > 0 {
+        output += &format!("\n{} project(s) on track to exceed the budget.\n", over);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::{parse_date, to_local};
+
+    fn session(task: &str, date: &str, hours: i64) -> Session {
+        let start = to_local(parse_date(date).unwrap().and_hms_opt(9, 0, 0).unwrap());
+        Session::new(task, start, Some(start + Duration::hours(hours)))
+    }
+
+    #[test]
+    fn test_forecast() {
+        // 2024-05 は平日が 23 日。2 日 (木) の時点で 2 日経過。
+        let sessions = vec![
+            session("clientA:fix", "2024-05-01", 4),
+            session("clientA:fix", "2024-05-02", 4),
+            session("mail", "2024-05-02", 1),
+        ];
+        let budget_of = |project: &str| match project {
+            "clientA" => Some(Duration::hours(80)),
+            "clientB" => Some(Duration::hours(10)),
+            _ => None,
+        };
+        let today = parse_date("2024-05-02").unwrap();
+        let now = to_local(today.and_hms_opt(18, 0, 0).unwrap());
+        let forecast = forecast(
+            &sessions,
+            today,
+            budget_of,
+            &["clientA", "clientB"],
+            &HoursProfile::default(),
+            now,
+        );
+        assert_eq!((forecast.elapsed_workdays, forecast.workdays), (2, 23));
+        let summary: Vec<(&str, i64, bool)> = forecast
+            .rows
+            .iter()
+            .map(|r| {
+                (
+                    r.project.as_str(),
+                    r.forecast.num_hours(),
+                    r.is_over_budget(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("clientA", 92, true),
+                ("(no project)", 11, false),
+                ("clientB", 0, false),
+            ]
+        );
+        let output = render(&forecast, Locale::Default);
+        assert!(output.starts_with("Forecast for May 2024 (2 of 23 workdays elapsed)\n"));
+        assert!(output.contains("clientA          8h00m    92h00m    80h00m  !\n"));
+        assert!(output.ends_with("1 project(s) on track to exceed the budget.\n"));
+    }
+}
@@ -0,0 +1,204 @@
+use crate::budget::BudgetUsage;
+use crate::duration::hours;
+use crate::hours::HoursProfile;
+use crate::json::Value;
+use crate::period::Period;
+use crate::report::daily_totals;
+use crate::session::Session;
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate};
+
+// 目標との差がこの分数以内なら達成 (met) とみなす
+const TOLERANCE_MINUTES: i64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Met,
+    Behind,
+    Exceeded,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Met => "met",
+            Status::Behind => "behind",
+            Status::Exceeded => "exceeded",
+        }
+    }
+}
+
+// 目標の時間に対する実績
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Attainment {
+    pub target: Duration,
+    pub actual: Duration,
+}
+
+impl Attainment {
+    pub fn delta(&self) -> Duration {
+        self.actual - self.target
+    }
+
+    pub fn status(&self) -> Status {
+        let tolerance = Duration::minutes(TOLERANCE_MINUTES);
+        match self.delta() {
+            delta if delta < -tolerance => Status::Behind,
+            delta if delta > tolerance => Status::Exceeded,
+            _ => Status::Met,
+        }
+    }
+
+    // key の値に続けて、時間数の目標・実績・差と判定を並べる
+    fn to_json(self, key: &str, label: Value) -> Value {
+        let number = |d: Duration| Value::Number((hours(d) * 100.0).round() / 100.0);
+        Value::Object(vec![
+            (key.to_string(), label),
+            ("target".to_string(), number(self.target)),
+            ("actual".to_string(), number(self.actual)),
+            ("delta".to_string(), number(self.delta())),
+            ("status".to_string(), self.status().as_str().into()),
+        ])
+    }
+}
+
+// 期間のうち today までの日ごとの、所定時間に対する実績
+pub fn daily(
+    sessions: &[Session],
+    period: &Period,
+    hours: &HoursProfile,
+    today: NaiveDate,
+    now: DateTime<FixedOffset>,
+) -> Vec<(NaiveDate, Attainment)> {
+    daily_totals(sessions, period, now)
+        .into_iter()
+        .filter(|(day, _)| *day <= today)
+        .map(|(day, actual)| {
+            let target = hours.target(day);
+            (day, Attainment { target, actual })
+        })
+        .collect()
+}
+
+// daily を週 (月曜始まり) ごとにまとめる
+pub fn weekly(days: &[(NaiveDate, Attainment)]) -> Vec<(NaiveDate, Attainment)> {
+    let mut weeks: Vec<(NaiveDate, Attainment)> = Vec::new();
+    for (day, attainment) in days {
+        let week = Period::week_of(*day).start;
+        match weeks.last_mut() {
+            Some((start, total)) if *start == week => {
+                total.target += attainment.target;
+                total.actual += attainment.actual;
+            }
+            _ => weeks.push((week, *attainment)),
+        }
+    }
+    weeks
+}
+
+pub fn to_json(
+    period: &Period,
+    days: &[(NaiveDate, Attainment)],
+    budgets: &[BudgetUsage],
+) -> String {
+    let dated = |key: &str, entries: &[(NaiveDate, Attainment)]| {
+        let entries = entries
+            .iter()
+            .map(|(date, a)| a.to_json(key, date.to_string().into()))
+            .collect();
+        Value::Array(entries)
+    };
+    let projects = budgets
+        .iter()
+        .map(|usage| {
+            let attainment = Attainment {
+                target: usage.budget,
+                actual: usage.used,
+            };
+            let mut object = attainment.to_json("project", usage.project.as_str().into());
+            if let Value::Object(members) = &mut object {
+                members.insert(1, ("period".to_string(), usage.period.name().into()));
+            }
+            object
+        })
+        .collect();
+    let document = Value::Object(vec![
+        (
+            "period".to_string(),
+            Value::Object(vec![
+                ("start".to_string(), period.start.to_string().into()),
+                ("end".to_string(), period.end.to_string().into()),
+            ]),
+        ),
+        ("days".to_string(), dated("date", days)),
+        ("weeks".to_string(), dated("week", &weekly(days))),
+        ("projects".to_string(), Value::Array(projects)),
+    ]);
+    document.to_pretty() + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::BudgetPeriod;
+    use crate::period::{parse_date, to_local};
+
+    fn session(date: &str, from: u32, to: u32) -> Session {
+        let at = |hour: u32| to_local(parse_date(date).unwrap().and_hms_opt(hour, 0, 0).unwrap());
+        Session::new("clientA:fix", at(from), Some(at(to)))
+    }
+
+    #[test]
+    fn test_status() {
+        let attainment = |target: i64, actual: i64| Attainment {
+            target: Duration::minutes(target),
+            actual: Duration::minutes(actual),
+        };
+        assert_eq!(attainment(480, 470).status(), Status::Behind);
+        assert_eq!(attainment(480, 476).status(), Status::Met);
+        assert_eq!(attainment(480, 490).status(), Status::Exceeded);
+        assert_eq!(attainment(0, 0).status(), Status::Met);
+    }
+
+    #[test]
+    fn test_daily_and_weekly() {
+        // 2024-05-05 は日曜、05-06 は月曜
+        let sessions = vec![
+            session("2024-05-03", 9, 17),
+            session("2024-05-05", 9, 11),
+            session("2024-05-06", 9, 19),
+        ];
+        let period = Period::parse("2024-05").unwrap();
+        let today = parse_date("2024-05-07").unwrap();
+        let now = to_local(today.and_hms_opt(12, 0, 0).unwrap());
+        let days = daily(&sessions, &period, &HoursProfile::default(), today, now);
+        assert_eq!(days.len(), 7);
+        let status: Vec<&str> = days.iter().map(|(_, a)| a.status().as_str()).collect();
+        assert_eq!(
+            status,
+            vec!["behind", "behind", "met", "met", "exceeded", "exceeded", "behind"]
+        );
+        let weeks = weekly(&days);
+        assert_eq!(weeks.len(), 2);
+        assert_eq!(weeks[0].0, parse_date("2024-04-29").unwrap());
+        assert_eq!(weeks[0].1.target, Duration::hours(24));
+        assert_eq!(weeks[0].1.actual, Duration::hours(10));
+        assert_eq!(weeks[1].1.delta(), Duration::hours(-6));
+
+        let budgets = vec![BudgetUsage::new(
+            &sessions,
+            "clientA",
+            Duration::hours(20),
+            BudgetPeriod::Month,
+            today,
+            now,
+        )];
+        let json = crate::json::parse(&to_json(&period, &days, &budgets)).unwrap();
+        let project = &json.get("projects").unwrap().as_array().unwrap()[0];
+        assert_eq!(project.get("period").unwrap().as_str(), Some("monthly"));
+        assert_eq!(project.get("delta").unwrap().as_f64(), Some(0.0));
+        assert_eq!(project.get("status").unwrap().as_str(), Some("met"));
+        let week = &json.get("weeks").unwrap().as_array().unwrap()[1];
+        assert_eq!(week.get("week").unwrap().as_str(), Some("2024-05-06"));
+        assert_eq!(week.get("status").unwrap().as_str(), Some("behind"));
+    }
+}
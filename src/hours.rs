@@ -0,0 +1,100 @@
+use crate::duration::parse_duration;
+use crate::period::Period;
+use chrono::{Datelike, Duration, NaiveDate};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+// 曜日ごとの所定時間。[hours] に fri = "6h" のように書き、書かない曜日は既定 (平日 8h、週末 0)。
+// 所定時間が 0 の曜日は休日として扱う。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "BTreeMap<String, String>")]
+pub struct HoursProfile {
+    targets: [Duration; 7],
+}
+
+impl Default for HoursProfile {
+    fn default() -> HoursProfile {
+        let mut targets = [Duration::hours(8); 7];
+        targets[5] = Duration::zero();
+        targets[6] = Duration::zero();
+        HoursProfile { targets }
+    }
+}
+
+impl HoursProfile {
+    pub fn target(&self, date: NaiveDate) -> Duration {
+        self.targets[date.weekday().num_days_from_monday() as usize]
+    }
+
+    pub fn is_workday(&self, date: NaiveDate) -> bool {
+        !self.target(date).is_zero()
+    }
+
+    // 期間のうち until までの日の所定時間の合計
+    pub fn total(&self, period: &Period, until: NaiveDate) -> Duration {
+        period
+            .days()
+            .filter(|d| *d <= until)
+            .map(|d| self.target(d))
+            .fold(Duration::zero(), |a, b| a + b)
+    }
+}
+
+impl TryFrom<BTreeMap<String, String>> for HoursProfile {
+    type Error = String;
+
+    fn try_from(table: BTreeMap<String, String>) -> Result<HoursProfile, String> {
+        let mut profile = HoursProfile::default();
+        for (key, value) in &table {
+            let index = WEEKDAYS
+                .iter()
+                .position(|day| day == key)
+                .ok_or_else(|| format!("Invalid weekday '{}'. Use mon..sun.", key))?;
+            profile.targets[index] = match value.trim() {
+                "0" => Duration::zero(),
+                value => parse_duration(value)?,
+            };
+        }
+        Ok(profile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::parse_date;
+
+    fn profile(entries: &[(&str, &str)]) -> Result<HoursProfile, String> {
+        HoursProfile::try_from(
+            entries
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<BTreeMap<String, String>>(),
+        )
+    }
+
+    #[test]
+    fn test_profile() {
+        let profile = profile(&[("fri", "6h"), ("mon", "0")]).unwrap();
+        // 2024-05-03 は金曜、05-04 は土曜、05-06 は月曜
+        let day = |s: &str| parse_date(s).unwrap();
+        assert_eq!(profile.target(day("2024-05-02")), Duration::hours(8));
+        assert_eq!(profile.target(day("2024-05-03")), Duration::hours(6));
+        assert!(!profile.is_workday(day("2024-05-04")));
+        assert!(!profile.is_workday(day("2024-05-06")));
+        let week = Period::week_of(day("2024-05-06"));
+        assert_eq!(profile.total(&week, day("2024-05-08")), Duration::hours(16));
+        assert_eq!(profile.total(&week, week.end), Duration::hours(30));
+    }
+
+    #[test]
+    fn test_invalid_profile() {
+        assert_eq!(
+            profile(&[("monday", "8h")]).unwrap_err(),
+            "Invalid weekday 'monday'. Use mon..sun."
+        );
+        assert!(profile(&[("mon", "8")]).is_err());
+    }
+}
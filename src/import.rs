@@ -0,0 +1,213 @@
+use crate::period::to_local;
+use crate::record::{Event, Record};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime};
+
+// 他のツールが書き出しがちな日時の形式。日と月の順序が曖昧なものは --date-format で指定する。
+const DATETIME_FORMATS: [&str; 8] = [
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+    "%Y/%m/%d %H:%M:%S",
+    "%Y/%m/%d %H:%M",
+    "%d.%m.%Y %H:%M:%S",
+    "%d.%m.%Y %H:%M",
+];
+const DATE_FORMATS: [&str; 3] = ["%Y-%m-%d", "%Y/%m/%d", "%d.%m.%Y"];
+const TIME_FORMATS: [&str; 2] = ["%H:%M:%S", "%H:%M"];
+
+// 見出し行の列名 (大文字小文字は区別しない)
+const TASK_COLUMNS: [&str; 2] = ["task", "description"];
+const START_COLUMNS: [&str; 2] = ["start", "start time"];
+const END_COLUMNS: [&str; 4] = ["end", "stop", "end time", "stop time"];
+const DATE_COLUMNS: [&str; 2] = ["date", "start date"];
+
+struct Columns {
+    task: usize,
+    start: usize,
+    end: usize,
+    date: Option<usize>,
+    project: Option<usize>,
+    tags: Option<usize>,
+}
+
+impl Columns {
+    fn find(header: &[String]) -> Result<Columns, String> {
+        let position = |names: &[&str]| {
+            header
+                .iter()
+                .position(|column| names.contains(&column.trim().to_lowercase().as_str()))
+        };
+        let required = |names: &[&str]| {
+            position(names).ok_or_else(|| format!("Missing column '{}'.", names[0]))
+        };
+        Ok(Columns {
+            task: required(&TASK_COLUMNS)?,
+            start: required(&START_COLUMNS)?,
+            end: required(&END_COLUMNS)?,
+            date: position(&DATE_COLUMNS),
+            project: position(&["project"]),
+            tags: position(&["tags"]),
+        })
+    }
+}
+
+// 見出し付きの表を、start/stop のレコードの組に変換する
+pub fn to_records(rows: &[Vec<String>], date_format: Option<&str>) -> Result<Vec<Record>, String> {
+    let Some((header, rows)) = rows.split_first() else {
+        return Ok(Vec::new());
+    };
+    let columns = Columns::find(header)?;
+    let mut records = Vec::new();
+
+    for (i, row) in rows.iter().enumerate() {
+        let line = |e: String| format!("row {}: {}", i + 2, e);
+        let cell = |index: usize| row.get(index).map(|s| s.trim()).unwrap_or_default();
+        let date = match columns.date {
+            Some(index) => Some(parse_import_date(cell(index), date_format).map_err(line)?),
+            None => None,
+        };
+        let start = parse_timestamp(cell(columns.start), date, date_format).map_err(line)?;
+        let mut end = parse_timestamp(cell(columns.end), date, date_format).map_err(line)?;
+        // 日付列と時刻だけの終了が開始より前なら、日をまたいだものとみなす
+        if end < start && date.is_some() {
+            end += chrono::Duration::days(1);
+        }
+        if end < start {
+            return Err(line(format!("End {} is before start {}.", end, start)));
+        }
+
+        let task = cell(columns.task);
+        let task = match columns.project.map(cell).filter(|p| !p.is_empty()) {
+            Some(project) => format!("{}:{}", project, task),
+            None => task.to_string(),
+        };
+        if task.is_empty() {
+            return Err(line("Empty task name.".to_string()));
+        }
+        if task.contains(['\t', '\n']) {
+            return Err(line(format!("Task '{}' contains a tab or newline.", task)));
+        }
+        let mut start_record = Record::new(start, Event::Start, &task);
+        if let Some(tags) = columns.tags.map(cell) {
+            for tag in tags.split([',', ' ']).filter(|tag| !tag.is_empty()) {
+                start_record = start_record.with_tag(tag.trim_start_matches('+'));
+            }
+        }
+        records.push(start_record);
+        records.push(Record::new(end, Event::Stop, ""));
+    }
+    Ok(records)
+}
+
+fn parse_import_date(s: &str, format: Option<&str>) -> Result<NaiveDate, String> {
+    if let Some(date) = format.and_then(|f| NaiveDate::parse_from_str(s, f).ok()) {
+        return Ok(date);
+    }
+    DATE_FORMATS
+        .iter()
+        .find_map(|f| NaiveDate::parse_from_str(s, f).ok())
+        .ok_or_else(|| format!("Invalid date '{}'.", s))
+}
+
+// RFC3339、よくある日時の形式、--date-format の形式、日付列があれば時刻だけ、の順に試す。
+// タイムゾーンの無いものはローカル時刻とみなす。
+pub fn parse_timestamp(
+    s: &str,
+    date: Option<NaiveDate>,
+    format: Option<&str>,
+) -> Result<DateTime<FixedOffset>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time);
+    }
+    if let Some(format) = format {
+        if let Ok(time) = DateTime::parse_from_str(s, format) {
+            return Ok(time);
+        }
+        if let Ok(naive) = NaiveDateTime::parse_from_str(s, format) {
+            return Ok(to_local(naive));
+        }
+    }
+    if let Some(naive) = DATETIME_FORMATS
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(s, f).ok())
+    {
+        return Ok(to_local(naive));
+    }
+    if let Some(date) = date {
+        if let Some(time) = TIME_FORMATS
+            .iter()
+            .find_map(|f| NaiveTime::parse_from_str(s, f).ok())
+        {
+            return Ok(to_local(date.and_time(time)));
+        }
+    }
+    Err(format!("Invalid time '{}'.", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csv;
+
+    fn local(s: &str) -> DateTime<FixedOffset> {
+        to_local(NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap())
+    }
+
+    #[test]
+    fn test_to_records() {
+        let rows = csv::parse(
+            "Description,Project,Start,End,Tags\n\
+             fix,clientA,2024-05-01 09:00,2024-05-01 10:30,\"review, urgent\"\n",
+            ',',
+        )
+        .unwrap();
+        let records = to_records(&rows, None).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].task, "clientA:fix");
+        assert_eq!(records[0].tags, vec!["review", "urgent"]);
+        assert_eq!(records[0].timestamp, local("2024-05-01 09:00"));
+        assert_eq!(records[1].event, Event::Stop);
+        assert_eq!(records[1].timestamp, local("2024-05-01 10:30"));
+    }
+
+    #[test]
+    fn test_to_records_with_date_column() {
+        let rows = csv::parse("date;task;start;end\n31.05.2024;night;23:00;01:00\n", ';').unwrap();
+        let records = to_records(&rows, None).unwrap();
+        assert_eq!(records[0].timestamp, local("2024-05-31 23:00"));
+        assert_eq!(records[1].timestamp, local("2024-06-01 01:00"));
+    }
+
+    #[test]
+    fn test_to_records_with_date_format() {
+        let rows =
+            csv::parse("task,start,end\na,05/01/2024 09:00,05/01/2024 10:00\n", ',').unwrap();
+        assert!(to_records(&rows, None).is_err());
+        let records = to_records(&rows, Some("%m/%d/%Y %H:%M")).unwrap();
+        assert_eq!(records[0].timestamp, local("2024-05-01 09:00"));
+    }
+
+    #[test]
+    fn test_to_records_errors() {
+        let rows = csv::parse("task,start\na,2024-05-01 09:00\n", ',').unwrap();
+        assert_eq!(
+            to_records(&rows, None).unwrap_err(),
+            "Missing column 'end'."
+        );
+        let rows =
+            csv::parse("task,start,end\na,2024-05-01 09:00,2024-05-01 08:00\n", ',').unwrap();
+        assert!(to_records(&rows, None).unwrap_err().starts_with("row 2:"));
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        let rfc = parse_timestamp("2024-05-01T09:00:00+02:00", None, None).unwrap();
+        assert_eq!(rfc.to_rfc3339(), "2024-05-01T09:00:00+02:00");
+        assert_eq!(
+            parse_timestamp("2024/05/01 09:00", None, None).unwrap(),
+            local("2024-05-01 09:00")
+        );
+        assert!(parse_timestamp("09:00", None, None).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use crate::json;
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const COMPONENT_TAG_PREFIX: &str = "component:";
+
+const CODEOWNERS_PATHS: [&str; 3] = ["CODEOWNERS", ".github/CODEOWNERS", "docs/CODEOWNERS"];
+
+#[derive(Debug, PartialEq)]
+pub struct Inference {
+    pub component: String,
+    // 推定に使ったファイル
+    pub source: PathBuf,
+}
+
+impl Inference {
+    pub fn tag(&self) -> String {
+        format!("{}{}", COMPONENT_TAG_PREFIX, self.component)
+    }
+}
+
+// cwd から上へたどり、最初に見つかったパッケージ名か CODEOWNERS の担当を返す。
+// リポジトリのルート (.git のあるディレクトリ) より上は見ない。
+pub fn infer_component(cwd: &Path) -> Option<Inference> {
+    for dir in cwd.ancestors() {
+        let found = package_name(&dir.join("Cargo.toml"), cargo_package_name)
+            .or_else(|| package_name(&dir.join("package.json"), npm_package_name))
+            .or_else(|| {
+                CODEOWNERS_PATHS.iter().find_map(|path| {
+                    let path = dir.join(path);
+                    let content = fs::read_to_string(&path).ok()?;
+                    let relative = cwd.strip_prefix(dir).ok()?;
+                    let owner = codeowner(&content, &relative.to_string_lossy())?;
+                    Some((owner, path))
+                })
+            });
+        if let Some((name, source)) = found {
+            let component = name.split_whitespace().collect::<Vec<_>>().join("-");
+            return Some(Inference { component, source });
+        }
+        if dir.join(".git").exists() {
+            break;
+        }
+    }
+    None
+}
+
+fn package_name(path: &Path, parse: fn(&str) -> Option<String>) -> Option<(String, PathBuf)> {
+    let content = fs::read_to_string(path).ok()?;
+    Some((parse(&content)?, path.to_path_buf()))
+}
+
+// ワークスペースだけの Cargo.toml には [package] がないので読み飛ばす
+fn cargo_package_name(content: &str) -> Option<String> {
+    let table: toml::Table = toml::from_str(content).ok()?;
+    Some(table.get("package")?.get("name")?.as_str()?.to_string())
+}
+
+fn npm_package_name(content: &str) -> Option<String> {
+    Some(
+        json::parse(content)
+            .ok()?
+            .get("name")?
+            .as_str()?
+            .to_string(),
+    )
+}
+
+// path (CODEOWNERS のあるディレクトリからの相対パス) に最後に一致した規則の、
+// 最初の担当者。"@org/team" は "team"、メールアドレスは @ より前を使う。
+fn codeowner(content: &str, path: &str) -> Option<String> {
+    let path = path.replace('\\', "/");
+    let mut owner = None;
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut columns = line.split_whitespace();
+        let pattern = columns.next().unwrap_or_default();
+        let Some(first_owner) = columns.next() else {
+            continue;
+        };
+        if pattern_regex(pattern).is_some_and(|re| re.is_match(&path)) {
+            let name = first_owner.trim_start_matches('@');
+            let name = name.rsplit('/').next().unwrap_or(name);
+            owner = name.split('@').next().map(str::to_string);
+        }
+    }
+    owner.filter(|name| !name.is_empty())
+}
+
+// gitignore と同じく、先頭か途中に / がある規則はルートからの位置で、
+// そうでなければどの階層の名前にも一致する。ディレクトリの規則はその下全体に効く。
+fn pattern_regex(pattern: &str) -> Option<Regex> {
+    let anchored = pattern.trim_end_matches('/').contains('/');
+    let pattern = pattern.trim_start_matches('/').trim_end_matches('/');
+    let mut glob = String::new();
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                glob += ".*";
+            }
+            '*' => glob += "[^/]*",
+            '?' => glob += "[^/]",
+            _ => glob += &regex::escape(&c.to_string()),
+        }
+    }
+    let prefix = if anchored { "^" } else { "^(.*/)?" };
+    Regex::new(&format!("{}{}(/.*)?$", prefix, glob)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_codeowner() {
+        let content = "\
+# 既定の担当
+*                @org/platform
+/services/api/   @org/api-team @alice
+docs/            writer@example.com
+*.md             @org/docs
+";
+        assert_eq!(codeowner(content, "").as_deref(), Some("platform"));
+        assert_eq!(
+            codeowner(content, "services/api/src").as_deref(),
+            Some("api-team")
+        );
+        assert_eq!(
+            codeowner(content, "services/web").as_deref(),
+            Some("platform")
+        );
+        assert_eq!(codeowner(content, "docs/guide").as_deref(), Some("writer"));
+        assert_eq!(codeowner("/tools/ @org/tools\n", "services/tools"), None);
+    }
+
+    #[test]
+    fn test_package_names() {
+        assert_eq!(
+            cargo_package_name("[package]\nname = \"billing-core\"\n").as_deref(),
+            Some("billing-core")
+        );
+        assert_eq!(cargo_package_name("[workspace]\nmembers = []\n"), None);
+        assert_eq!(
+            npm_package_name("{\"name\": \"@acme/web\", \"private\": true}").as_deref(),
+            Some("@acme/web")
+        );
+    }
+
+    #[test]
+    fn test_infer_component() {
+        let root = env::temp_dir().join("wtr_test_infer_component");
+        let _ = fs::remove_dir_all(&root);
+        let crate_dir = root.join("crates/billing");
+        let other_dir = root.join("services/api/src");
+        fs::create_dir_all(crate_dir.join("src")).unwrap();
+        fs::create_dir_all(&other_dir).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(root.join(".github")).unwrap();
+        fs::write(root.join("Cargo.toml"), "[workspace]\nmembers = []\n").unwrap();
+        fs::write(
+            crate_dir.join("Cargo.toml"),
+            "[package]\nname = \"billing\"\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join(".github/CODEOWNERS"),
+            "/services/api/ @org/api-team\n",
+        )
+        .unwrap();
+
+        let inference = infer_component(&crate_dir.join("src")).unwrap();
+        assert_eq!(inference.tag(), "component:billing");
+        assert_eq!(inference.source, crate_dir.join("Cargo.toml"));
+        let inference = infer_component(&other_dir).unwrap();
+        assert_eq!(inference.component, "api-team");
+        assert_eq!(infer_component(&root.join("crates")), None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
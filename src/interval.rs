@@ -0,0 +1,210 @@
+use crate::period::local_midnight;
+use crate::rounding::Rounding;
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate};
+
+// 半開区間 [start, end)。end が start より前なら空の区間にする。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Interval {
+    pub start: DateTime<FixedOffset>,
+    pub end: DateTime<FixedOffset>,
+}
+
+impl Interval {
+    pub fn new(start: DateTime<FixedOffset>, end: DateTime<FixedOffset>) -> Interval {
+        Interval {
+            start,
+            end: end.max(start),
+        }
+    }
+
+    // ローカル時刻の 1 日。夏時間の切り替え日は 23 / 25 時間になる。
+    pub fn day(date: NaiveDate) -> Interval {
+        Interval::new(
+            local_midnight(date),
+            local_midnight(date.succ_opt().unwrap()),
+        )
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.end <= self.start
+    }
+
+    // range に切り詰めた区間。重ならなければ None。
+    pub fn clip(&self, range: &Interval) -> Option<Interval> {
+        let clipped = Interval::new(self.start.max(range.start), self.end.min(range.end));
+        (!clipped.is_empty()).then_some(clipped)
+    }
+
+    pub fn overlap(&self, range: &Interval) -> Duration {
+        self.clip(range)
+            .map_or_else(Duration::zero, |clipped| clipped.duration())
+    }
+
+    // ローカル時刻の日付ごとに分ける
+    pub fn split_days(&self) -> Vec<(NaiveDate, Interval)> {
+        let first = self.start.with_timezone(&Local).date_naive();
+        let last = self.end.with_timezone(&Local).date_naive();
+        first
+            .iter_days()
+            .take_while(|day| *day <= last)
+            .filter_map(|day| self.clip(&Interval::day(day)).map(|part| (day, part)))
+            .collect()
+    }
+
+    // 始まりはそのままに、長さを丸める
+    pub fn round(&self, rounding: &Rounding) -> Interval {
+        Interval::new(
+            self.start,
+            self.start + rounding.round_duration(self.duration()),
+        )
+    }
+}
+
+// 重なるか接する区間をまとめ、開始順に並べる
+pub fn merge(intervals: impl IntoIterator<Item = Interval>) -> Vec<Interval> {
+    let mut intervals: Vec<Interval> = intervals.into_iter().filter(|i| !i.is_empty()).collect();
+    intervals.sort();
+    let mut merged: Vec<Interval> = Vec::new();
+    for interval in intervals {
+        match merged.last_mut() {
+            Some(last) if interval.start <= last.end => last.end = last.end.max(interval.end),
+            _ => merged.push(interval),
+        }
+    }
+    merged
+}
+
+// range のうち、busy のどれとも重ならない区間
+pub fn gaps(range: &Interval, busy: impl IntoIterator<Item = Interval>) -> Vec<Interval> {
+    let mut gaps = Vec::new();
+    let mut cursor = range.start;
+    for interval in merge(busy.into_iter().filter_map(|i| i.clip(range))) {
+        if interval.start > cursor {
+            gaps.push(Interval::new(cursor, interval.start));
+        }
+        cursor = interval.end;
+    }
+    if range.end > cursor {
+        gaps.push(Interval::new(cursor, range.end));
+    }
+    gaps
+}
+
+pub fn total(intervals: impl IntoIterator<Item = Interval>) -> Duration {
+    intervals
+        .into_iter()
+        .fold(Duration::zero(), |acc, i| acc + i.duration())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::{parse_date, to_local};
+    use crate::rounding::RoundingMode;
+
+    fn base() -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339("2024-05-01T00:00:00Z").unwrap()
+    }
+
+    fn hours(start: i64, end: i64) -> Interval {
+        Interval::new(
+            base() + Duration::hours(start),
+            base() + Duration::hours(end),
+        )
+    }
+
+    // 0-5 時の区間の組み合わせをすべて試す
+    fn all_intervals() -> Vec<Interval> {
+        (0..6)
+            .flat_map(|start| (start..6).map(move |end| hours(start, end)))
+            .collect()
+    }
+
+    #[test]
+    fn test_clip_and_overlap() {
+        assert_eq!(hours(1, 4).clip(&hours(2, 6)), Some(hours(2, 4)));
+        assert_eq!(hours(1, 2).clip(&hours(2, 6)), None);
+        assert_eq!(hours(3, 1), hours(3, 3));
+        assert_eq!(hours(1, 4).overlap(&hours(0, 2)), Duration::hours(1));
+
+        for a in all_intervals() {
+            for b in all_intervals() {
+                assert_eq!(a.overlap(&b), b.overlap(&a));
+                assert!(a.overlap(&b) <= a.duration().min(b.duration()));
+                if let Some(clipped) = a.clip(&b) {
+                    assert_eq!(clipped.clip(&a), Some(clipped));
+                    assert_eq!(clipped.clip(&b), Some(clipped));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_merge_and_gaps() {
+        assert_eq!(
+            merge([hours(3, 4), hours(0, 1), hours(1, 2), hours(3, 5)]),
+            vec![hours(0, 2), hours(3, 5)]
+        );
+        assert_eq!(
+            gaps(&hours(0, 6), [hours(1, 2), hours(4, 8)]),
+            vec![hours(0, 1), hours(2, 4)]
+        );
+
+        // 空き時間と使った時間で範囲がちょうど埋まる
+        let range = hours(1, 5);
+        for a in all_intervals() {
+            for b in all_intervals() {
+                let busy = merge([a, b].iter().filter_map(|i| i.clip(&range)));
+                let gaps = gaps(&range, [a, b]);
+                assert_eq!(total(busy.clone()) + total(gaps.clone()), range.duration());
+                assert!(gaps.iter().all(|g| g.overlap(&a).is_zero()));
+                assert!(gaps.iter().all(|g| g.overlap(&b).is_zero()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_days() {
+        let local = |date: &str, hour: u32| {
+            to_local(parse_date(date).unwrap().and_hms_opt(hour, 0, 0).unwrap())
+        };
+        let interval = Interval::new(local("2024-05-01", 22), local("2024-05-03", 1));
+        let parts = interval.split_days();
+        let days: Vec<(String, i64)> = parts
+            .iter()
+            .map(|(day, part)| (day.to_string(), part.duration().num_hours()))
+            .collect();
+        assert_eq!(
+            days,
+            vec![
+                ("2024-05-01".to_string(), 2),
+                ("2024-05-02".to_string(), 24),
+                ("2024-05-03".to_string(), 1)
+            ]
+        );
+        assert_eq!(
+            total(parts.into_iter().map(|(_, p)| p)),
+            interval.duration()
+        );
+        // ちょうど 0 時に終わる区間は翌日に何も残さない
+        let until_midnight = Interval::new(local("2024-05-01", 22), local("2024-05-02", 0));
+        assert_eq!(until_midnight.split_days().len(), 1);
+    }
+
+    #[test]
+    fn test_round() {
+        let rounding = Rounding {
+            interval: Duration::minutes(15),
+            mode: RoundingMode::Up,
+        };
+        let interval = Interval::new(base(), base() + Duration::minutes(50));
+        assert_eq!(
+            interval.round(&rounding),
+            Interval::new(base(), base() + Duration::hours(1))
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use crate::interval::{gaps, Interval};
+use crate::session::Session;
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+
+// invoice --finalize で請求済みにした時間。状態ファイルに残し、同じ時間を二度請求しない。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Invoice {
+    pub number: u32,
+    pub issued_at: DateTime<FixedOffset>,
+    pub spans: Vec<Span>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Span {
+    pub start: DateTime<FixedOffset>,
+    pub end: DateTime<FixedOffset>,
+}
+
+impl Span {
+    pub fn interval(&self) -> Interval {
+        Interval::new(self.start, self.end)
+    }
+}
+
+pub fn next_number(invoices: &[Invoice]) -> u32 {
+    invoices.iter().map(|i| i.number).max().unwrap_or(0) + 1
+}
+
+// 請求対象のセッションのうち、range 内でどの請求書にも含まれていない部分。
+// 一部だけ請求済みのセッションは、残りの区間ごとのセッションに分ける。
+pub fn uninvoiced(
+    sessions: &[Session],
+    invoices: &[Invoice],
+    range: &Interval,
+    now: DateTime<FixedOffset>,
+) -> Vec<Session> {
+    let invoiced: Vec<Interval> = invoices
+        .iter()
+        .flat_map(|invoice| invoice.spans.iter().map(Span::interval))
+        .collect();
+    let mut parts = Vec::new();
+    for session in sessions.iter().filter(|s| s.billable()) {
+        let interval = session.interval(now);
+        let Some(clipped) = interval.clip(range) else {
+            continue;
+        };
+        for gap in gaps(&clipped, invoiced.iter().copied()) {
+            let mut part = session.clone();
+            part.start = gap.start;
+            // 計測中のまま残る部分は計測中のままにする
+            if gap.end != interval.end || session.stop.is_some() {
+                part.stop = Some(gap.end);
+            }
+            part.laps
+                .retain(|(time, _)| gap.start < *time && *time < gap.end);
+            parts.push(part);
+        }
+    }
+    parts
+}
+
+// 請求書に含める区間。parts は uninvoiced の結果。
+pub fn spans(parts: &[Session], now: DateTime<FixedOffset>) -> Vec<Span> {
+    parts
+        .iter()
+        .map(|part| Span {
+            start: part.start,
+            end: part.end_or(now),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::BILLABLE_FIELD;
+    use chrono::Duration;
+
+    fn at(hour: i64) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339("2024-05-01T00:00:00+09:00").unwrap() + Duration::hours(hour)
+    }
+
+    fn session(task: &str, start: i64, stop: Option<i64>, billable: bool) -> Session {
+        let mut session = Session::new(task, at(start), stop.map(at));
+        session
+            .fields
+            .push((BILLABLE_FIELD.to_string(), billable.to_string()));
+        session
+    }
+
+    #[test]
+    fn test_uninvoiced() {
+        let sessions = vec![
+            session("a", 9, Some(12), true),
+            session("b", 13, Some(14), false),
+            session("c", 15, None, true),
+        ];
+        let range = Interval::new(at(0), at(24));
+        let now = at(17);
+        let parts = uninvoiced(&sessions, &[], &range, now);
+        assert_eq!(parts.len(), 2);
+
+        // 10-11 時と、計測中の c の 16 時までを請求済みにする
+        let invoice = Invoice {
+            number: next_number(&[]),
+            issued_at: now,
+            spans: vec![
+                Span {
+                    start: at(10),
+                    end: at(11),
+                },
+                Span {
+                    start: at(15),
+                    end: at(16),
+                },
+            ],
+        };
+        assert_eq!(invoice.number, 1);
+        let invoices = vec![invoice];
+        let parts = uninvoiced(&sessions, &invoices, &range, now);
+        let times: Vec<_> = parts
+            .iter()
+            .map(|p| (p.task.as_str(), p.start, p.stop))
+            .collect();
+        assert_eq!(
+            times,
+            vec![
+                ("a", at(9), Some(at(10))),
+                ("a", at(11), Some(at(12))),
+                ("c", at(16), None)
+            ]
+        );
+        assert_eq!(spans(&parts, now)[2].end, now);
+        assert_eq!(next_number(&invoices), 2);
+    }
+}
@@ -0,0 +1,382 @@
+use std::fmt::Write;
+
+// dump/load などで使う最小限の JSON。オブジェクトはキーの順序を保つ。
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, Value)]> {
+        match self {
+            Value::Object(members) => Some(members),
+            _ => None,
+        }
+    }
+
+    // 1 行に詰めて書き出す
+    pub fn to_compact(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, None, 0);
+        out
+    }
+
+    // 2 文字ずつ字下げして書き出す
+    pub fn to_pretty(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, Some(2), 0);
+        out
+    }
+
+    fn write(&self, out: &mut String, indent: Option<usize>, depth: usize) {
+        let newline = |out: &mut String, depth: usize| {
+            if let Some(indent) = indent {
+                out.push('\n');
+                out.push_str(&" ".repeat(indent * depth));
+            }
+        };
+        match self {
+            Value::Null => out.push_str("null"),
+            Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Value::Number(n) if n.is_finite() => write!(out, "{}", n).unwrap(),
+            Value::Number(_) => out.push_str("null"),
+            Value::String(s) => write_string(out, s),
+            Value::Array(items) if items.is_empty() => out.push_str("[]"),
+            Value::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    newline(out, depth + 1);
+                    item.write(out, indent, depth + 1);
+                }
+                newline(out, depth);
+                out.push(']');
+            }
+            Value::Object(members) if members.is_empty() => out.push_str("{}"),
+            Value::Object(members) => {
+                out.push('{');
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    newline(out, depth + 1);
+                    write_string(out, key);
+                    out.push(':');
+                    if indent.is_some() {
+                        out.push(' ');
+                    }
+                    value.write(out, indent, depth + 1);
+                }
+                newline(out, depth);
+                out.push('}');
+            }
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Value {
+        Value::String(s)
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+pub fn parse(s: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        chars: s.char_indices().peekable(),
+        source: s,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.chars.next() {
+        None => Ok(value),
+        Some((i, _)) => Err(parser.error(i, "unexpected trailing characters")),
+    }
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    source: &'a str,
+}
+
+impl Parser<'_> {
+    // 位置は行:列で示す
+    fn error(&self, index: usize, message: &str) -> String {
+        let before = &self.source[..index];
+        let line = before.matches('\n').count() + 1;
+        let column = before
+            .rsplit('\n')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .count()
+            + 1;
+        format!("Invalid JSON at {}:{}: {}.", line, column, message)
+    }
+
+    fn end(&self) -> usize {
+        self.source.len()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .chars
+            .next_if(|(_, c)| c.is_ascii_whitespace())
+            .is_some()
+        {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some((_, c)) if c == expected => Ok(()),
+            Some((i, _)) => Err(self.error(i, &format!("expected '{}'", expected))),
+            None => Err(self.error(self.end(), &format!("expected '{}'", expected))),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        let Some(&(i, c)) = self.chars.peek() else {
+            return Err(self.error(self.end(), "unexpected end of input"));
+        };
+        match c {
+            '{' => self.object(),
+            '[' => self.array(),
+            '"' => self.string().map(Value::String),
+            't' => self.literal("true", Value::Bool(true)),
+            'f' => self.literal("false", Value::Bool(false)),
+            'n' => self.literal("null", Value::Null),
+            '-' | '0'..='9' => self.number(),
+            _ => Err(self.error(i, &format!("unexpected '{}'", c))),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        let len = self.end();
+        let start = self.chars.peek().map_or(len, |(i, _)| *i);
+        for expected in word.chars() {
+            if self.chars.next_if(|(_, c)| *c == expected).is_none() {
+                return Err(self.error(start, "invalid literal"));
+            }
+        }
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let len = self.end();
+        let start = self.chars.peek().map_or(len, |(i, _)| *i);
+        let mut end = start;
+        while let Some((i, c)) = self
+            .chars
+            .next_if(|(_, c)| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            end = i + c.len_utf8();
+        }
+        self.source[start..end]
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| self.error(start, "invalid number"))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            let Some((i, c)) = self.chars.next() else {
+                return Err(self.error(self.end(), "unterminated string"));
+            };
+            match c {
+                '"' => return Ok(s),
+                '\\' => match self.chars.next().map(|(_, c)| c) {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('/') => s.push('/'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('u') => s.push(self.unicode_escape(i)?),
+                    _ => return Err(self.error(i, "invalid escape")),
+                },
+                c if (c as u32) < 0x20 => return Err(self.error(i, "control character in string")),
+                c => s.push(c),
+            }
+        }
+    }
+
+    // \uXXXX。サロゲートペアは 2 つ続けて読む。
+    fn unicode_escape(&mut self, start: usize) -> Result<char, String> {
+        let high = self.hex4(start)?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if self.chars.next().map(|(_, c)| c) != Some('\\')
+                || self.chars.next().map(|(_, c)| c) != Some('u')
+            {
+                return Err(self.error(start, "unpaired surrogate"));
+            }
+            let low = self.hex4(start)?;
+            0x10000 + ((high - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error(start, "invalid escape"))
+    }
+
+    fn hex4(&mut self, start: usize) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self.chars.next().and_then(|(_, c)| c.to_digit(16));
+            code = code * 16 + digit.ok_or_else(|| self.error(start, "invalid escape"))?;
+        }
+        Ok(code)
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.chars.next_if(|(_, c)| *c == ']').is_some() {
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, ']')) => return Ok(Value::Array(items)),
+                Some((i, _)) => return Err(self.error(i, "expected ',' or ']'")),
+                None => return Err(self.error(self.end(), "unexpected end of input")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.chars.next_if(|(_, c)| *c == '}').is_some() {
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, '}')) => return Ok(Value::Object(members)),
+                Some((i, _)) => return Err(self.error(i, "expected ',' or '}'")),
+                None => return Err(self.error(self.end(), "unexpected end of input")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let value = Value::Object(vec![
+            ("name".to_string(), "a\t\"b\"\n".into()),
+            ("count".to_string(), Value::Number(3.0)),
+            (
+                "items".to_string(),
+                Value::Array(vec![Value::Null, Value::Bool(true), Value::Number(-1.5)]),
+            ),
+            ("empty".to_string(), Value::Object(Vec::new())),
+        ]);
+        assert_eq!(parse(&value.to_pretty()).unwrap(), value);
+        assert_eq!(
+            value.to_compact(),
+            r#"{"name":"a\t\"b\"\n","count":3,"items":[null,true,-1.5],"empty":{}}"#
+        );
+    }
+
+    #[test]
+    fn test_pretty() {
+        let value = Value::Object(vec![(
+            "a".to_string(),
+            Value::Array(vec![Value::Number(1.0), Value::Number(2.0)]),
+        )]);
+        assert_eq!(value.to_pretty(), "{\n  \"a\": [\n    1,\n    2\n  ]\n}");
+    }
+
+    #[test]
+    fn test_parse_escapes() {
+        assert_eq!(
+            parse(r#""\u00e9\ud83d\ude00\/""#).unwrap(),
+            Value::String("é😀/".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse("{\n  \"a\": tru\n}").unwrap_err(),
+            "Invalid JSON at 2:8: invalid literal."
+        );
+        assert!(parse("[1, 2").is_err());
+        assert!(parse("{} x").is_err());
+        assert!(parse("\"abc").is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use crate::duration::hours;
+use crate::session::{Session, NOTE_FIELD};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate};
+use std::collections::BTreeMap;
+
+// hledger の timeclock 形式。タスク名 (project:task) をそのまま勘定科目にし、
+// メモがあれば 2 つの空白の後に説明として付ける。計測中のタスクは i だけを書く。
+pub fn to_timeclock(sessions: &[Session]) -> String {
+    let format = |t: DateTime<FixedOffset>| t.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S");
+    let mut output = String::new();
+    for session in sessions {
+        output += &format!("i {} {}", format(session.start), account(session));
+        if let Some(note) = session.field(NOTE_FIELD) {
+            output += &format!("  {}", note);
+        }
+        output += "\n";
+        if let Some(stop) = session.stop {
+            output += &format!("o {}\n", format(stop));
+        }
+    }
+    output
+}
+
+// hledger の timedot 形式。日ごとに勘定科目と時間 (小数) を並べる。
+// 日付をまたぐセッションはそれぞれの日に分ける。
+pub fn to_timedot(sessions: &[Session], now: DateTime<FixedOffset>) -> String {
+    let mut days: BTreeMap<NaiveDate, BTreeMap<String, f64>> = BTreeMap::new();
+    for session in sessions {
+        for (day, part) in session.interval(now).split_days() {
+            *days
+                .entry(day)
+                .or_default()
+                .entry(account(session))
+                .or_default() += hours(part.duration());
+        }
+    }
+
+    let mut output = String::new();
+    for (day, accounts) in &days {
+        if !output.is_empty() {
+            output += "\n";
+        }
+        output += &format!("{}\n", day.format("%Y-%m-%d"));
+        let width = accounts
+            .keys()
+            .map(|a| a.chars().count())
+            .max()
+            .unwrap_or(0);
+        for (account, hours) in accounts {
+            output += &format!("{:<width$}  {}\n", account, (hours * 100.0).round() / 100.0);
+        }
+    }
+    output
+}
+
+// 勘定科目に使えない 2 つ以上の空白やタブは 1 つの空白にする
+fn account(session: &Session) -> String {
+    session
+        .task
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::{parse_date, to_local};
+    use chrono::Duration;
+
+    fn local(date: &str, hour: u32, minute: u32) -> DateTime<FixedOffset> {
+        to_local(
+            parse_date(date)
+                .unwrap()
+                .and_hms_opt(hour, minute, 0)
+                .unwrap(),
+        )
+    }
+
+    fn sessions() -> Vec<Session> {
+        let mut fix = Session::new(
+            "clientA:fix",
+            local("2024-05-01", 9, 0),
+            Some(local("2024-05-01", 10, 30)),
+        );
+        fix.fields
+            .push((NOTE_FIELD.to_string(), "login bug".to_string()));
+        vec![
+            fix,
+            Session::new(
+                "deploy",
+                local("2024-05-01", 23, 0),
+                Some(local("2024-05-02", 0, 45)),
+            ),
+            Session::new("clientA:fix", local("2024-05-02", 9, 0), None),
+        ]
+    }
+
+    #[test]
+    fn test_to_timeclock() {
+        assert_eq!(
+            to_timeclock(&sessions()),
+            "i 2024-05-01 09:00:00 clientA:fix  login bug\n\
+             o 2024-05-01 10:30:00\n\
+             i 2024-05-01 23:00:00 deploy\n\
+             o 2024-05-02 00:45:00\n\
+             i 2024-05-02 09:00:00 clientA:fix\n"
+        );
+    }
+
+    #[test]
+    fn test_to_timedot() {
+        let now = local("2024-05-02", 9, 0) + Duration::minutes(20);
+        assert_eq!(
+            to_timedot(&sessions(), now),
+            "2024-05-01\n\
+             clientA:fix  1.5\n\
+             deploy       1\n\
+             \n\
+             2024-05-02\n\
+             clientA:fix  0.33\n\
+             deploy       0.75\n"
+        );
+    }
+}
@@ -0,0 +1,61 @@
+pub mod anomaly;
+pub mod approval;
+pub mod autostop;
+pub mod budget;
+pub mod cache;
+pub mod clock;
+pub mod close;
+pub mod compare;
+pub mod config;
+pub mod csv;
+pub mod demo;
+pub mod digest;
+pub mod doctor;
+pub mod dst;
+pub mod dump;
+pub mod duration;
+pub mod error;
+pub mod explain;
+pub mod export;
+pub mod filewatch;
+pub mod fiscal;
+pub mod forecast;
+pub mod goal;
+pub mod hours;
+pub mod import;
+pub mod infer;
+pub mod interval;
+pub mod invoice;
+pub mod json;
+pub mod ledger;
+pub mod log;
+pub mod names;
+pub mod note;
+pub mod notify;
+pub mod open;
+pub mod pdf;
+pub mod period;
+pub mod plan;
+pub mod policy;
+pub mod pomodoro;
+pub mod prune;
+pub mod record;
+pub mod recorder;
+pub mod recovery;
+pub mod recurring;
+pub mod reference;
+pub mod report;
+pub mod rounding;
+pub mod session;
+pub mod sources;
+pub mod state;
+pub mod stats;
+pub mod summary;
+pub mod timeline;
+pub mod timezone;
+pub mod untracked;
+pub mod watch;
+
+pub use error::RecorderError;
+pub use record::{Event, Record};
+pub use recorder::{RecordStore, Recorder};
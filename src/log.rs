@@ -0,0 +1,87 @@
+use crate::duration::format_duration;
+use crate::session::Session;
+use chrono::{DateTime, FixedOffset, Local};
+
+const RUNNING_LABEL: &str = "running";
+
+// セッションを 1 行ずつ、開始・終了・長さ・タスクの表にする。
+// タスク名は幅がまちまちなので最後の列に置く。時刻は time_format で書き、列の幅は書いた文字列に合わせる。
+pub fn render(sessions: &[Session], time_format: &str, now: DateTime<FixedOffset>) -> String {
+    let mut rows = vec![[
+        "Start".to_string(),
+        "Stop".to_string(),
+        "Duration".to_string(),
+        "Task".to_string(),
+    ]];
+    for session in sessions {
+        let start = session.start.with_timezone(&Local);
+        // 日をまたいだときだけ終了の日付を書く
+        let stop = match session.stop.map(|stop| stop.with_timezone(&Local)) {
+            Some(stop) if stop.date_naive() == start.date_naive() => {
+                stop.format(time_format).to_string()
+            }
+            Some(stop) => format!("{} {}", stop.format("%m-%d"), stop.format(time_format)),
+            None => RUNNING_LABEL.to_string(),
+        };
+        rows.push([
+            format!("{} {}", start.format("%Y-%m-%d"), start.format(time_format)),
+            stop,
+            format_duration(session.interval(now).duration()),
+            session.task.clone(),
+        ]);
+    }
+    let width = |column: usize| {
+        rows.iter()
+            .map(|row| row[column].chars().count())
+            .max()
+            .unwrap_or(0)
+    };
+    let (start_width, stop_width, duration_width) = (width(0), width(1), width(2));
+    let mut output = String::new();
+    for [start, stop, duration, task] in &rows {
+        output += &format!(
+            "{:<start_width$}  {:<stop_width$}  {:>duration_width$}  {}\n",
+            start, stop, duration, task
+        );
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::{parse_date, to_local};
+    use chrono::Duration;
+
+    #[test]
+    fn test_render() {
+        let start = to_local(
+            parse_date("2024-05-01")
+                .unwrap()
+                .and_hms_opt(22, 0, 0)
+                .unwrap(),
+        );
+        let sessions = vec![
+            Session::new("a", start, Some(start + Duration::minutes(150))),
+            Session::new("b", start, Some(start + Duration::minutes(30))),
+            Session::new("c", start + Duration::hours(3), None),
+        ];
+        let output = render(&sessions, "%H:%M", start + Duration::hours(4));
+        assert_eq!(
+            output,
+            "Start             Stop         Duration  Task\n\
+             2024-05-01 22:00  05-02 00:30     2h30m  a\n\
+             2024-05-01 22:00  22:30             30m  b\n\
+             2024-05-02 01:00  running         1h00m  c\n"
+        );
+        // 12 時間制でも列が揃う
+        let output = render(&sessions, "%I:%M %p", start + Duration::hours(4));
+        assert_eq!(
+            output,
+            "Start                Stop            Duration  Task\n\
+             2024-05-01 10:00 PM  05-02 12:30 AM     2h30m  a\n\
+             2024-05-01 10:00 PM  10:30 PM             30m  b\n\
+             2024-05-02 01:00 AM  running            1h00m  c\n"
+        );
+    }
+}
@@ -1,182 +1,4688 @@
-use chrono::{Local, SecondsFormat};
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Local, NaiveDate};
+use config::Config;
+use duration::{format_duration, parse_duration};
+use period::{parse_date, parse_local_datetime, Period};
+use record::{last_event, read_records, write_records, Event, Record, RecordFormat};
+use recovery::{
+    prompt_crash_recovery, prompt_stop_time, prompt_undo, CrashRecovery, Resolver,
+    FORGOTTEN_STOP_THRESHOLD_HOURS,
+};
+use recurring::Fill;
+use report::{render_report, total_between, GroupBy, ReportOptions};
+use session::{pair_sessions, OverlapMode};
+use state::State;
+use std::collections::BTreeMap;
 use std::env;
-use std::fs::OpenOptions;
-use std::io::Write;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use working_time_recorder::close::CloseExport;
+use working_time_recorder::error::RecorderError;
+use working_time_recorder::interval::Interval;
+use working_time_recorder::recorder::{
+    resume_record, start_record, with_host, RecordStore, Recorder,
+};
+use working_time_recorder::timezone::TimeZone;
+use working_time_recorder::{
+    anomaly, approval, budget, cache, clock, close, compare, config, csv, demo, digest, doctor,
+    dst, dump, duration, explain, export, filewatch, forecast, goal, import, infer, invoice,
+    ledger, log, names, note, notify, open, period, plan, policy, pomodoro, prune, record,
+    recovery, recurring, report, rounding, session, sources, state, stats, summary, timeline,
+    untracked, watch,
+};
+
+const RECORD_ENV: &str = "WORKING_TIME_RECORD";
+// start を引数なしで実行したときに並べるタスクの数
+const RECENT_TASKS: usize = 9;
+// log で期間を指定しないときに並べるセッションの数
+const DEFAULT_LOG_COUNT: usize = 20;
 
-const TASK_NAME_NOT_PROVIDED_MSG: &str = "タスク名が提供されていません。";
 const FILENAME_NOT_PROVIDED_MSG: &str = "ファイル名が指定されていません";
+const INVALID_PATH_MSG: &str = "ファイルのパスが UTF-8 ではありません。";
+const TIME_NOT_PROVIDED_MSG: &str = "時刻が指定されていません。";
+const OUTPUT_NOT_PROVIDED_MSG: &str = "出力ファイルを -o で指定してください。";
+const RETENTION_NOT_PROVIDED_MSG: &str =
+    "--older-than か設定ファイルの retention で保持期間を指定してください。";
+const PERIODS_NOT_PROVIDED_MSG: &str = "比較する期間を --a と --b で指定してください。";
+const STATS_MODE_NOT_PROVIDED_MSG: &str = "集計の種類 (--per-hour か --focus) を指定してください。";
+const CONFIG_USAGE_MSG: &str = "使い方: config show | config set <key> <value>";
+const CONFIG_DIR_NOT_FOUND_MSG: &str = "設定ファイルの場所が見つかりません。";
+const NOTHING_TO_RESUME_MSG: &str = "再開できるタスクがありません。";
+const PERIOD_NOT_PROVIDED_MSG: &str = "期間を --period で指定してください。";
+const ID_RANGE_NOT_PROVIDED_MSG: &str = "エントリの ID (例: 3 / 3-7 / 3,5) を指定してください。";
+const REVIEWER_NOT_PROVIDED_MSG: &str = "承認者を --by で指定してください。";
+const PLAN_USAGE_MSG: &str =
+    "使い方: plan add <from>-<to> <task_name> | plan list | plan clear [--date <date>]";
+const DUMP_NOT_PROVIDED_MSG: &str = "読み込む dump ファイルを指定してください。";
+const MONTH_NOT_PROVIDED_MSG: &str = "締める月を --month で指定してください。";
+const ADD_USAGE_MSG: &str = "使い方: add <task_name> <from> <to> [--yesterday | --date <date>]";
+const AMEND_USAGE_MSG: &str = "使い方: amend [--task <task_name>] [--time <time>]";
+const NOTHING_TO_AMEND_MSG: &str = "修正できるレコードがありません。";
+const STACK_EMPTY_MSG: &str = "push で中断したタスクがありません。";
+const WAIT_USAGE_MSG: &str = "使い方: wait --until-stopped [--timeout <duration>]";
+const NOTHING_TO_UNDO_MSG: &str = "取り消せるレコードがありません。";
+const UNDO_NOT_CONFIRMED_MSG: &str = "確認できないので、--yes を付けて実行してください。";
+const FORMAT_NOT_PROVIDED_MSG: &str = "書式を --format で指定してください (tsv か jsonl)。";
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let err = execute(&args);
+    let (args, utc) = take_utc_flag(env::args().collect());
+    // 設定ファイルは 1 回だけ読み、各コマンドに渡す。読めなければ、使うコマンドがそのエラーで終わる。
+    let config = Config::load();
+    apply_timezone(utc, config.as_ref().ok());
+    warn_clock_skew(&args, config.as_ref().ok());
+    let err = execute(&args, config.as_ref());
     if let Err(e) = err {
         eprintln!("Error: {}", e);
-        std::process::exit(1);
+        std::process::exit(e.exit_code());
     }
 }
 
-fn execute(args: &[String]) -> Result<(), String> {
+fn execute(args: &[String], config: Result<&Config, &String>) -> Result<(), RecorderError> {
     if args.len() < 2 {
-        return Err("No subcommand provided.".to_string());
+        return Err("No subcommand provided.".into());
     }
 
+    // init と config は壊れた設定ファイルを直すのにも使うので、設定を読めなくても動かす
     match args[1].as_str() {
         "help" => {
             display_help();
-            Ok(())
+            return Ok(());
+        }
+        "init" => return handle_init_command(args),
+        "config" => return handle_config_command(args, config),
+        "demo" => return handle_demo_command(args),
+        _ => {}
+    }
+    let config = config.map_err(|e| RecorderError::Other(e.clone()))?;
+
+    // 各コマンドは RecorderError を返し、main が種類ごとの終了コードで終わる
+    match args[1].as_str() {
+        "start" => handle_start_command(args, config)?,
+        "stop" => handle_stop_command(args, config)?,
+        "add" => handle_add_command(args, config)?,
+        "status" => handle_status_command(args, config)?,
+        "log" => handle_log_command(args, config)?,
+        "resume" | "continue" => handle_resume_command(args, config)?,
+        "lap" => handle_lap_command(args, config)?,
+        "cancel" => handle_cancel_command(args, config)?,
+        "undo" => handle_undo_command(args, config)?,
+        "toggle" => handle_toggle_command(args, config)?,
+        "push" => handle_push_command(args, config)?,
+        "pop" => handle_pop_command(args, config)?,
+        "amend" => handle_amend_command(args, config)?,
+        "report" => handle_report_command(args, config)?,
+        "compare" => handle_compare_command(args, config)?,
+        "stats" => handle_stats_command(args, config)?,
+        "timeline" => handle_timeline_command(args, config)?,
+        "digest" => handle_digest_command(args, config)?,
+        "fill" => handle_fill_command(args, config)?,
+        "since" => handle_since_command(args, config)?,
+        "untracked" => handle_untracked_command(args, config)?,
+        "forecast" => handle_forecast_command(args, config)?,
+        "summary" => handle_summary_command(args, config)?,
+        "watch" => handle_watch_command(args, config)?,
+        "wait" => handle_wait_command(args, config)?,
+        "close" => handle_close_command(args, config)?,
+        "invoice" => handle_invoice_command(args, config)?,
+        "prune" => handle_prune_command(args, config)?,
+        "export" => handle_export_command(args, config)?,
+        "dump" => handle_dump_command(args, config)?,
+        "load" => handle_load_command(args, config)?,
+        "convert" => handle_convert_command(args, config)?,
+        "import" => handle_import_command(args, config)?,
+        "lint" | "validate" => handle_lint_command(args, config)?,
+        "doctor" => handle_doctor_command(args, config)?,
+        "open" => handle_open_command(args, config)?,
+        "approve" => handle_approve_command(args, config)?,
+        "plan" => handle_plan_command(args, config)?,
+        "lock" => handle_lock_command(args, config)?,
+        "unlock" => handle_unlock_command(args, config)?,
+        "verify-clock" => handle_verify_clock_command(args, config)?,
+        _ => {
+            let expanded = config
+                .expand_alias(args)
+                .ok_or_else(|| format!("Invalid subcommand '{}'.", args[1]))?;
+            // 別名から別名へは展開しない
+            if expanded.len() < 2 || config.aliases.contains_key(&expanded[1]) {
+                return Err(format!("Invalid alias '{}'.", args[1]).into());
+            }
+            execute(&expanded, Ok(config))?
+        }
+    }
+    Ok(())
+}
+
+// --utc はどのサブコマンドにも付けられるので、サブコマンドに渡す前に取り除く
+fn take_utc_flag(mut args: Vec<String>) -> (Vec<String>, bool) {
+    let count = args.len();
+    args.retain(|a| a != "--utc");
+    let utc = args.len() < count;
+    (args, utc)
+}
+
+// --utc か設定の timezone を、以降の記録と表示に使う。設定の誤りは各コマンドが報告する。
+fn apply_timezone(utc: bool, config: Option<&Config>) {
+    let timezone = match utc {
+        true => Some(TimeZone::utc()),
+        false => config.and_then(|config| config.timezone.clone()),
+    };
+    if let Some(timezone) = timezone {
+        timezone.apply();
+    }
+}
+
+// 今の時刻を記録するコマンドの前に、[clock] verify なら時計のずれを確かめる。
+// 確かめられなくても、ずれていても、記録はそのまま続ける。
+fn warn_clock_skew(args: &[String], config: Option<&Config>) {
+    const RECORDING: [&str; 9] = [
+        "start", "stop", "lap", "resume", "continue", "toggle", "push", "pop", "cancel",
+    ];
+    if !args.get(1).is_some_and(|c| RECORDING.contains(&c.as_str())) {
+        return;
+    }
+    let check = match config {
+        Some(config) if config.clock.verify => &config.clock,
+        _ => return,
+    };
+    match clock::skew(&check.server, clock::TIMEOUT) {
+        Ok(skew) if skew.abs() > check.max_skew => eprintln!(
+            "Warning: the system clock is off by {} from {}.",
+            clock::format_skew(skew),
+            check.server
+        ),
+        Ok(_) => {}
+        Err(e) => eprintln!("Warning: could not verify the system clock: {}", e),
+    }
+}
+
+// コメント付きの既定の設定ファイルを書き出す
+fn handle_init_command(args: &[String]) -> Result<(), RecorderError> {
+    let mut force = false;
+    for arg in &args[2..] {
+        match arg.as_str() {
+            "--force" => force = true,
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    let config_path = config::config_path().ok_or(CONFIG_DIR_NOT_FOUND_MSG)?;
+    if config_path.exists() && !force {
+        return Err(format!(
+            "{} already exists. Use --force to overwrite it.",
+            config_path.display()
+        )
+        .into());
+    }
+    if let Some(dir) = config_path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::write(&config_path, config::DEFAULT_CONTENT).map_err(|e| e.to_string())?;
+    println!("Wrote {}.", config_path.display());
+    Ok(())
+}
+
+// NTP サーバーと比べ、ずれが max_skew を超えていればエラーにする
+fn handle_verify_clock_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let mut check = config.clock.clone();
+    let mut iter = args[2..].iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--server" => check.server = next_value(&mut iter, arg)?.to_string(),
+            "--max-skew" => check.max_skew = parse_duration(next_value(&mut iter, arg)?)?,
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
         }
-        "start" => handle_start_command(&args),
-        "stop" => handle_stop_command(&args),
-        _ => Err(format!("Invalid subcommand '{}'.", args[1])),
     }
+
+    let skew = clock::skew(&check.server, clock::TIMEOUT)?;
+    if skew.abs() > check.max_skew {
+        return Err(format!(
+            "The system clock is off by {} from {} (max {}).",
+            clock::format_skew(skew),
+            check.server,
+            clock::format_skew(check.max_skew)
+        )
+        .into());
+    }
+    println!(
+        "The system clock is off by {} from {}.",
+        clock::format_skew(skew),
+        check.server
+    );
+    Ok(())
 }
 
 fn display_help() {
     println!("Usage:");
-    println!("  start <task_name> [-f <file>]    Start tracking time for a task.");
-    println!("  stop                             Stop tracking time.");
+    println!("  start <task_name> [+<tag>|-t <tag>]... [--field <key=value>]... [--infer]");
+    println!("        [--billable|--non-billable] [--switch] [--at <time>] [--force-unlock]");
+    println!("        [--pomodoro <minutes>]");
+    println!("                                   Start tracking time for a task (default: now).");
+    println!("                                   --infer tags it with the component of the");
+    println!("                                   current directory (Cargo.toml, package.json");
+    println!("                                   or CODEOWNERS).");
+    println!("                                   --switch (or --force) stops the running task");
+    println!("                                   first. Without a task name, lists the last 9");
+    println!("                                   tasks; `start <n>` starts the n-th of them.");
+    println!("                                   --pomodoro waits, stops the task after the");
+    println!("                                   given length and announces the break with a");
+    println!("                                   desktop notification ([pomodoro] config).");
+    println!("  stop [[--yesterday] <time> | --at <time>] [--force] [--force-unlock]");
+    println!("                                   Stop tracking time (default: now). --force");
+    println!("                                   writes the stop even if nothing is running.");
+    println!("  add <task_name> <from> <to> [--yesterday | --date <date>] [-t <tag>]...");
+    println!("      [--field <key=value>]... [--billable|--non-billable] [--force-unlock]");
+    println!("      [--clip | --allow-overlap]");
+    println!("                                   Record a finished session (default: today).");
+    println!("                                   It must not overlap recorded sessions unless");
+    println!("                                   --clip keeps only the free time or");
+    println!("                                   --allow-overlap records it anyway.");
+    println!("  status                           Show the running task.");
+    println!("  log [-n <count>] [--today|--week] [--all-history]");
+    println!("                                   List recent sessions (default: the last 20).");
+    println!("  lap [note]                       Mark a lap within the running task.");
+    println!("  cancel [--force-unlock]          Discard the running task without recording it.");
+    println!("  push <task_name> [+<tag>]... [--force-unlock]");
+    println!("                                   Suspend the running task and start another.");
+    println!("  pop [--force-unlock]             Stop the current task and resume the last one");
+    println!("                                   suspended by push.");
+    println!("  toggle <task_name> [--force-unlock]");
+    println!("                                   Stop the task if it is running, otherwise start");
+    println!("                                   it (stopping any other running task).");
+    println!("  undo [--yes] [--force-unlock]");
+    println!("                                   Remove the most recently written record after");
+    println!("                                   asking (--yes skips the question).");
+    println!("  amend [--task <task_name>] [--time <time>] [--force-unlock]");
+    println!("                                   Fix the task name or time of the last record.");
+    println!("  resume [-n <n>] [--at <time>] [--force-unlock]");
+    println!("                                   Start the last task again (default: now).");
+    println!("                                   -n picks the n-th most recent task. Also");
+    println!("                                   available as `continue`.");
+    println!("  report [today|yesterday] [--from <date>] [--to <date>] [--period <period>]");
+    println!("         [--day|--week|--month [<date>]]");
+    println!("         [--group-by task|project|origin|field:<key>] [--sparkline] [--laps]");
+    println!("         [--billable] [--with-plan] [--field <key=value>]...");
+    println!("         [--top <n>] [--min-share <percent>] [--balance]");
+    println!("         [--max-session <duration>] [--host <name>] [--explain <task|day>]");
+    println!("         [--compare-to-goal] [--uninvoiced] [--no-cache] [--all-history]");
+    println!("         [--all-projects]");
+    println!("                                   Show per-task totals (default: this month)");
+    println!("                                   and flag suspicious entries. --top and");
+    println!("                                   --min-share collapse the rest into (other).");
+    println!("                                   --balance compares against the [hours] targets.");
+    println!("                                   field:<key> groups by a field, such as one");
+    println!("                                   filled in by [[references]].");
+    println!("                                   --compare-to-goal prints per-day, per-week and");
+    println!("                                   per-project goal status as JSON.");
+    println!("                                   --uninvoiced shows billable time not yet in a");
+    println!("                                   finalized invoice.");
+    println!("                                   --all-projects merges the record files of all");
+    println!("                                   [projects.<name>] entries.");
+    println!("                                   Output is cached until the records change.");
+    println!("  compare --a <period> --b <period>");
+    println!("          [--group-by task|project|origin|field:<key>]");
+    println!("                                   Compare totals of two periods.");
+    println!("  stats --per-hour|--focus [--from <date>] [--to <date>] [--period <period>]");
+    println!("        [--all-history]");
+    println!("                                   Show tracked time by weekday and hour of day,");
+    println!("                                   or task switches and focus blocks per day.");
+    println!("  close --month <month> [-o <dir>] [--force]");
+    println!("                                   Validate, lock and export a month in one step");
+    println!("                                   (exports: close_exports config).");
+    println!("  invoice [--period <period> | --from <date> --to <date>] [-o <file>] [--finalize]");
+    println!("                                   List uninvoiced billable time with rates as CSV");
+    println!("                                   (default: this month). --finalize marks it as");
+    println!("                                   invoiced.");
+    println!("  forecast                         Project end-of-month totals per project and flag");
+    println!("                                   projects on track to exceed their budget.");
+    println!("  watch [--threshold <duration>] [--interval <duration>] [--auto-stop] [--once]");
+    println!("                                   Notify when the running task exceeds the");
+    println!("                                   threshold (default: 10h, [watch] config).");
+    println!("                                   --auto-stop also stops it, tagged");
+    println!("                                   +auto-stopped, at the threshold.");
+    println!("  wait --until-stopped [--timeout <duration>]");
+    println!("                                   Block until no task is running, for scripts");
+    println!("                                   (returns at once if none is). Fails after");
+    println!("                                   --timeout.");
+    println!("  summary [--standup] [--all-history]");
+    println!("                                   Show per-task totals of the previous workday and");
+    println!("                                   today. --standup prints them on one line.");
+    println!("  timeline [--week] [--date <date>] [--all-history]");
+    println!(
+        "                                   Draw the day's (or week's) intervals per project."
+    );
+    println!("  digest [--week | --period <period>] [-o <file>] [--all-history]");
+    println!("                                   Write an HTML digest (default: this week).");
+    println!("  since <time> [--task <name>] [--project <name>]");
+    println!("                                   Show time tracked from <time> until now.");
+    println!("  untracked [--workday <from>-<to>] [--min-gap <duration>] [--from <date>]");
+    println!("            [--to <date>] [--period <period>]");
+    println!("                                   List untracked gaps in working hours");
+    println!("                                   (default: this week, workday config).");
+    println!("  prune [--older-than <duration>] [--project <name>] [--archive <file>] [--dry-run]");
+    println!("        [--force-unlock]");
+    println!("                                   Delete old records (default: retention config).");
+    println!(
+        "  export [csv [--locale en|de|fr|ja]|json|jsonl|timeclock|timedot] [--fields <list>]"
+    );
+    println!("         [--format <format>] [--from <date>] [--to <date>]");
+    println!("         [--anonymize [--salt <text>]] [--stamp] [-o <file>]");
+    println!("  export grid [--week [<date>]] [--markdown] [--locale en|de|fr|ja] [-o <file>]");
+    println!("  export pdf [--month [<month>] | --week [<date>]] [-o <file>]");
+    println!("                                   Write records (or sessions as CSV / JSON /");
+    println!("                                   JSON lines / hledger timeclock or timedot) to");
+    println!("                                   stdout or a file. CSV / JSON fields: start, end,");
+    println!("                                   duration, project, task, tags, note, rate,");
+    println!("                                   amount, field:<key>. grid writes hours per");
+    println!("                                   project and day (default: this week). pdf");
+    println!("                                   writes it as a printable timesheet with");
+    println!("                                   signature lines (default: this month).");
+    println!(
+        "  dump [-o <file>]                 Write records, state and config as one JSON document."
+    );
+    println!("  load <file> [--replace] [--with-config]");
+    println!("                                   Restore a store written by dump.");
+    println!("  convert --format tsv|jsonl       Rewrite the record file in another format;");
+    println!("                                   later writes keep it. Both are always readable.");
+    println!("  import <file> [--delimiter <char>] [--date-format <format>] [--dry-run]");
+    println!("         [--clip | --allow-overlap] [--force-unlock]");
+    println!("                                   Add sessions from a CSV/TSV timesheet.");
+    println!("                                   Overlaps are handled as in add.");
+    println!("  fill [--from <date>] [--to <date>] [--dry-run] [--force-unlock]");
+    println!(
+        "                                   Record configured recurring entries (default: today)."
+    );
+    println!(
+        "  lint | validate                  Check recorded tasks against the configured policies."
+    );
+    println!("  plan add <from>-<to> <task_name> | plan list | plan clear [--date <date>]");
+    println!("                                   Plan the day (default: today).");
+    println!("  approve <id-range> [--reject] [--by <name>] | --list [--period <period>]");
+    println!("                                   Mark entries as approved or rejected.");
+    println!(
+        "  lock --period <period> | --list  Mark a period as submitted so it can't be changed."
+    );
+    println!("  unlock --period <period>         Remove the lock of a period.");
+    println!("  open [record|config|data-dir]    Open a data file in $EDITOR or the file manager.");
+    println!("  init [--force]                   Write a commented default config file.");
+    println!("  verify-clock [--server <host>] [--max-skew <duration>]");
+    println!("                                   Compare the system clock with an NTP server");
+    println!("                                   (default: the [clock] config).");
+    println!("  doctor [--fix [--close-at <time>]]");
+    println!("                                   Report problems in the record file by line:");
+    println!("                                   unreadable or empty lines, records out of");
+    println!("                                   order, stops without a start, sessions left");
+    println!("                                   running. --fix sorts, drops empty lines and");
+    println!("                                   stray stops, moves unreadable lines to");
+    println!("                                   .rejected, closes the running session at");
+    println!("                                   --close-at, and keeps the original as .bak.");
+    println!("  demo [--days <n>] [--seed <n>] -o <file>");
+    println!("                                   Generate fake history to try the reports with.");
+    println!("  -f <file>                        Use another record file with any command;");
+    println!("                                   - reads records from stdin (read-only).");
+    println!("  -p <project>                     Use the record file of a [projects.<name>]");
+    println!("                                   entry with any command. Also --project,");
+    println!("                                   except in prune and since.");
+    println!("  --utc                            Record and show times in UTC with any command");
+    println!("                                   (overrides the timezone setting).");
+    println!("  --all-history                    Read the whole history in report, log, stats,");
+    println!("                                   timeline and digest, ignoring history_limit.");
     println!("  help                             Display this help message.");
+    println!();
+    println!("Other subcommands are looked up in the [aliases] table of the config file.");
+}
+
+fn handle_start_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let now = get_current_time();
+    let mut at = None;
+    let mut task_name = None;
+    let mut tags = Vec::new();
+    let mut fields = Vec::new();
+    let mut billable = None;
+    let mut infer = false;
+    let mut switch = false;
+    let mut force_unlock = false;
+    let mut pomodoro = None;
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-t" | "--tag" => tags.push(next_value(&mut iter, arg)?),
+            "--field" => fields.push(record::parse_field(next_value(&mut iter, arg)?)?),
+            "--billable" => billable = Some(true),
+            "--non-billable" => billable = Some(false),
+            "--infer" => infer = true,
+            "--switch" | "--force" => switch = true,
+            "--at" => {
+                at = Some(parse_local_datetime(
+                    next_value(&mut iter, arg)?,
+                    now.date_naive(),
+                )?)
+            }
+            "--force-unlock" => force_unlock = true,
+            "--pomodoro" => pomodoro = Some(pomodoro::parse_length(next_value(&mut iter, arg)?)?),
+            // start fix-login +backend +clientA のようにタグを並べられる
+            _ if arg.len() > 1 && arg.starts_with('+') => tags.push(&arg[1..]),
+            _ if task_name.is_none() => task_name = Some(arg.as_str()),
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    let timestamp = config.timestamp(at.unwrap_or(now));
+    if timestamp > now {
+        return Err(RecorderError::InvalidTimestamp(format!(
+            "{} is in the future.",
+            config.format_datetime(timestamp)
+        )));
+    }
+    // タスク名が無ければ最近のタスクを番号付きで並べ、1-9 の番号ならそのタスクを始める
+    let task_name = match task_name {
+        Some(task) if !is_recent_number(task) => task.to_string(),
+        _ => {
+            let sessions = RecordStore::new(&file_path).sessions(config)?;
+            let recent = session::recent_tasks(&sessions, RECENT_TASKS);
+            let Some(number) = task_name else {
+                if recent.is_empty() {
+                    return Err(RecorderError::MissingTask);
+                }
+                for (i, task) in recent.iter().enumerate() {
+                    println!("{}  {}", i + 1, task);
+                }
+                println!("Run `start <number>` to start one of them.");
+                return Ok(());
+            };
+            let index: usize = number.parse().unwrap();
+            recent
+                .get(index - 1)
+                .ok_or_else(|| format!("No recent task #{}.", index))?
+                .to_string()
+        }
+    };
+    let task_name = task_name.as_str();
+
+    // 作業ディレクトリのパッケージや CODEOWNERS からコンポーネントのタグを付ける
+    let inference = if infer {
+        let cwd = env::current_dir().map_err(|e| e.to_string())?;
+        let inference = infer::infer_component(&cwd);
+        if inference.is_none() {
+            eprintln!("No component found from '{}'.", cwd.display());
+        }
+        inference
+    } else {
+        None
+    };
+    let inferred_tag = inference.as_ref().map(infer::Inference::tag);
+    tags.extend(inferred_tag.as_deref());
+
+    if let Some(length) = pomodoro {
+        fields.push((
+            pomodoro::POMODORO_FIELD.to_string(),
+            format_duration(length),
+        ));
+    }
+    let record = start_record(task_name, timestamp, tags, fields, billable, config)?;
+    warn_budget(&file_path, config, &record)?;
+    apply_auto_stop(&file_path, config, timestamp)?;
+    // 計測中のタスクがあれば二重に start せず、--switch なら先に止める
+    let started = Recorder::new(RecordStore::new(&file_path), config)
+        .force_unlock(force_unlock)
+        .resolver(&TerminalResolver(config))
+        .record_start(record, switch)?;
+    // 書けたときだけ、推定したタグを伝える
+    if let Some(inference) = &inference {
+        println!(
+            "Tagged '+{}' (from {}).",
+            inference.tag(),
+            inference.source.display()
+        );
+    }
+    if let Some(stopped) = &started.stopped {
+        println!("Stopped '{}'.", stopped.task);
+    }
+    if started.continued {
+        println!("Continuing '{}'.", task_name);
+    }
+    match pomodoro {
+        Some(length) => run_pomodoro(&file_path, config, task_name, timestamp, length),
+        None => Ok(()),
+    }
 }
 
-fn handle_start_command(args: &[String]) -> Result<(), String> {
-    let (file_path, remaining_args) = parse_arguments(args)?;
-    let timestamp = get_current_time();
+// ポモドーロの終わりまで待って stop を書き、休憩の始まりと終わりを通知する。
+// 待つあいだに別のタスクを始めていれば止めない。
+fn run_pomodoro(
+    file_path: &str,
+    config: &Config,
+    task_name: &str,
+    start: DateTime<FixedOffset>,
+    length: Duration,
+) -> Result<(), RecorderError> {
+    let end = start + length;
+    println!(
+        "Pomodoro: stopping '{}' at {}. Press Ctrl-C to keep it running.",
+        task_name,
+        config.format_time(end)
+    );
+    sleep_until(end);
 
-    if remaining_args.is_empty() {
-        return Err(TASK_NAME_NOT_PROVIDED_MSG.into());
+    let recorder = Recorder::new(RecordStore::new(file_path), config);
+    match recorder.running()? {
+        Some(running) if running.task == task_name && running.timestamp <= start => {
+            recorder.stop(end)?;
+        }
+        _ => {
+            println!("'{}' is no longer running.", task_name);
+            return Ok(());
+        }
     }
+    let sessions = RecordStore::new(file_path)
+        .since(Some(end - Duration::days(1)))
+        .sessions(config)?;
+    let completed = pomodoro::completed_on(&sessions, end.date_naive());
+    let rest = config.pomodoro.break_after(completed);
+    let message = format!(
+        "Stopped '{}'. Take a {} break.",
+        task_name,
+        format_duration(rest)
+    );
+    println!("{}", message);
+    notify("Pomodoro finished", &message);
 
-    let task_name = remaining_args[0].as_str();
-    let record = format!("{}\tstart\t{}\n", timestamp, task_name);
-    write_to_file(&file_path, &record)
+    sleep_until(end + rest);
+    println!("Break is over.");
+    notify("Break is over", "Time to start the next pomodoro.");
+    Ok(())
 }
 
-fn handle_stop_command(args: &[String]) -> Result<(), String> {
-    let (file_path, _remaining_args) = parse_arguments(args)?;
-    let timestamp = get_current_time();
-    let record = format!("{}\tstop\t\n", timestamp);
-    write_to_file(&file_path, &record)
+fn sleep_until(time: DateTime<FixedOffset>) {
+    if let Ok(wait) = (time - get_current_time()).to_std() {
+        thread::sleep(wait);
+    }
 }
 
-// 共通の引数処理関数
-fn parse_arguments(args: &[String]) -> Result<(String, Vec<String>), String> {
-    let mut file_path = get_working_time_record_path();
-    let mut remaining_args = Vec::new();
-    let mut iter = args.iter().skip(2);
+// 通知を出せなくても記録には影響しないので、警告だけにする
+fn notify(title: &str, body: &str) {
+    let command = notify::command(title, body);
+    let result = Command::new(&command[0]).args(&command[1..]).output();
+    if let Err(e) = result {
+        eprintln!("Could not send a notification with '{}': {}", command[0], e);
+    }
+}
+
+fn is_recent_number(s: &str) -> bool {
+    matches!(s.as_bytes(), [b'1'..=b'9'])
+}
+
+fn handle_stop_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let now = get_current_time();
+    let mut date = now.date_naive();
+    let mut time = None;
+    let mut force = false;
+    let mut force_unlock = false;
+    let mut iter = remaining_args.iter();
 
     while let Some(arg) = iter.next() {
         match arg.as_str() {
-            "-f" | "--file" => file_path = iter.next().ok_or(FILENAME_NOT_PROVIDED_MSG)?.clone(),
-            _ => remaining_args.push(arg.clone()),
+            "--yesterday" => date = now.date_naive() - Duration::days(1),
+            "--at" if time.is_none() => time = Some(next_value(&mut iter, arg)?),
+            "--force" => force = true,
+            "--force-unlock" => force_unlock = true,
+            _ if time.is_none() => time = Some(arg.as_str()),
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
         }
     }
 
-    Ok((file_path, remaining_args))
+    let _lock = RecordStore::new(&file_path).lock()?;
+    // 時刻を指定したときは、止め忘れたタスクをその時刻で閉じる。閉じ方はもう尋ねない。
+    let (timestamp, resolver): (_, &dyn Resolver) = match time {
+        Some(time) => (
+            config.timestamp(parse_local_datetime(time, date)?),
+            &NoteResolver,
+        ),
+        None => (config.timestamp(now), &TerminalResolver(config)),
+    };
+    if timestamp > now {
+        return Err(RecorderError::InvalidTimestamp(format!(
+            "{} is in the future.",
+            config.format_datetime(timestamp)
+        )));
+    }
+    apply_auto_stop(&file_path, config, timestamp)?;
+    let stopped = Recorder::new(RecordStore::new(&file_path), config)
+        .force_unlock(force_unlock)
+        .resolver(resolver)
+        .record_stop(timestamp, force)?;
+    match stopped.start {
+        // 計測中でなければ、--force のときだけ対になる start の無い stop を書く
+        None => eprintln!("Warning: no task is running; writing the stop anyway."),
+        Some(start) if time.is_some() => println!(
+            "Stopped '{}' at {}.",
+            start.task,
+            config.format_datetime(timestamp)
+        ),
+        Some(_) => {}
+    }
+    Ok(())
 }
 
-fn get_current_time() -> String {
-    Local::now().to_rfc3339_opts(SecondsFormat::Secs, false)
+// 終わったセッションを後から記録する
+fn handle_add_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let now = get_current_time();
+    let mut date = now.date_naive();
+    let mut positional = Vec::new();
+    let mut tags = Vec::new();
+    let mut fields = Vec::new();
+    let mut billable = None;
+    let mut force_unlock = false;
+    let mut overlap = OverlapMode::Reject;
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--yesterday" => date = now.date_naive() - Duration::days(1),
+            "--date" => date = parse_date(next_value(&mut iter, arg)?)?,
+            "-t" | "--tag" => tags.push(next_value(&mut iter, arg)?),
+            "--field" => fields.push(record::parse_field(next_value(&mut iter, arg)?)?),
+            "--billable" => billable = Some(true),
+            "--non-billable" => billable = Some(false),
+            "--force-unlock" => force_unlock = true,
+            "--allow-overlap" => overlap = OverlapMode::Allow,
+            "--clip" => overlap = OverlapMode::Clip,
+            _ if positional.len() < 3 => positional.push(arg.as_str()),
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    let [task_name, from, to] = positional[..] else {
+        return Err(ADD_USAGE_MSG.into());
+    };
+
+    let from = config.timestamp(parse_local_datetime(from, date)?);
+    let to = config.timestamp(parse_local_datetime(to, date)?);
+    if to <= from {
+        return Err(RecorderError::InvalidTimestamp(format!(
+            "Invalid range: {} is after {}.",
+            config.format_datetime(from),
+            config.format_datetime(to)
+        )));
+    }
+    if to > now {
+        return Err(RecorderError::InvalidTimestamp(format!(
+            "{} is in the future.",
+            config.format_datetime(to)
+        )));
+    }
+    let start = start_record(task_name, from, tags, fields, billable, config)?;
+    let _lock = RecordStore::new(&file_path).lock()?;
+    Recorder::new(RecordStore::new(&file_path), config)
+        .force_unlock(force_unlock)
+        .check_unlocked([from, to].into_iter())?;
+
+    let records = RecordStore::new(&file_path).records(config)?;
+    // 重なりは書く前に確かめる。後から report で気づいても直しにくい
+    let free = session::resolve_overlap(
+        &pair_sessions(&records),
+        task_name,
+        Interval::new(from, to),
+        overlap,
+        config,
+        now,
+    )?;
+    if free.is_empty() {
+        return Err(format!(
+            "No free time between {} and {}.",
+            config.format_datetime(from),
+            config.format_datetime(to)
+        )
+        .into());
+    }
+    // 時刻順の位置に差し込み、ほかの行は書き直さない
+    let mut added = Vec::new();
+    for interval in &free {
+        let mut start = start.clone();
+        start.timestamp = interval.start;
+        let stop = with_host(Record::new(interval.end, Event::Stop, ""), config);
+        added.extend([start, stop]);
+    }
+    RecordStore::new(&file_path).insert(&added)?;
+    for interval in &free {
+        println!(
+            "Added '{}' {} - {}.",
+            task_name,
+            config.format_datetime(interval.start),
+            config.format_time(interval.end)
+        );
+    }
+    Ok(())
 }
 
-fn get_working_time_record_path() -> String {
-    env::var("WORKING_TIME_RECORD").unwrap_or_else(|_| {
-        dirs::home_dir()
-            .expect("ホームディレクトリが見つかりません")
-            .join("working_time_record.txt")
-            .to_str()
-            .unwrap()
-            .to_string()
-    })
+fn handle_report_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let now = get_current_time();
+    let mut period = Period::month_to_date(now.date_naive());
+    let mut options = ReportOptions::default();
+    options.anomaly_rules.hours = config.hours;
+    options.time_format = config.time_format.clone();
+    let mut host = None;
+    let mut fields = Vec::new();
+    let mut explain = None;
+    let mut with_plan = false;
+    let mut compare_to_goal = false;
+    let mut use_cache = true;
+    let mut all_history = false;
+    let mut uninvoiced = false;
+    let mut all_projects = false;
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        if let Some(day) = Period::relative_day(arg, now.date_naive()) {
+            period = day;
+            continue;
+        }
+        match arg.as_str() {
+            "--from" => period.start = parse_date(next_value(&mut iter, arg)?)?,
+            "--to" => period.end = parse_date(next_value(&mut iter, arg)?)?,
+            "--period" => period = config.fiscal.parse_period(next_value(&mut iter, arg)?)?,
+            "--day" | "--week" | "--month" => {
+                // 日付は省略でき、省略したときは今日を含む期間
+                let mut date = now.date_naive();
+                if let Some(next) = iter.as_slice().first().filter(|s| !s.starts_with('-')) {
+                    date = parse_anchor_date(next, now.date_naive(), arg == "--month")?;
+                    iter.next();
+                }
+                period = match arg.as_str() {
+                    "--day" => Period::day(date),
+                    "--week" => Period::week_of(date),
+                    _ => Period::month_of(date),
+                };
+            }
+            "--group-by" => options.group_by = GroupBy::parse(next_value(&mut iter, arg)?)?,
+            "--explain" => explain = Some(explain::Target::parse(next_value(&mut iter, arg)?)),
+            "--sparkline" => options.sparkline = true,
+            "--laps" => options.laps = true,
+            "--billable" => options.billable = true,
+            "--balance" => options.balance = true,
+            "--top" => options.top = Some(parse_number(next_value(&mut iter, arg)?)? as usize),
+            "--min-share" => {
+                options.min_share = Some(report::parse_share(next_value(&mut iter, arg)?)?)
+            }
+            "--with-plan" => with_plan = true,
+            "--compare-to-goal" => compare_to_goal = true,
+            "--no-cache" => use_cache = false,
+            "--all-history" => all_history = true,
+            "--uninvoiced" => uninvoiced = true,
+            "--all-projects" => all_projects = true,
+            "--host" => host = Some(next_value(&mut iter, arg)?),
+            "--field" => fields.push(record::parse_field(next_value(&mut iter, arg)?)?),
+            "--max-session" => {
+                options.anomaly_rules.max_session = parse_duration(next_value(&mut iter, arg)?)?
+            }
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    let period = Period::new(period.start, period.end)?;
+
+    apply_auto_stop(&file_path, config, now)?;
+    // 計測中は出力が刻々と変わるので、キャッシュを使わない
+    let since = history_since(config, all_history, Some(period.start), now);
+    let recorder = Recorder::new(RecordStore::new(&file_path).since(since), config);
+    let use_cache = use_cache
+        && !all_projects
+        && file_path != record::STDIN_PATH
+        && recorder.running()?.is_none();
+    let cache_key = use_cache.then(|| report_cache_key(&file_path, config, &remaining_args, now));
+    if let Some(output) = cache_key
+        .as_ref()
+        .and_then(|key| cache::load(&file_path, key))
+    {
+        print!("{}", output);
+        return Ok(());
+    }
+
+    let mut sessions = match all_projects {
+        true => project_sessions(config, since, now)?,
+        false => recorder.sessions()?,
+    };
+    if let Some(host) = host {
+        sessions.retain(|s| s.field(session::HOST_FIELD) == Some(host));
+    }
+    sessions.retain(|s| fields.iter().all(|(k, v)| s.field(k) == Some(v)));
+    // 請求対象のうち、まだどの請求書にも含めていない部分だけにする
+    if uninvoiced {
+        let range = Interval::new(period.start_time(), period.end_time());
+        let invoices = State::load(&file_path)?.invoices;
+        sessions = invoice::uninvoiced(&sessions, &invoices, &range, now);
+    }
+    let output = match explain {
+        // 所定時間と予算を目標に、達成具合を JSON で出す
+        _ if compare_to_goal => {
+            let days = goal::daily(&sessions, &period, &config.hours, now.date_naive(), now);
+            let budgets = budget_usages(&sessions, config, period.end, now);
+            goal::to_json(&period, &days, &budgets)
+        }
+        Some(target) => {
+            let rounding = config.rounding.report.as_ref();
+            explain::explain(&sessions, &period, options.group_by, &target, rounding, now)
+        }
+        None => {
+            if let Some(rounding) = &config.rounding.report {
+                rounding::round_sessions(&mut sessions, rounding);
+            }
+            let mut output = render_report(&sessions, &period, &options, now);
+            output += &budget::render(&budget_usages(&sessions, config, period.end, now));
+            if with_plan {
+                let plans = State::load(&file_path)?.plans;
+                output += &plan::render(&plan::compare(&plans, &sessions, &period, now));
+            }
+            output
+        }
+    };
+    print!("{}", output);
+    // キャッシュは速くするためだけのものなので、書けなくてもレポートは出す
+    if let Some(key) = cache_key {
+        if let Err(e) = cache::store(&file_path, &key, &output) {
+            eprintln!("Warning: could not write the report cache: {}", e);
+        }
+    }
+    Ok(())
+}
+
+// file のある全プロジェクトのセッション。プロジェクトの付いていないタスクは
+// "<プロジェクト>:<タスク>" にして、--group-by project や予算で見分けられるようにする。
+fn project_sessions(
+    config: &Config,
+    since: Option<DateTime<FixedOffset>>,
+    now: DateTime<FixedOffset>,
+) -> Result<Vec<session::Session>, RecorderError> {
+    let mut sessions = Vec::new();
+    for (project, path) in config.project_files() {
+        let path = path.to_str().ok_or(INVALID_PATH_MSG)?;
+        apply_auto_stop(path, config, now)?;
+        for mut session in RecordStore::new(path).since(since).file_sessions(config)? {
+            if session.project().is_none() {
+                session.task = format!("{}:{}", project, session.task);
+            }
+            sessions.push(session);
+        }
+    }
+    sessions.sort_by_key(|s| s.start);
+    Ok(sessions)
 }
 
-fn write_to_file(file_path: &str, content: &str) -> Result<(), String> {
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(file_path)
-        .map_err(|e| e.to_string())?;
-    file.write_all(content.as_bytes())
-        .map_err(|e| e.to_string())
+// 読むファイル (記録・sources・設定・状態) の長さと更新時刻、引数、今日の日付、
+// 表示に使うタイムゾーンから作るキー。--utc は引数から外れているので TZ と今のオフセットで見分ける。
+fn report_cache_key(
+    file_path: &str,
+    config: &Config,
+    args: &[String],
+    now: DateTime<FixedOffset>,
+) -> String {
+    let mut files: Vec<PathBuf> = sources::origins(file_path, config)
+        .into_iter()
+        .map(|(_, path)| path)
+        .collect();
+    files.extend(config::config_path());
+    files.push(PathBuf::from(state::state_path(file_path)));
+    let today = now.date_naive().to_string();
+    let timezone = format!("{} {}", env::var("TZ").unwrap_or_default(), now.offset());
+    let mut inputs: Vec<&str> = args.iter().map(String::as_str).collect();
+    inputs.push(&today);
+    inputs.push(&timezone);
+    cache::key(files.iter().map(PathBuf::as_path), &inputs)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
+fn handle_compare_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let mut a = None;
+    let mut b = None;
+    let mut group_by = GroupBy::default();
+    let mut iter = remaining_args.iter();
 
-    fn setup_test_file() -> String {
-        let test_file = "test_working_time_record.txt";
-        if fs::metadata(test_file).is_ok() {
-            fs::remove_file(test_file).unwrap();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--a" => a = Some(next_value(&mut iter, arg)?),
+            "--b" => b = Some(next_value(&mut iter, arg)?),
+            "--group-by" => group_by = GroupBy::parse(next_value(&mut iter, arg)?)?,
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
         }
-        test_file.to_string()
     }
+    let a = a.ok_or(PERIODS_NOT_PROVIDED_MSG)?;
+    let b = b.ok_or(PERIODS_NOT_PROVIDED_MSG)?;
 
-    #[test]
-    fn test_execute_empty_args() {
-        let args = vec!["program_name".to_string()];
-        let result = execute(&args);
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), "No subcommand provided.");
+    let now = get_current_time();
+    apply_auto_stop(&file_path, config, now)?;
+    let mut sessions = RecordStore::new(&file_path).sessions(config)?;
+    if let Some(rounding) = &config.rounding.report {
+        rounding::round_sessions(&mut sessions, rounding);
     }
+    let rows = compare::compare(
+        &sessions,
+        &config.fiscal.parse_period(a)?,
+        &config.fiscal.parse_period(b)?,
+        group_by,
+        now,
+    );
+    print!("{}", compare::render_compare(&rows, a, b));
+    Ok(())
+}
 
-    #[test]
-    fn test_execute_help() {
-        let args = vec!["program_name".to_string(), "help".to_string()];
-        assert!(execute(&args).is_ok());
+fn handle_stats_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let now = get_current_time();
+    let mut period = Period::month_to_date(now.date_naive());
+    let mut per_hour = false;
+    let mut focus = false;
+    let mut all_history = false;
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--from" => period.start = parse_date(next_value(&mut iter, arg)?)?,
+            "--to" => period.end = parse_date(next_value(&mut iter, arg)?)?,
+            "--period" => period = config.fiscal.parse_period(next_value(&mut iter, arg)?)?,
+            "--per-hour" => per_hour = true,
+            "--focus" => focus = true,
+            "--all-history" => all_history = true,
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
     }
+    if !per_hour && !focus {
+        return Err(STATS_MODE_NOT_PROVIDED_MSG.into());
+    }
+    let period = Period::new(period.start, period.end)?;
 
-    #[test]
-    fn test_execute_invalid_command() {
-        let args = vec!["program_name".to_string(), "invalid".to_string()];
-        let result = execute(&args);
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), "Invalid subcommand 'invalid'.");
+    apply_auto_stop(&file_path, config, now)?;
+    let since = history_since(config, all_history, Some(period.start), now);
+    let sessions = RecordStore::new(&file_path).since(since).sessions(config)?;
+    println!("{} - {}", period.start, period.end);
+    if per_hour {
+        print!(
+            "{}",
+            stats::render_per_hour(
+                &stats::per_hour(&sessions, &period, now),
+                config.locale.unwrap_or_default(),
+            )
+        );
+    }
+    if focus {
+        if per_hour {
+            println!();
+        }
+        print!(
+            "{}",
+            stats::render_focus(&stats::focus(&sessions, &period, now))
+        );
     }
+    Ok(())
+}
 
-    #[test]
-    fn test_handle_start_command() {
-        let test_file = setup_test_file();
-        let args = vec![
-            "program_name".to_string(),
-            "start".to_string(),
-            "test_task".to_string(),
-            "-f".to_string(),
-            test_file.clone(),
-        ];
-        assert!(handle_start_command(&args).is_ok());
-        let content = fs::read_to_string(&test_file).unwrap();
-        assert!(content.contains("start\ttest_task"));
-        fs::remove_file(test_file).unwrap();
+// 直近のセッションを表で並べる。期間を指定したときは、その期間のものをすべて。
+fn handle_log_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let now = get_current_time();
+    let mut count = None;
+    let mut period = None;
+    let mut all_history = false;
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-n" => count = Some(parse_number(next_value(&mut iter, arg)?)? as usize),
+            "--today" => period = Some(Period::day(now.date_naive())),
+            "--week" => period = Some(Period::week_of(now.date_naive())),
+            "--all-history" => all_history = true,
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
     }
 
-    #[test]
-    fn test_handle_start_command_missing_task_name() {
-        let test_file = setup_test_file();
-        let args = vec![
-            "program_name".to_string(),
-            "start".to_string(),
-            "-f".to_string(),
-            test_file.clone(),
-        ];
-        let result = handle_start_command(&args);
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), TASK_NAME_NOT_PROVIDED_MSG);
+    apply_auto_stop(&file_path, config, now)?;
+    let since = history_since(config, all_history, period.as_ref().map(|p| p.start), now);
+    let mut sessions = RecordStore::new(&file_path).since(since).sessions(config)?;
+    if let Some(period) = &period {
+        let (from, to) = (period.start_time(), period.end_time());
+        sessions.retain(|s| !s.overlap(from, to, now).is_zero());
     }
+    let count = match period {
+        Some(_) => count.unwrap_or(sessions.len()),
+        None => count.unwrap_or(DEFAULT_LOG_COUNT),
+    };
+    let sessions = &sessions[sessions.len().saturating_sub(count)..];
+    print!("{}", log::render(sessions, config.time_format(), now));
+    Ok(())
+}
 
-    #[test]
-    fn test_handle_stop_command() {
-        let test_file = setup_test_file();
-        let args = vec![
-            "program_name".to_string(),
-            "stop".to_string(),
-            "-f".to_string(),
-            test_file.clone(),
-        ];
-        assert!(handle_stop_command(&args).is_ok());
-        let content = fs::read_to_string(&test_file).unwrap();
-        assert!(content.contains("stop"));
-        fs::remove_file(test_file).unwrap();
+fn handle_timeline_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let now = get_current_time();
+    let mut date = now.date_naive();
+    let mut week = false;
+    let mut all_history = false;
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--week" => week = true,
+            "--date" => date = parse_date(next_value(&mut iter, arg)?)?,
+            "--all-history" => all_history = true,
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    let period = if week {
+        Period::week_of(date)
+    } else {
+        Period::day(date)
+    };
+
+    apply_auto_stop(&file_path, config, now)?;
+    let since = history_since(config, all_history, Some(period.start), now);
+    let sessions = RecordStore::new(&file_path).since(since).sessions(config)?;
+    let color = io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none();
+    print!(
+        "{}",
+        timeline::render(
+            &sessions,
+            &period,
+            now,
+            color,
+            config.locale.unwrap_or_default()
+        )
+    );
+    Ok(())
+}
+
+fn handle_digest_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let now = get_current_time();
+    let mut period = Period::week_of(now.date_naive());
+    let mut output = None;
+    let mut all_history = false;
+    let mut rules = anomaly::AnomalyRules {
+        hours: config.hours,
+        ..Default::default()
+    };
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--week" => period = Period::week_of(now.date_naive()),
+            "--period" => period = config.fiscal.parse_period(next_value(&mut iter, arg)?)?,
+            "--max-session" => rules.max_session = parse_duration(next_value(&mut iter, arg)?)?,
+            "-o" | "--output" => output = Some(next_value(&mut iter, arg)?),
+            "--all-history" => all_history = true,
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+
+    apply_auto_stop(&file_path, config, now)?;
+    let since = history_since(config, all_history, Some(period.start), now);
+    let mut sessions = RecordStore::new(&file_path).since(since).sessions(config)?;
+    if let Some(rounding) = &config.rounding.report {
+        rounding::round_sessions(&mut sessions, rounding);
+    }
+    write_output(
+        output,
+        &digest::render_html(
+            &sessions,
+            &period,
+            &rules,
+            config.locale.unwrap_or_default(),
+            now,
+        ),
+    )
+}
+
+fn handle_fill_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let now = get_current_time();
+    let today = now.date_naive();
+    let mut period = Period {
+        start: today,
+        end: today,
+    };
+    let mut dry_run = false;
+    let mut force_unlock = false;
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--from" => period.start = parse_date(next_value(&mut iter, arg)?)?,
+            "--to" => period.end = parse_date(next_value(&mut iter, arg)?)?,
+            "--dry-run" => dry_run = true,
+            "--force-unlock" => force_unlock = true,
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    let period = Period::new(period.start, period.end)?;
+
+    let _lock = RecordStore::new(&file_path).lock()?;
+    apply_auto_stop(&file_path, config, now)?;
+    let records = RecordStore::new(&file_path).records(config)?;
+    let fills = recurring::fill(&config.recurring, &period, &pair_sessions(&records), now);
+
+    let describe = |session: &session::Session| {
+        format!(
+            "{} {} - {}",
+            session.task,
+            config.format_datetime(session.start),
+            config.format_time(session.end_or(now))
+        )
+    };
+    let mut added = Vec::new();
+    for fill in &fills {
+        match fill {
+            Fill::Added(session) => {
+                let label = if dry_run { "Would add" } else { "Added" };
+                println!("{} {}", label, describe(session));
+                added.extend(recurring::to_records(session));
+            }
+            Fill::Overlapping(session) => {
+                println!("Skipped {} (overlaps tracked time)", describe(session))
+            }
+        }
+    }
+
+    if dry_run || added.is_empty() {
+        return Ok(());
+    }
+    let added_times = fills.iter().filter_map(|fill| match fill {
+        Fill::Added(session) => Some(session.start),
+        Fill::Overlapping(_) => None,
+    });
+    Recorder::new(RecordStore::new(&file_path), config)
+        .force_unlock(force_unlock)
+        .check_unlocked(added_times)?;
+    // 足したセッションだけを時刻順の位置に差し込み、ほかの行は書き直さない
+    RecordStore::new(&file_path).insert(&added)?;
+    Ok(())
+}
+
+fn handle_prune_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let mut older_than = None;
+    let mut project = None;
+    let mut archive = None;
+    let mut dry_run = false;
+    let mut force_unlock = false;
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--force-unlock" => force_unlock = true,
+            "--older-than" => older_than = Some(parse_duration(next_value(&mut iter, arg)?)?),
+            "--project" => project = Some(next_value(&mut iter, arg)?),
+            "--archive" => archive = Some(next_value(&mut iter, arg)?),
+            "--dry-run" => dry_run = true,
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+
+    let older_than = older_than
+        .or(config.retention)
+        .ok_or(RETENTION_NOT_PROVIDED_MSG)?;
+    let now = get_current_time();
+    let _lock = RecordStore::new(&file_path).lock()?;
+    apply_auto_stop(&file_path, config, now)?;
+    // 消す行の位置がわかるように読み、残す行は書き直さない
+    let tail = RecordStore::new(&file_path).tail(config, usize::MAX)?;
+    let prunable = prune::prunable(&tail.records, now - older_than, project);
+    let (removed, offsets): (Vec<Record>, Vec<u64>) = tail
+        .records
+        .into_iter()
+        .zip(tail.offsets)
+        .zip(prunable)
+        .filter(|(_, remove)| *remove)
+        .map(|(entry, _)| entry)
+        .unzip();
+
+    let (Some(first), Some(last)) = (removed.first(), removed.last()) else {
+        println!("Nothing to prune.");
+        return Ok(());
+    };
+    let label = if dry_run { "Would remove" } else { "Removed" };
+    println!(
+        "{} {} records from {} to {}.",
+        label,
+        removed.len(),
+        first.timestamp.format("%Y-%m-%d"),
+        last.timestamp.format("%Y-%m-%d")
+    );
+    if dry_run {
+        return Ok(());
+    }
+    Recorder::new(RecordStore::new(&file_path), config)
+        .force_unlock(force_unlock)
+        .check_unlocked(removed.iter().map(|r| r.timestamp))?;
+
+    if let Some(archive) = archive {
+        RecordStore::new(archive).append(&removed)?;
+    }
+    RecordStore::new(&file_path).remove(&offsets)?;
+    Ok(())
+}
+
+fn handle_export_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let mut anonymize = false;
+    let mut salt = "";
+    let mut stamp = false;
+    let mut output = None;
+    let mut format = export::Format::Records;
+    let mut fields = None;
+    let mut locale = None;
+    let mut from = None;
+    let mut to = None;
+    let mut week = None;
+    let mut month = None;
+    let mut markdown = false;
+    let today = get_current_time().date_naive();
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--anonymize" => anonymize = true,
+            "--week" => {
+                // 日付は省略でき、省略したときは今週
+                let mut date = today;
+                if let Some(next) = iter.as_slice().first().filter(|s| !s.starts_with('-')) {
+                    date = parse_anchor_date(next, today, false)?;
+                    iter.next();
+                }
+                week = Some(Period::week_of(date));
+            }
+            "--month" => {
+                let mut date = today;
+                if let Some(next) = iter.as_slice().first().filter(|s| !s.starts_with('-')) {
+                    date = parse_anchor_date(next, today, true)?;
+                    iter.next();
+                }
+                month = Some(Period::month_of(date));
+            }
+            "--markdown" => markdown = true,
+            "--format" => {
+                let value = next_value(&mut iter, arg)?;
+                format = match value {
+                    "records" => export::Format::Records,
+                    _ => export::Format::parse(value)
+                        .ok_or_else(|| format!("Invalid format '{}'.", value))?,
+                };
+            }
+            "--from" => from = Some(parse_date(next_value(&mut iter, arg)?)?),
+            "--to" => to = Some(parse_date(next_value(&mut iter, arg)?)?),
+            "--locale" => locale = Some(export::Locale::parse(next_value(&mut iter, arg)?)?),
+            "--stamp" => stamp = true,
+            "--salt" => salt = next_value(&mut iter, arg)?,
+            "--fields" => fields = Some(export::parse_fields(next_value(&mut iter, arg)?)?),
+            "-o" | "--output" => output = Some(next_value(&mut iter, arg)?),
+            _ => match export::Format::parse(arg) {
+                Some(parsed) if format == export::Format::Records => format = parsed,
+                _ => return Err(format!("Invalid option '{}'.", arg).into()),
+            },
+        }
+    }
+    let has_fields = matches!(
+        format,
+        export::Format::Csv | export::Format::Json | export::Format::Jsonl
+    );
+    if !has_fields && fields.is_some() {
+        return Err("--fields needs the csv, json or jsonl format.".into());
+    }
+    let is_grid = matches!(format, export::Format::Grid | export::Format::Pdf);
+    if (!is_grid && week.is_some()) || (format != export::Format::Grid && markdown) {
+        return Err("--week and --markdown need the grid format.".into());
+    }
+    if !is_grid && month.is_some() {
+        return Err("--month needs the grid or pdf format.".into());
+    }
+    if let (Some(from), Some(to)) = (from, to) {
+        Period::new(from, to)?;
+    }
+    // 表の列にする日。既定は今週 (pdf は今月) で、--from / --to で変えられる
+    let mut grid_period = week.or(month).unwrap_or(match format {
+        export::Format::Pdf => Period::month_of(today),
+        _ => Period::week_of(today),
+    });
+    grid_period.start = from.unwrap_or(grid_period.start);
+    grid_period.end = to.unwrap_or(grid_period.end);
+    // 日付の指定が無い側は制限しない
+    let from = from.map(period::local_midnight);
+    let to = to.map(|date| period::local_midnight(date + Duration::days(1)));
+    let in_range = |start: DateTime<FixedOffset>, end: DateTime<FixedOffset>| {
+        from.is_none_or(|from| end > from) && to.is_none_or(|to| start < to)
+    };
+    let is_csv = format == export::Format::Csv || (format == export::Format::Grid && !markdown);
+    if !is_csv && locale.is_some() {
+        return Err("--locale needs the csv format.".into());
+    }
+    if format != export::Format::Records && stamp {
+        return Err("--stamp only applies to the record format.".into());
+    }
+
+    let now = get_current_time();
+    apply_auto_stop(&file_path, config, now)?;
+    let mut records = RecordStore::new(&file_path).all_records(config)?;
+    if anonymize {
+        records = records.iter().map(|r| export::anonymize(r, salt)).collect();
+    }
+    if format != export::Format::Records {
+        // ファイルをまたいで start/stop を組にしないよう、origin ごとにセッションを組む
+        let mut by_origin: BTreeMap<Option<&str>, Vec<Record>> = BTreeMap::new();
+        for record in &records {
+            let origin = record::field(&record.fields, sources::ORIGIN_FIELD);
+            by_origin.entry(origin).or_default().push(record.clone());
+        }
+        let mut sessions: Vec<session::Session> =
+            by_origin.values().flat_map(|r| pair_sessions(r)).collect();
+        sessions.retain(|s| in_range(s.start, s.end_or(now)));
+        sessions.sort_by_key(|s| s.start);
+        let fields = fields.unwrap_or(export::DEFAULT_FIELDS.to_vec());
+        let rate_of = |s: &session::Session| config.rate(&s.task);
+        let content = match format {
+            export::Format::Csv => export::to_csv(
+                &sessions,
+                &fields,
+                locale.or(config.locale).unwrap_or_default(),
+                rate_of,
+                now,
+            ),
+            export::Format::Json => export::to_json(&sessions, &fields, rate_of, now),
+            export::Format::Jsonl => export::to_jsonl(&sessions, &fields, rate_of, now),
+            export::Format::Timeclock => ledger::to_timeclock(&sessions),
+            export::Format::Timedot => ledger::to_timedot(&sessions, now),
+            _ => {
+                let grid = export::Grid::new(
+                    &sessions,
+                    &Period::new(grid_period.start, grid_period.end)?,
+                    now,
+                );
+                if format == export::Format::Pdf {
+                    let title = format!("Timesheet {} - {}", grid_period.start, grid_period.end);
+                    let (pdf, unsupported) = grid.to_pdf(&title);
+                    for text in unsupported {
+                        eprintln!(
+                            "Warning: '{}' cannot be written with the PDF font; \
+                             unsupported characters became '?'.",
+                            text
+                        );
+                    }
+                    pdf
+                } else if markdown {
+                    grid.to_markdown()
+                } else {
+                    grid.to_csv(locale.or(config.locale).unwrap_or_default())
+                }
+            }
+        };
+        return write_output(output, &content);
+    }
+
+    let mut content = String::new();
+    if stamp {
+        for lock in State::load(&file_path)?.locks {
+            content += &format!(
+                "# {} submitted on {}\n",
+                lock.period,
+                lock.submitted_at.to_rfc3339()
+            );
+        }
+    }
+    content += &records
+        .iter()
+        .filter(|r| from.is_none_or(|from| r.timestamp >= from))
+        .filter(|r| to.is_none_or(|to| r.timestamp < to))
+        .map(Record::to_line)
+        .collect::<String>();
+    write_output(output, &content)
+}
+
+fn handle_dump_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let mut output = None;
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(next_value(&mut iter, arg)?),
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+
+    let config = match config::config_path() {
+        Some(path) => match fs::read_to_string(path) {
+            Ok(content) => Some(content),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(RecorderError::Io(e.to_string())),
+        },
+        None => None,
+    };
+    let store = dump::Store {
+        content: record::read_content(&file_path)?,
+        state: State::load(&file_path)?,
+        config,
+    };
+    write_output(output, &dump::dump(&store, get_current_time())?)
+}
+
+// 既存の記録は --replace が無ければ上書きしない。設定ファイルは --with-config のときだけ書く。
+fn handle_load_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let mut input = None;
+    let mut replace = false;
+    let mut with_config = false;
+
+    for arg in &remaining_args {
+        match arg.as_str() {
+            "--replace" => replace = true,
+            "--with-config" => with_config = true,
+            _ if input.is_none() => input = Some(arg.as_str()),
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    let input = input.ok_or(DUMP_NOT_PROVIDED_MSG)?;
+
+    let store = dump::load(&record::read_content(input)?)?;
+    record::ensure_writable(&file_path)?;
+    let _lock = RecordStore::new(&file_path).lock()?;
+    let existing = record::read_content(&file_path)?;
+    if !replace && !existing.trim().is_empty() {
+        return Err(format!(
+            "'{}' already has records. Use --replace to overwrite them.",
+            file_path
+        )
+        .into());
+    }
+    record::write_content(&file_path, &store.content)?;
+    store.state.save(&file_path)?;
+    println!(
+        "Loaded {} records into {}.",
+        read_records(&file_path)?.len(),
+        file_path
+    );
+    if with_config {
+        let config_path = config::config_path().ok_or(CONFIG_DIR_NOT_FOUND_MSG)?;
+        let content = store.config.unwrap_or_default();
+        Config::parse(&content)?;
+        if let Some(dir) = config_path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        fs::write(&config_path, content).map_err(|e| e.to_string())?;
+        println!("Wrote config to {}.", config_path.display());
+    }
+    Ok(())
+}
+
+// 記録ファイルを別の書式で書き直す。以降の追記や書き直しもその書式になる。
+fn handle_convert_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let mut format = None;
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--format" => format = Some(RecordFormat::parse(next_value(&mut iter, arg)?)?),
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    let format = format.ok_or(FORMAT_NOT_PROVIDED_MSG)?;
+
+    let store = RecordStore::new(&file_path);
+    let _lock = store.lock()?;
+    let records = read_records(&file_path)?;
+    record::ensure_representable(&records, format)?;
+    // 先頭に書式の印を置き、レコードがまだ無いファイルでも以降の追記をこの書式にする
+    let content: String = records.iter().map(|r| r.to_line_as(format)).collect();
+    record::write_content(&file_path, &(format.marker() + &content))?;
+    cache::invalidate(&file_path)?;
+    println!(
+        "Converted {} records in {} to {}.",
+        records.len(),
+        file_path,
+        format.as_str()
+    );
+    Ok(())
+}
+
+fn handle_config_command(
+    args: &[String],
+    config: Result<&Config, &String>,
+) -> Result<(), RecorderError> {
+    let config_path = config::config_path().ok_or(CONFIG_DIR_NOT_FOUND_MSG)?;
+    let content = match fs::read_to_string(&config_path) {
+        Ok(content) => Some(content),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(RecorderError::Io(e.to_string())),
+    };
+    // set は設定を読まずに書く。壊れた設定ファイルも直せるように。
+    if let [_, _, "set", key, value] = args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        let updated = config::set_value(&content.unwrap_or_default(), key, value)?;
+        if let Some(dir) = config_path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        fs::write(&config_path, updated).map_err(|e| e.to_string())?;
+        println!("Set {} in {}.", key, config_path.display());
+        return Ok(());
+    }
+    let config = config.map_err(|e| RecorderError::Other(e.clone()))?;
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let subcommand: Vec<&str> = remaining_args.iter().map(String::as_str).collect();
+
+    match subcommand.as_slice() {
+        ["show"] => {
+            let config_source = if env::var(config::CONFIG_ENV).is_ok() {
+                format!("env {}", config::CONFIG_ENV)
+            } else {
+                "default".to_string()
+            };
+            let missing = if content.is_none() { ", not found" } else { "" };
+            let record_source = if args.iter().any(|a| a == "-f" || a == "--file") {
+                "flag -f".to_string()
+            } else if args.iter().any(|a| a == "-p" || a == "--project") {
+                "flag -p".to_string()
+            } else if env::var(RECORD_ENV).is_ok() {
+                format!("env {}", RECORD_ENV)
+            } else if sources::primary_path(config).is_some() {
+                "config file, primary source".to_string()
+            } else {
+                "default".to_string()
+            };
+            println!(
+                "config file  {}  ({}{})",
+                config_path.display(),
+                config_source,
+                missing
+            );
+            println!("record file  {}  ({})", file_path, record_source);
+            println!("state file   {}", state::state_path(&file_path));
+            for source in &config.sources {
+                println!("source       {}  ({})", source.path.display(), source.name);
+            }
+            println!();
+            for setting in config::describe(&content.unwrap_or_default())? {
+                match setting.value {
+                    Some(value) => println!("{} = {}  # {}", setting.key, value, setting.source),
+                    None => println!("{}  # not set", setting.key),
+                }
+            }
+            Ok(())
+        }
+        _ => Err(CONFIG_USAGE_MSG.into()),
+    }
+}
+
+fn handle_import_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let mut input = None;
+    let mut delimiter = None;
+    let mut date_format = None;
+    let mut dry_run = false;
+    let mut force_unlock = false;
+    let mut overlap = OverlapMode::Reject;
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--delimiter" => delimiter = Some(parse_delimiter(next_value(&mut iter, arg)?)?),
+            "--date-format" => date_format = Some(next_value(&mut iter, arg)?),
+            "--dry-run" => dry_run = true,
+            "--force-unlock" => force_unlock = true,
+            "--allow-overlap" => overlap = OverlapMode::Allow,
+            "--clip" => overlap = OverlapMode::Clip,
+            _ if input.is_none() => input = Some(arg.as_str()),
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    let input = input.ok_or(FILENAME_NOT_PROVIDED_MSG)?;
+    let content = fs::read_to_string(input).map_err(|e| format!("{}: {}", input, e))?;
+    let delimiter = delimiter.unwrap_or_else(|| csv::detect_delimiter(&content));
+    let rows = csv::parse(&content, delimiter)?;
+    let imported = import::to_records(&rows, date_format)?;
+
+    let now = get_current_time();
+    let _lock = RecordStore::new(&file_path).lock()?;
+    apply_auto_stop(&file_path, config, now)?;
+    let records = RecordStore::new(&file_path).records(config)?;
+    // 同じタスクを同じ時刻に始めたものや、前に同じ取り込み元から取り込んだものは飛ばす。
+    // 取り込んだ後で記録を直していても、取り込み直しで元に戻らない。
+    let mut state = State::load(&file_path)?;
+    let source = fs::canonicalize(input)
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|_| input.to_string());
+    let mut added = Vec::new();
+    let mut skipped = 0;
+    // 記録済みのものと、先に取り込む行との重なりを確かめる
+    let mut busy = pair_sessions(&records);
+    for pair in imported.chunks(2) {
+        let duplicate = records.iter().any(|r| {
+            r.event == Event::Start && r.timestamp == pair[0].timestamp && r.task == pair[0].task
+        }) || pair.iter().all(|r| state.is_seen(&source, r));
+        if duplicate {
+            skipped += 1;
+            continue;
+        }
+        let (start, stop) = (&pair[0], &pair[1]);
+        let interval = Interval::new(start.timestamp, stop.timestamp);
+        for free in session::resolve_overlap(&busy, &start.task, interval, overlap, config, now)? {
+            let (mut start, mut stop) = (start.clone(), stop.clone());
+            (start.timestamp, stop.timestamp) = (free.start, free.end);
+            busy.push(session::Session::new(
+                &start.task,
+                free.start,
+                Some(free.end),
+            ));
+            added.extend([start, stop]);
+        }
+    }
+    let label = if dry_run { "Would import" } else { "Imported" };
+    println!(
+        "{} {} sessions ({} already recorded).",
+        label,
+        added.len() / 2,
+        skipped
+    );
+    if dry_run {
+        return Ok(());
+    }
+    Recorder::new(RecordStore::new(&file_path), config)
+        .force_unlock(force_unlock)
+        .check_unlocked(added.iter().map(|r| r.timestamp))?;
+    // 取り込んだレコードだけを時刻順の位置に差し込み、記録済みの行は書き直さない
+    if !added.is_empty() {
+        RecordStore::new(&file_path).insert(&added)?;
+    }
+    state.mark_seen(&source, imported.iter());
+    Ok(state.save(&file_path)?)
+}
+
+fn parse_delimiter(s: &str) -> Result<char, RecorderError> {
+    match s {
+        "\\t" | "tab" => Ok('\t'),
+        _ => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(c),
+                _ => Err(format!("Invalid delimiter '{}'.", s).into()),
+            }
+        }
+    }
+}
+
+fn handle_plan_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let mut date = get_current_time().date_naive();
+    let mut positional = Vec::new();
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--date" => date = parse_date(next_value(&mut iter, arg)?)?,
+            _ => positional.push(arg.as_str()),
+        }
+    }
+
+    let mut state = State::load(&file_path)?;
+    match positional.as_slice() {
+        ["add", range, task_name] => {
+            let plan = plan::Plan::parse(range, task_name, date)?;
+            println!("Planned {}.", plan.describe());
+            state.plans.push(plan);
+            state.plans.sort_by_key(|plan| plan.start);
+        }
+        ["list"] => {
+            for plan in state.plans.iter().filter(|plan| plan.date() == date) {
+                println!("{}", plan.describe());
+            }
+            return Ok(());
+        }
+        ["clear"] => {
+            let before = state.plans.len();
+            state.plans.retain(|plan| plan.date() != date);
+            println!("Removed {} plans for {}.", before - state.plans.len(), date);
+        }
+        _ => return Err(PLAN_USAGE_MSG.into()),
+    }
+    record::ensure_writable(&file_path)?;
+    Ok(state.save(&file_path)?)
+}
+
+// 判定は提出後に付けるものなので、ロックした期間でも書き込める
+fn handle_approve_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let now = get_current_time();
+    let mut ids = None;
+    let mut review = approval::Review::Approved;
+    let mut reviewer = None;
+    let mut list = false;
+    let mut period = Period::month_to_date(now.date_naive());
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--reject" => review = approval::Review::Rejected,
+            "--by" => reviewer = Some(next_value(&mut iter, arg)?.to_string()),
+            "--list" => list = true,
+            "--period" => period = config.fiscal.parse_period(next_value(&mut iter, arg)?)?,
+            _ if ids.is_none() => ids = Some(approval::parse_id_range(arg)?),
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+
+    let _lock = RecordStore::new(&file_path).lock()?;
+    // 書き換える行の位置がわかるように読む。時刻の精度は記録したままにする。
+    let tail = record::read_tail(&file_path, usize::MAX)?;
+    let mut records = tail.records.clone();
+    if list {
+        let (from, to) = (period.start_time(), period.end_time());
+        for (id, session) in pair_sessions(&records).iter().enumerate() {
+            if !session.overlap(from, to, now).is_zero() {
+                println!("{}", approval::describe_entry(id + 1, session));
+            }
+        }
+        return Ok(());
+    }
+    let ids = ids.ok_or(ID_RANGE_NOT_PROVIDED_MSG)?;
+    let reviewer = reviewer
+        .or_else(|| env::var("USER").ok())
+        .or_else(|| env::var("USERNAME").ok())
+        .ok_or(REVIEWER_NOT_PROVIDED_MSG)?;
+    approval::annotate(&mut records, &ids, review, &reviewer, now)?;
+    // 印を付けた行だけを差し替え、ほかの行は書き直さない
+    let changed: Vec<(u64, Record)> = tail
+        .offsets
+        .iter()
+        .zip(&tail.records)
+        .zip(records)
+        .filter(|((_, before), after)| *before != after)
+        .map(|((offset, _), after)| (*offset, after))
+        .collect();
+    let offsets: Vec<u64> = changed.iter().map(|(offset, _)| *offset).collect();
+    RecordStore::new(&file_path).edit(&offsets, &changed)?;
+    println!(
+        "Marked {} entries as {} by {}.",
+        ids.len(),
+        review.as_str(),
+        reviewer
+    );
+    Ok(())
+}
+
+// -f / 環境変数 / 設定ファイルで決まる実際の場所を開く
+fn handle_open_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let target = match remaining_args.as_slice() {
+        [] => open::Target::Record,
+        [target] => open::Target::parse(target)?,
+        [_, arg, ..] => return Err(format!("Invalid option '{}'.", arg).into()),
+    };
+    record::ensure_writable(&file_path)?;
+
+    let path = match target {
+        open::Target::Record => PathBuf::from(&file_path),
+        open::Target::Config => config::config_path().ok_or(CONFIG_DIR_NOT_FOUND_MSG)?,
+        open::Target::DataDir => match Path::new(&file_path).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        },
+    };
+    let is_dir = target == open::Target::DataDir;
+    let editor = env::var("VISUAL")
+        .ok()
+        .or_else(|| env::var("EDITOR").ok())
+        .filter(|editor| !editor.trim().is_empty());
+    let command = open::command(&path, is_dir, editor.as_deref());
+    println!("Opening {}.", path.display());
+    let status = Command::new(&command[0])
+        .args(&command[1..])
+        .status()
+        .map_err(|e| format!("Failed to run '{}': {}", command[0], e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("'{}' exited with {}.", command[0], status).into())
+    }
+}
+
+fn handle_lock_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let mut label = None;
+    let mut list = false;
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--period" => label = Some(next_value(&mut iter, arg)?),
+            "--list" => list = true,
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+
+    let mut state = State::load(&file_path)?;
+    if list {
+        for lock in &state.locks {
+            println!(
+                "{} ({} - {}) submitted on {}",
+                lock.period,
+                lock.start,
+                lock.end,
+                config.format_datetime(lock.submitted_at)
+            );
+        }
+        return Ok(());
+    }
+    let label = label.ok_or(PERIOD_NOT_PROVIDED_MSG)?;
+    let period = config.fiscal.parse_period(label)?;
+    state.lock(label, &period, get_current_time());
+    state.save(&file_path)?;
+    println!("Locked {} ({} - {}).", label, period.start, period.end);
+    Ok(())
+}
+
+// 月の締め: 検証、記録の無い平日の確認、ロック、エクスポートをまとめて行う
+fn handle_close_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let mut label = None;
+    let mut output_dir = None;
+    let mut force = false;
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--month" => label = Some(next_value(&mut iter, arg)?),
+            "-o" | "--output-dir" => output_dir = Some(next_value(&mut iter, arg)?),
+            "--force" => force = true,
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    let label = label.ok_or(MONTH_NOT_PROVIDED_MSG)?;
+
+    let period = config.fiscal.parse_period(label)?;
+    let now = get_current_time();
+    apply_auto_stop(&file_path, config, now)?;
+
+    let content = record::read_content(&file_path)?;
+    let issues = policy::lint(config, &content);
+    for (line, issue) in &issues {
+        println!("line {}: {}", line, issue);
+    }
+    println!(
+        "[{}] Validation: {} issue(s)",
+        mark(issues.is_empty()),
+        issues.len()
+    );
+
+    let mut sessions = RecordStore::new(&file_path).sessions(config)?;
+    sessions.retain(|s| {
+        !s.overlap(period.start_time(), period.end_time(), now)
+            .is_zero()
+    });
+    let untracked =
+        close::untracked_workdays(&sessions, &period, &config.hours, now.date_naive(), now);
+    println!(
+        "[{}] Untracked workdays: {}",
+        mark(untracked.is_empty()),
+        if untracked.is_empty() {
+            "none".to_string()
+        } else {
+            untracked
+                .iter()
+                .map(|d| d.format("%m-%d").to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+    );
+    let running = sessions.iter().any(|s| s.stop.is_none());
+    println!(
+        "[{}] Running task: {}",
+        mark(!running),
+        if running { "yes" } else { "no" }
+    );
+    if (!issues.is_empty() || !untracked.is_empty() || running) && !force {
+        return Err(format!(
+            "{} is not ready to close. Fix the items above or use --force.",
+            label
+        )
+        .into());
+    }
+
+    let mut state = State::load(&file_path)?;
+    state.lock(label, &period, now);
+    state.save(&file_path)?;
+    println!("[x] Locked {} ({} - {})", label, period.start, period.end);
+
+    let default_dir = format!("close-{}", label);
+    let dir = Path::new(output_dir.unwrap_or(&default_dir));
+    fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let exports = match config.close_exports.is_empty() {
+        true => close::DEFAULT_EXPORTS.to_vec(),
+        false => config.close_exports.clone(),
+    };
+    for export in exports {
+        let path = dir.join(export.file_name());
+        let content = export.render(&sessions, |s| config.rate(&s.task), now);
+        fs::write(&path, content).map_err(|e| format!("{}: {}", path.display(), e))?;
+        println!("[x] Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+// 請求対象でまだ請求していない時間を CSV にする。--finalize で請求書として状態ファイルに残す。
+fn handle_invoice_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let now = get_current_time();
+    let mut period = Period::month_to_date(now.date_naive());
+    let mut output = None;
+    let mut finalize = false;
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--from" => period.start = parse_date(next_value(&mut iter, arg)?)?,
+            "--to" => period.end = parse_date(next_value(&mut iter, arg)?)?,
+            "--period" => period = config.fiscal.parse_period(next_value(&mut iter, arg)?)?,
+            "-o" | "--output" => output = Some(next_value(&mut iter, arg)?),
+            "--finalize" => finalize = true,
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    let period = Period::new(period.start, period.end)?;
+
+    let _lock = RecordStore::new(&file_path).lock()?;
+    apply_auto_stop(&file_path, config, now)?;
+    let sessions = RecordStore::new(&file_path).sessions(config)?;
+    let mut state = State::load(&file_path)?;
+    let range = Interval::new(period.start_time(), period.end_time());
+    let parts = invoice::uninvoiced(&sessions, &state.invoices, &range, now);
+    if parts.is_empty() {
+        return Err(format!(
+            "No uninvoiced billable time in {} - {}.",
+            period.start, period.end
+        )
+        .into());
+    }
+    let csv = CloseExport::Billing.render(&parts, |s| config.rate(&s.task), now);
+    write_output(output, &csv)?;
+
+    if finalize {
+        let number = invoice::next_number(&state.invoices);
+        state.invoices.push(invoice::Invoice {
+            number,
+            issued_at: now,
+            spans: invoice::spans(&parts, now),
+        });
+        state.save(&file_path)?;
+        cache::invalidate(&file_path)?;
+        let total = parts.iter().fold(Duration::zero(), |total, part| {
+            total + part.interval(now).duration()
+        });
+        eprintln!(
+            "Finalized invoice #{} with {} session(s), {}.",
+            number,
+            parts.len(),
+            duration::format_duration(total)
+        );
+    }
+    Ok(())
+}
+
+fn mark(ok: bool) -> char {
+    if ok {
+        'x'
+    } else {
+        '!'
+    }
+}
+
+fn handle_unlock_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let mut label = None;
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--period" => label = Some(next_value(&mut iter, arg)?),
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+
+    let label = label.ok_or(PERIOD_NOT_PROVIDED_MSG)?;
+    let mut state = State::load(&file_path)?;
+    if !state.unlock(label) {
+        return Err(format!("{} is not locked.", label).into());
+    }
+    state.save(&file_path)?;
+    println!("Unlocked {}.", label);
+    Ok(())
+}
+
+fn handle_demo_command(args: &[String]) -> Result<(), RecorderError> {
+    let mut days = 90;
+    let mut seed = None;
+    let mut output = None;
+    let mut iter = args.iter().skip(2);
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--days" => days = parse_number(next_value(&mut iter, arg)?)?,
+            "--seed" => seed = Some(parse_number(next_value(&mut iter, arg)?)?),
+            "-o" | "--output" => output = Some(next_value(&mut iter, arg)?),
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    let output = output.ok_or(OUTPUT_NOT_PROVIDED_MSG)?;
+    if fs::metadata(output).is_ok() {
+        return Err(format!("'{}' already exists.", output).into());
+    }
+
+    let seed = seed.unwrap_or_else(|| Local::now().timestamp_micros() as u64);
+    let yesterday = get_current_time().date_naive() - Duration::days(1);
+    let records = demo::generate(days as u32, yesterday, &mut demo::Rng::new(seed));
+    write_records(output, &records)?;
+    println!("Wrote {} records to {}.", records.len(), output);
+    Ok(())
+}
+
+fn handle_lint_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    if let Some(arg) = remaining_args.first() {
+        return Err(format!("Invalid option '{}'.", arg).into());
+    }
+
+    let content = record::read_content(&file_path)?;
+    for (line, warning) in dst::ambiguous_times(&content, &Local) {
+        println!("line {}: warning: {}", line, warning);
+    }
+    let issues = policy::lint(config, &content);
+    for (line, issue) in &issues {
+        println!("line {}: {}", line, issue);
+    }
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} policy violations found.", issues.len()).into())
+    }
+}
+
+// 記録ファイルの問題を行番号付きで挙げ、--fix なら安全に直せるものを直す
+fn handle_doctor_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let now = get_current_time();
+    let mut fix = false;
+    let mut close_at = None;
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--fix" => fix = true,
+            "--close-at" => {
+                close_at = Some(parse_local_datetime(
+                    next_value(&mut iter, arg)?,
+                    now.date_naive(),
+                )?)
+            }
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    if close_at.is_some() && !fix {
+        return Err("--close-at needs --fix.".into());
+    }
+    if close_at.is_some_and(|time| time > now) {
+        return Err(RecorderError::InvalidTimestamp(format!(
+            "{} is in the future.",
+            config.format_datetime(close_at.unwrap())
+        )));
+    }
+
+    // 直すときは、読んでから書き終えるまでほかのプロセスに待ってもらう
+    let _lock = match fix {
+        true => Some(RecordStore::new(&file_path).lock()?),
+        false => None,
+    };
+    let content = record::read_content(&file_path)?;
+    let stale = Duration::hours(FORGOTTEN_STOP_THRESHOLD_HOURS);
+    let issues = doctor::diagnose(&content, stale, now);
+    for issue in &issues {
+        println!("line {}: {}", issue.line, issue.message);
+    }
+    let mut problems = issues.len();
+    if config.clock.verify {
+        match clock::skew(&config.clock.server, clock::TIMEOUT) {
+            Ok(skew) if skew.abs() <= config.clock.max_skew => {}
+            Ok(skew) => {
+                println!(
+                    "clock: off by {} from {}",
+                    clock::format_skew(skew),
+                    config.clock.server
+                );
+                problems += 1;
+            }
+            Err(e) => println!("clock: could not check: {}", e),
+        }
+    }
+
+    if !fix {
+        if problems == 0 {
+            println!("No problems found.");
+            return Ok(());
+        }
+        return Err(format!(
+            "{} found. `doctor --fix` repairs the safe ones.",
+            doctor::problems(problems)
+        )
+        .into());
+    }
+    let repaired = doctor::repair(&content, close_at.map(|t| config.timestamp(t)))?;
+    if repaired.fixes == 0 {
+        println!("Nothing to fix.");
+        return Ok(());
+    }
+    let backup = format!("{}.bak", file_path);
+    fs::write(&backup, &content).map_err(|e| format!("{}: {}", backup, e))?;
+    // 読めない行は並べ替えに巻き込まず、read_records と同じく .rejected に移す
+    if !repaired.rejected.is_empty() {
+        record::quarantine(&file_path, &repaired.rejected)?;
+    }
+    record::write_content(&file_path, &repaired.content)?;
+    println!(
+        "Fixed {}. The original is in {}.",
+        doctor::problems(repaired.fixes),
+        backup
+    );
+    Ok(())
+}
+
+fn handle_status_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    if let Some(arg) = remaining_args.first() {
+        return Err(format!("Invalid option '{}'.", arg).into());
+    }
+
+    let now = get_current_time();
+    apply_auto_stop(&file_path, config, now)?;
+    let records = RecordStore::new(&file_path).tail(config, 1)?.records;
+    let format = |t: DateTime<FixedOffset>| config.format_datetime(t);
+    match last_event(&records) {
+        Some(start) if start.event == Event::Start => {
+            let mut line = format!("Running '{}'", start.task);
+            for tag in &start.tags {
+                line += &format!(" +{}", tag);
+            }
+            line += &format!(
+                " since {} ({})",
+                format(start.timestamp),
+                duration::format_duration(now - start.timestamp)
+            );
+            let laps = records.iter().filter(|r| r.event == Event::Lap).count();
+            if laps > 0 {
+                line += &format!(", {} laps", laps);
+            }
+            println!("{}.", line);
+        }
+        Some(stop) => println!("Not running (stopped at {}).", format(stop.timestamp)),
+        None => println!("Not running."),
+    }
+    Ok(())
+}
+
+fn handle_lap_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let mut note = None;
+    let mut force_unlock = false;
+
+    for arg in &remaining_args {
+        match arg.as_str() {
+            "--force-unlock" => force_unlock = true,
+            _ if note.is_none() => note = Some(arg.as_str()),
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+
+    let timestamp = config.timestamp(get_current_time());
+    let _lock = RecordStore::new(&file_path).lock()?;
+    apply_auto_stop(&file_path, config, timestamp)?;
+    let recorder = Recorder::new(RecordStore::new(&file_path), config).force_unlock(force_unlock);
+    let lap = recorder.lap(note.unwrap_or_default(), timestamp)?;
+    let start = recorder.running()?.ok_or(RecorderError::NoOpenSession)?;
+    println!(
+        "Lap of '{}' at {} ({} since start).",
+        start.task,
+        config.format_time(lap.timestamp),
+        duration::format_duration(lap.timestamp - start.timestamp)
+    );
+    Ok(())
+}
+
+// 割り込みの仕事を始める。計測中のタスクは中断して状態ファイルに積み、pop で再開する。
+fn handle_push_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let timestamp = config.timestamp(get_current_time());
+    let mut task_name = None;
+    let mut tags = Vec::new();
+    let mut force_unlock = false;
+    for arg in &remaining_args {
+        match arg.as_str() {
+            "--force-unlock" => force_unlock = true,
+            _ if arg.len() > 1 && arg.starts_with('+') => tags.push(&arg[1..]),
+            _ if task_name.is_none() => task_name = Some(arg.as_str()),
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    let task_name = task_name.ok_or(RecorderError::MissingTask)?;
+    let record = start_record(task_name, timestamp, tags, Vec::new(), None, config)?;
+
+    let _lock = RecordStore::new(&file_path).lock()?;
+    apply_auto_stop(&file_path, config, timestamp)?;
+    let started = Recorder::new(RecordStore::new(&file_path), config)
+        .force_unlock(force_unlock)
+        .resolver(&TerminalResolver(config))
+        .record_start(record, true)?;
+    if let Some(running) = started.stopped {
+        let mut state = State::load(&file_path)?;
+        state.stack.push(state::Suspended {
+            task: running.task.clone(),
+            tags: running.tags,
+            suspended_at: timestamp,
+        });
+        state.save(&file_path)?;
+        println!("Suspended '{}'.", running.task);
+    }
+    println!("Started '{}'.", task_name);
+    Ok(())
+}
+
+fn handle_pop_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let timestamp = config.timestamp(get_current_time());
+    let mut force_unlock = false;
+    for arg in &remaining_args {
+        match arg.as_str() {
+            "--force-unlock" => force_unlock = true,
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+
+    let _lock = RecordStore::new(&file_path).lock()?;
+    let mut state = State::load(&file_path)?;
+    let suspended = state.stack.pop().ok_or(STACK_EMPTY_MSG)?;
+    apply_auto_stop(&file_path, config, timestamp)?;
+    let mut record = Record::new(timestamp, Event::Start, &suspended.task);
+    record.tags = suspended.tags;
+    let started = Recorder::new(RecordStore::new(&file_path), config)
+        .force_unlock(force_unlock)
+        .resolver(&TerminalResolver(config))
+        .record_start(with_host(record, config), true)?;
+    state.save(&file_path)?;
+    if let Some(running) = started.stopped {
+        println!("Stopped '{}'.", running.task);
+    }
+    println!("Resumed '{}'.", suspended.task);
+    Ok(())
+}
+
+// ボタン 1 つで打刻できるよう、同じタスクなら止め、それ以外なら切り替えて始める
+fn handle_toggle_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let now = get_current_time();
+    let mut task_name = None;
+    let mut force_unlock = false;
+    for arg in &remaining_args {
+        match arg.as_str() {
+            "--force-unlock" => force_unlock = true,
+            _ if task_name.is_none() => task_name = Some(arg.as_str()),
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    let task_name = task_name.ok_or(RecorderError::MissingTask)?;
+    let _lock = RecordStore::new(&file_path).lock()?;
+
+    let resolver = TerminalResolver(config);
+    let recorder = Recorder::new(RecordStore::new(&file_path), config)
+        .force_unlock(force_unlock)
+        .resolver(&resolver);
+    recorder.auto_stop(now)?;
+    recorder.resolve_open_start(now)?;
+    match recorder.running()? {
+        Some(running) if running.task == task_name => {
+            recorder.stop(now)?;
+            println!("Stopped '{}'.", task_name);
+        }
+        Some(running) => {
+            recorder.switch(task_name, now)?;
+            println!("Switched from '{}' to '{}'.", running.task, task_name);
+        }
+        None => match recorder.start(task_name, now)?.continued {
+            true => println!("Continuing '{}'.", task_name),
+            false => println!("Started '{}'.", task_name),
+        },
+    }
+    Ok(())
+}
+
+// 間違えて始めたセッションを、記録ごと取り消す
+fn handle_cancel_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let mut force_unlock = false;
+    for arg in &remaining_args {
+        match arg.as_str() {
+            "--force-unlock" => force_unlock = true,
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    let recorder = Recorder::new(RecordStore::new(&file_path), config).force_unlock(force_unlock);
+    let start = recorder.cancel()?;
+    println!(
+        "Cancelled '{}' (started at {}).",
+        start.task,
+        config.format_datetime(start.timestamp)
+    );
+    Ok(())
+}
+
+// 最後に書いたレコードを消す。端末なら確かめ、そうでなければ --yes を求める。
+fn handle_undo_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let mut yes = false;
+    let mut force_unlock = false;
+    for arg in &remaining_args {
+        match arg.as_str() {
+            "--yes" | "-y" => yes = true,
+            "--force-unlock" => force_unlock = true,
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    let _lock = RecordStore::new(&file_path).lock()?;
+    let recorder = Recorder::new(RecordStore::new(&file_path), config).force_unlock(force_unlock);
+    let last = recorder.last()?.ok_or(NOTHING_TO_UNDO_MSG)?;
+    // 尋ねる前に、ロック済みの期間なら断る
+    recorder.check_unlocked([last.timestamp].into_iter())?;
+    if !yes {
+        if !io::stdin().is_terminal() {
+            return Err(UNDO_NOT_CONFIRMED_MSG.into());
+        }
+        if !prompt_undo(&last, config, &mut io::stdin().lock(), &mut io::stderr())? {
+            println!("Nothing was changed.");
+            return Ok(());
+        }
+    }
+    let removed = recorder.undo()?.ok_or(NOTHING_TO_UNDO_MSG)?;
+    println!(
+        "Removed {} at {}.",
+        removed.event.as_str(),
+        config.format_datetime(removed.timestamp)
+    );
+    Ok(())
+}
+
+// 最後のレコードのタスク名か時刻を直す。時刻の順序が崩れる修正は拒む。
+fn handle_amend_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let now = get_current_time();
+    let mut task = None;
+    let mut time = None;
+    let mut force_unlock = false;
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--task" => task = Some(next_value(&mut iter, arg)?),
+            "--time" => time = Some(next_value(&mut iter, arg)?),
+            "--force-unlock" => force_unlock = true,
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    if task.is_none() && time.is_none() {
+        return Err(AMEND_USAGE_MSG.into());
+    }
+
+    let _lock = RecordStore::new(&file_path).lock()?;
+    let store = RecordStore::new(&file_path);
+    // 直すのは最後の行だけなので、ほかの行は読み直さず、精度も書き換えない
+    let mut tail = record::read_tail(&file_path, 2)?;
+    let last = tail.records.pop().ok_or(NOTHING_TO_AMEND_MSG)?;
+    let offset = tail.offsets[tail.records.len()];
+    let records = tail.records;
+    let mut amended = last.clone();
+    if let Some(task) = task {
+        if last.event == Event::Stop {
+            return Err("The last record is a stop and has no task name.".into());
+        }
+        amended.task = task.to_string();
+        if amended.event == Event::Start {
+            policy::check(config, &amended)?;
+        }
+    }
+    if let Some(time) = time {
+        // 日付を省いたときは、直すレコードの日付
+        let date = last.timestamp.with_timezone(&Local).date_naive();
+        amended.timestamp = config.timestamp(parse_local_datetime(time, date)?);
+        if amended.timestamp > now {
+            return Err(RecorderError::InvalidTimestamp(format!(
+                "{} is in the future.",
+                config.format_datetime(amended.timestamp)
+            )));
+        }
+        if let Some(previous) = records.last().filter(|r| amended.timestamp < r.timestamp) {
+            return Err(RecorderError::InvalidTimestamp(format!(
+                "{} is before the previous record ({}).",
+                config.format_datetime(amended.timestamp),
+                config.format_datetime(previous.timestamp)
+            )));
+        }
+    }
+    Recorder::new(RecordStore::new(&file_path), config)
+        .force_unlock(force_unlock)
+        .check_unlocked([last.timestamp, amended.timestamp].into_iter())?;
+
+    store.edit(&[offset], &[(offset, amended.clone())])?;
+    let time = config.format_datetime(amended.timestamp);
+    println!(
+        "Amended the last {} ({}).",
+        amended.event.as_str(),
+        format!("{} {}", time, amended.task).trim_end()
+    );
+    Ok(())
+}
+
+fn handle_resume_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let now = config.timestamp(get_current_time());
+    let mut at = now;
+    let mut nth = 1;
+    let mut force_unlock = false;
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--at" => at = parse_local_datetime(next_value(&mut iter, arg)?, now.date_naive())?,
+            "-n" => nth = parse_number(next_value(&mut iter, arg)?)?.max(1) as usize,
+            "--force-unlock" => force_unlock = true,
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    if at > now {
+        return Err(RecorderError::InvalidTimestamp(format!(
+            "{} is in the future.",
+            config.format_datetime(at)
+        )));
+    }
+    let _lock = RecordStore::new(&file_path).lock()?;
+    apply_auto_stop(&file_path, config, now)?;
+    let mut records = RecordStore::new(&file_path).tail(config, 2)?.records;
+    if nth > 1 || !records.iter().any(|r| r.event == Event::Start) {
+        records = RecordStore::new(&file_path).records(config)?;
+    }
+    let last = last_event(&records).ok_or(NOTHING_TO_RESUME_MSG)?;
+    if last.event == Event::Start {
+        return Err(format!("'{}' is still running. Stop it before resuming.", last.task).into());
+    }
+    // -n 3 なら、新しい方から数えて 3 つ目のタスクを最後に始めたときのもの
+    let mut tasks: Vec<&str> = Vec::new();
+    let start = records
+        .iter()
+        .rev()
+        .filter(|r| r.event == Event::Start)
+        .filter(|r| {
+            let first = !tasks.contains(&r.task.as_str());
+            if first {
+                tasks.push(&r.task);
+            }
+            first
+        })
+        .nth(nth - 1);
+    let start = match start {
+        Some(start) => start,
+        None if nth > 1 => return Err(format!("No recent task #{}.", nth).into()),
+        None => return Err(NOTHING_TO_RESUME_MSG.into()),
+    };
+    let record = resume_record(start, at, config);
+    Recorder::new(RecordStore::new(&file_path), config)
+        .force_unlock(force_unlock)
+        .resolver(&TerminalResolver(config))
+        .record_start(record.clone(), false)?;
+    println!(
+        "Resumed '{}' at {}.",
+        record.task,
+        config.format_datetime(at)
+    );
+    Ok(())
+}
+
+fn handle_since_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let now = get_current_time();
+    let mut anchor = None;
+    let mut task = None;
+    let mut project = None;
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--task" => task = Some(next_value(&mut iter, arg)?),
+            "--project" => project = Some(next_value(&mut iter, arg)?),
+            _ if anchor.is_none() => anchor = Some(arg.as_str()),
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    let anchor = parse_local_datetime(anchor.ok_or(TIME_NOT_PROVIDED_MSG)?, now.date_naive())?;
+    if anchor > now {
+        return Err(RecorderError::InvalidTimestamp(format!(
+            "{} is in the future.",
+            config.format_datetime(anchor)
+        )));
+    }
+
+    apply_auto_stop(&file_path, config, now)?;
+    let sessions = RecordStore::new(&file_path).sessions(config)?;
+    let matching = sessions
+        .iter()
+        .filter(|s| task.is_none_or(|task| s.task == task))
+        .filter(|s| project.is_none_or(|project| s.project() == Some(project)));
+    println!(
+        "{} since {}",
+        duration::format_duration(total_between(matching, anchor, now, now)),
+        config.format_datetime(anchor)
+    );
+    Ok(())
+}
+
+// 予算のあるプロジェクトごとに、date を含む予算期間の使用量
+fn budget_usages(
+    sessions: &[session::Session],
+    config: &Config,
+    date: NaiveDate,
+    now: DateTime<FixedOffset>,
+) -> Vec<budget::BudgetUsage> {
+    config
+        .projects
+        .keys()
+        .filter_map(|project| {
+            let (budget, period) = config.budget(project)?;
+            Some(budget::BudgetUsage::new(
+                sessions, project, budget, period, date, now,
+            ))
+        })
+        .collect()
+}
+
+// 予算を使い切ったプロジェクトのタスクを始めるときに警告する
+fn warn_budget(file_path: &str, config: &Config, record: &Record) -> Result<(), RecorderError> {
+    let Some(project) = session::project_of(&record.task) else {
+        return Ok(());
+    };
+    let Some((budget, period)) = config.budget(project) else {
+        return Ok(());
+    };
+    let sessions = RecordStore::new(file_path).sessions(config)?;
+    let date = record.timestamp.with_timezone(&Local).date_naive();
+    let usage =
+        budget::BudgetUsage::new(&sessions, project, budget, period, date, record.timestamp);
+    if usage.is_exhausted() {
+        eprintln!("{}", usage.warning());
+    }
+    Ok(())
+}
+
+fn handle_forecast_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    if let Some(arg) = remaining_args.first() {
+        return Err(format!("Invalid option '{}'.", arg).into());
+    }
+    let now = get_current_time();
+    apply_auto_stop(&file_path, config, now)?;
+    let sessions = RecordStore::new(&file_path).sessions(config)?;
+    // 月末の見込みと比べられるのは月ごとの予算だけ
+    let monthly_budget = |project: &str| match config.budget(project) {
+        Some((budget, budget::BudgetPeriod::Month)) => Some(budget),
+        _ => None,
+    };
+    let budgeted: Vec<&str> = config
+        .projects
+        .keys()
+        .map(String::as_str)
+        .filter(|name| monthly_budget(name).is_some())
+        .collect();
+    let forecast = forecast::forecast(
+        &sessions,
+        now.date_naive(),
+        monthly_budget,
+        &budgeted,
+        &config.hours,
+        now,
+    );
+    print!(
+        "{}",
+        forecast::render(&forecast, config.locale.unwrap_or_default())
+    );
+    Ok(())
+}
+
+// 前の勤務日と今日の、タスクごとの合計
+fn handle_summary_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let now = get_current_time();
+    let mut standup = false;
+    let mut all_history = false;
+
+    for arg in &remaining_args {
+        match arg.as_str() {
+            "--standup" => standup = true,
+            "--all-history" => all_history = true,
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+
+    apply_auto_stop(&file_path, config, now)?;
+    let today = now.date_naive();
+    let previous = summary::previous_workday(today, &config.hours);
+    let since = history_since(config, all_history, Some(previous), now);
+    let mut sessions = RecordStore::new(&file_path).since(since).sessions(config)?;
+    if let Some(rounding) = &config.rounding.report {
+        rounding::round_sessions(&mut sessions, rounding);
+    }
+    let summary = summary::Summary::new(
+        &sessions,
+        today,
+        &config.hours,
+        config.locale.unwrap_or_default(),
+        now,
+    );
+    match standup {
+        true => print!("{}", summary.to_standup()),
+        false => print!("{}", summary.render()),
+    }
+    Ok(())
+}
+
+// 計測中のセッションが長すぎないか見張り、threshold を超えたら通知する
+fn handle_watch_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let mut watch = config.watch.clone();
+    let mut once = false;
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--threshold" => watch.threshold = parse_duration(next_value(&mut iter, arg)?)?,
+            "--interval" => watch.interval = parse_duration(next_value(&mut iter, arg)?)?,
+            "--auto-stop" => watch.auto_stop = true,
+            "--once" => once = true,
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    let interval = watch
+        .interval
+        .to_std()
+        .ok()
+        .filter(|interval| !interval.is_zero())
+        .ok_or("The interval must be positive.")?;
+
+    // 同じセッションについては一度だけ知らせる
+    let mut notified = None;
+    loop {
+        let now = get_current_time();
+        apply_auto_stop(&file_path, config, now)?;
+        let recorder = Recorder::new(RecordStore::new(&file_path), config);
+        let alert = recorder
+            .running()?
+            .filter(|start| notified != Some(start.timestamp))
+            .and_then(|start| watch::check(&start, &watch, now).map(|a| (start, a)));
+        if let Some((start, alert)) = alert {
+            if let Some(stop) = alert.stop {
+                let store = RecordStore::new(&file_path);
+                let _lock = store.lock()?;
+                // 確かめてから書くまでに止められていなければ止める
+                if recorder
+                    .running()?
+                    .is_some_and(|r| r.timestamp == start.timestamp)
+                {
+                    // ほかの書き込みと同じく、ロック済みの期間には書かない
+                    recorder.check_unlocked([stop.timestamp].into_iter())?;
+                    store.append(&[with_host(stop, config)])?;
+                }
+            }
+            println!("{}", alert.message);
+            notify("Long session", &alert.message);
+            notified = Some(start.timestamp);
+        }
+        if once {
+            return Ok(());
+        }
+        thread::sleep(interval);
+    }
+}
+
+fn handle_wait_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let mut until_stopped = false;
+    let mut timeout = None;
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--until-stopped" => until_stopped = true,
+            "--timeout" => timeout = Some(parse_duration(next_value(&mut iter, arg)?)?),
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    if !until_stopped {
+        return Err(WAIT_USAGE_MSG.into());
+    }
+    let deadline = timeout.map(|timeout| get_current_time() + timeout);
+
+    let recorder = Recorder::new(RecordStore::new(&file_path), config);
+    let Some(start) = recorder.running()? else {
+        return Ok(());
+    };
+    // 書き込まれたときだけ読み直す
+    let mut watch = filewatch::FileWatch::new(Path::new(&file_path));
+    loop {
+        let remaining = match deadline.map(|deadline| (deadline - get_current_time()).to_std()) {
+            Some(Ok(remaining)) if !remaining.is_zero() => Some(remaining),
+            Some(_) => {
+                return Err(format!("Timed out waiting for '{}' to stop.", start.task).into())
+            }
+            None => None,
+        };
+        if !watch.wait(remaining) {
+            continue;
+        }
+        if recorder.running()?.is_none() {
+            println!("Stopped '{}'.", start.task);
+            return Ok(());
+        }
+    }
+}
+
+fn handle_untracked_command(args: &[String], config: &Config) -> Result<(), RecorderError> {
+    let (file_path, remaining_args) = parse_arguments(args, config)?;
+    let now = get_current_time();
+    let mut period = Period::week_of(now.date_naive());
+    let mut workday = config.workday;
+    let mut min_gap = Duration::minutes(5);
+    let mut iter = remaining_args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--workday" => workday = untracked::Workday::parse(next_value(&mut iter, arg)?)?,
+            "--min-gap" => min_gap = parse_duration(next_value(&mut iter, arg)?)?,
+            "--from" => period.start = parse_date(next_value(&mut iter, arg)?)?,
+            "--to" => period.end = parse_date(next_value(&mut iter, arg)?)?,
+            "--period" => period = config.fiscal.parse_period(next_value(&mut iter, arg)?)?,
+            _ => return Err(format!("Invalid option '{}'.", arg).into()),
+        }
+    }
+    let period = Period::new(period.start, period.end)?;
+
+    apply_auto_stop(&file_path, config, now)?;
+    let sessions = RecordStore::new(&file_path).sessions(config)?;
+    let gaps = untracked::gaps(&sessions, &period, &workday, &config.hours, min_gap, now);
+    let locale = config.locale.unwrap_or_default();
+    for (from, to) in &gaps {
+        println!(
+            "{} {}  {} - {}  {:>6}",
+            names::pad(
+                names::weekday_short(from.with_timezone(&Local).weekday(), locale),
+                names::weekday_width(locale)
+            ),
+            from.with_timezone(&Local).format("%Y-%m-%d"),
+            config.format_time(*from),
+            config.format_time(*to),
+            duration::format_duration(*to - *from)
+        );
+    }
+    let total = report::sum(gaps.iter().map(|(from, to)| *to - *from));
+    println!(
+        "Untracked {} in working hours ({} - {}).",
+        duration::format_duration(total),
+        workday.start.format(config.time_format()),
+        workday.end.format(config.time_format())
+    );
+    Ok(())
+}
+
+// 読み込みだけのコマンドが読む履歴の始まり。history_limit だけさかのぼり、
+// 見たい期間がそれより前から始まるならそこまで広げる。--all-history か未設定なら全部。
+fn history_since(
+    config: &Config,
+    all_history: bool,
+    from: Option<NaiveDate>,
+    now: DateTime<FixedOffset>,
+) -> Option<DateTime<FixedOffset>> {
+    let since = now - config.history_limit.filter(|_| !all_history)?;
+    Some(from.map_or(since, |from| since.min(period::local_midnight(from))))
+}
+
+// 前回の実行以降に max_session / auto_stop_at を過ぎたセッションを上限時刻で閉じる
+fn apply_auto_stop(
+    file_path: &str,
+    config: &Config,
+    now: DateTime<FixedOffset>,
+) -> Result<(), RecorderError> {
+    // 標準入力から読むときは書き戻せないので何もしない
+    if file_path == record::STDIN_PATH {
+        return Ok(());
+    }
+    let recorder = Recorder::new(RecordStore::new(file_path), config);
+    let running = recorder.running()?;
+    if let Some(stop) = recorder.auto_stop(now)? {
+        eprintln!(
+            "Auto-stopped '{}' at {} (auto-stop limit reached).",
+            running.map(|r| r.task).unwrap_or_default(),
+            config.format_datetime(stop.timestamp)
+        );
+    }
+    Ok(())
+}
+
+// 開いたままの start の閉じ方を端末で尋ねる。端末でなければ警告だけして開いたままにする。
+struct TerminalResolver<'a>(&'a Config);
+
+impl Resolver for TerminalResolver<'_> {
+    fn forgotten(
+        &self,
+        start: &Record,
+        candidates: &[(DateTime<FixedOffset>, &str)],
+        now: DateTime<FixedOffset>,
+    ) -> Result<Option<DateTime<FixedOffset>>, String> {
+        if !io::stdin().is_terminal() {
+            eprintln!(
+                "Warning: '{}' has been running since {}.",
+                start.task,
+                self.0.format_datetime(start.timestamp)
+            );
+            return Ok(None);
+        }
+        prompt_stop_time(
+            start,
+            candidates,
+            now,
+            self.0,
+            &mut io::stdin().lock(),
+            &mut io::stderr(),
+        )
+    }
+
+    fn crashed(
+        &self,
+        start: &Record,
+        boot: DateTime<FixedOffset>,
+    ) -> Result<CrashRecovery, String> {
+        if !io::stdin().is_terminal() {
+            // --at にそのまま渡せるよう、time_format ではなく読める書式で書く
+            eprintln!(
+                "Warning: '{}' was running when the machine went down. \
+                 Run `stop` or `resume --at \"{}\"` to fix it.",
+                start.task,
+                boot.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+            );
+            return Ok(CrashRecovery::Keep);
+        }
+        prompt_crash_recovery(
+            start,
+            boot,
+            self.0,
+            &mut io::stdin().lock(),
+            &mut io::stderr(),
+        )
+    }
+
+    fn note(&self, start: &Record, history: &[&str]) -> Result<Option<String>, String> {
+        ask_note(start, history)
+    }
+}
+
+// 時刻を指定した stop 用。開いたままの start はその時刻で閉じるので、メモだけを尋ねる。
+struct NoteResolver;
+
+impl Resolver for NoteResolver {
+    fn forgotten(
+        &self,
+        _: &Record,
+        _: &[(DateTime<FixedOffset>, &str)],
+        _: DateTime<FixedOffset>,
+    ) -> Result<Option<DateTime<FixedOffset>>, String> {
+        Ok(None)
+    }
+
+    fn crashed(&self, _: &Record, _: DateTime<FixedOffset>) -> Result<CrashRecovery, String> {
+        Ok(CrashRecovery::Keep)
+    }
+
+    fn note(&self, start: &Record, history: &[&str]) -> Result<Option<String>, String> {
+        ask_note(start, history)
+    }
+}
+
+// 止めるタスクのメモを端末で尋ねる。端末でなければメモなし。
+fn ask_note(start: &Record, history: &[&str]) -> Result<Option<String>, String> {
+    if !io::stdin().is_terminal() {
+        return Ok(None);
+    }
+    note::prompt_note(
+        &start.task,
+        history,
+        &mut io::stdin().lock(),
+        &mut io::stderr(),
+    )
+}
+
+// 共通の引数処理関数
+fn parse_arguments(
+    args: &[String],
+    config: &Config,
+) -> Result<(String, Vec<String>), RecorderError> {
+    let mut file_path = get_working_time_record_path(config);
+    let mut remaining_args = Vec::new();
+    // prune と since では --project はタスクのプロジェクトで絞り込む
+    let project_filter = matches!(args.get(1).map(String::as_str), Some("prune" | "since"));
+    let mut iter = args.iter().skip(2);
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-f" | "--file" => file_path = iter.next().ok_or(FILENAME_NOT_PROVIDED_MSG)?.clone(),
+            "--project" if project_filter => remaining_args.push(arg.clone()),
+            "-p" | "--project" => {
+                let path = config.project_file(next_value(&mut iter, arg)?)?;
+                file_path = path.to_str().ok_or(INVALID_PATH_MSG)?.to_string();
+            }
+            _ => remaining_args.push(arg.clone()),
+        }
+    }
+
+    Ok((file_path, remaining_args))
+}
+
+// --day / --week / --month の基準日。today / yesterday と、--month なら 2024-05 も使える。
+fn parse_anchor_date(s: &str, today: NaiveDate, month: bool) -> Result<NaiveDate, RecorderError> {
+    if let Some(day) = Period::relative_day(s, today) {
+        return Ok(day.start);
+    }
+    match parse_date(s) {
+        Err(_) if month => Ok(parse_date(&format!("{}-01", s))?),
+        result => Ok(result?),
+    }
+}
+
+fn next_value<'a>(
+    iter: &mut impl Iterator<Item = &'a String>,
+    option: &str,
+) -> Result<&'a str, RecorderError> {
+    iter.next()
+        .map(|s| s.as_str())
+        .ok_or(format!("Option '{}' requires a value.", option).into())
+}
+
+fn parse_number(s: &str) -> Result<u64, RecorderError> {
+    s.parse()
+        .map_err(|_| RecorderError::Parse(format!("Invalid number '{}'.", s)))
+}
+
+fn get_current_time() -> DateTime<FixedOffset> {
+    Local::now().fixed_offset()
+}
+
+// 環境変数、設定の primary な source、ホームディレクトリの順に探す
+fn get_working_time_record_path(config: &Config) -> String {
+    env::var(RECORD_ENV).unwrap_or_else(|_| {
+        let path = match sources::primary_path(config) {
+            Some(path) => path.to_path_buf(),
+            None => dirs::home_dir()
+                .expect("ホームディレクトリが見つかりません")
+                .join("working_time_record.txt"),
+        };
+        path.to_str().unwrap().to_string()
+    })
+}
+
+fn write_output(output: Option<&str>, content: &str) -> Result<(), RecorderError> {
+    match output {
+        Some(path) => fs::write(path, content).map_err(|e| RecorderError::Io(e.to_string())),
+        None => {
+            print!("{}", content);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use std::process::Stdio;
+
+    fn setup_test_file() -> String {
+        let test_file = "test_working_time_record.txt";
+        if fs::metadata(test_file).is_ok() {
+            fs::remove_file(test_file).unwrap();
+        }
+        test_file.to_string()
+    }
+
+    #[test]
+    fn test_execute_empty_args() {
+        let args = vec!["program_name".to_string()];
+        let result = execute(&args, Ok(&Config::default()));
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().to_string(), "No subcommand provided.");
+    }
+
+    #[test]
+    fn test_execute_help() {
+        let args = vec!["program_name".to_string(), "help".to_string()];
+        assert!(execute(&args, Ok(&Config::default())).is_ok());
+    }
+
+    #[test]
+    fn test_execute_reports_broken_config() {
+        let broken = "Invalid config 'config.toml': expected a value".to_string();
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<String>>();
+        // 記録ファイルの場所を決める前に、設定の誤りで終わる
+        let result = execute(&args(&["program_name", "status"]), Err(&broken));
+        assert_eq!(result.unwrap_err().to_string(), broken);
+        assert!(execute(&args(&["program_name", "help"]), Err(&broken)).is_ok());
+    }
+
+    #[test]
+    fn test_execute_invalid_command() {
+        let args = vec!["program_name".to_string(), "invalid".to_string()];
+        let result = execute(&args, Ok(&Config::default()));
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Invalid subcommand 'invalid'."
+        );
+    }
+
+    #[test]
+    fn test_take_utc_flag() {
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<String>>();
+        assert_eq!(
+            take_utc_flag(args(&["program_name", "report", "--utc", "--week"])),
+            (args(&["program_name", "report", "--week"]), true)
+        );
+        assert_eq!(
+            take_utc_flag(args(&["program_name", "status"])),
+            (args(&["program_name", "status"]), false)
+        );
+    }
+
+    #[test]
+    fn test_history_since() {
+        let config = Config::parse("history_limit = \"30d\"").unwrap();
+        let today = parse_date("2024-05-31").unwrap();
+        let now = period::to_local(today.and_hms_opt(12, 0, 0).unwrap());
+        assert_eq!(
+            history_since(&config, false, None, now),
+            Some(now - Duration::days(30))
+        );
+        // 見たい期間が古ければそこまで読む
+        let from = parse_date("2024-01-01").unwrap();
+        assert_eq!(
+            history_since(&config, false, Some(from), now),
+            Some(period::local_midnight(from))
+        );
+        assert_eq!(history_since(&config, true, None, now), None);
+        assert_eq!(history_since(&Config::default(), false, None, now), None);
+    }
+
+    #[test]
+    fn test_handle_start_command() {
+        let test_file = setup_test_file();
+        let args = vec![
+            "program_name".to_string(),
+            "start".to_string(),
+            "test_task".to_string(),
+            "-f".to_string(),
+            test_file.clone(),
+        ];
+        assert!(handle_start_command(&args, &Config::default()).is_ok());
+        let content = fs::read_to_string(&test_file).unwrap();
+        assert!(content.contains("start\ttest_task"));
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_start_command_plus_tags() {
+        let test_file = "test_start_plus_tags_record.txt";
+        let _ = fs::remove_file(test_file);
+        let args: Vec<String> = [
+            "program_name",
+            "start",
+            "fix-login",
+            "+backend",
+            "+clientA",
+            "-f",
+            test_file,
+        ]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+        assert!(handle_start_command(&args, &Config::default()).is_ok());
+        let records = read_records(test_file).unwrap();
+        assert_eq!(records[0].task, "fix-login");
+        assert_eq!(records[0].tags, vec!["backend", "clientA"]);
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_start_command_already_running() {
+        let test_file = "test_start_running_record.txt";
+        fs::write(test_file, "2001-05-01T09:00:00+09:00\tstart\ta\n").unwrap();
+        let args = |extra: &[&str]| {
+            let mut args = vec!["program_name", "start", "b", "-f", test_file];
+            args.extend(extra);
+            args.iter().map(|a| a.to_string()).collect::<Vec<String>>()
+        };
+        assert_eq!(
+            handle_start_command(&args(&[]), &Config::default()).unwrap_err(),
+            RecorderError::AlreadyRunning("a".to_string())
+        );
+        assert!(handle_start_command(&args(&["--switch"]), &Config::default()).is_ok());
+        let records = read_records(test_file).unwrap();
+        let events: Vec<(Event, &str)> =
+            records.iter().map(|r| (r.event, r.task.as_str())).collect();
+        assert_eq!(
+            events,
+            vec![(Event::Start, "a"), (Event::Stop, ""), (Event::Start, "b")]
+        );
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_start_command_recent_number() {
+        let test_file = "test_start_recent_record.txt";
+        fs::write(
+            test_file,
+            "2001-05-01T09:00:00+09:00\tstart\ta\n\
+             2001-05-01T10:00:00+09:00\tstop\t\n\
+             2001-05-01T11:00:00+09:00\tstart\tb\n\
+             2001-05-01T12:00:00+09:00\tstop\t\n",
+        )
+        .unwrap();
+        let args = |extra: &[&str]| {
+            let mut args = vec!["program_name", "start", "-f", test_file];
+            args.extend(extra);
+            args.iter().map(|a| a.to_string()).collect::<Vec<String>>()
+        };
+        assert!(handle_start_command(&args(&[]), &Config::default()).is_ok());
+        assert_eq!(
+            handle_start_command(&args(&["3"]), &Config::default())
+                .unwrap_err()
+                .to_string(),
+            "No recent task #3."
+        );
+        assert!(handle_start_command(&args(&["2"]), &Config::default()).is_ok());
+        let records = read_records(test_file).unwrap();
+        assert_eq!(records.len(), 5);
+        assert_eq!(records[4].task, "a");
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_start_command_missing_task_name() {
+        // 最近のタスクが無い記録ファイル
+        let test_file = "test_start_missing_task_record.txt";
+        let args = vec![
+            "program_name".to_string(),
+            "start".to_string(),
+            "-f".to_string(),
+            test_file.to_string(),
+        ];
+        let result = handle_start_command(&args, &Config::default());
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err(), RecorderError::MissingTask);
+    }
+
+    #[test]
+    fn test_handle_start_command_rejects_tab_in_task_name() {
+        let test_file = "test_start_tab_record.txt";
+        let _ = fs::remove_file(test_file);
+        let args = vec![
+            "program_name".to_string(),
+            "start".to_string(),
+            "foo\tbar".to_string(),
+            "-f".to_string(),
+            test_file.to_string(),
+        ];
+        let result = handle_start_command(&args, &Config::default());
+        assert!(matches!(result, Err(RecorderError::Parse(_))));
+        assert!(fs::metadata(test_file).is_err());
+    }
+
+    #[test]
+    fn test_handle_start_command_with_tags() {
+        let test_file = "test_start_tags_record.txt";
+        let args = vec![
+            "program_name".to_string(),
+            "start".to_string(),
+            "test_task".to_string(),
+            "-t".to_string(),
+            "billable".to_string(),
+            "--tag".to_string(),
+            "urgent".to_string(),
+            "-f".to_string(),
+            test_file.to_string(),
+        ];
+        assert!(handle_start_command(&args, &Config::default()).is_ok());
+        let content = fs::read_to_string(test_file).unwrap();
+        assert!(content.contains("start\ttest_task\t+billable\t+urgent"));
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_start_command_with_fields() {
+        let test_file = "test_start_fields_record.txt";
+        let args = vec![
+            "program_name".to_string(),
+            "start".to_string(),
+            "test_task".to_string(),
+            "--field".to_string(),
+            "ticket=ABC-1".to_string(),
+            "--field".to_string(),
+            "location=office".to_string(),
+            "-f".to_string(),
+            test_file.to_string(),
+        ];
+        assert!(handle_start_command(&args, &Config::default()).is_ok());
+        let content = fs::read_to_string(test_file).unwrap();
+        assert!(content.contains("start\ttest_task\tticket=ABC-1\tlocation=office"));
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_start_command_billable() {
+        let test_file = "test_start_billable_record.txt";
+        let args = vec![
+            "program_name".to_string(),
+            "start".to_string(),
+            "test_task".to_string(),
+            "--non-billable".to_string(),
+            "-f".to_string(),
+            test_file.to_string(),
+        ];
+        assert!(handle_start_command(&args, &Config::default()).is_ok());
+        let content = fs::read_to_string(test_file).unwrap();
+        assert!(content.contains("start\ttest_task\tbillable=false"));
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_stop_command() {
+        let test_file = "test_stop_record.txt";
+        let _ = fs::remove_file(test_file);
+        let args = |extra: &[&str]| {
+            let mut args = vec!["program_name", "stop", "-f", test_file];
+            args.extend(extra);
+            args.iter().map(|a| a.to_string()).collect::<Vec<String>>()
+        };
+        assert_eq!(
+            handle_stop_command(&args(&[]), &Config::default()).unwrap_err(),
+            RecorderError::NoOpenSession
+        );
+        assert!(fs::metadata(test_file).is_err());
+
+        let start = Record::new(get_current_time(), Event::Start, "a");
+        fs::write(test_file, start.to_line()).unwrap();
+        assert!(handle_stop_command(&args(&[]), &Config::default()).is_ok());
+        assert_eq!(
+            handle_stop_command(&args(&[]), &Config::default()).unwrap_err(),
+            RecorderError::NoOpenSession
+        );
+        assert!(handle_stop_command(&args(&["--force"]), &Config::default()).is_ok());
+        let records = read_records(test_file).unwrap();
+        let events: Vec<Event> = records.iter().map(|r| r.event).collect();
+        assert_eq!(events, vec![Event::Start, Event::Stop, Event::Stop]);
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_start_and_stop_at() {
+        let test_file = "test_start_stop_at_record.txt";
+        let at = |s: &str| parse_local_datetime(s, NaiveDate::MIN).unwrap();
+        let stop = Record::new(at("2001-05-01T09:00"), Event::Stop, "");
+        fs::write(test_file, stop.to_line()).unwrap();
+        let args = |extra: &[&str]| {
+            let mut args = vec!["program_name"];
+            args.extend(extra);
+            args.extend(["-f", test_file]);
+            args.iter().map(|a| a.to_string()).collect::<Vec<String>>()
+        };
+        assert_eq!(
+            handle_start_command(
+                &args(&["start", "a", "--at", "2001-05-01T08:00"]),
+                &Config::default()
+            )
+            .unwrap_err(),
+            RecorderError::InvalidTimestamp(
+                "2001-05-01 08:00 is before the last record (2001-05-01 09:00).".to_string()
+            )
+        );
+        assert!(handle_start_command(
+            &args(&["start", "a", "--at", "2001-05-01T09:30"]),
+            &Config::default()
+        )
+        .is_ok());
+        let lap = Record::new(at("2001-05-01T10:00"), Event::Lap, "x");
+        fs::write(
+            test_file,
+            fs::read_to_string(test_file).unwrap() + &lap.to_line(),
+        )
+        .unwrap();
+        // lap より前では止めない
+        assert!(handle_stop_command(
+            &args(&["stop", "--at", "2001-05-01T09:45"]),
+            &Config::default()
+        )
+        .unwrap_err()
+        .to_string()
+        .contains("is before the last record"));
+        assert!(handle_stop_command(
+            &args(&["stop", "--at", "2001-05-01T11:00"]),
+            &Config::default()
+        )
+        .is_ok());
+        let records = read_records(test_file).unwrap();
+        let events: Vec<(Event, DateTime<FixedOffset>)> =
+            records.iter().map(|r| (r.event, r.timestamp)).collect();
+        assert_eq!(
+            events,
+            vec![
+                (Event::Stop, at("2001-05-01T09:00")),
+                (Event::Start, at("2001-05-01T09:30")),
+                (Event::Lap, at("2001-05-01T10:00")),
+                (Event::Stop, at("2001-05-01T11:00")),
+            ]
+        );
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_start_command_pomodoro() {
+        let test_file = "test_start_pomodoro_record.txt";
+        let _ = fs::remove_file(test_file);
+        let at = |s: &str| parse_local_datetime(s, NaiveDate::MIN).unwrap();
+        let args = |extra: &[&str]| {
+            let mut args = vec!["program_name", "start", "a"];
+            args.extend(extra);
+            args.extend(["-f", test_file]);
+            args.iter().map(|a| a.to_string()).collect::<Vec<String>>()
+        };
+        assert!(handle_start_command(&args(&["--pomodoro", "0"]), &Config::default()).is_err());
+        // 終わりの時刻を過ぎているので待たずに止める
+        handle_start_command(
+            &args(&["--at", "2001-05-01T09:00", "--pomodoro", "25"]),
+            &Config::default(),
+        )
+        .unwrap();
+        let records = read_records(test_file).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            record::field(&records[0].fields, pomodoro::POMODORO_FIELD),
+            Some("25m")
+        );
+        assert_eq!(records[1].event, Event::Stop);
+        assert_eq!(records[1].timestamp, at("2001-05-01T09:25"));
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_watch_command() {
+        let test_file = "test_watch_record.txt";
+        let at = |s: &str| parse_local_datetime(s, NaiveDate::MIN).unwrap();
+        let start = Record::new(at("2001-05-01T09:00"), Event::Start, "a");
+        fs::write(test_file, start.to_line()).unwrap();
+        let args = |extra: &[&str]| {
+            let mut args = vec!["program_name", "watch", "--once"];
+            args.extend(extra);
+            args.extend(["-f", test_file]);
+            args.iter().map(|a| a.to_string()).collect::<Vec<String>>()
+        };
+        assert!(handle_watch_command(&args(&["--interval", "0"]), &Config::default()).is_err());
+        // 知らせるだけでは記録を変えない
+        handle_watch_command(&args(&[]), &Config::default()).unwrap();
+        assert_eq!(read_records(test_file).unwrap().len(), 1);
+        // ロック済みの期間には止める stop を書かない
+        let mut state = State::load(test_file).unwrap();
+        let period = Config::default().fiscal.parse_period("2001-05").unwrap();
+        state.lock("2001-05", &period, get_current_time());
+        state.save(test_file).unwrap();
+        let auto_stop = args(&["--threshold", "8h", "--auto-stop"]);
+        let err = handle_watch_command(&auto_stop, &Config::default()).unwrap_err();
+        assert!(err.to_string().starts_with("2001-05 is locked"));
+        assert_eq!(read_records(test_file).unwrap().len(), 1);
+        fs::remove_file(state::state_path(test_file)).unwrap();
+        handle_watch_command(
+            &args(&["--threshold", "8h", "--auto-stop"]),
+            &Config::default(),
+        )
+        .unwrap();
+        let records = read_records(test_file).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].timestamp, at("2001-05-01T17:00"));
+        assert_eq!(records[1].tags, vec!["auto-stopped"]);
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_undo_command() {
+        let test_file = "test_undo_record.txt";
+        fs::write(
+            test_file,
+            "2001-05-01T09:00:00+09:00\tstart\ta\n2001-05-01T10:00:00+09:00\tstop\t\n",
+        )
+        .unwrap();
+        let args = |extra: &[&str]| {
+            let mut args = vec!["program_name", "undo"];
+            args.extend(extra);
+            args.extend(["-f", test_file]);
+            args.iter().map(|a| a.to_string()).collect::<Vec<String>>()
+        };
+        handle_undo_command(&args(&["--yes"]), &Config::default()).unwrap();
+        assert_eq!(
+            fs::read_to_string(test_file).unwrap(),
+            "2001-05-01T09:00:00+09:00\tstart\ta\n"
+        );
+        handle_undo_command(&args(&["--yes"]), &Config::default()).unwrap();
+        assert!(handle_undo_command(&args(&["--yes"]), &Config::default()).is_err());
+
+        // 最後のレコードより後ろの手書きの行は消さない
+        fs::write(
+            test_file,
+            "2001-05-01T09:00:00+09:00\tstart\ta\n\
+             2001-05-01T10:00:00+09:00\tstop\t\n\
+             hand-written note: left early\n",
+        )
+        .unwrap();
+        handle_undo_command(&args(&["--yes"]), &Config::default()).unwrap();
+        assert_eq!(
+            fs::read_to_string(test_file).unwrap(),
+            "2001-05-01T09:00:00+09:00\tstart\ta\nhand-written note: left early\n"
+        );
+        fs::remove_file(test_file).unwrap();
+        let _ = fs::remove_file(record::rejected_path(test_file));
+    }
+
+    #[test]
+    fn test_handle_wait_command() {
+        let test_file = "test_wait_record.txt";
+        let at = |s: &str| parse_local_datetime(s, NaiveDate::MIN).unwrap();
+        let start = Record::new(at("2001-05-01T09:00"), Event::Start, "a");
+        fs::write(test_file, start.to_line()).unwrap();
+        let args = |extra: &[&str]| {
+            let mut args = vec!["program_name", "wait"];
+            args.extend(extra);
+            args.extend(["-f", test_file]);
+            args.iter().map(|a| a.to_string()).collect::<Vec<String>>()
+        };
+        assert!(handle_wait_command(&args(&[]), &Config::default()).is_err());
+        assert!(handle_wait_command(
+            &args(&["--until-stopped", "--timeout", "0s"]),
+            &Config::default()
+        )
+        .is_err());
+
+        // 別のスレッドで止めると戻る
+        let stopper = thread::spawn(move || {
+            thread::sleep(filewatch::POLL_INTERVAL * 2);
+            let stop = Record::new(at("2001-05-01T10:00"), Event::Stop, "");
+            RecordStore::new(test_file).append(&[stop]).unwrap();
+        });
+        handle_wait_command(
+            &args(&["--until-stopped", "--timeout", "1m"]),
+            &Config::default(),
+        )
+        .unwrap();
+        stopper.join().unwrap();
+        // 計測中でなければすぐ戻る
+        handle_wait_command(&args(&["--until-stopped"]), &Config::default()).unwrap();
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_doctor_command() {
+        let test_file = "test_doctor_record.txt";
+        let backup = format!("{}.bak", test_file);
+        let content = "2001-05-01T10:00:00+09:00\tstop\t\n\n\
+                       2001-05-01T09:00:00+09:00\tstart\ta\n";
+        fs::write(test_file, content).unwrap();
+        let args = |extra: &[&str]| {
+            let mut args = vec!["program_name", "doctor"];
+            args.extend(extra);
+            args.extend(["-f", test_file]);
+            args.iter().map(|a| a.to_string()).collect::<Vec<String>>()
+        };
+        assert!(handle_doctor_command(&args(&[]), &Config::default()).is_err());
+        assert!(handle_doctor_command(
+            &args(&["--close-at", "2001-05-01T11:00"]),
+            &Config::default()
+        )
+        .is_err());
+        handle_doctor_command(&args(&["--fix"]), &Config::default()).unwrap();
+        assert_eq!(fs::read_to_string(&backup).unwrap(), content);
+        assert_eq!(
+            fs::read_to_string(test_file).unwrap(),
+            "2001-05-01T09:00:00+09:00\tstart\ta\n2001-05-01T10:00:00+09:00\tstop\t\n"
+        );
+        assert!(handle_doctor_command(&args(&[]), &Config::default()).is_ok());
+        fs::remove_file(test_file).unwrap();
+        fs::remove_file(backup).unwrap();
+    }
+
+    #[test]
+    fn test_handle_push_and_pop_commands() {
+        let test_file = "test_push_pop_record.txt";
+        let _ = fs::remove_file(test_file);
+        let _ = fs::remove_file(state::state_path(test_file));
+        let args = |extra: &[&str]| {
+            let mut args = vec!["program_name"];
+            args.extend(extra);
+            args.extend(["-f", test_file]);
+            args.iter().map(|a| a.to_string()).collect::<Vec<String>>()
+        };
+        assert_eq!(
+            handle_pop_command(&args(&["pop"]), &Config::default())
+                .unwrap_err()
+                .to_string(),
+            STACK_EMPTY_MSG
+        );
+        assert!(handle_push_command(&args(&["push", "a", "+x"]), &Config::default()).is_ok());
+        assert!(handle_push_command(&args(&["push", "b"]), &Config::default()).is_ok());
+        assert!(handle_push_command(&args(&["push", "c"]), &Config::default()).is_ok());
+        assert_eq!(State::load(test_file).unwrap().stack.len(), 2);
+        assert!(handle_pop_command(&args(&["pop"]), &Config::default()).is_ok());
+        assert!(handle_pop_command(&args(&["pop"]), &Config::default()).is_ok());
+
+        let records = read_records(test_file).unwrap();
+        let starts: Vec<&str> = records
+            .iter()
+            .filter(|r| r.event == Event::Start)
+            .map(|r| r.task.as_str())
+            .collect();
+        assert_eq!(starts, vec!["a", "b", "c", "b", "a"]);
+        assert_eq!(records.last().unwrap().tags, vec!["x".to_string()]);
+        assert!(State::load(test_file).unwrap().stack.is_empty());
+        fs::remove_file(test_file).unwrap();
+        fs::remove_file(state::state_path(test_file)).unwrap();
+    }
+
+    #[test]
+    fn test_handle_toggle_command() {
+        let test_file = "test_toggle_record.txt";
+        let _ = fs::remove_file(test_file);
+        let args = |task: &str| {
+            ["program_name", "toggle", task, "-f", test_file]
+                .iter()
+                .map(|a| a.to_string())
+                .collect::<Vec<String>>()
+        };
+        for task in ["a", "b", "b"] {
+            assert!(handle_toggle_command(&args(task), &Config::default()).is_ok());
+        }
+        let records = read_records(test_file).unwrap();
+        let events: Vec<(Event, &str)> =
+            records.iter().map(|r| (r.event, r.task.as_str())).collect();
+        assert_eq!(
+            events,
+            vec![
+                (Event::Start, "a"),
+                (Event::Stop, ""),
+                (Event::Start, "b"),
+                (Event::Stop, "")
+            ]
+        );
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_cancel_command() {
+        let test_file = "test_cancel_record.txt";
+        let stop = Record::new(get_current_time() - Duration::hours(2), Event::Stop, "");
+        let start = Record::new(get_current_time() - Duration::hours(1), Event::Start, "a");
+        fs::write(test_file, stop.to_line() + &start.to_line()).unwrap();
+        let args = vec![
+            "program_name".to_string(),
+            "cancel".to_string(),
+            "-f".to_string(),
+            test_file.to_string(),
+        ];
+        assert!(handle_cancel_command(&args, &Config::default()).is_ok());
+        let records = read_records(test_file).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].event, Event::Stop);
+        assert_eq!(
+            handle_cancel_command(&args, &Config::default()).unwrap_err(),
+            RecorderError::NoOpenSession
+        );
+
+        // lap ごと消し、後ろの手書きの行は残す
+        let lap = Record::new(get_current_time() - Duration::minutes(30), Event::Lap, "x");
+        fs::write(
+            test_file,
+            stop.to_line() + &start.to_line() + &lap.to_line() + "hand-written note\n",
+        )
+        .unwrap();
+        assert!(handle_cancel_command(&args, &Config::default()).is_ok());
+        assert_eq!(
+            fs::read_to_string(test_file).unwrap(),
+            stop.to_line() + "hand-written note\n"
+        );
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_amend_command() {
+        let test_file = "test_amend_record.txt";
+        let at = |s: &str| parse_local_datetime(s, NaiveDate::MIN).unwrap();
+        let records = [
+            Record::new(at("2001-05-01T09:00"), Event::Start, "a"),
+            Record::new(at("2001-05-01T10:00"), Event::Stop, ""),
+            Record::new(at("2001-05-01T11:00"), Event::Start, "tpyo"),
+        ];
+        write_records(test_file, &records).unwrap();
+        // 直さない行はコメントも読めない行も、秒未満の時刻もそのまま残す
+        let head = format!(
+            "# week 18\n{}not a record\n",
+            records[0].to_line().replace("09:00:00", "09:00:00.250")
+        );
+        let content = fs::read_to_string(test_file).unwrap();
+        let rest = &content[records[0].to_line().len()..];
+        fs::write(test_file, format!("{}{}", head, rest)).unwrap();
+        let args = |extra: &[&str]| {
+            let mut args = vec!["program_name", "amend", "-f", test_file];
+            args.extend(extra);
+            args.iter().map(|a| a.to_string()).collect::<Vec<String>>()
+        };
+        assert_eq!(
+            handle_amend_command(&args(&[]), &Config::default())
+                .unwrap_err()
+                .to_string(),
+            AMEND_USAGE_MSG
+        );
+        // start と同じ種類のエラーで、同じ終了コードになる
+        assert_eq!(
+            handle_amend_command(&args(&["--time", "09:30"]), &Config::default()).unwrap_err(),
+            RecorderError::InvalidTimestamp(
+                "2001-05-01 09:30 is before the previous record (2001-05-01 10:00).".to_string()
+            )
+        );
+        assert_eq!(
+            handle_amend_command(&args(&["--time", "2999-01-01 10:00"]), &Config::default())
+                .unwrap_err()
+                .exit_code(),
+            RecorderError::InvalidTimestamp(String::new()).exit_code()
+        );
+        assert!(handle_amend_command(
+            &args(&["--task", "typo", "--time", "10:30"]),
+            &Config::default()
+        )
+        .is_ok());
+        let content = fs::read_to_string(test_file).unwrap();
+        assert!(content.starts_with(&format!("{}{}", head, records[1].to_line())));
+        let amended = record::parse_records_lenient(&content).0;
+        assert_eq!(amended[2].task, "typo");
+        assert_eq!(amended[2].timestamp, at("2001-05-01T10:30"));
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_log_command() {
+        let test_file = "test_log_record.txt";
+        fs::write(
+            test_file,
+            "2001-05-01T09:00:00+09:00\tstart\ta\n2001-05-01T10:00:00+09:00\tstop\t\n",
+        )
+        .unwrap();
+        let args = |extra: &[&str]| {
+            let mut args = vec!["program_name", "log", "-f", test_file];
+            args.extend(extra);
+            args.iter().map(|a| a.to_string()).collect::<Vec<String>>()
+        };
+        assert!(handle_log_command(&args(&[]), &Config::default()).is_ok());
+        assert!(handle_log_command(&args(&["-n", "1", "--today"]), &Config::default()).is_ok());
+        assert!(handle_log_command(&args(&["-n", "x"]), &Config::default()).is_err());
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_status_command() {
+        let test_file = "test_status_record.txt";
+        let args = vec![
+            "program_name".to_string(),
+            "status".to_string(),
+            "-f".to_string(),
+            test_file.to_string(),
+        ];
+        assert!(handle_status_command(&args, &Config::default()).is_ok());
+        fs::write(test_file, "2001-05-01T09:00:00+09:00\tstart\ta\n").unwrap();
+        assert!(handle_status_command(&args, &Config::default()).is_ok());
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_resume_command() {
+        let test_file = "test_resume_record.txt";
+        fs::write(
+            test_file,
+            "2001-05-01T09:00:00+09:00\tstart\ta\t+x\n2001-05-01T10:00:00+09:00\tstop\t\n",
+        )
+        .unwrap();
+        let args = vec![
+            "program_name".to_string(),
+            "resume".to_string(),
+            "-f".to_string(),
+            test_file.to_string(),
+        ];
+        // 最後のレコードより前には再開しない
+        let mut before_stop = args.clone();
+        before_stop.extend(["--at".to_string(), "2001-04-30 00:00".to_string()]);
+        assert!(handle_resume_command(&before_stop, &Config::default())
+            .unwrap_err()
+            .to_string()
+            .contains("is before the last record"));
+        assert!(handle_resume_command(&args, &Config::default()).is_ok());
+        let records = read_records(test_file).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[2].task, "a");
+        assert_eq!(records[2].tags, vec!["x".to_string()]);
+        assert!(handle_resume_command(&args, &Config::default())
+            .unwrap_err()
+            .to_string()
+            .contains("is still running"));
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_resume_command_nth() {
+        let test_file = "test_resume_nth_record.txt";
+        fs::write(
+            test_file,
+            "2001-05-01T09:00:00+09:00\tstart\ta\t+x\n2001-05-01T10:00:00+09:00\tstop\t\n\
+             2001-05-01T11:00:00+09:00\tstart\tb\n2001-05-01T12:00:00+09:00\tstop\t\n\
+             2001-05-01T13:00:00+09:00\tstart\tb\n2001-05-01T14:00:00+09:00\tstop\t\n",
+        )
+        .unwrap();
+        let args = |n: &str| {
+            ["program_name", "continue", "-n", n, "-f", test_file]
+                .iter()
+                .map(|a| a.to_string())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            handle_resume_command(&args("3"), &Config::default())
+                .unwrap_err()
+                .to_string(),
+            "No recent task #3."
+        );
+        assert!(handle_resume_command(&args("2"), &Config::default()).is_ok());
+        let records = read_records(test_file).unwrap();
+        assert_eq!(records[6].task, "a");
+        assert_eq!(records[6].tags, vec!["x".to_string()]);
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_stop_command_yesterday() {
+        let test_file = "test_stop_yesterday_record.txt";
+        fs::write(test_file, "2001-05-01T09:00:00+09:00\tstart\ta\n").unwrap();
+        let args = vec![
+            "program_name".to_string(),
+            "stop".to_string(),
+            "--yesterday".to_string(),
+            "18:00".to_string(),
+            "-f".to_string(),
+            test_file.to_string(),
+        ];
+        assert!(handle_stop_command(&args, &Config::default()).is_ok());
+        let records = read_records(test_file).unwrap();
+        assert_eq!(records.len(), 2);
+        let yesterday = Local::now().date_naive() - Duration::days(1);
+        assert_eq!(
+            records[1].timestamp.naive_local().to_string(),
+            format!("{} 18:00:00", yesterday)
+        );
+        assert_eq!(
+            handle_stop_command(&args, &Config::default()).unwrap_err(),
+            RecorderError::NoOpenSession
+        );
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_add_command() {
+        let test_file = "test_add_record.txt";
+        fs::write(
+            test_file,
+            "2024-05-01T09:00:00.250+09:00\tstart\ta\n2024-05-01T10:00:00+09:00\tstop\t\n\
+             # holiday\n\
+             2024-05-03T09:00:00+09:00\tstart\tc\n2024-05-03T10:00:00+09:00\tstop\t\n",
+        )
+        .unwrap();
+        let args = |extra: &[&str]| {
+            let mut args = vec!["program_name".to_string(), "add".to_string()];
+            args.extend(extra.iter().map(|s| s.to_string()));
+            args.extend(["-f".to_string(), test_file.to_string()]);
+            args
+        };
+        assert!(handle_add_command(
+            &args(&[
+                "b",
+                "2024-05-02T13:00:00+09:00",
+                "2024-05-02T14:30:00+09:00",
+                "-t",
+                "x"
+            ]),
+            &Config::default()
+        )
+        .is_ok());
+        // 書き足した行のほかは、コメントも秒未満の時刻もそのまま
+        let content = fs::read_to_string(test_file).unwrap();
+        assert!(content.starts_with("2024-05-01T09:00:00.250+09:00\tstart\ta\n"));
+        assert!(content.contains("# holiday\n"));
+        let records = read_records(test_file).unwrap();
+        assert_eq!(records.len(), 6);
+        assert_eq!(records[2].task, "b");
+        assert_eq!(records[2].tags, vec!["x".to_string()]);
+        assert_eq!(records[3].event, Event::Stop);
+        assert_eq!(
+            handle_add_command(&args(&["b", "10:00"]), &Config::default())
+                .unwrap_err()
+                .to_string(),
+            ADD_USAGE_MSG
+        );
+        assert!(handle_add_command(
+            &args(&["b", "--yesterday", "14:00", "13:00"]),
+            &Config::default()
+        )
+        .is_err());
+
+        // a (09:00-10:00) と重なる
+        let overlapping = [
+            "d",
+            "2024-05-01T09:30:00+09:00",
+            "2024-05-01T10:30:00+09:00",
+        ];
+        assert!(handle_add_command(&args(&overlapping), &Config::default())
+            .unwrap_err()
+            .to_string()
+            .starts_with("'d' overlaps 'a' "));
+        assert_eq!(read_records(test_file).unwrap().len(), 6);
+        let clipped = [&overlapping[..], &["--clip"]].concat();
+        handle_add_command(&args(&clipped), &Config::default()).unwrap();
+        let records = read_records(test_file).unwrap();
+        assert_eq!(records.len(), 8);
+        assert_eq!(records[2].task, "d");
+        assert_eq!(
+            records[2].timestamp,
+            DateTime::parse_from_rfc3339("2024-05-01T10:00:00+09:00").unwrap()
+        );
+        let allowed = [&overlapping[..], &["--allow-overlap"]].concat();
+        handle_add_command(&args(&allowed), &Config::default()).unwrap();
+        assert_eq!(read_records(test_file).unwrap().len(), 10);
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_dump_and_load_commands() {
+        let test_file = "test_dump_record.txt";
+        let dump_file = "test_dump_record.json";
+        let loaded_file = "test_dump_loaded_record.txt";
+        let content = "# 2024-05 submitted on 2024-06-01T12:00:00+09:00\n\
+                       2024-05-01T09:00:00+09:00\tstart\ta\t+x\n\
+                       2024-05-01T10:00:00+09:00\tstop\t\n";
+        fs::write(test_file, content).unwrap();
+        let dump_args = vec![
+            "program_name".to_string(),
+            "dump".to_string(),
+            "-o".to_string(),
+            dump_file.to_string(),
+            "-f".to_string(),
+            test_file.to_string(),
+        ];
+        assert!(handle_dump_command(&dump_args, &Config::default()).is_ok());
+        let load_args = vec![
+            "program_name".to_string(),
+            "load".to_string(),
+            dump_file.to_string(),
+            "-f".to_string(),
+            loaded_file.to_string(),
+        ];
+        assert!(handle_load_command(&load_args, &Config::default()).is_ok());
+        assert_eq!(fs::read_to_string(loaded_file).unwrap(), content);
+        assert!(handle_load_command(&load_args, &Config::default())
+            .unwrap_err()
+            .to_string()
+            .contains("already has records"));
+        for file in [test_file, dump_file, loaded_file] {
+            fs::remove_file(file).unwrap();
+        }
+        fs::remove_file(state::state_path(loaded_file)).unwrap();
+    }
+
+    #[test]
+    fn test_handle_convert_command() {
+        let test_file = "test_convert_record.txt";
+        fs::write(
+            test_file,
+            "2001-05-01T09:00:00+09:00\tstart\ta\t+x\n2001-05-01T10:00:00+09:00\tstop\t\n",
+        )
+        .unwrap();
+        let args = |args: &[&str]| {
+            let mut args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            args.splice(0..0, ["program_name".to_string()]);
+            args.extend(["-f".to_string(), test_file.to_string()]);
+            args
+        };
+        let before = read_records(test_file).unwrap();
+        assert_eq!(
+            handle_convert_command(&args(&["convert"]), &Config::default())
+                .unwrap_err()
+                .to_string(),
+            FORMAT_NOT_PROVIDED_MSG
+        );
+        assert!(handle_convert_command(
+            &args(&["convert", "--format", "jsonl"]),
+            &Config::default()
+        )
+        .is_ok());
+        assert_eq!(read_records(test_file).unwrap(), before);
+
+        // 追記も JSON Lines になる
+        assert!(handle_start_command(&args(&["start", "a\tb"]), &Config::default()).is_ok());
+        let content = fs::read_to_string(test_file).unwrap();
+        assert!(content.starts_with("# format: jsonl\n"));
+        assert!(content.lines().skip(1).all(|line| line.starts_with('{')));
+        assert_eq!(read_records(test_file).unwrap()[2].task, "a\tb");
+
+        // 空のファイルでも書式を覚えておく
+        fs::write(test_file, "").unwrap();
+        assert!(handle_convert_command(
+            &args(&["convert", "--format", "jsonl"]),
+            &Config::default()
+        )
+        .is_ok());
+        assert!(handle_start_command(&args(&["start", "b"]), &Config::default()).is_ok());
+        let content = fs::read_to_string(test_file).unwrap();
+        assert!(content.lines().nth(1).unwrap().starts_with('{'));
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_plan_command() {
+        let test_file = "test_plan_record.txt";
+        let args = |extra: &[&str]| {
+            let mut args = vec!["program_name".to_string(), "plan".to_string()];
+            args.extend(extra.iter().map(|s| s.to_string()));
+            args.extend(["-f".to_string(), test_file.to_string()]);
+            args
+        };
+        let plan = ["add", "09:00-11:00", "spec review", "--date", "2024-05-01"];
+        assert!(handle_plan_command(&args(&plan), &Config::default()).is_ok());
+        let plans = State::load(test_file).unwrap().plans;
+        assert_eq!(plans.len(), 1);
+        assert_eq!(plans[0].task, "spec review");
+        assert!(handle_plan_command(
+            &args(&["clear", "--date", "2024-05-01"]),
+            &Config::default()
+        )
+        .is_ok());
+        assert!(State::load(test_file).unwrap().plans.is_empty());
+        assert_eq!(
+            handle_plan_command(&args(&["add", "09:00-11:00"]), &Config::default())
+                .unwrap_err()
+                .to_string(),
+            PLAN_USAGE_MSG
+        );
+        fs::remove_file(state::state_path(test_file)).unwrap();
+    }
+
+    #[test]
+    fn test_handle_approve_command() {
+        let test_file = "test_approve_record.txt";
+        fs::write(
+            test_file,
+            "# May\n2024-05-01T09:00:00+09:00\tstart\ta\n2024-05-01T10:00:00.250+09:00\tstop\t\n",
+        )
+        .unwrap();
+        let args = |extra: &[&str]| {
+            let mut args = vec!["program_name".to_string(), "approve".to_string()];
+            args.extend(extra.iter().map(|s| s.to_string()));
+            args.extend(["-f".to_string(), test_file.to_string()]);
+            args
+        };
+        assert!(handle_approve_command(
+            &args(&["1", "--reject", "--by", "lead"]),
+            &Config::default()
+        )
+        .is_ok());
+        let records = read_records(test_file).unwrap();
+        assert_eq!(records[0].fields[0], ("review".into(), "rejected".into()));
+        assert_eq!(records[0].fields[1], ("reviewer".into(), "lead".into()));
+        // 印を付けない行は、コメントも秒未満の時刻もそのまま
+        let content = fs::read_to_string(test_file).unwrap();
+        assert!(content.starts_with("# May\n"));
+        assert!(content.ends_with("\n2024-05-01T10:00:00.250+09:00\tstop\t\n"));
+        assert_eq!(
+            handle_approve_command(&args(&["2", "--by", "lead"]), &Config::default())
+                .unwrap_err()
+                .to_string(),
+            "No entry with id 2 (1 entries recorded)."
+        );
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_report_command() {
+        let test_file = "test_report_record.txt";
+        fs::write(
+            test_file,
+            "2024-05-01T09:00:00+09:00\tstart\ttest_task\n2024-05-01T10:00:00+09:00\tstop\t\n",
+        )
+        .unwrap();
+        let args = vec![
+            "program_name".to_string(),
+            "report".to_string(),
+            "--from".to_string(),
+            "2024-04-30".to_string(),
+            "--to".to_string(),
+            "2024-05-02".to_string(),
+            "--sparkline".to_string(),
+            "-f".to_string(),
+            test_file.to_string(),
+        ];
+        assert!(handle_report_command(&args, &Config::default()).is_ok());
+        // 2 回目はキャッシュから出す
+        assert!(fs::metadata(cache::cache_path(test_file)).is_ok());
+        assert!(handle_report_command(&args, &Config::default()).is_ok());
+        fs::remove_file(cache::cache_path(test_file)).unwrap();
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_report_cache_key_covers_timezone() {
+        let config = Config::default();
+        let args = vec!["--day".to_string(), "2024-05-01".to_string()];
+        let local = DateTime::parse_from_rfc3339("2024-05-01T12:00:00+09:00").unwrap();
+        let utc = local.with_timezone(&chrono::Utc).fixed_offset();
+        assert_ne!(
+            report_cache_key("test_cache_key_record.txt", &config, &args, local),
+            report_cache_key("test_cache_key_record.txt", &config, &args, utc)
+        );
+    }
+
+    #[test]
+    fn test_load_sessions_unions_sources() {
+        let primary = "test_sources_primary.txt";
+        let team = "test_sources_team.txt";
+        fs::write(primary, "2024-05-01T09:00:00+09:00\tstart\tmine\n").unwrap();
+        fs::write(
+            team,
+            "2024-05-01T08:00:00+09:00\tstart\tshared\n2024-05-01T10:00:00+09:00\tstop\t\n",
+        )
+        .unwrap();
+        let config = Config::parse(&format!(
+            "[[sources]]\nname = \"team\"\npath = \"{}\"\n",
+            team
+        ))
+        .unwrap();
+
+        let sessions = RecordStore::new(primary).sessions(&config).unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].task, "shared");
+        assert_eq!(sessions[0].field(sources::ORIGIN_FIELD), Some("team"));
+        // 別のファイルの start では閉じない
+        assert_eq!(sessions[1].stop, None);
+        assert_eq!(sessions[1].field(sources::ORIGIN_FIELD), Some("primary"));
+
+        let records = RecordStore::new(primary).all_records(&config).unwrap();
+        assert_eq!(records.len(), 3);
+        assert!(records[0].to_line().ends_with("\torigin=team\n"));
+        fs::remove_file(primary).unwrap();
+        fs::remove_file(team).unwrap();
+    }
+
+    #[test]
+    fn test_project_sessions() {
+        let client_a = "test_projects_client_a.txt";
+        let client_b = "test_projects_client_b.txt";
+        fs::write(
+            client_a,
+            "2024-05-01T09:00:00+09:00\tstart\tfix\n2024-05-01T10:00:00+09:00\tstop\t\n",
+        )
+        .unwrap();
+        fs::write(
+            client_b,
+            "2024-05-01T08:00:00+09:00\tstart\tother:call\n2024-05-01T08:30:00+09:00\tstop\t\n",
+        )
+        .unwrap();
+        let config = Config::parse(&format!(
+            "[projects.clientA]\nfile = \"{}\"\n[projects.clientB]\nfile = \"{}\"\n",
+            client_a, client_b
+        ))
+        .unwrap();
+
+        let now = parse_local_datetime("2024-05-02T09:00", NaiveDate::MIN).unwrap();
+        let sessions = project_sessions(&config, None, now).unwrap();
+        let tasks: Vec<&str> = sessions.iter().map(|s| s.task.as_str()).collect();
+        // プロジェクトの付いたタスクはそのまま
+        assert_eq!(tasks, vec!["other:call", "clientA:fix"]);
+        fs::remove_file(client_a).unwrap();
+        fs::remove_file(client_b).unwrap();
+    }
+
+    #[test]
+    fn test_handle_report_command_reads_stdin() {
+        let args: Vec<String> = ["program_name", "report", "--day", "2001-05-01", "-f", "-"]
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        // 子プロセスとして呼ばれたときは、親が流し込んだ標準入力で report する
+        if env::var_os("WTR_TEST_STDIN_CHILD").is_some() {
+            handle_report_command(&args, &Config::default()).unwrap();
+            return;
+        }
+        let mut child = Command::new(env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "tests::test_handle_report_command_reads_stdin",
+                "--nocapture",
+            ])
+            .env("WTR_TEST_STDIN_CHILD", "1")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(
+                b"2001-05-01T09:00:00+09:00\tstart\tpiped\n2001-05-01T10:00:00+09:00\tstop\t\n",
+            )
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert!(stdout.contains("piped"), "{}", stdout);
+        assert!(stdout.contains("1h00m"), "{}", stdout);
+    }
+
+    #[test]
+    fn test_handle_report_command_period_units() {
+        let test_file = "test_report_units_record.txt";
+        fs::write(
+            test_file,
+            "2024-05-01T09:00:00+09:00\tstart\ttest_task\n2024-05-01T10:00:00+09:00\tstop\t\n",
+        )
+        .unwrap();
+        for unit in [
+            ["--day", "2024-05-01"],
+            ["--week", "2024-05-03"],
+            ["--month", "2024-05"],
+        ] {
+            let args = vec![
+                "program_name".to_string(),
+                "report".to_string(),
+                unit[0].to_string(),
+                unit[1].to_string(),
+                "-f".to_string(),
+                test_file.to_string(),
+            ];
+            assert!(handle_report_command(&args, &Config::default()).is_ok());
+        }
+        cache::invalidate(test_file).unwrap();
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_parse_anchor_date() {
+        let today = parse_date("2024-05-10").unwrap();
+        assert_eq!(
+            parse_anchor_date("yesterday", today, false).unwrap(),
+            parse_date("2024-05-09").unwrap()
+        );
+        assert_eq!(
+            parse_anchor_date("2024-04", today, true).unwrap(),
+            parse_date("2024-04-01").unwrap()
+        );
+        assert!(parse_anchor_date("2024-04", today, false).is_err());
+    }
+
+    #[test]
+    fn test_handle_report_command_invalid_option() {
+        let args = vec![
+            "program_name".to_string(),
+            "report".to_string(),
+            "--bogus".to_string(),
+        ];
+        let result = handle_report_command(&args, &Config::default());
+        assert_eq!(result.unwrap_err().to_string(), "Invalid option '--bogus'.");
+    }
+
+    #[test]
+    fn test_handle_compare_command_missing_period() {
+        let args = vec![
+            "program_name".to_string(),
+            "compare".to_string(),
+            "--a".to_string(),
+            "2024-04".to_string(),
+        ];
+        let result = handle_compare_command(&args, &Config::default());
+        assert_eq!(result.unwrap_err().to_string(), PERIODS_NOT_PROVIDED_MSG);
+    }
+
+    #[test]
+    fn test_handle_fill_command_without_entries() {
+        let test_file = "test_fill_record.txt";
+        let args = vec![
+            "program_name".to_string(),
+            "fill".to_string(),
+            "--from".to_string(),
+            "2024-05-01".to_string(),
+            "--to".to_string(),
+            "2024-05-31".to_string(),
+            "-f".to_string(),
+            test_file.to_string(),
+        ];
+        assert!(handle_fill_command(&args, &Config::default()).is_ok());
+        assert!(fs::metadata(test_file).is_err());
+    }
+
+    #[test]
+    fn test_handle_since_command_missing_time() {
+        let args = vec![
+            "program_name".to_string(),
+            "since".to_string(),
+            "--task".to_string(),
+            "incident".to_string(),
+        ];
+        let result = handle_since_command(&args, &Config::default());
+        assert_eq!(result.unwrap_err().to_string(), TIME_NOT_PROVIDED_MSG);
+    }
+
+    #[test]
+    fn test_handle_prune_command_archives_old_records() {
+        let test_file = "test_prune_record.txt";
+        let archive_file = "test_prune_archive.txt";
+        fs::write(
+            test_file,
+            "2001-05-01T09:00:00+09:00\tstart\told\n2001-05-01T10:00:00+09:00\tstop\t\n\
+             # kept by hand\n\
+             2001-05-02T09:00:00.250+09:00\tstart\trunning\n",
+        )
+        .unwrap();
+        let args = vec![
+            "program_name".to_string(),
+            "prune".to_string(),
+            "--older-than".to_string(),
+            "3y".to_string(),
+            "--archive".to_string(),
+            archive_file.to_string(),
+            "-f".to_string(),
+            test_file.to_string(),
+        ];
+        assert!(handle_prune_command(&args, &Config::default()).is_ok());
+        // 消した行のほかは、コメントも秒未満の時刻もそのまま
+        assert_eq!(
+            fs::read_to_string(test_file).unwrap(),
+            "# kept by hand\n2001-05-02T09:00:00.250+09:00\tstart\trunning\n"
+        );
+        assert!(fs::read_to_string(archive_file)
+            .unwrap()
+            .contains("start\told"));
+        fs::remove_file(test_file).unwrap();
+        fs::remove_file(archive_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_lock_command_protects_period() {
+        let test_file = "test_lock_record.txt";
+        let content = "2001-05-01T09:00:00+09:00\tstart\told\n2001-05-01T10:00:00+09:00\tstop\t\n";
+        fs::write(test_file, content).unwrap();
+        let args = |extra: &[&str]| -> Vec<String> {
+            ["program_name"]
+                .iter()
+                .chain(extra)
+                .chain(&["-f", test_file])
+                .map(|s| s.to_string())
+                .collect()
+        };
+        assert!(
+            handle_lock_command(&args(&["lock", "--period", "2001-05"]), &Config::default())
+                .is_ok()
+        );
+
+        let prune = args(&["prune", "--older-than", "3y"]);
+        let err = handle_prune_command(&prune, &Config::default())
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("2001-05 is locked"));
+        assert_eq!(fs::read_to_string(test_file).unwrap(), content);
+
+        assert!(handle_unlock_command(
+            &args(&["unlock", "--period", "2001-05"]),
+            &Config::default()
+        )
+        .is_ok());
+        assert!(handle_prune_command(&prune, &Config::default()).is_ok());
+        fs::remove_file(test_file).unwrap();
+        fs::remove_file(state::state_path(test_file)).unwrap();
+    }
+
+    #[test]
+    fn test_handle_close_command() {
+        let test_file = "test_close_record.txt";
+        let output_dir = "test_close_output";
+        let content = "2001-05-01T09:00:00+09:00\tstart\ta\n2001-05-01T10:00:00+09:00\tstop\t\n";
+        fs::write(test_file, content).unwrap();
+        let args = |extra: &[&str]| -> Vec<String> {
+            [
+                "program_name",
+                "close",
+                "--month",
+                "2001-05",
+                "-o",
+                output_dir,
+            ]
+            .iter()
+            .chain(extra)
+            .chain(&["-f", test_file])
+            .map(|s| s.to_string())
+            .collect()
+        };
+        // 記録の無い平日があるので --force が無ければ締めない
+        let err = handle_close_command(&args(&[]), &Config::default())
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("2001-05 is not ready to close."));
+        assert!(!Path::new(output_dir).exists());
+
+        assert!(handle_close_command(&args(&["--force"]), &Config::default()).is_ok());
+        assert_eq!(State::load(test_file).unwrap().locks[0].period, "2001-05");
+        let sessions = fs::read_to_string(Path::new(output_dir).join("sessions.csv")).unwrap();
+        assert_eq!(sessions.lines().count(), 2);
+        assert!(Path::new(output_dir).join("billing.csv").exists());
+        fs::remove_file(test_file).unwrap();
+        fs::remove_file(state::state_path(test_file)).unwrap();
+        fs::remove_dir_all(output_dir).unwrap();
+    }
+
+    #[test]
+    fn test_handle_invoice_command() {
+        let test_file = "test_invoice_record.txt";
+        let output = "test_invoice_output.csv";
+        let content = "2001-05-01T09:00:00+09:00\tstart\tclientA:fix\tbillable=true\n\
+                       2001-05-01T10:00:00+09:00\tstop\t\n\
+                       2001-05-01T11:00:00+09:00\tstart\tinternal\n\
+                       2001-05-01T12:00:00+09:00\tstop\t\n";
+        fs::write(test_file, content).unwrap();
+        let args = |command: &str, extra: &[&str]| -> Vec<String> {
+            [
+                "program_name",
+                command,
+                "--from",
+                "2001-04-30",
+                "--to",
+                "2001-05-02",
+            ]
+            .iter()
+            .chain(extra)
+            .chain(&["-f", test_file])
+            .map(|s| s.to_string())
+            .collect()
+        };
+        assert!(handle_invoice_command(
+            &args("invoice", &["-o", output, "--finalize"]),
+            &Config::default()
+        )
+        .is_ok());
+        let csv = fs::read_to_string(output).unwrap();
+        assert_eq!(csv.lines().count(), 2);
+        assert!(csv.contains("clientA,fix"));
+        let invoices = State::load(test_file).unwrap().invoices;
+        assert_eq!(invoices.len(), 1);
+        assert_eq!(invoices[0].number, 1);
+
+        // 請求済みの時間は二度請求しない
+        assert!(
+            handle_invoice_command(&args("invoice", &[]), &Config::default())
+                .unwrap_err()
+                .to_string()
+                .starts_with("No uninvoiced billable time")
+        );
+        assert!(handle_report_command(
+            &args("report", &["--uninvoiced", "--no-cache"]),
+            &Config::default()
+        )
+        .is_ok());
+        fs::remove_file(test_file).unwrap();
+        fs::remove_file(output).unwrap();
+        fs::remove_file(state::state_path(test_file)).unwrap();
+    }
+
+    #[test]
+    fn test_handle_import_command_skips_duplicates() {
+        let test_file = "test_import_record.txt";
+        let input_file = "test_import_input.csv";
+        fs::write(
+            input_file,
+            "\u{feff}\"Task\";\"Start\";\"End\"\n\"a; b\";2001-05-01 09:00;2001-05-01 10:00\n",
+        )
+        .unwrap();
+        fs::write(test_file, "# from the old tracker\n").unwrap();
+        let args = vec![
+            "program_name".to_string(),
+            "import".to_string(),
+            input_file.to_string(),
+            "--delimiter".to_string(),
+            ";".to_string(),
+            "-f".to_string(),
+            test_file.to_string(),
+        ];
+        assert!(handle_import_command(&args, &Config::default()).is_ok());
+        assert!(handle_import_command(&args, &Config::default()).is_ok());
+        let records = read_records(test_file).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].task, "a; b");
+        assert!(fs::read_to_string(test_file)
+            .unwrap()
+            .starts_with("# from the old tracker\n"));
+
+        // 取り込んだ後で直したものも、取り込み済みのハッシュで飛ばす
+        let content = fs::read_to_string(test_file).unwrap();
+        fs::write(test_file, content.replace("a; b", "a")).unwrap();
+        assert!(handle_import_command(&args, &Config::default()).is_ok());
+        assert_eq!(read_records(test_file).unwrap().len(), 2);
+        fs::remove_file(test_file).unwrap();
+        fs::remove_file(state::state_path(test_file)).unwrap();
+        fs::remove_file(input_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_export_command_anonymize() {
+        let test_file = "test_export_record.txt";
+        let output_file = "test_export_output.txt";
+        fs::write(
+            test_file,
+            "2024-05-01T09:00:00+09:00\tstart\tclientA:secret\n",
+        )
+        .unwrap();
+        let args = vec![
+            "program_name".to_string(),
+            "export".to_string(),
+            "--anonymize".to_string(),
+            "-o".to_string(),
+            output_file.to_string(),
+            "-f".to_string(),
+            test_file.to_string(),
+        ];
+        assert!(handle_export_command(&args, &Config::default()).is_ok());
+        let content = fs::read_to_string(output_file).unwrap();
+        assert!(content.starts_with("2024-05-01T09:00:00+09:00\tstart\tproject-"));
+        assert!(!content.contains("secret"));
+        fs::remove_file(test_file).unwrap();
+        fs::remove_file(output_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_export_command_grid() {
+        let test_file = "test_export_grid_record.txt";
+        let output_file = "test_export_grid_output.md";
+        fs::write(
+            test_file,
+            "2024-05-08T12:00:00Z\tstart\tclientA:fix\n2024-05-08T14:00:00Z\tstop\t\n",
+        )
+        .unwrap();
+        let args = |extra: &[&str]| {
+            let mut args = vec!["program_name", "export"];
+            args.extend(extra);
+            args.extend(["-o", output_file, "-f", test_file]);
+            args.iter().map(|a| a.to_string()).collect::<Vec<String>>()
+        };
+        assert!(handle_export_command(
+            &args(&["grid", "--week", "2024-05-08", "--markdown"]),
+            &Config::default()
+        )
+        .is_ok());
+        let content = fs::read_to_string(output_file).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("| project | 2024-05-06 | "));
+        assert!(lines[2].starts_with("| clientA | "));
+        assert!(lines[3].ends_with(" | 2 |"));
+        assert_eq!(
+            handle_export_command(&args(&["csv", "--week"]), &Config::default())
+                .unwrap_err()
+                .to_string(),
+            "--week and --markdown need the grid format."
+        );
+        assert!(
+            handle_export_command(&args(&["pdf", "--month", "2024-05"]), &Config::default())
+                .is_ok()
+        );
+        let content = fs::read_to_string(output_file).unwrap();
+        assert!(content.starts_with("%PDF-"));
+        assert!(content.contains("(Timesheet 2024-05-01 - 2024-05-31)"));
+        assert!(content.contains("(2024-05-31 Fri)"));
+        assert!(handle_export_command(&args(&["csv", "--month"]), &Config::default()).is_err());
+        fs::remove_file(test_file).unwrap();
+        fs::remove_file(output_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_export_command_json_range() {
+        let test_file = "test_export_json_record.txt";
+        let output_file = "test_export_json_output.json";
+        fs::write(
+            test_file,
+            "2024-05-01T12:00:00Z\tstart\ta\n2024-05-01T13:00:00Z\tstop\t\n\
+             2024-05-03T12:00:00Z\tstart\tb\n2024-05-03T13:00:00Z\tstop\t\n",
+        )
+        .unwrap();
+        let args = vec![
+            "program_name".to_string(),
+            "export".to_string(),
+            "--format".to_string(),
+            "json".to_string(),
+            "--fields".to_string(),
+            "task".to_string(),
+            "--from".to_string(),
+            "2024-05-02".to_string(),
+            "-o".to_string(),
+            output_file.to_string(),
+            "-f".to_string(),
+            test_file.to_string(),
+        ];
+        assert!(handle_export_command(&args, &Config::default()).is_ok());
+        let content = fs::read_to_string(output_file).unwrap();
+        assert_eq!(content, "[\n  {\n    \"task\": \"b\"\n  }\n]\n");
+        fs::remove_file(test_file).unwrap();
+        fs::remove_file(output_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_export_command_csv_fields() {
+        let test_file = "test_export_csv_record.txt";
+        let output_file = "test_export_csv_output.csv";
+        fs::write(
+            test_file,
+            "2024-05-01T09:00:00+09:00\tstart\tclientA:fix\n2024-05-01T10:30:00+09:00\tstop\t\n",
+        )
+        .unwrap();
+        let args = vec![
+            "program_name".to_string(),
+            "export".to_string(),
+            "csv".to_string(),
+            "--fields".to_string(),
+            "project,task,duration".to_string(),
+            "-o".to_string(),
+            output_file.to_string(),
+            "-f".to_string(),
+            test_file.to_string(),
+        ];
+        assert!(handle_export_command(&args, &Config::default()).is_ok());
+        let content = fs::read_to_string(output_file).unwrap();
+        assert_eq!(content, "project,task,duration\nclientA,fix,1.5\n");
+        fs::remove_file(test_file).unwrap();
+        fs::remove_file(output_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_untracked_command() {
+        let test_file = "test_untracked_record.txt";
+        fs::write(
+            test_file,
+            "2024-05-03T09:00:00+09:00\tstart\ta\n2024-05-03T12:00:00+09:00\tstop\t\n",
+        )
+        .unwrap();
+        let args = |workday: &str| {
+            vec![
+                "program_name".to_string(),
+                "untracked".to_string(),
+                "--workday".to_string(),
+                workday.to_string(),
+                "--from".to_string(),
+                "2024-05-01".to_string(),
+                "--to".to_string(),
+                "2024-05-03".to_string(),
+                "-f".to_string(),
+                test_file.to_string(),
+            ]
+        };
+        assert!(handle_untracked_command(&args("09:00-18:00"), &Config::default()).is_ok());
+        assert_eq!(
+            handle_untracked_command(&args("18:00"), &Config::default())
+                .unwrap_err()
+                .to_string(),
+            "Invalid working hours '18:00'."
+        );
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_handle_demo_command() {
+        let output_file = "test_demo_output.txt";
+        let args = vec![
+            "program_name".to_string(),
+            "demo".to_string(),
+            "--days".to_string(),
+            "14".to_string(),
+            "--seed".to_string(),
+            "3".to_string(),
+            "-o".to_string(),
+            output_file.to_string(),
+        ];
+        assert!(handle_demo_command(&args).is_ok());
+        assert!(!read_records(output_file).unwrap().is_empty());
+        assert_eq!(
+            handle_demo_command(&args).unwrap_err().to_string(),
+            format!("'{}' already exists.", output_file)
+        );
+        fs::remove_file(output_file).unwrap();
     }
 
     #[test]
@@ -186,7 +4692,7 @@ mod tests {
             "start".to_string(),
             "test_task".to_string(),
         ];
-        let (file_path, remaining_args) = parse_arguments(&args).unwrap();
+        let (file_path, remaining_args) = parse_arguments(&args, &Config::default()).unwrap();
         assert!(file_path.contains("working_time_record.txt"));
         assert_eq!(remaining_args, vec!["test_task".to_string()]);
     }
@@ -200,7 +4706,7 @@ mod tests {
             "-f".to_string(),
             "custom_file.txt".to_string(),
         ];
-        let (file_path, remaining_args) = parse_arguments(&args).unwrap();
+        let (file_path, remaining_args) = parse_arguments(&args, &Config::default()).unwrap();
         assert_eq!(file_path, "custom_file.txt");
         assert_eq!(remaining_args, vec!["test_task".to_string()]);
     }
@@ -212,8 +4718,8 @@ mod tests {
             "start".to_string(),
             "-f".to_string(),
         ];
-        let result = parse_arguments(&args);
+        let result = parse_arguments(&args, &Config::default());
         assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), FILENAME_NOT_PROVIDED_MSG);
+        assert_eq!(result.unwrap_err().to_string(), FILENAME_NOT_PROVIDED_MSG);
     }
 }
@@ -0,0 +1,178 @@
+use crate::export::Locale;
+use chrono::{Datelike, NaiveDate, Weekday};
+
+// 曜日と月の名前。Default は英語。並びは月曜始まりと 1 月始まり。
+const WEEKDAYS_SHORT: [[&str; 7]; 4] = [
+    ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"],
+    ["Mo", "Di", "Mi", "Do", "Fr", "Sa", "So"],
+    ["lun.", "mar.", "mer.", "jeu.", "ven.", "sam.", "dim."],
+    ["月", "火", "水", "木", "金", "土", "日"],
+];
+const WEEKDAYS_LONG: [[&str; 7]; 4] = [
+    [
+        "Monday",
+        "Tuesday",
+        "Wednesday",
+        "Thursday",
+        "Friday",
+        "Saturday",
+        "Sunday",
+    ],
+    [
+        "Montag",
+        "Dienstag",
+        "Mittwoch",
+        "Donnerstag",
+        "Freitag",
+        "Samstag",
+        "Sonntag",
+    ],
+    [
+        "lundi", "mardi", "mercredi", "jeudi", "vendredi", "samedi", "dimanche",
+    ],
+    [
+        "月曜日",
+        "火曜日",
+        "水曜日",
+        "木曜日",
+        "金曜日",
+        "土曜日",
+        "日曜日",
+    ],
+];
+const MONTHS: [[&str; 12]; 3] = [
+    [
+        "January",
+        "February",
+        "March",
+        "April",
+        "May",
+        "June",
+        "July",
+        "August",
+        "September",
+        "October",
+        "November",
+        "December",
+    ],
+    [
+        "Januar",
+        "Februar",
+        "März",
+        "April",
+        "Mai",
+        "Juni",
+        "Juli",
+        "August",
+        "September",
+        "Oktober",
+        "November",
+        "Dezember",
+    ],
+    [
+        "janvier",
+        "février",
+        "mars",
+        "avril",
+        "mai",
+        "juin",
+        "juillet",
+        "août",
+        "septembre",
+        "octobre",
+        "novembre",
+        "décembre",
+    ],
+];
+
+fn index(locale: Locale) -> usize {
+    match locale {
+        Locale::Default | Locale::En => 0,
+        Locale::De => 1,
+        Locale::Fr => 2,
+        Locale::Ja => 3,
+    }
+}
+
+pub fn weekday_short(weekday: Weekday, locale: Locale) -> &'static str {
+    WEEKDAYS_SHORT[index(locale)][weekday.num_days_from_monday() as usize]
+}
+
+pub fn weekday_long(weekday: Weekday, locale: Locale) -> &'static str {
+    WEEKDAYS_LONG[index(locale)][weekday.num_days_from_monday() as usize]
+}
+
+// "May 2024" / "Mai 2024" / "mai 2024" / "2024年5月"
+pub fn month_year(date: NaiveDate, locale: Locale) -> String {
+    match locale {
+        Locale::Ja => format!("{}年{}月", date.year(), date.month()),
+        _ => format!(
+            "{} {}",
+            MONTHS[index(locale)][date.month0() as usize],
+            date.year()
+        ),
+    }
+}
+
+// 端末での表示幅。全角の文字は 2 桁と数える。
+pub fn display_width(s: &str) -> usize {
+    s.chars()
+        .map(|c| match c {
+            '\u{1100}'..='\u{115f}' | '\u{2e80}'..='\u{a4cf}' | '\u{ac00}'..='\u{d7a3}' => 2,
+            '\u{f900}'..='\u{faff}' | '\u{fe30}'..='\u{fe4f}' | '\u{ff00}'..='\u{ff60}' => 2,
+            _ => 1,
+        })
+        .sum()
+}
+
+// 表示幅が width になるよう右を空白で埋める
+pub fn pad(s: &str, width: usize) -> String {
+    format!(
+        "{}{}",
+        s,
+        " ".repeat(width.saturating_sub(display_width(s)))
+    )
+}
+
+// 短い曜日名の表示幅の最大。日付の列をそろえるのに使う。
+pub fn weekday_width(locale: Locale) -> usize {
+    WEEKDAYS_SHORT[index(locale)]
+        .iter()
+        .map(|name| display_width(name))
+        .max()
+        .unwrap_or(0)
+}
+
+// "Mon 05-06" のような、曜日付きの短い日付。曜日の幅はそろえる。
+pub fn short_date(date: NaiveDate, locale: Locale) -> String {
+    format!(
+        "{} {}",
+        pad(weekday_short(date.weekday(), locale), weekday_width(locale)),
+        date.format("%m-%d")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 6).unwrap();
+        assert_eq!(weekday_short(date.weekday(), Locale::Default), "Wed");
+        assert_eq!(weekday_long(date.weekday(), Locale::De), "Mittwoch");
+        assert_eq!(month_year(date, Locale::Fr), "mars 2024");
+        assert_eq!(month_year(date, Locale::De), "März 2024");
+        assert_eq!(month_year(date, Locale::Ja), "2024年3月");
+        assert_eq!(short_date(date, Locale::Ja), "水 03-06");
+        assert_eq!(short_date(date, Locale::Fr), "mer. 03-06");
+        assert_eq!(short_date(date, Locale::De), "Mi 03-06");
+    }
+
+    #[test]
+    fn test_pad() {
+        assert_eq!(display_width("月曜日"), 6);
+        assert_eq!(pad("月", 3), "月 ");
+        assert_eq!(pad("Mon", 2), "Mon");
+    }
+}
@@ -0,0 +1,130 @@
+use crate::session::{Session, NOTE_FIELD};
+use serde::Deserialize;
+use std::io::{BufRead, Write};
+
+// 候補として見せる過去のメモの数
+const HISTORY_SIZE: usize = 5;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteOnStop {
+    #[default]
+    Never,
+    // 端末から stop したときに 1 行のメモを尋ねる
+    Ask,
+}
+
+// 同じタスクに付けたメモを新しい順に重複なく返す
+pub fn history<'a>(sessions: &'a [Session], task: &str) -> Vec<&'a str> {
+    let mut notes: Vec<&str> = Vec::new();
+    for note in sessions
+        .iter()
+        .rev()
+        .filter(|s| s.task == task)
+        .filter_map(|s| s.field(NOTE_FIELD))
+    {
+        if !notes.contains(&note) {
+            notes.push(note);
+        }
+        if notes.len() == HISTORY_SIZE {
+            break;
+        }
+    }
+    notes
+}
+
+// 空行ならメモなし。"!!" は直前のメモ、"!n" は n 番目の候補を使う。
+pub fn prompt_note(
+    task: &str,
+    history: &[&str],
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<Option<String>, String> {
+    let mut prompt = String::new();
+    for (i, note) in history.iter().enumerate() {
+        prompt += &format!("  !{} {}\n", i + 1, note);
+    }
+    prompt += &format!("Note for '{}' (empty to skip): ", task);
+
+    loop {
+        write!(output, "{}", prompt).map_err(|e| e.to_string())?;
+        output.flush().map_err(|e| e.to_string())?;
+        let mut line = String::new();
+        if input.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        let reused = match line {
+            "!!" => history.first(),
+            _ => match line.strip_prefix('!').map(str::parse::<usize>) {
+                Some(Ok(n)) if n >= 1 => history.get(n - 1),
+                _ => return Ok(sanitize(line)),
+            },
+        };
+        match reused {
+            Some(note) => return Ok(Some(note.to_string())),
+            None => writeln!(output, "No such note.").map_err(|e| e.to_string())?,
+        }
+    }
+}
+
+// 記録ファイルの 1 列に収まるよう、タブや改行を含む空白を 1 つの空白にする
+fn sanitize(note: &str) -> Option<String> {
+    let note = note.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!note.is_empty()).then_some(note)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Duration};
+
+    fn session(task: &str, note: Option<&str>, minutes: i64) -> Session {
+        let start = DateTime::parse_from_rfc3339("2024-05-01T09:00:00Z").unwrap()
+            + Duration::minutes(minutes);
+        let mut session = Session::new(task, start, Some(start + Duration::minutes(10)));
+        if let Some(note) = note {
+            session
+                .fields
+                .push((NOTE_FIELD.to_string(), note.to_string()));
+        }
+        session
+    }
+
+    #[test]
+    fn test_history() {
+        let sessions = vec![
+            session("a", Some("first"), 0),
+            session("b", Some("other"), 10),
+            session("a", None, 20),
+            session("a", Some("second"), 30),
+            session("a", Some("first"), 40),
+        ];
+        assert_eq!(history(&sessions, "a"), vec!["first", "second"]);
+        assert!(history(&sessions, "c").is_empty());
+    }
+
+    #[test]
+    fn test_prompt_note() {
+        let history = ["fixed login", "reviewed spec"];
+        let mut output = Vec::new();
+        let note = prompt_note("a", &history, &mut "  tidy\tup  \n".as_bytes(), &mut output);
+        assert_eq!(note.unwrap().as_deref(), Some("tidy up"));
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("  !2 reviewed spec\n"));
+
+        let note = prompt_note("a", &history, &mut "!3\n!2\n".as_bytes(), &mut Vec::new());
+        assert_eq!(note.unwrap().as_deref(), Some("reviewed spec"));
+        let note = prompt_note("a", &history, &mut "!!\n".as_bytes(), &mut Vec::new());
+        assert_eq!(note.unwrap().as_deref(), Some("fixed login"));
+    }
+
+    #[test]
+    fn test_prompt_note_skip() {
+        let note = prompt_note("a", &[], &mut "\n".as_bytes(), &mut Vec::new());
+        assert_eq!(note.unwrap(), None);
+        let note = prompt_note("a", &[], &mut "".as_bytes(), &mut Vec::new());
+        assert_eq!(note.unwrap(), None);
+    }
+}
@@ -0,0 +1,31 @@
+// デスクトップ通知。OS ごとのコマンドに任せる。
+pub fn command(title: &str, body: &str) -> Vec<String> {
+    if cfg!(target_os = "macos") {
+        let script = format!("display notification {:?} with title {:?}", body, title);
+        vec!["osascript".to_string(), "-e".to_string(), script]
+    } else if cfg!(windows) {
+        vec![
+            "msg".to_string(),
+            "*".to_string(),
+            format!("{}: {}", title, body),
+        ]
+    } else {
+        vec![
+            "notify-send".to_string(),
+            title.to_string(),
+            body.to_string(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command() {
+        let command = command("Pomodoro finished", "Take a 5m break.");
+        assert!(command.len() >= 2);
+        assert!(command.iter().any(|a| a.contains("Take a 5m break.")));
+    }
+}
@@ -0,0 +1,71 @@
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Record,
+    Config,
+    DataDir,
+}
+
+impl Target {
+    pub fn parse(s: &str) -> Result<Target, String> {
+        match s {
+            "record" => Ok(Target::Record),
+            "config" => Ok(Target::Config),
+            "data-dir" => Ok(Target::DataDir),
+            _ => Err(format!(
+                "Invalid target '{}'. Use record, config or data-dir.",
+                s
+            )),
+        }
+    }
+}
+
+// 開くためのコマンド。ファイルは $VISUAL / $EDITOR で、ディレクトリや
+// エディタが未設定のときは OS のファイルマネージャ (関連付け) で開く。
+pub fn command(path: &Path, is_dir: bool, editor: Option<&str>) -> Vec<String> {
+    let mut command: Vec<String> = match editor.filter(|_| !is_dir) {
+        Some(editor) => editor.split_whitespace().map(str::to_string).collect(),
+        None => vec![system_opener().to_string()],
+    };
+    command.push(path.display().to_string());
+    command
+}
+
+fn system_opener() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(windows) {
+        "explorer"
+    } else {
+        "xdg-open"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(Target::parse("data-dir").unwrap(), Target::DataDir);
+        assert!(Target::parse("state").is_err());
+    }
+
+    #[test]
+    fn test_command() {
+        let path = Path::new("/tmp/record.txt");
+        assert_eq!(
+            command(path, false, Some("code -w")),
+            vec!["code", "-w", "/tmp/record.txt"]
+        );
+        assert_eq!(
+            command(path, true, Some("vim")),
+            vec![system_opener(), "/tmp/record.txt"]
+        );
+        assert_eq!(
+            command(path, false, None),
+            vec![system_opener(), "/tmp/record.txt"]
+        );
+    }
+}
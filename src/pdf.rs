@@ -0,0 +1,195 @@
+// 表と罫線だけを書く最小限の PDF。フォントは埋め込まず標準の Helvetica を使うので、
+// Latin-1 に無い文字は '?' になる。どの文字列がそうなるかは unsupported で確かめる。
+// 出力は ASCII だけなので文字列のまま扱える。
+
+// A4 縦 (pt)
+pub const PAGE_WIDTH: f64 = 595.0;
+pub const PAGE_HEIGHT: f64 = 842.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Font {
+    Regular,
+    Bold,
+}
+
+impl Font {
+    fn resource(&self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+        }
+    }
+}
+
+// 1 ページ分の描画命令。座標は左下が原点。
+#[derive(Debug, Default)]
+pub struct Page {
+    content: String,
+}
+
+impl Page {
+    pub fn new() -> Page {
+        Page::default()
+    }
+
+    pub fn text(&mut self, x: f64, y: f64, font: Font, size: f64, text: &str) {
+        self.content += &format!(
+            "BT /{} {} Tf {:.1} {:.1} Td ({}) Tj ET\n",
+            font.resource(),
+            size,
+            x,
+            y,
+            escape(text)
+        );
+    }
+
+    // 右端を x にそろえる。幅は Helvetica の平均的な字幅から見積もる。
+    pub fn text_right(&mut self, x: f64, y: f64, font: Font, size: f64, text: &str) {
+        self.text(x - text_width(text, size), y, font, size, text);
+    }
+
+    pub fn line(&mut self, x1: f64, y1: f64, x2: f64, y2: f64) {
+        self.content += &format!("{:.1} {:.1} m {:.1} {:.1} l S\n", x1, y1, x2, y2);
+    }
+}
+
+pub fn text_width(text: &str, size: f64) -> f64 {
+    text.chars().count() as f64 * size * 0.55
+}
+
+// 幅に収まるよう末尾を削り、削ったら '.' を付ける
+pub fn fit(text: &str, width: f64, size: f64) -> String {
+    if text_width(text, size) <= width {
+        return text.to_string();
+    }
+    let mut fitted = String::new();
+    for c in text.chars() {
+        if text_width(&format!("{}{}.", fitted, c), size) > width {
+            break;
+        }
+        fitted.push(c);
+    }
+    fitted + "."
+}
+
+// Helvetica で書けず '?' になる文字を含むか
+pub fn unsupported(text: &str) -> bool {
+    text.chars()
+        .any(|c| !matches!(c, ' '..='~' | '\u{a0}'..='\u{ff}'))
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' '..='~' => escaped.push(c),
+            // WinAnsiEncoding の Latin-1 部分は 8 進数で書く
+            '\u{a0}'..='\u{ff}' => escaped += &format!("\\{:03o}", c as u32),
+            _ => escaped.push('?'),
+        }
+    }
+    escaped
+}
+
+pub fn render(pages: &[Page]) -> String {
+    let page_count = pages.len();
+    // 1: カタログ, 2: ページツリー, 3-4: フォント, 5 以降: ページと内容を交互に
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            (0..page_count)
+                .map(|i| format!("{} 0 R", 5 + i * 2))
+                .collect::<Vec<_>>()
+                .join(" "),
+            page_count
+        ),
+        font_object("Helvetica"),
+        font_object("Helvetica-Bold"),
+    ];
+    for (i, page) in pages.iter().enumerate() {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+             /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+            PAGE_WIDTH,
+            PAGE_HEIGHT,
+            6 + i * 2
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{}endstream",
+            page.content.len(),
+            page.content
+        ));
+    }
+
+    let mut output = "%PDF-1.4\n".to_string();
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(output.len());
+        output += &format!("{} 0 obj\n{}\nendobj\n", i + 1, object);
+    }
+    let xref = output.len();
+    output += &format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        output += &format!("{:010} 00000 n \n", offset);
+    }
+    output += &format!(
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref
+    );
+    output
+}
+
+fn font_object(name: &str) -> String {
+    format!(
+        "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>",
+        name
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a (b) \\"), "a \\(b\\) \\\\");
+        assert_eq!(escape("Café 作業"), "Caf\\351 ??");
+    }
+
+    #[test]
+    fn test_unsupported() {
+        assert!(!unsupported("Café (clientA)"));
+        assert!(unsupported("作業"));
+        assert!(unsupported("tab\there"));
+    }
+
+    #[test]
+    fn test_fit() {
+        assert_eq!(fit("short", 100.0, 10.0), "short");
+        assert_eq!(fit("a long project name", 30.0, 10.0), "a lo.");
+    }
+
+    #[test]
+    fn test_render() {
+        let mut page = Page::new();
+        page.text(40.0, 800.0, Font::Bold, 12.0, "Timesheet");
+        page.line(40.0, 790.0, 555.0, 790.0);
+        let pdf = render(&[page, Page::new()]);
+        assert!(pdf.starts_with("%PDF-1.4\n"));
+        assert!(pdf.contains("/Kids [5 0 R 7 0 R] /Count 2"));
+        assert!(pdf.contains("BT /F2 12 Tf 40.0 800.0 Td (Timesheet) Tj ET\n"));
+        // xref の位置と各オブジェクトの位置が合っている
+        let startxref: usize = pdf.lines().rev().nth(1).unwrap().parse().unwrap();
+        assert!(pdf[startxref..].starts_with("xref\n0 9\n"));
+        let first_page: usize = pdf[startxref..].lines().nth(7).unwrap()[..10]
+            .parse()
+            .unwrap();
+        assert!(pdf[first_page..].starts_with("5 0 obj\n<< /Type /Page "));
+    }
+}
@@ -0,0 +1,262 @@
+use crate::dst::resolve_local;
+use chrono::{
+    DateTime, Datelike, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, Weekday,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Period {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl Period {
+    pub fn new(start: NaiveDate, end: NaiveDate) -> Result<Period, String> {
+        if end < start {
+            return Err(format!("Invalid range: {} is after {}.", start, end));
+        }
+        Ok(Period { start, end })
+    }
+
+    // 2024-05-01 / 2024-W18 / 2024-05 / 2024 のいずれか
+    pub fn parse(s: &str) -> Result<Period, String> {
+        let invalid = || format!("Invalid period '{}'.", s);
+        if let Ok(date) = parse_date(s) {
+            return Ok(Period {
+                start: date,
+                end: date,
+            });
+        }
+        if let Some((year, week)) = s.split_once("-W") {
+            let year = year.parse().map_err(|_| invalid())?;
+            let week = week.parse().map_err(|_| invalid())?;
+            let start = NaiveDate::from_isoywd_opt(year, week, Weekday::Mon).ok_or_else(invalid)?;
+            return Ok(Period {
+                start,
+                end: start + chrono::Duration::days(6),
+            });
+        }
+        if let Some((year, month)) = s.split_once('-') {
+            let year = year.parse().map_err(|_| invalid())?;
+            let month = month.parse().map_err(|_| invalid())?;
+            return Period::month(year, month).ok_or_else(invalid);
+        }
+        let year = s.parse().map_err(|_| invalid())?;
+        let start = NaiveDate::from_ymd_opt(year, 1, 1).ok_or_else(invalid)?;
+        let end = NaiveDate::from_ymd_opt(year, 12, 31).ok_or_else(invalid)?;
+        Ok(Period { start, end })
+    }
+
+    pub fn day(date: NaiveDate) -> Period {
+        Period {
+            start: date,
+            end: date,
+        }
+    }
+
+    // date を含む月曜始まりの週
+    pub fn week_of(date: NaiveDate) -> Period {
+        let monday = date - chrono::Duration::days(date.weekday().num_days_from_monday() as i64);
+        Period {
+            start: monday,
+            end: monday + chrono::Duration::days(6),
+        }
+    }
+
+    // report の位置引数で使える today / yesterday
+    pub fn relative_day(s: &str, today: NaiveDate) -> Option<Period> {
+        match s {
+            "today" => Some(Period::day(today)),
+            "yesterday" => Some(Period::day(today.pred_opt()?)),
+            _ => None,
+        }
+    }
+
+    pub fn month(year: i32, month: u32) -> Option<Period> {
+        let start = NaiveDate::from_ymd_opt(year, month, 1)?;
+        let next = if month == 12 {
+            NaiveDate::from_ymd_opt(year + 1, 1, 1)?
+        } else {
+            NaiveDate::from_ymd_opt(year, month + 1, 1)?
+        };
+        Some(Period {
+            start,
+            end: next.pred_opt()?,
+        })
+    }
+
+    pub fn month_of(date: NaiveDate) -> Period {
+        Period::month(date.year(), date.month()).unwrap()
+    }
+
+    pub fn month_to_date(today: NaiveDate) -> Period {
+        Period {
+            start: today.with_day(1).unwrap(),
+            end: today,
+        }
+    }
+
+    pub fn days(&self) -> impl Iterator<Item = NaiveDate> {
+        let end = self.end;
+        self.start.iter_days().take_while(move |d| *d <= end)
+    }
+
+    pub fn start_time(&self) -> DateTime<FixedOffset> {
+        local_midnight(self.start)
+    }
+
+    pub fn end_time(&self) -> DateTime<FixedOffset> {
+        local_midnight(self.end.succ_opt().unwrap())
+    }
+}
+
+pub fn parse_date(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").map_err(|_| format!("Invalid date '{}'.", s))
+}
+
+pub fn parse_time(s: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(s, "%H:%M").map_err(|_| format!("Invalid time '{}'.", s))
+}
+
+// RFC3339 / "2024-05-01 09:00" / "09:00" (date の日付) のいずれか
+pub fn parse_local_datetime(s: &str, date: NaiveDate) -> Result<DateTime<FixedOffset>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time);
+    }
+    for format in [
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M:%S",
+    ] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(s, format) {
+            return Ok(to_local(naive));
+        }
+    }
+    for format in ["%H:%M", "%H:%M:%S"] {
+        if let Ok(time) = NaiveTime::parse_from_str(s, format) {
+            return Ok(to_local(date.and_time(time)));
+        }
+    }
+    Err(format!("Invalid time '{}'.", s))
+}
+
+pub fn local_midnight(date: NaiveDate) -> DateTime<FixedOffset> {
+    to_local(date.and_hms_opt(0, 0, 0).unwrap())
+}
+
+pub fn to_local(naive: NaiveDateTime) -> DateTime<FixedOffset> {
+    resolve_local(&Local, naive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        parse_date(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(
+            date("2024-05-01"),
+            NaiveDate::from_ymd_opt(2024, 5, 1).unwrap()
+        );
+        assert_eq!(
+            parse_date("2024/05/01").unwrap_err(),
+            "Invalid date '2024/05/01'."
+        );
+    }
+
+    #[test]
+    fn test_period_days() {
+        let period = Period::new(date("2024-04-29"), date("2024-05-02")).unwrap();
+        assert_eq!(period.days().count(), 4);
+    }
+
+    #[test]
+    fn test_period_rejects_reversed_range() {
+        assert!(Period::new(date("2024-05-02"), date("2024-05-01")).is_err());
+    }
+
+    #[test]
+    fn test_parse_period() {
+        let day = Period::parse("2024-05-03").unwrap();
+        assert_eq!(
+            (day.start, day.end),
+            (date("2024-05-03"), date("2024-05-03"))
+        );
+        let week = Period::parse("2024-W18").unwrap();
+        assert_eq!(
+            (week.start, week.end),
+            (date("2024-04-29"), date("2024-05-05"))
+        );
+        let month = Period::parse("2024-02").unwrap();
+        assert_eq!(
+            (month.start, month.end),
+            (date("2024-02-01"), date("2024-02-29"))
+        );
+        let year = Period::parse("2024").unwrap();
+        assert_eq!(
+            (year.start, year.end),
+            (date("2024-01-01"), date("2024-12-31"))
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_period() {
+        assert_eq!(
+            Period::parse("2024-13").unwrap_err(),
+            "Invalid period '2024-13'."
+        );
+        assert!(Period::parse("last month").is_err());
+    }
+
+    #[test]
+    fn test_parse_local_datetime() {
+        let today = date("2024-05-01");
+        let rfc = parse_local_datetime("2024-05-01T09:30:00+09:00", today).unwrap();
+        assert_eq!(rfc.to_rfc3339(), "2024-05-01T09:30:00+09:00");
+
+        let full = parse_local_datetime("2024-04-30 18:15", today).unwrap();
+        assert_eq!(full.naive_local().to_string(), "2024-04-30 18:15:00");
+
+        let time_only = parse_local_datetime("09:30", today).unwrap();
+        assert_eq!(time_only.naive_local().to_string(), "2024-05-01 09:30:00");
+
+        assert_eq!(
+            parse_local_datetime("9時", today).unwrap_err(),
+            "Invalid time '9時'."
+        );
+    }
+
+    #[test]
+    fn test_week_of() {
+        let week = Period::week_of(date("2024-05-01"));
+        assert_eq!(
+            (week.start, week.end),
+            (date("2024-04-29"), date("2024-05-05"))
+        );
+    }
+
+    #[test]
+    fn test_relative_day() {
+        let today = date("2024-05-01");
+        assert_eq!(
+            Period::relative_day("today", today),
+            Some(Period::day(today))
+        );
+        assert_eq!(
+            Period::relative_day("yesterday", today),
+            Some(Period::day(date("2024-04-30")))
+        );
+        assert_eq!(Period::relative_day("2024-05", today), None);
+    }
+
+    #[test]
+    fn test_month_to_date() {
+        let period = Period::month_to_date(date("2024-05-17"));
+        assert_eq!(period.start, date("2024-05-01"));
+        assert_eq!(period.end, date("2024-05-17"));
+    }
+}
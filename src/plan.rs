@@ -0,0 +1,201 @@
+use crate::duration::{format_delta, format_duration};
+use crate::period::{parse_local_datetime, Period};
+use crate::report::total_between;
+use crate::session::Session;
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+// 予定と実績がこれ以上ずれたタスクに印を付ける
+const DEVIATION_THRESHOLD_MINUTES: i64 = 15;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    pub task: String,
+    pub start: DateTime<FixedOffset>,
+    pub end: DateTime<FixedOffset>,
+}
+
+impl Plan {
+    // "09:00-11:00" を date の予定として読む
+    pub fn parse(range: &str, task: &str, date: NaiveDate) -> Result<Plan, String> {
+        let (from, to) = range
+            .split_once('-')
+            .ok_or(format!("Invalid time range '{}'.", range))?;
+        let start = parse_local_datetime(from.trim(), date)?;
+        let end = parse_local_datetime(to.trim(), date)?;
+        if end <= start {
+            return Err(format!("Invalid time range '{}'.", range));
+        }
+        Ok(Plan {
+            task: task.to_string(),
+            start,
+            end,
+        })
+    }
+
+    pub fn date(&self) -> NaiveDate {
+        self.start.with_timezone(&Local).date_naive()
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{} - {}  {}",
+            self.start.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+            self.end.with_timezone(&Local).format("%H:%M"),
+            self.task
+        )
+    }
+}
+
+pub struct PlanRow {
+    pub task: String,
+    pub planned: Duration,
+    pub actual: Duration,
+}
+
+impl PlanRow {
+    pub fn is_deviation(&self) -> bool {
+        (self.actual - self.planned).abs() >= Duration::minutes(DEVIATION_THRESHOLD_MINUTES)
+    }
+}
+
+// 予定にあるタスクを予定順に、予定外に記録したタスクをその後に並べる
+pub fn compare(
+    plans: &[Plan],
+    sessions: &[Session],
+    period: &Period,
+    now: DateTime<FixedOffset>,
+) -> Vec<PlanRow> {
+    let (from, to) = (period.start_time(), period.end_time());
+    let mut tasks: Vec<&str> = Vec::new();
+    let planned: Vec<&Plan> = plans
+        .iter()
+        .filter(|plan| plan.end > from && plan.start < to)
+        .collect();
+    for task in planned
+        .iter()
+        .map(|plan| plan.task.as_str())
+        .chain(sessions.iter().map(|s| s.task.as_str()))
+    {
+        if !tasks.contains(&task) {
+            tasks.push(task);
+        }
+    }
+
+    tasks
+        .into_iter()
+        .map(|task| {
+            let planned = planned
+                .iter()
+                .filter(|plan| plan.task == task)
+                .map(|plan| plan.end.min(to) - plan.start.max(from))
+                .fold(Duration::zero(), |sum, d| sum + d);
+            let matching = sessions.iter().filter(|s| s.task == task);
+            PlanRow {
+                task: task.to_string(),
+                planned,
+                actual: total_between(matching, from, to, now),
+            }
+        })
+        .filter(|row| !row.planned.is_zero() || !row.actual.is_zero())
+        .collect()
+}
+
+pub fn render(rows: &[PlanRow]) -> String {
+    if rows.is_empty() {
+        return String::new();
+    }
+    let width = rows
+        .iter()
+        .map(|row| row.task.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("Task".len());
+    let mut output = format!(
+        "\nPlan vs. actual:\n{:<width$}  {:>8}  {:>8}  {:>8}\n",
+        "Task", "Planned", "Actual", "Diff"
+    );
+    for row in rows {
+        output += &format!(
+            "{:<width$}  {:>8}  {:>8}  {:>8}{}\n",
+            row.task,
+            format_duration(row.planned),
+            format_duration(row.actual),
+            format_delta(row.actual - row.planned),
+            if row.is_deviation() { "  !" } else { "" }
+        );
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::parse_date;
+
+    fn ts(s: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(s).unwrap()
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        parse_date(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse_plan() {
+        let plan = Plan::parse("09:00-11:00", "spec review", date("2024-05-01")).unwrap();
+        assert_eq!(plan.end - plan.start, Duration::hours(2));
+        assert_eq!(plan.date(), date("2024-05-01"));
+        assert_eq!(
+            Plan::parse("11:00-09:00", "a", date("2024-05-01")).unwrap_err(),
+            "Invalid time range '11:00-09:00'."
+        );
+        assert!(Plan::parse("09:00", "a", date("2024-05-01")).is_err());
+    }
+
+    #[test]
+    fn test_compare_and_render() {
+        let period = Period::new(date("2024-05-01"), date("2024-05-01")).unwrap();
+        let plans = vec![
+            Plan::parse("09:00-11:00", "spec review", date("2024-05-01")).unwrap(),
+            Plan::parse("11:00-11:30", "mail", date("2024-05-01")).unwrap(),
+        ];
+        let start = plans[0].start;
+        let sessions = vec![
+            Session::new("spec review", start, Some(start + Duration::minutes(90))),
+            Session::new(
+                "mail",
+                start + Duration::minutes(90),
+                Some(start + Duration::minutes(115)),
+            ),
+            Session::new(
+                "incident",
+                start + Duration::minutes(120),
+                Some(start + Duration::minutes(180)),
+            ),
+        ];
+        let rows = compare(&plans, &sessions, &period, ts("2030-01-01T00:00:00Z"));
+        let summary: Vec<(&str, i64, i64, bool)> = rows
+            .iter()
+            .map(|r| {
+                (
+                    r.task.as_str(),
+                    r.planned.num_minutes(),
+                    r.actual.num_minutes(),
+                    r.is_deviation(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("spec review", 120, 90, true),
+                ("mail", 30, 25, false),
+                ("incident", 0, 60, true),
+            ]
+        );
+        let output = render(&rows);
+        assert!(output.contains("spec review     2h00m     1h30m      -30m  !\n"));
+        assert!(output.contains("mail              30m       25m       -5m\n"));
+    }
+}
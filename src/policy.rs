@@ -0,0 +1,218 @@
+use crate::config::Config;
+use crate::record::{Event, Record};
+use crate::session::project_of;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NamingPolicy {
+    #[serde(deserialize_with = "deserialize_patterns")]
+    pub patterns: Vec<Regex>,
+    pub max_length: Option<usize>,
+}
+
+// start レコードが設定のポリシーに違反していれば、その理由を返す
+pub fn violations(config: &Config, record: &Record) -> Vec<String> {
+    let mut violations = Vec::new();
+    if record.event != Event::Start {
+        return violations;
+    }
+
+    let naming = &config.naming;
+    for pattern in &naming.patterns {
+        if !pattern.is_match(&record.task) {
+            violations.push(format!("does not match '{}'", pattern));
+        }
+    }
+    if let Some(max_length) = naming.max_length {
+        if record.task.chars().count() > max_length {
+            violations.push(format!("is longer than {} characters", max_length));
+        }
+    }
+
+    let required = &config.required_tags;
+    if !required.is_empty() && !record.tags.iter().any(|tag| required.contains(tag)) {
+        let required: Vec<String> = required.iter().map(|tag| format!("+{}", tag)).collect();
+        violations.push(format!(
+            "has none of the required tags {}",
+            required.join(", ")
+        ));
+    }
+
+    if let Some(project) = project_of(&record.task) {
+        let allowed = &config.allowed_projects;
+        if !allowed.is_empty() && !allowed.iter().any(|p| p == project) {
+            let suggestions: Vec<String> = suggest(project, allowed)
+                .iter()
+                .map(|s| format!("'{}'", s))
+                .collect();
+            if suggestions.is_empty() {
+                violations.push(format!("uses unknown project '{}'", project));
+            } else {
+                violations.push(format!(
+                    "uses unknown project '{}' (did you mean {}?)",
+                    project,
+                    suggestions.join(", ")
+                ));
+            }
+        }
+    }
+    violations
+}
+
+// 大文字小文字の違いか、編集距離が近い候補を近い順に返す
+pub fn suggest<'a>(name: &str, candidates: &'a [String]) -> Vec<&'a str> {
+    let name = name.to_lowercase();
+    let max_distance = (name.chars().count() / 3).max(1);
+    let mut matches: Vec<(usize, &str)> = candidates
+        .iter()
+        .map(|c| (levenshtein(&name, &c.to_lowercase()), c.as_str()))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    matches.sort();
+    matches.into_iter().map(|(_, c)| c).take(3).collect()
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                previous
+            } else {
+                1 + previous.min(row[j]).min(row[j + 1])
+            };
+            previous = current;
+        }
+    }
+    row[b.len()]
+}
+
+pub fn check(config: &Config, record: &Record) -> Result<(), String> {
+    let violations = violations(config, record);
+    if violations.is_empty() {
+        return Ok(());
+    }
+    Err(format!("Task '{}' {}.", record.task, violations.join(", ")))
+}
+
+// 記録済みの start レコードを検査し、(行番号, 理由) を返す
+pub fn lint(config: &Config, content: &str) -> Vec<(usize, String)> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| Record::parse(line).ok().map(|r| (i + 1, r)))
+        .filter_map(|(line, record)| check(config, &record).err().map(|e| (line, e)))
+        .collect()
+}
+
+fn deserialize_patterns<'de, D>(deserializer: D) -> Result<Vec<Regex>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|pattern| Regex::new(pattern).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config::parse(
+            r#"
+            [naming]
+            patterns = ['^PROJ-\d+ ']
+            max_length = 20
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn start(task: &str) -> Record {
+        Record::parse(&format!("2024-05-01T09:00:00+09:00\tstart\t{}", task)).unwrap()
+    }
+
+    #[test]
+    fn test_valid_task_name() {
+        assert!(check(&config(), &start("PROJ-12 fix login")).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_task_name() {
+        assert_eq!(
+            check(&config(), &start("fix login")).unwrap_err(),
+            "Task 'fix login' does not match '^PROJ-\\d+ '."
+        );
+        let violations = violations(&config(), &start("PROJ-1 a very long task name"));
+        assert_eq!(violations, vec!["is longer than 20 characters"]);
+    }
+
+    #[test]
+    fn test_stop_records_are_not_checked() {
+        let stop = Record::parse("2024-05-01T10:00:00+09:00\tstop\t").unwrap();
+        assert!(check(&config(), &stop).is_ok());
+    }
+
+    #[test]
+    fn test_lint_reports_line_numbers() {
+        let content = "2024-05-01T09:00:00+09:00\tstart\tPROJ-1 ok\n\
+                       2024-05-01T10:00:00+09:00\tstop\t\n\
+                       2024-05-01T11:00:00+09:00\tstart\tbad\n";
+        let issues = lint(&config(), content);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].0, 3);
+    }
+
+    #[test]
+    fn test_unknown_project_with_suggestion() {
+        let config = Config::parse(r#"allowed_projects = ["clientA", "internal"]"#).unwrap();
+        assert!(check(&config, &start("clientA:fix")).is_ok());
+        assert!(check(&config, &start("no project")).is_ok());
+        assert_eq!(
+            check(&config, &start("clinetA:fix")).unwrap_err(),
+            "Task 'clinetA:fix' uses unknown project 'clinetA' (did you mean 'clientA'?)."
+        );
+        assert_eq!(
+            check(&config, &start("zzz:fix")).unwrap_err(),
+            "Task 'zzz:fix' uses unknown project 'zzz'."
+        );
+    }
+
+    #[test]
+    fn test_required_tags() {
+        let config = Config::parse(r#"required_tags = ["billable", "non-billable"]"#).unwrap();
+        assert!(check(&config, &start("a\t+non-billable")).is_ok());
+        assert_eq!(
+            check(&config, &start("a\t+urgent")).unwrap_err(),
+            "Task 'a' has none of the required tags +billable, +non-billable."
+        );
+    }
+
+    #[test]
+    fn test_suggest() {
+        let candidates = vec!["Backend".to_string(), "frontend".to_string()];
+        assert_eq!(suggest("backend", &candidates), vec!["Backend"]);
+        assert_eq!(suggest("fronted", &candidates), vec!["frontend"]);
+        assert!(suggest("mobile", &candidates).is_empty());
+    }
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("same", "same"), 0);
+    }
+
+    #[test]
+    fn test_invalid_pattern_in_config() {
+        assert!(Config::parse("[naming]\npatterns = ['(']").is_err());
+    }
+}
@@ -0,0 +1,114 @@
+use crate::config::deserialize_required_duration;
+use crate::duration::parse_duration;
+use crate::session::Session;
+use chrono::{Duration, NaiveDate};
+use serde::Deserialize;
+
+// start --pomodoro で始めたセッションに付けるフィールド
+pub const POMODORO_FIELD: &str = "pomodoro";
+
+// [pomodoro] の設定。long_break_every 回ごとの休憩を長くする。
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PomodoroConfig {
+    #[serde(deserialize_with = "deserialize_required_duration")]
+    pub short_break: Duration,
+    #[serde(deserialize_with = "deserialize_required_duration")]
+    pub long_break: Duration,
+    pub long_break_every: u32,
+}
+
+impl Default for PomodoroConfig {
+    fn default() -> PomodoroConfig {
+        PomodoroConfig {
+            short_break: Duration::minutes(5),
+            long_break: Duration::minutes(15),
+            long_break_every: 4,
+        }
+    }
+}
+
+impl PomodoroConfig {
+    // n 回目 (1 始まり) のポモドーロのあとの休憩
+    pub fn break_after(&self, n: usize) -> Duration {
+        let every = self.long_break_every as usize;
+        match every > 0 && n > 0 && n.is_multiple_of(every) {
+            true => self.long_break,
+            false => self.short_break,
+        }
+    }
+}
+
+// --pomodoro の長さ。数字だけなら分とみなす。
+pub fn parse_length(s: &str) -> Result<Duration, String> {
+    let length = match s.parse::<i64>() {
+        Ok(minutes) => Duration::minutes(minutes),
+        Err(_) => parse_duration(s)?,
+    };
+    if length <= Duration::zero() {
+        return Err(format!("Invalid pomodoro length '{}'.", s));
+    }
+    Ok(length)
+}
+
+// その日に終えたポモドーロの数
+pub fn completed_on(sessions: &[Session], date: NaiveDate) -> usize {
+    sessions
+        .iter()
+        .filter(|s| s.stop.is_some() && s.start.date_naive() == date)
+        .filter(|s| s.field(POMODORO_FIELD).is_some())
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    #[test]
+    fn test_parse_length() {
+        assert_eq!(parse_length("25").unwrap(), Duration::minutes(25));
+        assert_eq!(parse_length("1h").unwrap(), Duration::hours(1));
+        assert!(parse_length("0").is_err());
+        assert!(parse_length("soon").is_err());
+    }
+
+    #[test]
+    fn test_break_after() {
+        let config = PomodoroConfig::default();
+        assert_eq!(config.break_after(1), Duration::minutes(5));
+        assert_eq!(config.break_after(4), Duration::minutes(15));
+        assert_eq!(config.break_after(5), Duration::minutes(5));
+        let config = PomodoroConfig {
+            long_break_every: 0,
+            ..Default::default()
+        };
+        assert_eq!(config.break_after(4), Duration::minutes(5));
+    }
+
+    #[test]
+    fn test_completed_on() {
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap();
+        let pomodoro = |start: &str, stop: Option<&str>| {
+            let mut session = Session::new("a", at(start), stop.map(at));
+            session
+                .fields
+                .push((POMODORO_FIELD.to_string(), "25m".to_string()));
+            session
+        };
+        let sessions = vec![
+            pomodoro(
+                "2024-05-01T09:00:00+09:00",
+                Some("2024-05-01T09:25:00+09:00"),
+            ),
+            pomodoro(
+                "2024-05-01T09:30:00+09:00",
+                Some("2024-05-01T09:55:00+09:00"),
+            ),
+            Session::new("b", at("2024-05-01T10:00:00+09:00"), None),
+            pomodoro("2024-05-01T10:00:00+09:00", None),
+        ];
+        let date = at("2024-05-01T09:00:00+09:00").date_naive();
+        assert_eq!(completed_on(&sessions, date), 2);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::record::{Event, Record};
+use crate::session::project_of;
+use chrono::{DateTime, FixedOffset};
+
+// cutoff より前に終わったセッションのレコードを取り除き、(残すもの, 取り除いたもの) を返す。
+// 開いたままのセッションは対象外。project を指定した場合はそのプロジェクトのものだけ。
+pub fn prune(
+    records: &[Record],
+    cutoff: DateTime<FixedOffset>,
+    project: Option<&str>,
+) -> (Vec<Record>, Vec<Record>) {
+    let (removed, kept): (Vec<_>, Vec<_>) = records
+        .iter()
+        .cloned()
+        .zip(prunable(records, cutoff, project))
+        .partition(|(_, remove)| *remove);
+    (
+        kept.into_iter().map(|(r, _)| r).collect(),
+        removed.into_iter().map(|(r, _)| r).collect(),
+    )
+}
+
+// records[i] を消すなら i 番目が true。行の位置から消すときに使う。
+pub fn prunable(
+    records: &[Record],
+    cutoff: DateTime<FixedOffset>,
+    project: Option<&str>,
+) -> Vec<bool> {
+    let matches = |task: &str| project.is_none_or(|p| project_of(task) == Some(p));
+    let mut remove = vec![false; records.len()];
+    let mut open: Option<usize> = None;
+    // 開いているセッションの lap はセッションと一緒に扱う
+    let mut laps: Vec<usize> = Vec::new();
+
+    for (i, record) in records.iter().enumerate() {
+        match record.event {
+            Event::Start => {
+                if let Some(start) = open {
+                    let prunable = record.timestamp < cutoff && matches(&records[start].task);
+                    remove[start] = prunable;
+                    for lap in laps.drain(..) {
+                        remove[lap] = prunable;
+                    }
+                }
+                open = Some(i);
+            }
+            Event::Stop => match open.take() {
+                Some(start) => {
+                    let prunable = record.timestamp < cutoff && matches(&records[start].task);
+                    remove[start] = prunable;
+                    remove[i] = prunable;
+                    for lap in laps.drain(..) {
+                        remove[lap] = prunable;
+                    }
+                }
+                None => remove[i] = record.timestamp < cutoff && project.is_none(),
+            },
+            Event::Lap if open.is_some() => laps.push(i),
+            Event::Lap => remove[i] = record.timestamp < cutoff && project.is_none(),
+        }
+    }
+    remove
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::parse_records;
+
+    fn ts(s: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(s).unwrap()
+    }
+
+    fn records() -> Vec<Record> {
+        parse_records(
+            "2020-05-01T09:00:00+09:00\tstart\tx:old\n\
+             2020-05-01T10:00:00+09:00\tstop\t\n\
+             2020-05-02T09:00:00+09:00\tstart\ty:old\n\
+             2020-05-02T10:00:00+09:00\tstop\t\n\
+             2024-05-01T09:00:00+09:00\tstart\tx:new\n\
+             2024-05-01T10:00:00+09:00\tstop\t\n",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_prune_before_cutoff() {
+        let (kept, removed) = prune(&records(), ts("2023-01-01T00:00:00+09:00"), None);
+        assert_eq!(kept.len(), 2);
+        assert_eq!(removed.len(), 4);
+        assert_eq!(kept[0].task, "x:new");
+    }
+
+    #[test]
+    fn test_prune_by_project() {
+        let (kept, removed) = prune(&records(), ts("2023-01-01T00:00:00+09:00"), Some("y"));
+        assert_eq!(kept.len(), 4);
+        assert_eq!(removed.len(), 2);
+        assert_eq!(removed[0].task, "y:old");
+    }
+
+    #[test]
+    fn test_prune_keeps_open_session() {
+        let records = parse_records("2020-05-01T09:00:00+09:00\tstart\tforever\n").unwrap();
+        let (kept, removed) = prune(&records, ts("2023-01-01T00:00:00+09:00"), None);
+        assert_eq!(kept.len(), 1);
+        assert!(removed.is_empty());
+    }
+
+    #[test]
+    fn test_prune_session_closed_by_next_start() {
+        let records = parse_records(
+            "2020-05-01T09:00:00+09:00\tstart\ta\n\
+             2020-05-01T10:00:00+09:00\tstart\tb\n",
+        )
+        .unwrap();
+        let (kept, removed) = prune(&records, ts("2023-01-01T00:00:00+09:00"), None);
+        assert_eq!(kept.len(), 1);
+        assert_eq!(removed[0].task, "a");
+    }
+
+    #[test]
+    fn test_prune_removes_laps_with_session() {
+        let records = parse_records(
+            "2020-05-01T09:00:00+09:00\tstart\tx:old\n\
+             2020-05-01T09:30:00+09:00\tlap\tnote\n\
+             2020-05-01T10:00:00+09:00\tstop\t\n\
+             2020-05-02T09:00:00+09:00\tstart\tforever\n\
+             2020-05-02T09:30:00+09:00\tlap\tkept\n",
+        )
+        .unwrap();
+        let (kept, removed) = prune(&records, ts("2023-01-01T00:00:00+09:00"), None);
+        assert_eq!(removed.len(), 3);
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[1].task, "kept");
+    }
+}
@@ -0,0 +1,1013 @@
+use crate::json::{self, Value};
+use chrono::{
+    DateTime, DurationRound, FixedOffset, SecondsFormat, SubsecRound, TimeDelta, Timelike,
+};
+use serde::Deserialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// -f - で標準入力から読む。標準入力には書き込めない。
+pub const STDIN_PATH: &str = "-";
+const STDIN_NOT_WRITABLE_MSG: &str = "標準入力 (-f -) には書き込めません。";
+const TAIL_CHUNK: u64 = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Start,
+    Stop,
+    // 計測中のセッションに打つ区切り。task 列にメモを入れる。
+    Lap,
+}
+
+impl Event {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Event::Start => "start",
+            Event::Stop => "stop",
+            Event::Lap => "lap",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Event> {
+        match s {
+            "start" => Some(Event::Start),
+            "stop" => Some(Event::Stop),
+            "lap" => Some(Event::Lap),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Precision {
+    Minutes,
+    #[default]
+    Seconds,
+    Millis,
+}
+
+impl Precision {
+    pub fn truncate(&self, time: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        match self {
+            Precision::Minutes => time.duration_trunc(TimeDelta::minutes(1)).unwrap_or(time),
+            Precision::Seconds => time.trunc_subsecs(0),
+            Precision::Millis => time.trunc_subsecs(3),
+        }
+    }
+}
+
+const FORMAT_MARKER: &str = "# format: ";
+
+// 記録ファイルの書式。TSV は 1 行 1 レコードをタブで区切り、JSON Lines は 1 行 1 オブジェクト。
+// 読むときは行ごとに見分けるので、混ざっていても読める。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecordFormat {
+    #[default]
+    Tsv,
+    Jsonl,
+}
+
+impl RecordFormat {
+    pub fn parse(s: &str) -> Result<RecordFormat, String> {
+        match s {
+            "tsv" => Ok(RecordFormat::Tsv),
+            "jsonl" => Ok(RecordFormat::Jsonl),
+            _ => Err(format!("Invalid record format '{}'. Use tsv or jsonl.", s)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecordFormat::Tsv => "tsv",
+            RecordFormat::Jsonl => "jsonl",
+        }
+    }
+
+    // 最初のレコードの行か、その前の FORMAT_MARKER の書式。どちらも無ければ既定の TSV。
+    pub fn detect(content: &str) -> RecordFormat {
+        content
+            .lines()
+            .find_map(RecordFormat::of_line)
+            .unwrap_or_default()
+    }
+
+    // 書式がわかる行ならその書式。空行とほかのコメントは None。
+    fn of_line(line: &str) -> Option<RecordFormat> {
+        if let Some(name) = line.strip_prefix(FORMAT_MARKER) {
+            return RecordFormat::parse(name.trim()).ok();
+        }
+        match line {
+            _ if line.trim().is_empty() || line.starts_with('#') => None,
+            _ if line.starts_with('{') => Some(RecordFormat::Jsonl),
+            _ => Some(RecordFormat::Tsv),
+        }
+    }
+
+    // レコードがまだ無くても書式がわかるように、convert がファイルの先頭に書く行
+    pub fn marker(&self) -> String {
+        format!("{}{}\n", FORMAT_MARKER, self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub timestamp: DateTime<FixedOffset>,
+    pub event: Event,
+    pub task: String,
+    pub tags: Vec<String>,
+    pub fields: Vec<(String, String)>,
+}
+
+impl Record {
+    pub fn new(timestamp: DateTime<FixedOffset>, event: Event, task: &str) -> Record {
+        Record {
+            timestamp,
+            event,
+            task: task.to_string(),
+            tags: Vec::new(),
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn with_tag(mut self, tag: &str) -> Record {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    pub fn with_field(mut self, key: &str, value: &str) -> Record {
+        self.fields.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn parse(line: &str) -> Result<Record, String> {
+        if line.starts_with('{') {
+            return Record::parse_json(line);
+        }
+        let mut columns = line.split('\t');
+        let timestamp = columns.next().unwrap_or_default();
+        let timestamp = DateTime::parse_from_rfc3339(timestamp)
+            .map_err(|e| format!("Invalid timestamp '{}': {}", timestamp, e))?;
+        let event = columns.next().unwrap_or_default();
+        let event = Event::parse(event).ok_or(format!("Invalid event '{}'.", event))?;
+        let task = columns.next().unwrap_or_default();
+        let mut record = Record::new(timestamp, event, task);
+
+        // 4 列目以降は "+tag" または "key=value" 形式の付加情報
+        for column in columns {
+            if let Some(tag) = column.strip_prefix('+').filter(|tag| !tag.is_empty()) {
+                record.tags.push(tag.to_string());
+            } else if let Some((key, value)) = column.split_once('=').filter(|(k, _)| !k.is_empty())
+            {
+                record.fields.push((key.to_string(), value.to_string()));
+            } else {
+                return Err(format!("Invalid column '{}'.", column));
+            }
+        }
+        Ok(record)
+    }
+
+    // {"ts":…,"event":…,"task":…} に、あれば tags と fields を続ける
+    fn parse_json(line: &str) -> Result<Record, String> {
+        let value = json::parse(line)?;
+        let text = |key: &str| {
+            value
+                .get(key)
+                .and_then(Value::as_str)
+                .ok_or(format!("Missing '{}'.", key))
+        };
+        let timestamp = text("ts")?;
+        let timestamp = DateTime::parse_from_rfc3339(timestamp)
+            .map_err(|e| format!("Invalid timestamp '{}': {}", timestamp, e))?;
+        let event = text("event")?;
+        let event = Event::parse(event).ok_or(format!("Invalid event '{}'.", event))?;
+        let mut record = Record::new(timestamp, event, text("task").unwrap_or_default());
+        for tag in value
+            .get("tags")
+            .and_then(Value::as_array)
+            .unwrap_or_default()
+        {
+            record = record.with_tag(tag.as_str().ok_or("Invalid tags: expected strings.")?);
+        }
+        for (key, field) in value
+            .get("fields")
+            .and_then(Value::as_object)
+            .unwrap_or_default()
+        {
+            let field = field.as_str().ok_or("Invalid fields: expected strings.")?;
+            record = record.with_field(key, field);
+        }
+        Ok(record)
+    }
+
+    pub fn to_line_as(&self, format: RecordFormat) -> String {
+        match format {
+            RecordFormat::Tsv => self.to_line(),
+            RecordFormat::Jsonl => self.to_json_line(),
+        }
+    }
+
+    fn to_json_line(&self) -> String {
+        let mut members = vec![
+            ("ts".to_string(), format_timestamp(self.timestamp).into()),
+            ("event".to_string(), self.event.as_str().into()),
+            ("task".to_string(), self.task.as_str().into()),
+        ];
+        if !self.tags.is_empty() {
+            let tags = self.tags.iter().map(|t| t.as_str().into()).collect();
+            members.push(("tags".to_string(), Value::Array(tags)));
+        }
+        if !self.fields.is_empty() {
+            let fields = self
+                .fields
+                .iter()
+                .map(|(k, v)| (k.clone(), v.as_str().into()))
+                .collect();
+            members.push(("fields".to_string(), Value::Object(fields)));
+        }
+        Value::Object(members).to_compact() + "\n"
+    }
+
+    pub fn to_line(&self) -> String {
+        let mut line = format!(
+            "{}\t{}\t{}",
+            format_timestamp(self.timestamp),
+            self.event.as_str(),
+            self.task
+        );
+        for tag in &self.tags {
+            line += &format!("\t+{}", tag);
+        }
+        for (key, value) in &self.fields {
+            line += &format!("\t{}={}", key, value);
+        }
+        line + "\n"
+    }
+
+    // 同じ内容を二度取り込まないための、行の内容のハッシュ
+    pub fn content_hash(&self) -> String {
+        hash_text(&self.to_line())
+    }
+}
+
+// 暗号用ではない、安定した 64 ビットのハッシュ (FNV-1a)
+pub fn hash_text(text: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in text.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+// 秒未満を持つ時刻だけミリ秒まで書き出す
+pub fn format_timestamp(timestamp: DateTime<FixedOffset>) -> String {
+    let seconds_format = if timestamp.nanosecond() == 0 {
+        SecondsFormat::Secs
+    } else {
+        SecondsFormat::Millis
+    };
+    timestamp.to_rfc3339_opts(seconds_format, false)
+}
+
+pub fn field<'a>(fields: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    fields
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.as_str())
+}
+
+// コマンドラインの "key=value" を付加情報の列として読む
+pub fn parse_field(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value))
+            if !key.is_empty()
+                && !key.starts_with('+')
+                && !key.contains(char::is_whitespace)
+                && !value.is_empty()
+                && !value.contains(['\t', '\n', '\r']) =>
+        {
+            Ok((key.to_string(), value.to_string()))
+        }
+        _ => Err(format!("Invalid field '{}'. Use key=value.", s)),
+    }
+}
+
+// 読めない行は <record>.rejected に理由と一緒に移し、残りのレコードだけを返す
+pub fn read_records(file_path: &str) -> Result<Vec<Record>, String> {
+    let (records, rejected) = parse_records_lenient(&read_content(file_path)?);
+    if !rejected.is_empty() {
+        quarantine(file_path, &rejected)?;
+    }
+    Ok(records)
+}
+
+pub fn rejected_path(file_path: &str) -> String {
+    format!("{}.rejected", file_path)
+}
+
+// 既に隔離した行は重ねて書かない
+pub fn quarantine(file_path: &str, rejected: &[Rejected]) -> Result<(), String> {
+    if file_path == STDIN_PATH {
+        eprintln!("Skipped {} unparseable line(s).", rejected.len());
+        return Ok(());
+    }
+    let path = rejected_path(file_path);
+    let existing = read_content(&path)?;
+    let mut content = String::new();
+    for entry in rejected {
+        if !existing.lines().any(|line| line == entry.text) {
+            content += &format!("# line {}: {}\n{}\n", entry.line, entry.reason, entry.text);
+        }
+    }
+    if !content.is_empty() {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .map_err(|e| format!("{}: {}", path, e))?;
+    }
+    eprintln!(
+        "Skipped {} unparseable line(s); see {}.",
+        rejected.len(),
+        path
+    );
+    Ok(())
+}
+
+// 記録ファイルの中身。無ければ空、STDIN_PATH なら標準入力を読む。
+pub fn read_content(file_path: &str) -> Result<String, String> {
+    if file_path == STDIN_PATH {
+        let mut content = String::new();
+        io::stdin()
+            .read_to_string(&mut content)
+            .map_err(|e| e.to_string())?;
+        return Ok(content);
+    }
+    match fs::read_to_string(file_path) {
+        Ok(content) => Ok(content),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub fn ensure_writable(file_path: &str) -> Result<(), String> {
+    if file_path == STDIN_PATH {
+        return Err(STDIN_NOT_WRITABLE_MSG.to_string());
+    }
+    Ok(())
+}
+
+// lap を除いた最後のレコード。計測中かどうかの判定に使う。
+pub fn last_event(records: &[Record]) -> Option<&Record> {
+    records.iter().rev().find(|r| r.event != Event::Lap)
+}
+
+// 記録ファイルの末尾部分。offsets[i] は records[i] の行が始まる位置。
+#[derive(Debug, Default)]
+pub struct Tail {
+    pub records: Vec<Record>,
+    pub offsets: Vec<u64>,
+}
+
+// 末尾から読み、lap 以外のレコードが count 個そろうかファイルの先頭に達するまで範囲を広げる。
+// start/stop のように直近の状態しか要らないコマンドが、履歴全体を読まずに済むようにする。
+pub fn read_tail(file_path: &str, count: usize) -> Result<Tail, String> {
+    read_back(file_path, |tail| {
+        tail.records
+            .iter()
+            .filter(|r| r.event != Event::Lap)
+            .count()
+            >= count
+    })
+}
+
+// since 以降のレコード。末尾から読み、since より前のレコードに届いたところで止める。
+pub fn read_since(file_path: &str, since: DateTime<FixedOffset>) -> Result<Vec<Record>, String> {
+    let tail = read_back(file_path, |tail| {
+        tail.records.first().is_some_and(|r| r.timestamp < since)
+    })?;
+    Ok(tail
+        .records
+        .into_iter()
+        .filter(|r| r.timestamp >= since)
+        .collect())
+}
+
+// 末尾から読む範囲を、enough が真になるかファイルの先頭に達するまで倍々に広げる
+fn read_back(file_path: &str, enough: impl Fn(&Tail) -> bool) -> Result<Tail, String> {
+    ensure_writable(file_path)?;
+    let mut file = match File::open(file_path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Tail::default()),
+        Err(e) => return Err(e.to_string()),
+    };
+    let len = file.metadata().map_err(|e| e.to_string())?.len();
+    let mut window = TAIL_CHUNK;
+
+    loop {
+        let from = len.saturating_sub(window);
+        file.seek(SeekFrom::Start(from))
+            .map_err(|e| e.to_string())?;
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer).map_err(|e| e.to_string())?;
+
+        // 途中から読んだ場合、最初の行は欠けているので捨てる
+        let skip = if from == 0 {
+            Some(0)
+        } else {
+            buffer.iter().position(|b| *b == b'\n').map(|i| i + 1)
+        };
+        if let Some(skip) = skip {
+            let content = std::str::from_utf8(&buffer[skip..]).map_err(|e| e.to_string())?;
+            let tail = parse_tail(content, from + skip as u64);
+            if from == 0 || enough(&tail) {
+                return Ok(tail);
+            }
+        }
+        window *= 2;
+    }
+}
+
+fn parse_tail(content: &str, mut offset: u64) -> Tail {
+    let mut tail = Tail::default();
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_end_matches(['\n', '\r']);
+        // 読めない行は read_records が隔離するので、ここでは飛ばす
+        if !trimmed.trim().is_empty() && !trimmed.starts_with('#') {
+            if let Ok(record) = Record::parse(trimmed) {
+                tail.records.push(record);
+                tail.offsets.push(offset);
+            }
+        }
+        offset += line.len() as u64;
+    }
+    tail
+}
+
+// offset 以降を切り捨てる
+pub fn truncate_records(file_path: &str, offset: u64) -> Result<(), String> {
+    ensure_writable(file_path)?;
+    OpenOptions::new()
+        .write(true)
+        .open(file_path)
+        .and_then(|file| file.set_len(offset))
+        .map_err(|e| e.to_string())
+}
+
+// offsets から始まる行だけを消して、一時ファイル経由で書き直す。
+// コメントや読めない行は、消す行より後ろにあっても残す。
+pub fn remove_lines(file_path: &str, offsets: &[u64]) -> Result<(), String> {
+    edit_lines(file_path, offsets, &[])
+}
+
+// remove の位置から始まる行を消し、inserts のレコードをその位置から始まる行の前に書く。
+// 位置がファイルの長さなら末尾に書く。同じ位置に入れるレコードは inserts の順に並べる。
+// 触れない行は、コメントや読めない行も含めて 1 バイトも変えずに残す。
+pub fn edit_lines(
+    file_path: &str,
+    remove: &[u64],
+    inserts: &[(u64, Record)],
+) -> Result<(), String> {
+    ensure_writable(file_path)?;
+    let format = file_format(file_path)?;
+    let records: Vec<Record> = inserts.iter().map(|(_, record)| record.clone()).collect();
+    ensure_representable(&records, format)?;
+    let content = read_content(file_path)?;
+    let lines_at = |offset: u64| -> String {
+        inserts
+            .iter()
+            .filter(|(at, _)| *at == offset)
+            .map(|(_, record)| record.to_line_as(format))
+            .collect()
+    };
+    let mut edited = String::new();
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        edited += &lines_at(offset);
+        if !remove.contains(&offset) {
+            edited += line;
+        }
+        offset += line.len() as u64;
+    }
+    let appended = lines_at(offset);
+    if !appended.is_empty() && !edited.is_empty() && !edited.ends_with('\n') {
+        edited.push('\n');
+    }
+    edited += &appended;
+    write_content(file_path, &edited)
+}
+
+// records を時刻順の位置に差し込む。同じ時刻なら既にあるレコードの後ろに置く。
+pub fn insert_records(file_path: &str, records: &[Record]) -> Result<(), String> {
+    ensure_writable(file_path)?;
+    let content = read_content(file_path)?;
+    let existing = parse_tail(&content, 0);
+    let mut inserts: Vec<(u64, Record)> = records
+        .iter()
+        .map(|record| {
+            let at = existing
+                .records
+                .iter()
+                .position(|r| sort_key(r) > sort_key(record))
+                .map_or(content.len() as u64, |i| existing.offsets[i]);
+            (at, record.clone())
+        })
+        .collect();
+    inserts.sort_by_key(|(at, record)| (*at, sort_key(record)));
+    edit_lines(file_path, &[], &inserts)
+}
+
+// 今の書式のまま書き直す
+pub fn write_records(file_path: &str, records: &[Record]) -> Result<(), String> {
+    write_records_as(file_path, records, file_format(file_path)?)
+}
+
+pub fn write_records_as(
+    file_path: &str,
+    records: &[Record],
+    format: RecordFormat,
+) -> Result<(), String> {
+    ensure_representable(records, format)?;
+    write_content(
+        file_path,
+        &records
+            .iter()
+            .map(|r| r.to_line_as(format))
+            .collect::<String>(),
+    )
+}
+
+// TSV はタブと改行で列と行を区切るので、それを含むタスク名などは書けない。
+// 書いてしまうと読み戻せないため、書く前に断る。
+pub fn ensure_representable(records: &[Record], format: RecordFormat) -> Result<(), String> {
+    if format != RecordFormat::Tsv {
+        return Ok(());
+    }
+    let breaks = |s: &str| s.contains(['\t', '\n', '\r']);
+    for record in records {
+        let texts = std::iter::once(&record.task)
+            .chain(&record.tags)
+            .chain(record.fields.iter().flat_map(|(k, v)| [k, v]));
+        if let Some(text) = texts.into_iter().find(|s| breaks(s)) {
+            return Err(format!(
+                "{:?} contains a tab or line break, which the TSV record file can't store. \
+                 Remove it, or switch the file with `convert --format jsonl`.",
+                text
+            ));
+        }
+    }
+    Ok(())
+}
+
+// 記録ファイルの書式。追記や書き直しはこれに合わせる。
+// 最初のレコードの行まで読めばわかるので、ファイル全体は読まない。
+pub fn file_format(file_path: &str) -> Result<RecordFormat, String> {
+    ensure_writable(file_path)?;
+    let file = match File::open(file_path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(RecordFormat::default()),
+        Err(e) => return Err(e.to_string()),
+    };
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        if let Some(format) = RecordFormat::of_line(&line) {
+            return Ok(format);
+        }
+    }
+    Ok(RecordFormat::default())
+}
+
+// 一時ファイルに書いてから置き換える
+pub fn write_content(file_path: &str, content: &str) -> Result<(), String> {
+    ensure_writable(file_path)?;
+    let temp_path = format!("{}.tmp", file_path);
+    fs::write(&temp_path, content).map_err(|e| e.to_string())?;
+    fs::rename(&temp_path, Path::new(file_path)).map_err(|e| e.to_string())
+}
+
+// 時刻順に並べる。同時刻では stop, start, lap の順に置く。
+pub fn sort_records(records: &mut [Record]) {
+    records.sort_by_key(sort_key);
+}
+
+pub fn sort_key(record: &Record) -> (DateTime<FixedOffset>, u8) {
+    let order = match record.event {
+        Event::Stop => 0,
+        Event::Start => 1,
+        Event::Lap => 2,
+    };
+    (record.timestamp, order)
+}
+
+// 読めない行があればエラーにする。テストでレコードを用意するときに使う。
+#[cfg(test)]
+pub fn parse_records(content: &str) -> Result<Vec<Record>, String> {
+    let (records, rejected) = parse_records_lenient(content);
+    match rejected.first() {
+        Some(entry) => Err(format!("line {}: {}", entry.line, entry.reason)),
+        None => Ok(records),
+    }
+}
+
+// 読めなかった行
+#[derive(Debug, PartialEq)]
+pub struct Rejected {
+    pub line: usize,
+    pub text: String,
+    pub reason: String,
+}
+
+pub fn parse_records_lenient(content: &str) -> (Vec<Record>, Vec<Rejected>) {
+    let mut records = Vec::new();
+    let mut rejected = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match Record::parse(line) {
+            Ok(record) => records.push(record),
+            Err(reason) => rejected.push(Rejected {
+                line: i + 1,
+                text: line.to_string(),
+                reason,
+            }),
+        }
+    }
+    (records, rejected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_start_record() {
+        let record = Record::parse("2024-05-01T09:00:00+09:00\tstart\ttest_task").unwrap();
+        assert_eq!(record.event, Event::Start);
+        assert_eq!(record.task, "test_task");
+        assert_eq!(record.timestamp.to_rfc3339(), "2024-05-01T09:00:00+09:00");
+    }
+
+    #[test]
+    fn test_parse_stop_record() {
+        let record = Record::parse("2024-05-01T18:00:00+09:00\tstop\t").unwrap();
+        assert_eq!(record.event, Event::Stop);
+        assert_eq!(record.task, "");
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        let line = "2024-05-01T09:00:00+09:00\tstart\ttest_task\n";
+        assert_eq!(Record::parse(line.trim_end()).unwrap().to_line(), line);
+    }
+
+    #[test]
+    fn test_record_tags_round_trip() {
+        let line = "2024-05-01T19:00:00+09:00\tstop\t\t+auto-stopped\n";
+        let record = Record::parse(line.trim_end()).unwrap();
+        assert_eq!(record.tags, vec!["auto-stopped".to_string()]);
+        assert_eq!(record.to_line(), line);
+    }
+
+    #[test]
+    fn test_record_fields_round_trip() {
+        let line = "2024-05-01T09:00:00+09:00\tstart\ta\t+x\thost=laptop\n";
+        let record = Record::parse(line.trim_end()).unwrap();
+        assert_eq!(field(&record.fields, "host"), Some("laptop"));
+        assert_eq!(field(&record.fields, "note"), None);
+        assert_eq!(record.to_line(), line);
+    }
+
+    #[test]
+    fn test_jsonl_round_trip() {
+        let line = "{\"ts\":\"2024-05-01T09:00:00+09:00\",\"event\":\"start\",\"task\":\"a\\tb\\nc\",\"tags\":[\"x\"],\"fields\":{\"host\":\"laptop\"}}\n";
+        let record = Record::parse(line.trim_end()).unwrap();
+        assert_eq!(record.task, "a\tb\nc");
+        assert_eq!(record.tags, vec!["x".to_string()]);
+        assert_eq!(field(&record.fields, "host"), Some("laptop"));
+        assert_eq!(record.to_line_as(RecordFormat::Jsonl), line);
+
+        let stop =
+            Record::parse("{\"ts\":\"2024-05-01T10:00:00+09:00\",\"event\":\"stop\"}").unwrap();
+        assert_eq!((stop.event, stop.task.as_str()), (Event::Stop, ""));
+        assert!(Record::parse("{\"event\":\"stop\"}").is_err());
+
+        // 書式は最初のレコードの行で見分け、TSV と混ざっていても読める
+        let content = format!("# comment\n{}{}", line, stop.to_line());
+        assert_eq!(RecordFormat::detect(&content), RecordFormat::Jsonl);
+        assert_eq!(RecordFormat::detect(""), RecordFormat::Tsv);
+        // レコードが無くても、convert が書いた印で見分ける
+        let marker = RecordFormat::Jsonl.marker();
+        assert_eq!(RecordFormat::detect(&marker), RecordFormat::Jsonl);
+        assert_eq!(RecordFormat::detect("# format: xml\n"), RecordFormat::Tsv);
+        let (records, rejected) = parse_records_lenient(&content);
+        assert_eq!(records, vec![record, stop]);
+        assert!(rejected.is_empty());
+    }
+
+    #[test]
+    fn test_ensure_representable() {
+        let time = DateTime::parse_from_rfc3339("2024-05-01T09:00:00+09:00").unwrap();
+        let ok = Record::new(time, Event::Start, "a b");
+        assert!(ensure_representable(&[ok], RecordFormat::Tsv).is_ok());
+        for record in [
+            Record::new(time, Event::Start, "foo\tbar"),
+            Record::new(time, Event::Start, "a").with_tag("x\ny"),
+            Record::new(time, Event::Start, "a").with_field("note", "x\ry"),
+        ] {
+            let records = [record];
+            assert!(ensure_representable(&records, RecordFormat::Tsv)
+                .unwrap_err()
+                .contains("convert --format jsonl"));
+            assert!(ensure_representable(&records, RecordFormat::Jsonl).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid_column() {
+        let result = Record::parse("2024-05-01T09:00:00+09:00\tstart\ta\tjunk");
+        assert_eq!(result.unwrap_err(), "Invalid column 'junk'.");
+    }
+
+    #[test]
+    fn test_precision_truncate() {
+        let time = DateTime::parse_from_rfc3339("2024-05-01T09:30:15.123456+09:00").unwrap();
+        let truncated = |p: Precision| p.truncate(time).to_rfc3339();
+        assert_eq!(truncated(Precision::Minutes), "2024-05-01T09:30:00+09:00");
+        assert_eq!(truncated(Precision::Seconds), "2024-05-01T09:30:15+09:00");
+        assert_eq!(
+            truncated(Precision::Millis),
+            "2024-05-01T09:30:15.123+09:00"
+        );
+    }
+
+    #[test]
+    fn test_millisecond_record_round_trip() {
+        let line = "2024-05-01T09:00:00.250+09:00\tstart\ta\n";
+        assert_eq!(Record::parse(line.trim_end()).unwrap().to_line(), line);
+    }
+
+    #[test]
+    fn test_parse_records_reports_line_number() {
+        let content = "2024-05-01T09:00:00+09:00\tstart\ta\n\nbroken\tstop\t\n";
+        let err = parse_records(content).unwrap_err();
+        assert!(err.starts_with("line 3:"));
+    }
+
+    #[test]
+    fn test_parse_records_skips_comments() {
+        let content = "# 2024-05 submitted on 2024-06-01\n2024-05-01T09:00:00+09:00\tstart\ta\n";
+        assert_eq!(parse_records(content).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_sort_records_puts_stop_first() {
+        let mut records = parse_records(
+            "2024-05-01T10:00:00+09:00\tstart\tb\n\
+             2024-05-01T09:00:00+09:00\tstart\ta\n\
+             2024-05-01T10:00:00+09:00\tstop\t\n",
+        )
+        .unwrap();
+        sort_records(&mut records);
+        let order: Vec<(Event, &str)> =
+            records.iter().map(|r| (r.event, r.task.as_str())).collect();
+        assert_eq!(
+            order,
+            vec![(Event::Start, "a"), (Event::Stop, ""), (Event::Start, "b")]
+        );
+    }
+
+    #[test]
+    fn test_write_records() {
+        let file_path = "test_write_records.txt";
+        let records = parse_records("2024-05-01T09:00:00+09:00\tstart\ta\n").unwrap();
+        write_records(file_path, &records).unwrap();
+        assert_eq!(read_records(file_path).unwrap(), records);
+        fs::remove_file(file_path).unwrap();
+    }
+
+    #[test]
+    fn test_lap_round_trip() {
+        let line = "2024-05-01T10:00:00+09:00\tlap\trepro found\n";
+        let record = Record::parse(line.trim_end()).unwrap();
+        assert_eq!(record.event, Event::Lap);
+        assert_eq!(record.to_line(), line);
+    }
+
+    #[test]
+    fn test_last_event_skips_laps() {
+        let records = parse_records(
+            "2024-05-01T09:00:00+09:00\tstart\ta\n2024-05-01T10:00:00+09:00\tlap\tx\n",
+        )
+        .unwrap();
+        assert_eq!(last_event(&records).unwrap().event, Event::Start);
+    }
+
+    #[test]
+    fn test_read_tail() {
+        let file_path = "test_read_tail.txt";
+        let mut content = String::new();
+        for (month, day) in (1..=12).flat_map(|month| (1..=28).map(move |day| (month, day))) {
+            for (hour, event) in [(9, "start\ttask"), (18, "stop\t")] {
+                content += &format!(
+                    "2024-{:02}-{:02}T{:02}:00:00+09:00\t{}\n",
+                    month, day, hour, event
+                );
+            }
+        }
+        fs::write(file_path, &content).unwrap();
+
+        let tail = read_tail(file_path, 2).unwrap();
+        assert!(tail.records.len() >= 2 && tail.records.len() < 672);
+        let last = tail.records.last().unwrap();
+        assert_eq!(last.timestamp.to_rfc3339(), "2024-12-28T18:00:00+09:00");
+        let offset = *tail.offsets.last().unwrap() as usize;
+        assert_eq!(&content[offset..], last.to_line());
+
+        assert_eq!(read_tail(file_path, 1000).unwrap().records.len(), 672);
+        fs::remove_file(file_path).unwrap();
+        assert!(read_tail(file_path, 1).unwrap().records.is_empty());
+    }
+
+    #[test]
+    fn test_read_since() {
+        let file_path = "test_read_since.txt";
+        let mut content = String::new();
+        for (month, day) in (1..=12).flat_map(|month| (1..=28).map(move |day| (month, day))) {
+            content += &format!("2024-{:02}-{:02}T09:00:00+09:00\tstart\ttask\n", month, day);
+            content += &format!("2024-{:02}-{:02}T18:00:00+09:00\tstop\t\n", month, day);
+        }
+        fs::write(file_path, &content).unwrap();
+
+        let since = DateTime::parse_from_rfc3339("2024-12-27T12:00:00+09:00").unwrap();
+        let records = read_since(file_path, since).unwrap();
+        let times: Vec<String> = records.iter().map(|r| r.timestamp.to_rfc3339()).collect();
+        assert_eq!(
+            times,
+            vec![
+                "2024-12-27T18:00:00+09:00",
+                "2024-12-28T09:00:00+09:00",
+                "2024-12-28T18:00:00+09:00"
+            ]
+        );
+        let since = DateTime::parse_from_rfc3339("2000-01-01T00:00:00+09:00").unwrap();
+        assert_eq!(read_since(file_path, since).unwrap().len(), 672);
+        fs::remove_file(file_path).unwrap();
+    }
+
+    #[test]
+    fn test_stdin_is_not_writable() {
+        assert!(write_records(STDIN_PATH, &[]).is_err());
+        assert!(read_tail(STDIN_PATH, 1).is_err());
+    }
+
+    #[test]
+    fn test_truncate_records() {
+        let file_path = "test_truncate_records.txt";
+        fs::write(
+            file_path,
+            "2024-05-01T09:00:00+09:00\tstart\ta\n2024-05-01T10:00:00+09:00\tstop\t\n",
+        )
+        .unwrap();
+        let tail = read_tail(file_path, 2).unwrap();
+        truncate_records(file_path, tail.offsets[1]).unwrap();
+        assert_eq!(read_records(file_path).unwrap().len(), 1);
+        fs::remove_file(file_path).unwrap();
+    }
+
+    #[test]
+    fn test_remove_lines() {
+        let file_path = "test_remove_lines.txt";
+        fs::write(
+            file_path,
+            "2024-05-01T09:00:00+09:00\tstart\ta\n\
+             2024-05-01T10:00:00+09:00\tstop\t\n\
+             hand-written note: left early\n",
+        )
+        .unwrap();
+        let tail = read_tail(file_path, 2).unwrap();
+        remove_lines(file_path, &tail.offsets[1..]).unwrap();
+        assert_eq!(
+            fs::read_to_string(file_path).unwrap(),
+            "2024-05-01T09:00:00+09:00\tstart\ta\nhand-written note: left early\n"
+        );
+        fs::remove_file(file_path).unwrap();
+    }
+
+    #[test]
+    fn test_edit_lines() {
+        let file_path = "test_edit_lines.txt";
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap();
+        fs::write(
+            file_path,
+            "# started late\n\
+             2024-05-01T09:00:00.250+09:00\tstart\ta\n\
+             not a record\n\
+             2024-05-01T10:00:00+09:00\tstop\t",
+        )
+        .unwrap();
+        let tail = read_tail(file_path, 2).unwrap();
+        let end = fs::metadata(file_path).unwrap().len();
+        // 止めた時刻を直し、末尾に次のセッションを足す
+        let stop = Record::new(at("2024-05-01T10:30:00+09:00"), Event::Stop, "");
+        let next = Record::new(at("2024-05-01T11:00:00+09:00"), Event::Start, "b");
+        edit_lines(
+            file_path,
+            &tail.offsets[1..],
+            &[(tail.offsets[1], stop), (end, next)],
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(file_path).unwrap(),
+            "# started late\n\
+             2024-05-01T09:00:00.250+09:00\tstart\ta\n\
+             not a record\n\
+             2024-05-01T10:30:00+09:00\tstop\t\n\
+             2024-05-01T11:00:00+09:00\tstart\tb\n"
+        );
+        fs::remove_file(file_path).unwrap();
+    }
+
+    #[test]
+    fn test_insert_records() {
+        let file_path = "test_insert_records.txt";
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap();
+        fs::write(
+            file_path,
+            "2024-05-01T09:00:00.250+09:00\tstart\ta\n\
+             2024-05-01T10:00:00+09:00\tstop\t\n\
+             # afternoon\n\
+             2024-05-01T13:00:00+09:00\tstart\tb\n",
+        )
+        .unwrap();
+        insert_records(
+            file_path,
+            &[
+                Record::new(at("2024-05-01T12:00:00+09:00"), Event::Stop, ""),
+                Record::new(at("2024-05-01T11:00:00+09:00"), Event::Start, "c"),
+                Record::new(at("2024-05-01T14:00:00+09:00"), Event::Stop, ""),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(file_path).unwrap(),
+            "2024-05-01T09:00:00.250+09:00\tstart\ta\n\
+             2024-05-01T10:00:00+09:00\tstop\t\n\
+             # afternoon\n\
+             2024-05-01T11:00:00+09:00\tstart\tc\n\
+             2024-05-01T12:00:00+09:00\tstop\t\n\
+             2024-05-01T13:00:00+09:00\tstart\tb\n\
+             2024-05-01T14:00:00+09:00\tstop\t\n"
+        );
+        fs::remove_file(file_path).unwrap();
+    }
+
+    #[test]
+    fn test_parse_field() {
+        assert_eq!(
+            parse_field("ticket=ABC-1").unwrap(),
+            ("ticket".to_string(), "ABC-1".to_string())
+        );
+        assert_eq!(parse_field("url=a?b=c").unwrap().1, "a?b=c".to_string());
+        assert_eq!(
+            parse_field("ticket").unwrap_err(),
+            "Invalid field 'ticket'. Use key=value."
+        );
+        assert!(parse_field("=x").is_err());
+        assert!(parse_field("+a=x").is_err());
+        assert!(parse_field("a=").is_err());
+        assert!(parse_field("a=x\ty").is_err());
+    }
+
+    #[test]
+    fn test_read_records_quarantines_invalid_lines() {
+        let file_path = "test_quarantine_records.txt";
+        let rejected = rejected_path(file_path);
+        let _ = fs::remove_file(&rejected);
+        fs::write(
+            file_path,
+            "2024-05-01T09:00:00+09:00\tstart\ta\nyesterday 9am start a\n\
+             2024-05-01T10:00:00+09:00\tpause\t\n2024-05-01T11:00:00+09:00\tstop\t\n",
+        )
+        .unwrap();
+        assert_eq!(read_records(file_path).unwrap().len(), 2);
+        assert_eq!(read_records(file_path).unwrap().len(), 2);
+        let content = fs::read_to_string(&rejected).unwrap();
+        assert_eq!(content.lines().count(), 4);
+        assert!(content.starts_with("# line 2: Invalid timestamp 'yesterday 9am start a'"));
+        assert!(content
+            .contains("# line 3: Invalid event 'pause'.\n2024-05-01T10:00:00+09:00\tpause\t\n"));
+        assert_eq!(read_tail(file_path, 2).unwrap().records.len(), 2);
+        fs::remove_file(file_path).unwrap();
+        fs::remove_file(rejected).unwrap();
+    }
+
+    #[test]
+    fn test_parse_invalid_event() {
+        let result = Record::parse("2024-05-01T09:00:00+09:00\tpause\t");
+        assert_eq!(result.unwrap_err(), "Invalid event 'pause'.");
+    }
+}
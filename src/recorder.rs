@@ -0,0 +1,995 @@
+use crate::autostop::auto_stop_record;
+use crate::cache;
+use crate::config::Config;
+use crate::error::RecorderError;
+use crate::note::{self, NoteOnStop};
+use crate::policy;
+use crate::record::{
+    edit_lines, ensure_representable, field, file_format, insert_records, last_event, read_records,
+    read_since, read_tail, remove_lines, sort_records, truncate_records, write_records_as, Event,
+    Record, Tail, STDIN_PATH,
+};
+use crate::recovery::{
+    boot_time, find_crashed_start, find_forgotten_start, stop_candidates, CrashRecovery, Resolver,
+    FORGOTTEN_STOP_THRESHOLD_HOURS,
+};
+use crate::reference;
+use crate::session::{
+    is_continuation, pair_sessions, Session, BILLABLE_FIELD, HOST_FIELD, NOTE_FIELD,
+};
+use crate::sources;
+use crate::state::State;
+use chrono::{DateTime, FixedOffset};
+use std::cell::RefCell;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// ほかのプロセスが書き終えるのを待つ時間と、ロックを取り直す間隔
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+thread_local! {
+    // このスレッドがロックを持っている記録ファイル。入れ子の lock はそのまま通す。
+    static HELD_LOCKS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+// RecordStore::lock の間だけ持つ、"<記録ファイル>.lock" の排他ロック。
+// drop でロックファイルを消してから閉じ、ロックを外す。
+#[derive(Debug)]
+pub struct StoreLock {
+    held: Option<(String, File)>,
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        if let Some((path, _)) = &self.held {
+            let _ = fs::remove_file(lock_path(path));
+            HELD_LOCKS.with(|held| held.borrow_mut().retain(|p| p != path));
+        }
+    }
+}
+
+fn lock_path(path: &str) -> String {
+    format!("{}.lock", path)
+}
+
+// 待っている間に前の持ち主がロックファイルを消していたら、取ったロックは無効
+#[cfg(unix)]
+fn is_current(file: &File, path: &str) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_current(_file: &File, path: &str) -> bool {
+    fs::metadata(path).is_ok()
+}
+
+// 1 つの記録ファイル。読み込みは設定の精度に揃え、書き込みは追記か一括の置き換え。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordStore {
+    path: String,
+    // sessions で読む履歴の始まり。None なら全部。
+    since: Option<DateTime<FixedOffset>>,
+}
+
+impl RecordStore {
+    pub fn new(path: &str) -> RecordStore {
+        RecordStore {
+            path: path.to_string(),
+            since: None,
+        }
+    }
+
+    // sessions が since より前を読まないようにする。records や書き込みは全体のまま。
+    pub fn since(mut self, since: Option<DateTime<FixedOffset>>) -> RecordStore {
+        self.since = since;
+        self
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // 読んで確かめてから書くあいだ、ほかのプロセスが書かないようにする。
+    // 待っても取れなければ Busy。
+    pub fn lock(&self) -> Result<StoreLock, RecorderError> {
+        let nested = HELD_LOCKS.with(|held| held.borrow().contains(&self.path));
+        if nested || self.path == STDIN_PATH {
+            return Ok(StoreLock { held: None });
+        }
+        let lock_path = lock_path(&self.path);
+        let deadline = Instant::now() + LOCK_TIMEOUT;
+        let file = loop {
+            let file = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&lock_path)
+                .map_err(io_error)?;
+            match file.try_lock() {
+                Ok(()) if is_current(&file, &lock_path) => break file,
+                Ok(()) => continue,
+                Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                    thread::sleep(LOCK_RETRY_INTERVAL)
+                }
+                Err(TryLockError::WouldBlock) => return Err(RecorderError::Busy),
+                Err(TryLockError::Error(e)) => return Err(io_error(e)),
+            }
+        };
+        HELD_LOCKS.with(|held| held.borrow_mut().push(self.path.clone()));
+        Ok(StoreLock {
+            held: Some((self.path.clone(), file)),
+        })
+    }
+
+    pub fn records(&self, config: &Config) -> Result<Vec<Record>, RecorderError> {
+        let mut records = read_records(&self.path).map_err(RecorderError::Io)?;
+        for record in &mut records {
+            record.timestamp = config.timestamp_precision.truncate(record.timestamp);
+        }
+        Ok(records)
+    }
+
+    // 末尾の count 個のイベントだけを読む
+    pub fn tail(&self, config: &Config, count: usize) -> Result<Tail, RecorderError> {
+        let mut tail = read_tail(&self.path, count).map_err(RecorderError::Io)?;
+        for record in &mut tail.records {
+            record.timestamp = config.timestamp_precision.truncate(record.timestamp);
+        }
+        Ok(tail)
+    }
+
+    // このファイルと設定の sources それぞれでセッションを組み、開始順に合わせる。
+    // sources があれば、どのファイルのものかを origin フィールドに入れる。
+    pub fn sessions(&self, config: &Config) -> Result<Vec<Session>, RecorderError> {
+        let mut sessions = Vec::new();
+        for (origin, path) in sources::origins(&self.path, config) {
+            let path = path.to_str().ok_or(RecorderError::InvalidPath)?;
+            let mut paired = pair_sessions(&self.history(path, config)?);
+            if let Some(origin) = origin {
+                for session in &mut paired {
+                    session
+                        .fields
+                        .push((sources::ORIGIN_FIELD.to_string(), origin.clone()));
+                }
+            }
+            sessions.extend(paired);
+        }
+        sessions.sort_by_key(|s| s.start);
+        Ok(sessions)
+    }
+
+    // sources を合わせず、このファイルだけでセッションを組む
+    pub fn file_sessions(&self, config: &Config) -> Result<Vec<Session>, RecorderError> {
+        Ok(pair_sessions(&self.history(&self.path, config)?))
+    }
+
+    // since があれば末尾から since までだけを読む。標準入力は末尾から読めないので全部。
+    fn history(&self, path: &str, config: &Config) -> Result<Vec<Record>, RecorderError> {
+        let since = match self.since {
+            Some(since) if path != STDIN_PATH => since,
+            _ => return RecordStore::new(path).records(config),
+        };
+        let mut records = read_since(path, since).map_err(RecorderError::Io)?;
+        for record in &mut records {
+            record.timestamp = config.timestamp_precision.truncate(record.timestamp);
+        }
+        Ok(records)
+    }
+
+    // sessions と同じく、sources のレコードを origin を付けて合わせる
+    pub fn all_records(&self, config: &Config) -> Result<Vec<Record>, RecorderError> {
+        let mut records = Vec::new();
+        for (origin, path) in sources::origins(&self.path, config) {
+            let path = path.to_str().ok_or(RecorderError::InvalidPath)?;
+            let loaded = RecordStore::new(path).records(config)?;
+            records.extend(loaded.into_iter().map(|record| match &origin {
+                Some(origin) => record.with_field(sources::ORIGIN_FIELD, origin),
+                None => record,
+            }));
+        }
+        sort_records(&mut records);
+        Ok(records)
+    }
+
+    pub fn append(&self, records: &[Record]) -> Result<(), RecorderError> {
+        let format = file_format(&self.path).map_err(RecorderError::Io)?;
+        ensure_representable(records, format).map_err(RecorderError::Parse)?;
+        let content: String = records.iter().map(|r| r.to_line_as(format)).collect();
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .map_err(io_error)?;
+        cache::invalidate(&self.path).map_err(RecorderError::Io)
+    }
+
+    pub fn replace(&self, records: &[Record]) -> Result<(), RecorderError> {
+        let format = file_format(&self.path).map_err(RecorderError::Io)?;
+        ensure_representable(records, format).map_err(RecorderError::Parse)?;
+        write_records_as(&self.path, records, format).map_err(RecorderError::Io)?;
+        cache::invalidate(&self.path).map_err(RecorderError::Io)
+    }
+
+    // offsets から始まるレコードの行を消す。ほかの行は残す。
+    pub fn remove(&self, offsets: &[u64]) -> Result<(), RecorderError> {
+        remove_lines(&self.path, offsets).map_err(RecorderError::Io)?;
+        cache::invalidate(&self.path).map_err(RecorderError::Io)
+    }
+
+    // offsets から始まる行を消し、inserts のレコードを位置ごとにその行の前へ書く。ほかの行は残す。
+    pub fn edit(&self, remove: &[u64], inserts: &[(u64, Record)]) -> Result<(), RecorderError> {
+        let format = file_format(&self.path).map_err(RecorderError::Io)?;
+        let records: Vec<Record> = inserts.iter().map(|(_, record)| record.clone()).collect();
+        ensure_representable(&records, format).map_err(RecorderError::Parse)?;
+        edit_lines(&self.path, remove, inserts).map_err(RecorderError::Io)?;
+        cache::invalidate(&self.path).map_err(RecorderError::Io)
+    }
+
+    // records を時刻順の位置に差し込む。ほかの行は残す。
+    pub fn insert(&self, records: &[Record]) -> Result<(), RecorderError> {
+        let format = file_format(&self.path).map_err(RecorderError::Io)?;
+        ensure_representable(records, format).map_err(RecorderError::Parse)?;
+        insert_records(&self.path, records).map_err(RecorderError::Io)?;
+        cache::invalidate(&self.path).map_err(RecorderError::Io)
+    }
+
+    // offset 以降を切り捨てる
+    pub fn truncate(&self, offset: u64) -> Result<(), RecorderError> {
+        truncate_records(&self.path, offset).map_err(RecorderError::Io)?;
+        cache::invalidate(&self.path).map_err(RecorderError::Io)
+    }
+}
+
+// start / switch の結果
+#[derive(Debug, Clone, PartialEq)]
+pub struct Started {
+    pub record: Record,
+    // 切り替えのために止めたタスクの start
+    pub stopped: Option<Record>,
+    // merge_gap 以内に同じタスクを始めたので、直前の stop を消して前のセッションを続けた
+    pub continued: bool,
+}
+
+// stop の結果
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stopped {
+    pub record: Record,
+    // 止めた start。force で計測中でないまま書いたときは None。
+    pub start: Option<Record>,
+}
+
+// 設定に従ってレコードを作り、RecordStore に書く。ロック済みの期間には書かない。
+// 対話的な確認はせず、開いたままの start の閉じ方は resolver に任せる。
+pub struct Recorder<'a> {
+    store: RecordStore,
+    config: &'a Config,
+    force_unlock: bool,
+    resolver: Option<&'a dyn Resolver>,
+}
+
+impl<'a> Recorder<'a> {
+    pub fn new(store: RecordStore, config: &'a Config) -> Recorder<'a> {
+        Recorder {
+            store,
+            config,
+            force_unlock: false,
+            resolver: None,
+        }
+    }
+
+    // ロック済みの期間にも書く (--force-unlock)
+    pub fn force_unlock(mut self, force_unlock: bool) -> Recorder<'a> {
+        self.force_unlock = force_unlock;
+        self
+    }
+
+    // 止め忘れやマシンの停止で開いたままの start を、start / stop の前に閉じさせる。
+    // 無ければ開いたままにする。
+    pub fn resolver(mut self, resolver: &'a dyn Resolver) -> Recorder<'a> {
+        self.resolver = Some(resolver);
+        self
+    }
+
+    pub fn store(&self) -> &RecordStore {
+        &self.store
+    }
+
+    pub fn config(&self) -> &Config {
+        self.config
+    }
+
+    // 計測中の start レコード。標準入力は末尾から読めないので None。
+    pub fn running(&self) -> Result<Option<Record>, RecorderError> {
+        if self.store.path() == STDIN_PATH {
+            return Ok(None);
+        }
+        let records = self.store.tail(self.config, 1)?.records;
+        Ok(last_event(&records)
+            .filter(|r| r.event == Event::Start)
+            .cloned())
+    }
+
+    // max_session / auto_stop_at を過ぎたセッションを上限時刻で閉じ、書いた stop を返す
+    pub fn auto_stop(&self, now: DateTime<FixedOffset>) -> Result<Option<Record>, RecorderError> {
+        // 標準入力から読むときは書き戻せないので何もしない
+        if self.store.path() == STDIN_PATH {
+            return Ok(None);
+        }
+        // 閉じるものが無ければロックを取らない。読み取り専用の場所でも report や log が動くように。
+        if self.config.max_session.is_none() && self.config.auto_stop_at.is_none() {
+            return Ok(None);
+        }
+        let records = self.store.tail(self.config, 1)?.records;
+        if auto_stop_record(&records, self.config, now).is_none() {
+            return Ok(None);
+        }
+        // ロックを取るまでにほかのプロセスが止めているかもしれないので読み直す
+        let _lock = self.store.lock()?;
+        let records = self.store.tail(self.config, 1)?.records;
+        let stop = auto_stop_record(&records, self.config, now);
+        if let Some(stop) = &stop {
+            self.store.append(std::slice::from_ref(stop))?;
+        }
+        Ok(stop)
+    }
+
+    // ロック済みの期間に times が入っていればエラーにする
+    pub fn check_unlocked(
+        &self,
+        times: impl Iterator<Item = DateTime<FixedOffset>>,
+    ) -> Result<(), RecorderError> {
+        if self.force_unlock {
+            return Ok(());
+        }
+        Ok(State::load(self.store.path())?.check_unlocked(times)?)
+    }
+
+    // 止め忘れやマシンの停止で開いたままの start を resolver に従って閉じる。
+    // 計測中のタスクが無くなるように stop を書いたら、その stop を返す。
+    pub fn resolve_open_start(
+        &self,
+        now: DateTime<FixedOffset>,
+    ) -> Result<Option<Record>, RecorderError> {
+        self.resolve_open_start_with(now, boot_time())
+    }
+
+    fn resolve_open_start_with(
+        &self,
+        now: DateTime<FixedOffset>,
+        boot: Option<DateTime<FixedOffset>>,
+    ) -> Result<Option<Record>, RecorderError> {
+        let Some(resolver) = self.resolver else {
+            return Ok(None);
+        };
+        let _lock = self.store.lock()?;
+        let records = self.store.tail(self.config, 1)?.records;
+        let stop = |time| with_host(Record::new(time, Event::Stop, ""), self.config);
+        if let Some((start, boot)) =
+            boot.and_then(|boot| Some((find_crashed_start(&records, boot)?, boot)))
+        {
+            return match resolver.crashed(start, boot)? {
+                CrashRecovery::Stop(time) => {
+                    self.check_unlocked([time].into_iter())?;
+                    self.store.append(&[stop(time)])?;
+                    Ok(Some(stop(time)))
+                }
+                CrashRecovery::StopAndResume(time, resume_at) => {
+                    self.check_unlocked([time, resume_at].into_iter())?;
+                    let resumed = resume_record(start, resume_at, self.config);
+                    self.store.append(&[stop(time), resumed])?;
+                    Ok(None)
+                }
+                CrashRecovery::Keep => Ok(None),
+            };
+        }
+        let threshold = chrono::Duration::hours(FORGOTTEN_STOP_THRESHOLD_HOURS);
+        let Some(start) = find_forgotten_start(&records, threshold, now) else {
+            return Ok(None);
+        };
+        let candidates = stop_candidates(&records, start, self.config.workday.end, now);
+        match resolver.forgotten(start, &candidates, now)? {
+            Some(time) => {
+                self.check_unlocked([time].into_iter())?;
+                self.store.append(&[stop(time)])?;
+                Ok(Some(stop(time)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // 計測中のタスクがあればエラーにする
+    pub fn start(&self, task: &str, now: DateTime<FixedOffset>) -> Result<Started, RecorderError> {
+        let record = start_record(
+            task,
+            self.config.timestamp(now),
+            Vec::new(),
+            Vec::new(),
+            None,
+            self.config,
+        )?;
+        self.record_start(record, false)
+    }
+
+    // 計測中のタスクがあれば、同じ時刻で止めてから start する
+    pub fn switch(&self, task: &str, now: DateTime<FixedOffset>) -> Result<Started, RecorderError> {
+        let record = start_record(
+            task,
+            self.config.timestamp(now),
+            Vec::new(),
+            Vec::new(),
+            None,
+            self.config,
+        )?;
+        self.record_start(record, true)
+    }
+
+    // start_record で作ったレコードを書く。最後のレコードより前には書かず、
+    // 書く前に上限を過ぎたセッションと開いたままの start を閉じる。
+    // merge_gap 以内に同じタスクを始め直したなら、直前の stop を消して続ける。
+    pub fn record_start(&self, record: Record, switch: bool) -> Result<Started, RecorderError> {
+        let timestamp = record.timestamp;
+        // 読んで確かめてから書き終えるまで、ほかのプロセスには待ってもらう
+        let _lock = self.store.lock()?;
+        self.check_unlocked([timestamp].into_iter())?;
+        self.ensure_after_last_record(timestamp)?;
+        self.auto_stop(timestamp)?;
+        self.resolve_open_start(timestamp)?;
+
+        let mut started = Started {
+            record: record.clone(),
+            stopped: None,
+            continued: false,
+        };
+        if let Some(running) = self.running()? {
+            if !switch {
+                return Err(RecorderError::AlreadyRunning(running.task));
+            }
+            let stop = with_host(Record::new(timestamp, Event::Stop, ""), self.config);
+            self.store.append(&[stop, record])?;
+            started.stopped = Some(running);
+            return Ok(started);
+        }
+        if let Some(gap) = self.config.merge_gap {
+            let tail = self.store.tail(self.config, 2)?;
+            if is_continuation(&tail.records, &record, gap) {
+                let stop = tail.records.iter().rposition(|r| r.event == Event::Stop);
+                self.store.remove(&[tail.offsets[stop.unwrap()]])?;
+                started.continued = true;
+                return Ok(started);
+            }
+        }
+        self.store.append(&[record])?;
+        Ok(started)
+    }
+
+    // 時刻を遡って書くとき、直前のレコードより前にはしない
+    pub fn ensure_after_last_record(
+        &self,
+        timestamp: DateTime<FixedOffset>,
+    ) -> Result<(), RecorderError> {
+        match self.store.tail(self.config, 1)?.records.last() {
+            Some(last) if timestamp < last.timestamp => {
+                Err(RecorderError::InvalidTimestamp(format!(
+                    "{} is before the last record ({}).",
+                    self.config.format_datetime(timestamp),
+                    self.config.format_datetime(last.timestamp)
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn stop(&self, now: DateTime<FixedOffset>) -> Result<Record, RecorderError> {
+        Ok(self.record_stop(self.config.timestamp(now), false)?.record)
+    }
+
+    // timestamp で計測中のタスクを止める。force なら計測中でなくても stop を書く。
+    // 途中の lap や start より前では止めず、note_on_stop = "ask" なら resolver にメモを尋ねる。
+    pub fn record_stop(
+        &self,
+        timestamp: DateTime<FixedOffset>,
+        force: bool,
+    ) -> Result<Stopped, RecorderError> {
+        let _lock = self.store.lock()?;
+        self.check_unlocked([timestamp].into_iter())?;
+        self.auto_stop(timestamp)?;
+        let running = self.running()?;
+        // 開いたままだった start を閉じたなら、それを止めたものとする
+        if let Some(stop) = self.resolve_open_start(timestamp)? {
+            return Ok(Stopped {
+                record: stop,
+                start: running,
+            });
+        }
+        let Some(start) = self.running()? else {
+            if !force {
+                return Err(RecorderError::NoOpenSession);
+            }
+            let record = with_host(Record::new(timestamp, Event::Stop, ""), self.config);
+            self.store.append(std::slice::from_ref(&record))?;
+            return Ok(Stopped {
+                record,
+                start: None,
+            });
+        };
+        if timestamp < start.timestamp {
+            return Err(RecorderError::InvalidTimestamp(format!(
+                "'{}' was started at {}, after {}.",
+                start.task,
+                self.config.format_datetime(start.timestamp),
+                self.config.format_datetime(timestamp)
+            )));
+        }
+        self.ensure_after_last_record(timestamp)?;
+        let mut record = with_host(Record::new(timestamp, Event::Stop, ""), self.config);
+        if let Some(note) = self.ask_note(&start)? {
+            record = record.with_field(NOTE_FIELD, &note);
+        }
+        self.store.append(std::slice::from_ref(&record))?;
+        Ok(Stopped {
+            record,
+            start: Some(start),
+        })
+    }
+
+    fn ask_note(&self, start: &Record) -> Result<Option<String>, RecorderError> {
+        let Some(resolver) = self.resolver else {
+            return Ok(None);
+        };
+        if self.config.note_on_stop != NoteOnStop::Ask {
+            return Ok(None);
+        }
+        let sessions = self.sessions()?;
+        Ok(resolver.note(start, &note::history(&sessions, &start.task))?)
+    }
+
+    pub fn lap(&self, note: &str, now: DateTime<FixedOffset>) -> Result<Record, RecorderError> {
+        let timestamp = self.config.timestamp(now);
+        let _lock = self.store.lock()?;
+        self.check_unlocked([timestamp].into_iter())?;
+        self.auto_stop(timestamp)?;
+        self.running()?.ok_or(RecorderError::NoOpenSession)?;
+        let record = Record::new(timestamp, Event::Lap, note);
+        self.store.append(std::slice::from_ref(&record))?;
+        Ok(record)
+    }
+
+    // 計測中のセッションを start ごと (途中の lap も) 消し、消した start を返す。
+    // 後ろの読めない行やコメントは残す。
+    pub fn cancel(&self) -> Result<Record, RecorderError> {
+        let _lock = self.store.lock()?;
+        let tail = self.store.tail(self.config, 1)?;
+        let index = tail
+            .records
+            .iter()
+            .rposition(|r| r.event != Event::Lap)
+            .filter(|i| tail.records[*i].event == Event::Start)
+            .ok_or(RecorderError::NoOpenSession)?;
+        self.check_unlocked([tail.records[index].timestamp].into_iter())?;
+        self.store.remove(&tail.offsets[index..])?;
+        Ok(tail.records[index].clone())
+    }
+
+    // 最後に書いたレコード
+    pub fn last(&self) -> Result<Option<Record>, RecorderError> {
+        Ok(self.store.tail(self.config, 1)?.records.pop())
+    }
+
+    // 最後のレコードの行を消し、消したレコードを返す。後ろの読めない行やコメントは残す。
+    pub fn undo(&self) -> Result<Option<Record>, RecorderError> {
+        let _lock = self.store.lock()?;
+        let mut tail = self.store.tail(self.config, 1)?;
+        let (Some(record), Some(offset)) = (tail.records.pop(), tail.offsets.pop()) else {
+            return Ok(None);
+        };
+        self.check_unlocked([record.timestamp].into_iter())?;
+        self.store.remove(&[offset])?;
+        Ok(Some(record))
+    }
+
+    pub fn sessions(&self) -> Result<Vec<Session>, RecorderError> {
+        self.store.sessions(self.config)
+    }
+}
+
+// タグ・フィールド・参照・請求対象の既定値・ホスト名を付け、命名規則を確かめた start レコード
+pub fn start_record(
+    task_name: &str,
+    timestamp: DateTime<FixedOffset>,
+    tags: Vec<&str>,
+    fields: Vec<(String, String)>,
+    billable: Option<bool>,
+    config: &Config,
+) -> Result<Record, RecorderError> {
+    let mut record = Record::new(timestamp, Event::Start, task_name);
+    for tag in tags {
+        if tag.is_empty() || tag.contains(char::is_whitespace) {
+            return Err(RecorderError::Parse(format!("Invalid tag '{}'.", tag)));
+        }
+        record = record.with_tag(tag);
+    }
+    record.fields = fields;
+    // 明示したフィールドはタスク名から取り出した参照より優先する
+    for (key, value) in reference::extract(&config.references, task_name) {
+        if field(&record.fields, &key).is_none() {
+            record = record.with_field(&key, &value);
+        }
+    }
+    if let Some(billable) = billable.or_else(|| config.billable_default(task_name)) {
+        record = record.with_field(BILLABLE_FIELD, &billable.to_string());
+    }
+    let record = with_host(record, config);
+    policy::check(config, &record)?;
+    Ok(record)
+}
+
+fn io_error(error: std::io::Error) -> RecorderError {
+    RecorderError::Io(error.to_string())
+}
+
+pub fn with_host(record: Record, config: &Config) -> Record {
+    match config.host() {
+        Some(host) => record.with_field(HOST_FIELD, &host),
+        None => record,
+    }
+}
+
+// 直前のタスクを同じタグで start し直すレコード
+pub fn resume_record(start: &Record, at: DateTime<FixedOffset>, config: &Config) -> Record {
+    let mut record = Record::new(at, Event::Start, &start.task);
+    record.tags = start.tags.clone();
+    with_host(record, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use std::fs;
+    use std::sync::mpsc;
+
+    fn now() -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339("2024-05-01T09:00:00+09:00").unwrap()
+    }
+
+    #[test]
+    fn test_recorder_start_lap_stop() {
+        let path = "test_recorder_records.txt";
+        let _ = fs::remove_file(path);
+        let config = Config::default();
+        let recorder = Recorder::new(RecordStore::new(path), &config);
+        assert_eq!(
+            recorder.stop(now()).unwrap_err(),
+            RecorderError::NoOpenSession
+        );
+
+        recorder.start("clientA:fix", now()).unwrap();
+        recorder
+            .lap("reproduced", now() + Duration::minutes(30))
+            .unwrap();
+        assert_eq!(recorder.running().unwrap().unwrap().task, "clientA:fix");
+        recorder.stop(now() + Duration::hours(1)).unwrap();
+        assert_eq!(recorder.running().unwrap(), None);
+
+        let sessions = recorder.sessions().unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].laps.len(), 1);
+        assert_eq!(sessions[0].stop, Some(now() + Duration::hours(1)));
+        fs::remove_file(path).unwrap();
+    }
+
+    // 止め忘れは開いたままにし、メモは決まったものを付ける
+    struct Note(&'static str);
+
+    impl Resolver for Note {
+        fn forgotten(
+            &self,
+            _: &Record,
+            _: &[(DateTime<FixedOffset>, &str)],
+            _: DateTime<FixedOffset>,
+        ) -> Result<Option<DateTime<FixedOffset>>, String> {
+            Ok(None)
+        }
+
+        fn crashed(&self, _: &Record, _: DateTime<FixedOffset>) -> Result<CrashRecovery, String> {
+            Ok(CrashRecovery::Keep)
+        }
+
+        fn note(&self, _: &Record, _: &[&str]) -> Result<Option<String>, String> {
+            Ok(Some(self.0.to_string()))
+        }
+    }
+
+    #[test]
+    fn test_recorder_record_stop() {
+        let path = "test_recorder_record_stop.txt";
+        let _ = fs::remove_file(path);
+        let config = Config::parse("note_on_stop = \"ask\"").unwrap();
+        let note = Note("done");
+        let recorder = Recorder::new(RecordStore::new(path), &config).resolver(&note);
+        // force なら計測中でなくても書く
+        let stopped = recorder.record_stop(now(), true).unwrap();
+        assert_eq!(stopped.start, None);
+        assert_eq!(field(&stopped.record.fields, NOTE_FIELD), None);
+
+        recorder.start("a", now() + Duration::hours(1)).unwrap();
+        recorder.lap("x", now() + Duration::hours(2)).unwrap();
+        // start や途中の lap より前では止めない
+        assert!(matches!(
+            recorder.record_stop(now() + Duration::minutes(30), false),
+            Err(RecorderError::InvalidTimestamp(_))
+        ));
+        assert!(matches!(
+            recorder.record_stop(now() + Duration::minutes(90), false),
+            Err(RecorderError::InvalidTimestamp(_))
+        ));
+        let stopped = recorder
+            .record_stop(now() + Duration::hours(3), false)
+            .unwrap();
+        assert_eq!(stopped.start.unwrap().task, "a");
+        assert_eq!(field(&stopped.record.fields, NOTE_FIELD), Some("done"));
+        assert_eq!(recorder.running().unwrap(), None);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_recorder_refuses_double_start() {
+        let path = "test_recorder_double_start.txt";
+        let _ = fs::remove_file(path);
+        let config = Config::default();
+        let recorder = Recorder::new(RecordStore::new(path), &config);
+        recorder.start("a", now()).unwrap();
+        let later = now() + Duration::hours(1);
+        assert_eq!(
+            recorder.start("b", later).unwrap_err(),
+            RecorderError::AlreadyRunning("a".to_string())
+        );
+        recorder.switch("b", later).unwrap();
+        let sessions = recorder.sessions().unwrap();
+        let tasks: Vec<(&str, Option<DateTime<FixedOffset>>)> =
+            sessions.iter().map(|s| (s.task.as_str(), s.stop)).collect();
+        assert_eq!(tasks, vec![("a", Some(later)), ("b", None)]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_recorder_start_checks() {
+        let path = "test_recorder_start_checks.txt";
+        let _ = fs::remove_file(path);
+        let config = Config::parse("merge_gap = \"5m\"").unwrap();
+        let recorder = Recorder::new(RecordStore::new(path), &config);
+        recorder.start("a", now()).unwrap();
+        recorder.stop(now() + Duration::hours(1)).unwrap();
+        // 最後のレコードより前には書かない
+        assert!(matches!(
+            recorder.start("b", now()),
+            Err(RecorderError::InvalidTimestamp(_))
+        ));
+        // merge_gap 以内なら stop を消して続ける
+        let started = recorder.start("a", now() + Duration::minutes(62)).unwrap();
+        assert!(started.continued);
+        assert_eq!(recorder.sessions().unwrap().len(), 1);
+        assert_eq!(recorder.running().unwrap().unwrap().timestamp, now());
+
+        // ロック済みの期間には force_unlock がなければ書かない
+        let mut state = State::default();
+        let day = crate::period::Period::day(now().date_naive());
+        state.lock("day", &day, now());
+        state.save(path).unwrap();
+        let later = now() + Duration::hours(2);
+        assert!(matches!(recorder.stop(later), Err(RecorderError::Other(_))));
+        let unlocked = Recorder::new(RecordStore::new(path), &config).force_unlock(true);
+        unlocked.stop(later).unwrap();
+        fs::remove_file(path).unwrap();
+        fs::remove_file(crate::state::state_path(path)).unwrap();
+    }
+
+    // 止め忘れは決まった時刻で閉じ、落ちたときは起動の 1 時間前で閉じて起動時刻から再開する
+    struct StopAt(DateTime<FixedOffset>);
+
+    impl Resolver for StopAt {
+        fn forgotten(
+            &self,
+            _: &Record,
+            candidates: &[(DateTime<FixedOffset>, &str)],
+            _: DateTime<FixedOffset>,
+        ) -> Result<Option<DateTime<FixedOffset>>, String> {
+            assert_eq!(candidates.len(), 1);
+            Ok(Some(self.0))
+        }
+
+        fn crashed(
+            &self,
+            _: &Record,
+            boot: DateTime<FixedOffset>,
+        ) -> Result<CrashRecovery, String> {
+            Ok(CrashRecovery::StopAndResume(
+                boot - Duration::hours(1),
+                boot,
+            ))
+        }
+    }
+
+    #[test]
+    fn test_recorder_resolve_open_start() {
+        let path = "test_recorder_resolve.txt";
+        let _ = fs::remove_file(path);
+        let config = Config::parse("record_host = true\ndevice_name = \"laptop\"").unwrap();
+        let stop_at = StopAt(now() + Duration::hours(9));
+        let next_day = now() + Duration::hours(24);
+        // resolver がなければ開いたまま
+        let recorder = Recorder::new(RecordStore::new(path), &config);
+        recorder.start("a", now()).unwrap();
+        assert_eq!(recorder.resolve_open_start(next_day).unwrap(), None);
+
+        let recorder = recorder.resolver(&stop_at);
+        let stop = recorder.resolve_open_start_with(next_day, None).unwrap();
+        assert_eq!(stop.as_ref().unwrap().timestamp, stop_at.0);
+        assert_eq!(
+            stop.unwrap().fields,
+            vec![(HOST_FIELD.to_string(), "laptop".to_string())]
+        );
+        assert_eq!(recorder.running().unwrap(), None);
+
+        // 起動より前からの start は、落ちた時刻で閉じて起動時刻から再開する
+        recorder.start("b", now() + Duration::hours(10)).unwrap();
+        let boot = now() + Duration::hours(12);
+        assert_eq!(
+            recorder
+                .resolve_open_start_with(next_day, Some(boot))
+                .unwrap(),
+            None
+        );
+        let running = recorder.running().unwrap().unwrap();
+        assert_eq!((running.task.as_str(), running.timestamp), ("b", boot));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_recorder_cancel() {
+        let path = "test_recorder_cancel.txt";
+        let _ = fs::remove_file(path);
+        let config = Config::default();
+        let recorder = Recorder::new(RecordStore::new(path), &config);
+        recorder.start("a", now()).unwrap();
+        recorder.stop(now() + Duration::hours(1)).unwrap();
+        assert_eq!(recorder.cancel().unwrap_err(), RecorderError::NoOpenSession);
+        recorder.start("b", now() + Duration::hours(2)).unwrap();
+        recorder.lap("x", now() + Duration::hours(3)).unwrap();
+        assert_eq!(recorder.cancel().unwrap().task, "b");
+        let sessions = recorder.sessions().unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].task, "a");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_store_since() {
+        let path = "test_recorder_since.txt";
+        let _ = fs::remove_file(path);
+        let config = Config::default();
+        let recorder = Recorder::new(RecordStore::new(path), &config);
+        for (hours, task) in [(0, "old"), (24, "new")] {
+            recorder
+                .start(task, now() + Duration::hours(hours))
+                .unwrap();
+            recorder.stop(now() + Duration::hours(hours + 1)).unwrap();
+        }
+        let store = RecordStore::new(path).since(Some(now() + Duration::hours(12)));
+        let sessions = store.sessions(&config).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].task, "new");
+        // 書き込みに使う records は全体のまま
+        assert_eq!(store.records(&config).unwrap().len(), 4);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_recorder_auto_stop() {
+        let path = "test_recorder_auto_stop.txt";
+        let _ = fs::remove_file(path);
+        let config = Config::parse("max_session = \"2h\"").unwrap();
+        let recorder = Recorder::new(RecordStore::new(path), &config);
+        recorder.start("a", now()).unwrap();
+        let stop = recorder.auto_stop(now() + Duration::hours(5)).unwrap();
+        assert_eq!(stop.unwrap().timestamp, now() + Duration::hours(2));
+        assert_eq!(recorder.running().unwrap(), None);
+
+        // 閉じるものが無ければ、ほかが持っているロックを待たない
+        recorder.start("b", now() + Duration::hours(6)).unwrap();
+        let (locked_tx, locked_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let holder = thread::spawn(move || {
+            let _lock = RecordStore::new(path).lock().unwrap();
+            locked_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+        });
+        locked_rx.recv().unwrap();
+        let started = Instant::now();
+        assert_eq!(
+            recorder.auto_stop(now() + Duration::hours(7)).unwrap(),
+            None
+        );
+        assert!(started.elapsed() < LOCK_TIMEOUT);
+        release_tx.send(()).unwrap();
+        holder.join().unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_record_store_replace_and_truncate() {
+        let path = "test_record_store.txt";
+        let store = RecordStore::new(path);
+        let config = Config::default();
+        let records = vec![
+            Record::new(now(), Event::Start, "a"),
+            Record::new(now() + Duration::hours(1), Event::Stop, ""),
+        ];
+        store.replace(&records).unwrap();
+        assert_eq!(store.records(&config).unwrap(), records);
+        let tail = store.tail(&config, 1).unwrap();
+        store
+            .truncate(tail.offsets[tail.offsets.len() - 1])
+            .unwrap();
+        assert_eq!(store.records(&config).unwrap(), records[..1]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_record_store_lock() {
+        let path = "test_record_store_lock.txt";
+        let store = RecordStore::new(path);
+        let lock = store.lock().unwrap();
+        // 同じスレッドでの入れ子は待たない
+        drop(store.lock().unwrap());
+        // ほかのプロセスからは、ファイルを別に開いたときと同じく弾かれる
+        let other = File::open(lock_path(path)).unwrap();
+        assert!(matches!(other.try_lock(), Err(TryLockError::WouldBlock)));
+        assert!(is_current(&other, &lock_path(path)));
+        drop(lock);
+        // 外すときにロックファイルを消すので、待っていた側のロックは古いものになる
+        assert!(other.try_lock().is_ok());
+        assert!(!is_current(&other, &lock_path(path)));
+        drop(other);
+        drop(store.lock().unwrap());
+        assert!(fs::metadata(lock_path(path)).is_err());
+    }
+
+    #[test]
+    fn test_start_record_rejects_invalid_tag() {
+        let result = start_record(
+            "a",
+            now(),
+            vec!["two words"],
+            Vec::new(),
+            None,
+            &Config::default(),
+        );
+        assert_eq!(
+            result.unwrap_err(),
+            RecorderError::Parse("Invalid tag 'two words'.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_start_record_extracts_references() {
+        let config =
+            Config::parse("[[references]]\nfield = \"jira\"\npattern = '[A-Z]+-\\d+'").unwrap();
+        let record =
+            start_record("PROJ-12 fix", now(), Vec::new(), Vec::new(), None, &config).unwrap();
+        assert_eq!(field(&record.fields, "jira"), Some("PROJ-12"));
+        // 明示したフィールドはそのまま
+        let fields = vec![("jira".to_string(), "PROJ-1".to_string())];
+        let record = start_record("PROJ-12 fix", now(), Vec::new(), fields, None, &config).unwrap();
+        assert_eq!(record.fields.len(), 1);
+        assert_eq!(field(&record.fields, "jira"), Some("PROJ-1"));
+    }
+}
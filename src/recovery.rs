@@ -0,0 +1,452 @@
+use crate::config::Config;
+use crate::duration::format_duration;
+use crate::period::{parse_local_datetime, to_local};
+use crate::record::{last_event, Event, Record};
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveTime};
+use std::fs;
+use std::io::{BufRead, Write};
+
+pub const FORGOTTEN_STOP_THRESHOLD_HOURS: i64 = 16;
+
+// 最後のレコードが閾値より長く開いたままの start なら返す
+pub fn find_forgotten_start(
+    records: &[Record],
+    threshold: Duration,
+    now: DateTime<FixedOffset>,
+) -> Option<&Record> {
+    last_event(records).filter(|r| r.event == Event::Start && now - r.timestamp > threshold)
+}
+
+// 開始日の終業時刻 (設定の workday の終わり)。開始が終業時刻より後なら翌日の終業時刻。
+pub fn end_of_workday(start: DateTime<FixedOffset>, time: NaiveTime) -> DateTime<FixedOffset> {
+    let local = start.with_timezone(&Local);
+    let end = to_local(local.date_naive().and_time(time));
+    if end > start {
+        end
+    } else {
+        to_local(local.date_naive().succ_opt().unwrap().and_time(time))
+    }
+}
+
+// 開いたままの start を閉じる候補。最後の lap (最後の操作) と終業時刻のうち、
+// start より後で now までのもの。records は start 以降を含む末尾。
+pub fn stop_candidates(
+    records: &[Record],
+    start: &Record,
+    workday_end: NaiveTime,
+    now: DateTime<FixedOffset>,
+) -> Vec<(DateTime<FixedOffset>, &'static str)> {
+    let last_lap = records
+        .iter()
+        .rev()
+        .take_while(|r| r.event == Event::Lap)
+        .next()
+        .map(|lap| lap.timestamp);
+    let mut candidates: Vec<_> = last_lap.map(|t| (t, "last activity")).into_iter().collect();
+    candidates.push((
+        end_of_workday(start.timestamp, workday_end),
+        "end of workday",
+    ));
+    candidates.retain(|(time, _)| *time > start.timestamp && *time <= now);
+    candidates
+}
+
+// 閉じる時刻を対話的に選ばせる。候補に続けて、入力した時刻と開いたままにする選択肢を出す。
+// None は開いたままにする。
+pub fn prompt_stop_time(
+    start: &Record,
+    candidates: &[(DateTime<FixedOffset>, &str)],
+    now: DateTime<FixedOffset>,
+    config: &Config,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<Option<DateTime<FixedOffset>>, String> {
+    let format = |t: DateTime<FixedOffset>| config.format_datetime(t);
+    let mut prompt = format!(
+        "'{}' has been running since {} ({}).\n",
+        start.task,
+        format(start.timestamp),
+        format_duration(now - start.timestamp)
+    );
+    for (i, (time, label)) in candidates.iter().enumerate() {
+        prompt += &format!("  {}) stop it at {} ({})\n", i + 1, format(*time), label);
+    }
+    let enter = (candidates.len() + 1).to_string();
+    let keep = (candidates.len() + 2).to_string();
+    prompt += &format!(
+        "  {}) stop it at a time you enter\n  {}) keep it running\nChoose [1-{}]: ",
+        enter, keep, keep
+    );
+
+    loop {
+        write!(output, "{}", prompt).map_err(|e| e.to_string())?;
+        output.flush().map_err(|e| e.to_string())?;
+        let answer = read_line(input)?;
+        if let Some((time, _)) = answer
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| candidates.get(n.checked_sub(1)?))
+        {
+            return Ok(Some(*time));
+        }
+        if answer == enter {
+            write!(output, "Stop time (HH:MM or YYYY-MM-DD HH:MM): ").map_err(|e| e.to_string())?;
+            output.flush().map_err(|e| e.to_string())?;
+            let date = start.timestamp.with_timezone(&Local).date_naive();
+            let time = parse_local_datetime(&read_line(input)?, date)?;
+            if time <= start.timestamp || time > now {
+                return Err(format!(
+                    "Stop time must be between {} and now.",
+                    format(start.timestamp)
+                ));
+            }
+            return Ok(Some(time));
+        }
+        if answer == keep {
+            return Ok(None);
+        }
+    }
+}
+
+// 開いたままの start の閉じ方や stop に付けるメモを決める。Recorder は決まった値で書くだけで、
+// 尋ねるかどうかは実装に任せる (CLI は端末で尋ねる)。
+pub trait Resolver {
+    // 長く開いたままの start を閉じる時刻。None は開いたままにする。
+    fn forgotten(
+        &self,
+        start: &Record,
+        candidates: &[(DateTime<FixedOffset>, &str)],
+        now: DateTime<FixedOffset>,
+    ) -> Result<Option<DateTime<FixedOffset>>, String>;
+
+    // マシンが落ちる前から開いたままの start の扱い
+    fn crashed(&self, start: &Record, boot: DateTime<FixedOffset>)
+        -> Result<CrashRecovery, String>;
+
+    // note_on_stop = "ask" のとき、止める start に付けるメモ。history は同じタスクの過去のメモ。
+    fn note(&self, _start: &Record, _history: &[&str]) -> Result<Option<String>, String> {
+        Ok(None)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CrashRecovery {
+    Stop(DateTime<FixedOffset>),
+    // 落ちた時刻で閉じ、起動時刻から同じタスクを再開する
+    StopAndResume(DateTime<FixedOffset>, DateTime<FixedOffset>),
+    Keep,
+}
+
+// システムの起動時刻。Linux の /proc/stat からしか取れない。
+pub fn boot_time() -> Option<DateTime<FixedOffset>> {
+    parse_boot_time(&fs::read_to_string("/proc/stat").ok()?)
+}
+
+pub fn parse_boot_time(stat: &str) -> Option<DateTime<FixedOffset>> {
+    let seconds = stat
+        .lines()
+        .find_map(|line| line.strip_prefix("btime "))?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(
+        DateTime::from_timestamp(seconds, 0)?
+            .with_timezone(&Local)
+            .fixed_offset(),
+    )
+}
+
+// 起動より前から開いたままの start は、マシンが落ちたときに計測中だったもの
+pub fn find_crashed_start(records: &[Record], boot: DateTime<FixedOffset>) -> Option<&Record> {
+    last_event(records).filter(|r| r.event == Event::Start && r.timestamp < boot)
+}
+
+pub fn prompt_crash_recovery(
+    start: &Record,
+    boot: DateTime<FixedOffset>,
+    config: &Config,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<CrashRecovery, String> {
+    let format = |t: DateTime<FixedOffset>| config.format_datetime(t);
+    let prompt = format!(
+        "'{}' was running when the machine went down (started {}, booted {}).\n  \
+         1) stop it at {} (system boot)\n  \
+         2) stop it at a time you enter and resume it at {}\n  \
+         3) keep it running\nChoose [1-3]: ",
+        start.task,
+        format(start.timestamp),
+        format(boot),
+        format(boot),
+        format(boot)
+    );
+
+    loop {
+        write!(output, "{}", prompt).map_err(|e| e.to_string())?;
+        output.flush().map_err(|e| e.to_string())?;
+        match read_line(input)?.as_str() {
+            "1" => return Ok(CrashRecovery::Stop(boot)),
+            "2" => {
+                write!(output, "Went down at (HH:MM or YYYY-MM-DD HH:MM): ")
+                    .map_err(|e| e.to_string())?;
+                output.flush().map_err(|e| e.to_string())?;
+                let date = boot.with_timezone(&Local).date_naive();
+                let time = parse_local_datetime(&read_line(input)?, date)?;
+                if time <= start.timestamp || time > boot {
+                    return Err(format!(
+                        "The time must be between {} and {}.",
+                        format(start.timestamp),
+                        format(boot)
+                    ));
+                }
+                return Ok(CrashRecovery::StopAndResume(time, boot));
+            }
+            "3" => return Ok(CrashRecovery::Keep),
+            _ => continue,
+        }
+    }
+}
+
+// undo で消す最後のレコードを見せて確かめる。y 以外は取りやめ。
+pub fn prompt_undo(
+    record: &Record,
+    config: &Config,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<bool, String> {
+    let mut prompt = format!(
+        "Remove the last record: {} at {}",
+        record.event.as_str(),
+        config.format_datetime(record.timestamp)
+    );
+    if !record.task.is_empty() {
+        prompt += &format!(" '{}'", record.task);
+    }
+    write!(output, "{}? [y/N]: ", prompt).map_err(|e| e.to_string())?;
+    output.flush().map_err(|e| e.to_string())?;
+    Ok(matches!(
+        read_line(input)?.to_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
+
+fn read_line(input: &mut impl BufRead) -> Result<String, String> {
+    let mut line = String::new();
+    if input.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+        return Err("No answer given.".to_string());
+    }
+    Ok(line.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::{local_midnight, parse_date};
+
+    fn local(date: &str, hour: i64) -> DateTime<FixedOffset> {
+        local_midnight(parse_date(date).unwrap()) + Duration::hours(hour)
+    }
+
+    fn start(time: DateTime<FixedOffset>) -> Record {
+        Record::new(time, Event::Start, "task")
+    }
+
+    #[test]
+    fn test_find_forgotten_start() {
+        let threshold = Duration::hours(FORGOTTEN_STOP_THRESHOLD_HOURS);
+        let records = vec![start(local("2024-05-01", 9))];
+        assert!(find_forgotten_start(&records, threshold, local("2024-05-02", 9)).is_some());
+        assert!(find_forgotten_start(&records, threshold, local("2024-05-01", 20)).is_none());
+
+        let stopped = vec![
+            start(local("2024-05-01", 9)),
+            Record::new(local("2024-05-01", 18), Event::Stop, ""),
+        ];
+        assert!(find_forgotten_start(&stopped, threshold, local("2024-05-03", 9)).is_none());
+    }
+
+    fn end(hour: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, 0, 0).unwrap()
+    }
+
+    // 終業時刻 18:00 の候補
+    fn candidates(
+        record: &Record,
+        now: DateTime<FixedOffset>,
+    ) -> Vec<(DateTime<FixedOffset>, &'static str)> {
+        stop_candidates(std::slice::from_ref(record), record, end(18), now)
+    }
+
+    #[test]
+    fn test_end_of_workday() {
+        assert_eq!(
+            end_of_workday(local("2024-05-01", 9), end(18)),
+            local("2024-05-01", 18)
+        );
+        assert_eq!(
+            end_of_workday(local("2024-05-01", 20), end(18)),
+            local("2024-05-02", 18)
+        );
+        assert_eq!(
+            end_of_workday(local("2024-05-01", 9), end(17)),
+            local("2024-05-01", 17)
+        );
+    }
+
+    #[test]
+    fn test_stop_candidates() {
+        let records = vec![
+            start(local("2024-05-01", 9)),
+            Record::new(local("2024-05-01", 12), Event::Lap, "lunch"),
+            Record::new(local("2024-05-01", 14), Event::Lap, "review"),
+        ];
+        assert_eq!(
+            stop_candidates(&records, &records[0], end(18), local("2024-05-02", 9)),
+            vec![
+                (local("2024-05-01", 14), "last activity"),
+                (local("2024-05-01", 18), "end of workday")
+            ]
+        );
+        // まだ来ていない終業時刻は出さない
+        assert_eq!(
+            stop_candidates(&records, &records[0], end(18), local("2024-05-01", 17)),
+            vec![(local("2024-05-01", 14), "last activity")]
+        );
+    }
+
+    #[test]
+    fn test_prompt_stop_at_end_of_workday() {
+        let record = start(local("2024-05-01", 9));
+        let now = local("2024-05-02", 15);
+        let mut input = "1\n".as_bytes();
+        let mut output = Vec::new();
+        let time = prompt_stop_time(
+            &record,
+            &candidates(&record, now),
+            now,
+            &Config::default(),
+            &mut input,
+            &mut output,
+        );
+        assert_eq!(time.unwrap(), Some(local("2024-05-01", 18)));
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("has been running since"));
+        assert!(output.contains("  1) stop it at 2024-05-01 18:00 (end of workday)\n"));
+        assert!(output.ends_with("Choose [1-3]: "));
+    }
+
+    #[test]
+    fn test_prompt_undo() {
+        let record = start(local("2024-05-01", 9));
+        let config = Config::default();
+        let mut output = Vec::new();
+        assert!(prompt_undo(&record, &config, &mut "y\n".as_bytes(), &mut output).unwrap());
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Remove the last record: start at 2024-05-01 09:00 'task'? [y/N]: "
+        );
+        assert!(!prompt_undo(&record, &config, &mut "\n".as_bytes(), &mut Vec::new()).unwrap());
+        assert!(prompt_undo(&record, &config, &mut "".as_bytes(), &mut Vec::new()).is_err());
+
+        // time_format に従って書く
+        let config = Config::parse("time_format = \"%I:%M %p\"").unwrap();
+        let mut output = Vec::new();
+        assert!(prompt_undo(&record, &config, &mut "y\n".as_bytes(), &mut output).unwrap());
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("start at 2024-05-01 09:00 AM 'task'"));
+    }
+
+    #[test]
+    fn test_prompt_stop_at_entered_time() {
+        let record = start(local("2024-05-01", 9));
+        let mut input = "x\n2\n17:30\n".as_bytes();
+        let now = local("2024-05-02", 15);
+        let time = prompt_stop_time(
+            &record,
+            &candidates(&record, now),
+            now,
+            &Config::default(),
+            &mut input,
+            &mut Vec::new(),
+        );
+        assert_eq!(
+            time.unwrap(),
+            Some(local("2024-05-01", 17) + Duration::minutes(30))
+        );
+    }
+
+    #[test]
+    fn test_prompt_keep_running() {
+        let record = start(local("2024-05-01", 9));
+        let mut input = "3\n".as_bytes();
+        let now = local("2024-05-02", 15);
+        let time = prompt_stop_time(
+            &record,
+            &candidates(&record, now),
+            now,
+            &Config::default(),
+            &mut input,
+            &mut Vec::new(),
+        );
+        assert_eq!(time.unwrap(), None);
+    }
+
+    #[test]
+    fn test_prompt_rejects_time_before_start() {
+        let record = start(local("2024-05-01", 9));
+        let mut input = "2\n08:00\n".as_bytes();
+        let now = local("2024-05-02", 15);
+        let result = prompt_stop_time(
+            &record,
+            &candidates(&record, now),
+            now,
+            &Config::default(),
+            &mut input,
+            &mut Vec::new(),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_boot_time() {
+        let stat = "cpu  1 2 3\nbtime 1714521600\nprocesses 10\n";
+        let boot = parse_boot_time(stat).unwrap();
+        assert_eq!(boot.timestamp(), 1714521600);
+        assert_eq!(parse_boot_time("cpu  1 2 3\n"), None);
+    }
+
+    #[test]
+    fn test_find_crashed_start() {
+        let records = vec![start(local("2024-05-01", 9))];
+        assert!(find_crashed_start(&records, local("2024-05-01", 11)).is_some());
+        assert!(find_crashed_start(&records, local("2024-05-01", 8)).is_none());
+    }
+
+    #[test]
+    fn test_prompt_crash_stop_and_resume() {
+        let record = start(local("2024-05-01", 9));
+        let boot = local("2024-05-01", 11);
+        let mut input = "2\n10:30\n".as_bytes();
+        let mut output = Vec::new();
+        let choice =
+            prompt_crash_recovery(&record, boot, &Config::default(), &mut input, &mut output);
+        assert_eq!(
+            choice.unwrap(),
+            CrashRecovery::StopAndResume(local("2024-05-01", 10) + Duration::minutes(30), boot)
+        );
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("was running when the machine went down"));
+
+        let mut input = "1\n".as_bytes();
+        let choice = prompt_crash_recovery(
+            &record,
+            boot,
+            &Config::default(),
+            &mut input,
+            &mut Vec::new(),
+        );
+        assert_eq!(choice.unwrap(), CrashRecovery::Stop(boot));
+    }
+}
@@ -0,0 +1,161 @@
+use crate::config::{deserialize_required_time, deserialize_weekdays};
+use crate::period::{to_local, Period};
+use crate::record::{Event, Record};
+use crate::session::Session;
+use chrono::{DateTime, Datelike, FixedOffset, NaiveTime, Weekday};
+use serde::Deserialize;
+
+pub const RECURRING_TAG: &str = "recurring";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RecurringEntry {
+    pub task: String,
+    #[serde(deserialize_with = "deserialize_required_time")]
+    pub start: NaiveTime,
+    #[serde(deserialize_with = "deserialize_required_time")]
+    pub end: NaiveTime,
+    #[serde(default = "weekdays", deserialize_with = "deserialize_weekdays")]
+    pub days: Vec<Weekday>,
+}
+
+fn weekdays() -> Vec<Weekday> {
+    vec![
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+    ]
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Fill {
+    Added(Session),
+    Overlapping(Session),
+}
+
+// 期間内の定例エントリを、記録済みの時間と重ならないものだけ実体化する。
+// 既に同じタスク・同じ開始時刻で記録されているものと、まだ終わっていないものは対象外。
+pub fn fill(
+    entries: &[RecurringEntry],
+    period: &Period,
+    sessions: &[Session],
+    now: DateTime<FixedOffset>,
+) -> Vec<Fill> {
+    let mut fills = Vec::new();
+
+    for day in period.days() {
+        for entry in entries.iter().filter(|e| e.days.contains(&day.weekday())) {
+            let start = to_local(day.and_time(entry.start));
+            let stop = to_local(day.and_time(entry.end));
+            if stop <= start || stop > now {
+                continue;
+            }
+            if sessions
+                .iter()
+                .any(|s| s.task == entry.task && s.start == start)
+            {
+                continue;
+            }
+
+            let mut session = Session::new(&entry.task, start, Some(stop));
+            session.tags.push(RECURRING_TAG.to_string());
+            if sessions
+                .iter()
+                .any(|s| !s.overlap(start, stop, now).is_zero())
+            {
+                fills.push(Fill::Overlapping(session));
+            } else {
+                fills.push(Fill::Added(session));
+            }
+        }
+    }
+
+    fills
+}
+
+pub fn to_records(session: &Session) -> Vec<Record> {
+    let mut start = Record::new(session.start, Event::Start, &session.task);
+    start.tags = session.tags.clone();
+    let stop = Record::new(session.stop.unwrap_or(session.start), Event::Stop, "");
+    vec![start, stop]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::{local_midnight, parse_date};
+    use chrono::Duration;
+
+    fn local(date: &str, minutes: i64) -> DateTime<FixedOffset> {
+        local_midnight(parse_date(date).unwrap()) + Duration::minutes(minutes)
+    }
+
+    fn standup() -> RecurringEntry {
+        RecurringEntry {
+            task: "standup".to_string(),
+            start: NaiveTime::from_hms_opt(9, 30, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(9, 45, 0).unwrap(),
+            days: weekdays(),
+        }
+    }
+
+    fn period(start: &str, end: &str) -> Period {
+        Period::new(parse_date(start).unwrap(), parse_date(end).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_fill_weekdays_only() {
+        // 2024-05-03 (金) から 2024-05-06 (月)
+        let fills = fill(
+            &[standup()],
+            &period("2024-05-03", "2024-05-06"),
+            &[],
+            local("2024-05-07", 0),
+        );
+        assert_eq!(fills.len(), 2);
+        assert!(fills.iter().all(|f| matches!(f, Fill::Added(_))));
+    }
+
+    #[test]
+    fn test_fill_skips_existing_and_future_entries() {
+        let existing = Session::new(
+            "standup",
+            local("2024-05-01", 570),
+            Some(local("2024-05-01", 585)),
+        );
+        let fills = fill(
+            &[standup()],
+            &period("2024-05-01", "2024-05-02"),
+            &[existing],
+            local("2024-05-02", 540),
+        );
+        assert!(fills.is_empty());
+    }
+
+    #[test]
+    fn test_fill_reports_overlap() {
+        let tracked = Session::new(
+            "a",
+            local("2024-05-01", 540),
+            Some(local("2024-05-01", 600)),
+        );
+        let fills = fill(
+            &[standup()],
+            &period("2024-05-01", "2024-05-01"),
+            &[tracked],
+            local("2024-05-02", 0),
+        );
+        assert!(matches!(&fills[..], [Fill::Overlapping(_)]));
+    }
+
+    #[test]
+    fn test_to_records() {
+        let session = Session::new("a", local("2024-05-01", 0), Some(local("2024-05-01", 15)));
+        let records = to_records(&session);
+        assert_eq!(records[0].event, Event::Start);
+        assert_eq!(records[1].event, Event::Stop);
+        assert_eq!(records[1].timestamp, local("2024-05-01", 15));
+    }
+}
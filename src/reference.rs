@@ -0,0 +1,91 @@
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+
+// [[references]] の 1 件。タスク名から pattern に合う部分を取り出し、field に書く。
+// pattern にグループがあれば最初のグループを、なければ一致した全体を使う。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReferenceRule {
+    pub field: String,
+    #[serde(deserialize_with = "deserialize_pattern")]
+    pub pattern: Regex,
+}
+
+impl ReferenceRule {
+    pub fn extract(&self, task: &str) -> Option<String> {
+        let captures = self.pattern.captures(task)?;
+        captures
+            .get(1)
+            .or_else(|| captures.get(0))
+            .map(|m| m.as_str().to_string())
+    }
+}
+
+// タスク名から取り出した (フィールド名, 値)。同じフィールドは最初の規則が優先する。
+pub fn extract(rules: &[ReferenceRule], task: &str) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for rule in rules {
+        if fields.iter().any(|(key, _)| *key == rule.field) {
+            continue;
+        }
+        if let Some(value) = rule.extract(task) {
+            fields.push((rule.field.clone(), value));
+        }
+    }
+    fields
+}
+
+fn deserialize_pattern<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn rules() -> Vec<ReferenceRule> {
+        Config::parse(
+            r#"
+            [[references]]
+            field = "jira"
+            pattern = '\b[A-Z][A-Z0-9]+-\d+\b'
+
+            [[references]]
+            field = "issue"
+            pattern = 'github\.com/[^/\s]+/[^/\s]+/issues/(\d+)'
+
+            [[references]]
+            field = "issue"
+            pattern = '#(\d+)'
+            "#,
+        )
+        .unwrap()
+        .references
+    }
+
+    #[test]
+    fn test_extract() {
+        assert_eq!(
+            extract(&rules(), "PROJ-12 fix login (#34)"),
+            vec![
+                ("jira".to_string(), "PROJ-12".to_string()),
+                ("issue".to_string(), "34".to_string())
+            ]
+        );
+        assert_eq!(
+            extract(&rules(), "review https://github.com/a/b/issues/7 #8"),
+            vec![("issue".to_string(), "7".to_string())]
+        );
+        assert!(extract(&rules(), "meeting").is_empty());
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        assert!(Config::parse("[[references]]\nfield = \"a\"\npattern = '('").is_err());
+    }
+}
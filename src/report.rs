@@ -0,0 +1,555 @@
+use crate::anomaly::{find_anomalies, AnomalyRules};
+use crate::approval;
+use crate::config::DEFAULT_TIME_FORMAT;
+use crate::duration::{format_duration, hours};
+use crate::hours::HoursProfile;
+use crate::interval::Interval;
+use crate::period::Period;
+use crate::session::Session;
+use crate::sources::ORIGIN_FIELD;
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate};
+
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const NO_PROJECT_LABEL: &str = "(no project)";
+const NO_ORIGIN_LABEL: &str = "(no origin)";
+const NON_BILLABLE_LABEL: &str = "Non-billable";
+const OTHER_LABEL: &str = "(other)";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum GroupBy {
+    #[default]
+    Task,
+    Project,
+    Origin,
+    // field:<name> のフィールドの値。[[references]] で取り出したチケット番号など。
+    Field(String),
+}
+
+impl GroupBy {
+    pub fn parse(s: &str) -> Result<GroupBy, String> {
+        match s {
+            "task" => Ok(GroupBy::Task),
+            "project" => Ok(GroupBy::Project),
+            "origin" => Ok(GroupBy::Origin),
+            _ => match s.strip_prefix("field:") {
+                Some(name) if !name.is_empty() => Ok(GroupBy::Field(name.to_string())),
+                _ => Err(format!("Invalid group '{}'.", s)),
+            },
+        }
+    }
+
+    pub fn key(&self, session: &Session) -> String {
+        match self {
+            GroupBy::Task => session.task.clone(),
+            GroupBy::Project => session.project().unwrap_or(NO_PROJECT_LABEL).to_string(),
+            GroupBy::Origin => session
+                .field(ORIGIN_FIELD)
+                .unwrap_or(NO_ORIGIN_LABEL)
+                .to_string(),
+            GroupBy::Field(name) => session
+                .field(name)
+                .map_or_else(|| format!("(no {})", name), str::to_string),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ReportOptions {
+    pub group_by: GroupBy,
+    pub sparkline: bool,
+    pub laps: bool,
+    pub billable: bool,
+    pub anomaly_rules: AnomalyRules,
+    // 上位 top 件か、合計に占める割合が min_share (%) 以上のものだけを残す
+    pub top: Option<usize>,
+    pub min_share: Option<f64>,
+    // anomaly_rules.hours の所定時間と比べた過不足を出す
+    pub balance: bool,
+    // lap の区間を書く時刻の書式。None なら DEFAULT_TIME_FORMAT。
+    pub time_format: Option<String>,
+}
+
+pub fn totals(
+    sessions: &[Session],
+    period: &Period,
+    group_by: GroupBy,
+    now: DateTime<FixedOffset>,
+) -> Vec<(String, Duration)> {
+    let (from, to) = (period.start_time(), period.end_time());
+    let mut totals: Vec<(String, Duration)> = Vec::new();
+
+    for session in sessions {
+        let overlap = session.overlap(from, to, now);
+        if overlap.is_zero() {
+            continue;
+        }
+        let key = group_by.key(session);
+        match totals.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, total)) => *total += overlap,
+            None => totals.push((key, overlap)),
+        }
+    }
+
+    totals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    totals
+}
+
+pub fn daily_totals(
+    sessions: &[Session],
+    period: &Period,
+    now: DateTime<FixedOffset>,
+) -> Vec<(NaiveDate, Duration)> {
+    period
+        .days()
+        .map(|day| {
+            let range = Interval::day(day);
+            (
+                day,
+                total_between(sessions.iter(), range.start, range.end, now),
+            )
+        })
+        .collect()
+}
+
+pub fn total_between<'a>(
+    sessions: impl Iterator<Item = &'a Session>,
+    from: DateTime<FixedOffset>,
+    to: DateTime<FixedOffset>,
+    now: DateTime<FixedOffset>,
+) -> Duration {
+    sum(sessions.map(|s| s.overlap(from, to, now)))
+}
+
+pub fn sum(durations: impl Iterator<Item = Duration>) -> Duration {
+    durations.fold(Duration::zero(), |acc, d| acc + d)
+}
+
+pub fn sparkline(values: &[f64]) -> String {
+    let max = values.iter().cloned().fold(0.0, f64::max);
+    values
+        .iter()
+        .map(|v| {
+            if max <= 0.0 || *v <= 0.0 {
+                SPARK_LEVELS[0]
+            } else {
+                let level = 1 + (v / max * (SPARK_LEVELS.len() - 2) as f64).round() as usize;
+                SPARK_LEVELS[level.min(SPARK_LEVELS.len() - 1)]
+            }
+        })
+        .collect()
+}
+
+// 残さなかったものは (other) にまとめ、最後に置く
+pub fn collapse(
+    totals: Vec<(String, Duration)>,
+    top: Option<usize>,
+    min_share: Option<f64>,
+) -> Vec<(String, Duration)> {
+    let grand_total = hours(sum(totals.iter().map(|(_, d)| *d)));
+    let mut kept = Vec::new();
+    let mut other = Duration::zero();
+    for (i, (key, total)) in totals.into_iter().enumerate() {
+        let share = if grand_total > 0.0 {
+            hours(total) / grand_total * 100.0
+        } else {
+            0.0
+        };
+        if top.is_some_and(|top| i >= top) || min_share.is_some_and(|min| share < min) {
+            other += total;
+        } else {
+            kept.push((key, total));
+        }
+    }
+    if !other.is_zero() {
+        kept.push((OTHER_LABEL.to_string(), other));
+    }
+    kept
+}
+
+// "5" や "5%" を割合 (%) として読む
+pub fn parse_share(s: &str) -> Result<f64, String> {
+    s.strip_suffix('%')
+        .unwrap_or(s)
+        .parse::<f64>()
+        .ok()
+        .filter(|share| (0.0..=100.0).contains(share))
+        .ok_or_else(|| format!("Invalid share '{}'.", s))
+}
+
+pub fn render_report(
+    sessions: &[Session],
+    period: &Period,
+    options: &ReportOptions,
+    now: DateTime<FixedOffset>,
+) -> String {
+    let totals = collapse(
+        totals(sessions, period, options.group_by.clone(), now),
+        options.top,
+        options.min_share,
+    );
+    let grand_total = sum(totals.iter().map(|(_, d)| *d));
+    let width = totals
+        .iter()
+        .map(|(key, _)| key.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(if options.billable {
+            NON_BILLABLE_LABEL.len()
+        } else {
+            "Total".len()
+        });
+
+    let mut output = format!("{} - {}\n", period.start, period.end);
+    for (key, total) in &totals {
+        output += &format!("{:<width$}  {:>8}\n", key, format_duration(*total));
+    }
+    output += &format!("{:<width$}  {:>8}\n", "Total", format_duration(grand_total));
+    if options.billable {
+        let (from, to) = (period.start_time(), period.end_time());
+        let billable = total_between(sessions.iter().filter(|s| s.billable()), from, to, now);
+        output += &format!("{:<width$}  {:>8}\n", "Billable", format_duration(billable));
+        output += &format!(
+            "{:<width$}  {:>8}\n",
+            NON_BILLABLE_LABEL,
+            format_duration(grand_total - billable)
+        );
+    }
+
+    if options.balance {
+        output += &render_balance(grand_total, period, &options.anomaly_rules.hours, now);
+    }
+
+    if options.sparkline {
+        let daily = daily_totals(sessions, period, now);
+        let values: Vec<f64> = daily.iter().map(|(_, d)| hours(*d)).collect();
+        let max = daily
+            .iter()
+            .map(|(_, d)| *d)
+            .max()
+            .unwrap_or_else(Duration::zero);
+        output += &format!(
+            "\n{}  max {}/day\n",
+            sparkline(&values),
+            format_duration(max)
+        );
+    }
+
+    if options.laps {
+        let time_format = options.time_format.as_deref();
+        output += &render_laps(
+            sessions,
+            period,
+            time_format.unwrap_or(DEFAULT_TIME_FORMAT),
+            now,
+        );
+    }
+    output += &approval::render_summary(sessions, period, now);
+
+    let anomalies = find_anomalies(sessions, period, &options.anomaly_rules, now);
+    if !anomalies.is_empty() {
+        output += "\nAnomalies:\n";
+        for anomaly in &anomalies {
+            output += &format!("  ! {}\n", anomaly);
+        }
+    }
+
+    output
+}
+
+// 今日までの所定時間と、記録した時間との過不足
+fn render_balance(
+    tracked: Duration,
+    period: &Period,
+    hours: &HoursProfile,
+    now: DateTime<FixedOffset>,
+) -> String {
+    let target = hours.total(period, now.with_timezone(&Local).date_naive());
+    let balance = tracked - target;
+    let sign = if balance < Duration::zero() { "-" } else { "+" };
+    format!(
+        "\nTarget   {:>8}\nBalance  {:>8}\n",
+        format_duration(target),
+        format!("{}{}", sign, format_duration(balance.abs()))
+    )
+}
+
+// lap のあるセッションを区間ごとに表示する
+fn render_laps(
+    sessions: &[Session],
+    period: &Period,
+    time_format: &str,
+    now: DateTime<FixedOffset>,
+) -> String {
+    let (from, to) = (period.start_time(), period.end_time());
+    let mut output = String::new();
+    for session in sessions {
+        if session.laps.is_empty() || session.overlap(from, to, now).is_zero() {
+            continue;
+        }
+        if output.is_empty() {
+            output += "\nLaps:\n";
+        }
+        let time = |t: DateTime<FixedOffset>| t.with_timezone(&Local).format(time_format);
+        output += &format!(
+            "  {} {} {}-{}\n",
+            session.task,
+            session.start.with_timezone(&Local).format("%Y-%m-%d"),
+            time(session.start),
+            time(session.end_or(now))
+        );
+        for (start, end, note) in session.segments(now) {
+            output += &format!(
+                "    {}-{}  {:>8}",
+                time(start),
+                time(end),
+                format_duration(end - start)
+            );
+            if let Some(note) = note.filter(|note| !note.is_empty()) {
+                output += &format!("  {}", note);
+            }
+            output += "\n";
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::parse_date;
+
+    fn ts(s: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(s).unwrap()
+    }
+
+    fn session(task: &str, start: &str, stop: &str) -> Session {
+        Session::new(task, ts(start), Some(ts(stop)))
+    }
+
+    fn period(start: &str, end: &str) -> Period {
+        Period::new(parse_date(start).unwrap(), parse_date(end).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_sparkline_scales_to_max() {
+        assert_eq!(sparkline(&[0.0, 4.0, 8.0]), "▁▅█");
+        assert_eq!(sparkline(&[0.0, 0.0]), "▁▁");
+        assert_eq!(sparkline(&[0.1, 8.0]), "▂█");
+    }
+
+    #[test]
+    fn test_totals_sorted_by_duration() {
+        let now = ts("2030-01-01T00:00:00Z");
+        let sessions = vec![
+            session("a", "2024-05-01T09:00:00Z", "2024-05-01T10:00:00Z"),
+            session("b", "2024-05-01T10:00:00Z", "2024-05-01T13:00:00Z"),
+            session("a", "2024-05-02T09:00:00Z", "2024-05-02T09:30:00Z"),
+        ];
+        let totals = totals(
+            &sessions,
+            &period("2024-04-01", "2024-06-30"),
+            GroupBy::Task,
+            now,
+        );
+        assert_eq!(
+            totals,
+            vec![
+                ("b".to_string(), Duration::hours(3)),
+                ("a".to_string(), Duration::minutes(90)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_totals_by_project() {
+        let now = ts("2030-01-01T00:00:00Z");
+        let sessions = vec![
+            session("x:a", "2024-05-01T09:00:00Z", "2024-05-01T10:00:00Z"),
+            session("x:b", "2024-05-01T10:00:00Z", "2024-05-01T11:00:00Z"),
+            session("c", "2024-05-01T11:00:00Z", "2024-05-01T11:30:00Z"),
+        ];
+        let totals = totals(
+            &sessions,
+            &period("2024-05-01", "2024-05-31"),
+            GroupBy::Project,
+            now,
+        );
+        assert_eq!(
+            totals,
+            vec![
+                ("x".to_string(), Duration::hours(2)),
+                (NO_PROJECT_LABEL.to_string(), Duration::minutes(30)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_totals_by_field() {
+        let now = ts("2030-01-01T00:00:00Z");
+        let mut sessions = vec![
+            session("PROJ-1 a", "2024-05-01T09:00:00Z", "2024-05-01T10:00:00Z"),
+            session("b", "2024-05-01T10:00:00Z", "2024-05-01T10:30:00Z"),
+        ];
+        sessions[0]
+            .fields
+            .push(("jira".to_string(), "PROJ-1".to_string()));
+        let group_by = GroupBy::parse("field:jira").unwrap();
+        let totals = totals(
+            &sessions,
+            &period("2024-05-01", "2024-05-31"),
+            group_by,
+            now,
+        );
+        assert_eq!(
+            totals,
+            vec![
+                ("PROJ-1".to_string(), Duration::hours(1)),
+                ("(no jira)".to_string(), Duration::minutes(30)),
+            ]
+        );
+        assert!(GroupBy::parse("field:").is_err());
+    }
+
+    #[test]
+    fn test_totals_excludes_sessions_outside_period() {
+        let now = ts("2030-01-01T00:00:00Z");
+        let sessions = vec![session("a", "2023-05-01T09:00:00Z", "2023-05-01T10:00:00Z")];
+        let period = period("2024-05-01", "2024-05-31");
+        assert!(totals(&sessions, &period, GroupBy::Task, now).is_empty());
+    }
+
+    #[test]
+    fn test_total_between_clips_to_anchor() {
+        let now = ts("2024-05-01T12:00:00Z");
+        let sessions = [
+            session("a", "2024-05-01T08:00:00Z", "2024-05-01T10:00:00Z"),
+            Session::new("b", ts("2024-05-01T11:00:00Z"), None),
+        ];
+        let total = total_between(sessions.iter(), ts("2024-05-01T09:00:00Z"), now, now);
+        assert_eq!(total, Duration::hours(2));
+    }
+
+    #[test]
+    fn test_daily_totals_has_entry_per_day() {
+        let now = ts("2030-01-01T00:00:00Z");
+        let daily = daily_totals(&[], &period("2024-05-01", "2024-05-07"), now);
+        assert_eq!(daily.len(), 7);
+        assert!(daily.iter().all(|(_, d)| d.is_zero()));
+    }
+
+    #[test]
+    fn test_render_report_with_sparkline() {
+        let now = ts("2030-01-01T00:00:00Z");
+        let options = ReportOptions {
+            sparkline: true,
+            ..Default::default()
+        };
+        let output = render_report(&[], &period("2024-05-01", "2024-05-03"), &options, now);
+        assert!(output.contains("Total"));
+        assert!(output.contains("▁▁▁  max 0m/day"));
+    }
+
+    #[test]
+    fn test_render_report_with_laps() {
+        let now = ts("2030-01-01T00:00:00Z");
+        let mut with_laps = session("a", "2024-05-01T09:00:00Z", "2024-05-01T12:00:00Z");
+        with_laps.laps = vec![(ts("2024-05-01T09:40:00Z"), "repro found".to_string())];
+        let options = ReportOptions {
+            laps: true,
+            ..Default::default()
+        };
+        let output = render_report(
+            &[with_laps],
+            &period("2024-04-01", "2024-06-30"),
+            &options,
+            now,
+        );
+        assert!(output.contains("Laps:"));
+        assert!(output.contains("     40m  repro found\n"));
+        assert!(output.contains("   2h20m\n"));
+    }
+
+    #[test]
+    fn test_render_report_with_billable_split() {
+        let now = ts("2030-01-01T00:00:00Z");
+        let mut billable = session("a", "2024-05-01T09:00:00Z", "2024-05-01T11:00:00Z");
+        billable.fields = vec![("billable".to_string(), "true".to_string())];
+        let sessions = vec![
+            billable,
+            session("b", "2024-05-01T11:00:00Z", "2024-05-01T11:30:00Z"),
+        ];
+        let options = ReportOptions {
+            billable: true,
+            ..Default::default()
+        };
+        let output = render_report(
+            &sessions,
+            &period("2024-05-01", "2024-05-31"),
+            &options,
+            now,
+        );
+        assert!(output.contains("Billable         2h00m\n"));
+        assert!(output.contains("Non-billable       30m\n"));
+    }
+
+    #[test]
+    fn test_render_report_balance() {
+        let sessions = vec![session("a", "2024-05-08T10:00:00Z", "2024-05-08T12:00:00Z")];
+        let options = ReportOptions {
+            balance: true,
+            ..Default::default()
+        };
+        let output = render_report(
+            &sessions,
+            &period("2024-05-06", "2024-05-12"),
+            &options,
+            ts("2030-01-01T00:00:00Z"),
+        );
+        assert!(output.contains("\nTarget     40h00m\nBalance   -38h00m\n"));
+    }
+
+    #[test]
+    fn test_collapse() {
+        let totals = vec![
+            ("a".to_string(), Duration::hours(6)),
+            ("b".to_string(), Duration::hours(3)),
+            ("c".to_string(), Duration::minutes(30)),
+            ("d".to_string(), Duration::minutes(30)),
+        ];
+        let keys = |collapsed: Vec<(String, Duration)>| -> Vec<(String, i64)> {
+            collapsed
+                .into_iter()
+                .map(|(k, d)| (k, d.num_minutes()))
+                .collect()
+        };
+        assert_eq!(
+            keys(collapse(totals.clone(), Some(1), None)),
+            vec![("a".to_string(), 360), ("(other)".to_string(), 240)]
+        );
+        assert_eq!(
+            keys(collapse(totals.clone(), None, Some(10.0))),
+            vec![
+                ("a".to_string(), 360),
+                ("b".to_string(), 180),
+                ("(other)".to_string(), 60)
+            ]
+        );
+        assert_eq!(collapse(totals.clone(), Some(4), None), totals);
+        assert_eq!(parse_share("5%"), Ok(5.0));
+        assert!(parse_share("150").is_err());
+    }
+
+    #[test]
+    fn test_render_report_flags_anomalies() {
+        let now = ts("2030-01-01T00:00:00Z");
+        let sessions = vec![session("a", "2024-05-04T00:00:00Z", "2024-05-05T00:00:00Z")];
+        let output = render_report(
+            &sessions,
+            &period("2024-05-04", "2024-05-05"),
+            &ReportOptions::default(),
+            now,
+        );
+        assert!(output.contains("Anomalies:"));
+        assert!(output.contains("'a' ran for 24h00m"));
+    }
+}
@@ -0,0 +1,136 @@
+use crate::config::deserialize_required_duration;
+use crate::interval::Interval;
+use crate::session::Session;
+use chrono::{DateTime, Duration, DurationRound, FixedOffset};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoundingMode {
+    #[default]
+    Nearest,
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rounding {
+    #[serde(deserialize_with = "deserialize_required_duration")]
+    pub interval: Duration,
+    #[serde(default)]
+    pub mode: RoundingMode,
+}
+
+// 記録時 (storage) と集計時 (report) の丸めは別々に設定する
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoundingConfig {
+    pub storage: Option<Rounding>,
+    pub report: Option<Rounding>,
+}
+
+impl Rounding {
+    // 時刻をローカル時刻の区切りに揃える
+    pub fn round_time(&self, time: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        let rounded = match self.mode {
+            RoundingMode::Nearest => time.duration_round(self.interval),
+            RoundingMode::Up => time.duration_round_up(self.interval),
+            RoundingMode::Down => time.duration_trunc(self.interval),
+        };
+        rounded.unwrap_or(time)
+    }
+
+    pub fn round_duration(&self, duration: Duration) -> Duration {
+        let interval = self.interval.num_seconds();
+        if interval <= 0 {
+            return duration;
+        }
+        let seconds = duration.num_seconds();
+        let units = match self.mode {
+            RoundingMode::Nearest => (seconds + interval / 2) / interval,
+            RoundingMode::Up => (seconds + interval - 1) / interval,
+            RoundingMode::Down => seconds / interval,
+        };
+        Duration::seconds(units * interval)
+    }
+}
+
+// 終了済みのセッションの長さを丸める。開いているセッションはそのまま。
+pub fn round_sessions(sessions: &mut [Session], rounding: &Rounding) {
+    for session in sessions {
+        if let Some(stop) = session.stop {
+            session.stop = Some(Interval::new(session.start, stop).round(rounding).end);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::Period;
+    use crate::report::{totals, GroupBy};
+
+    fn ts(s: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(s).unwrap()
+    }
+
+    fn rounding(minutes: i64, mode: RoundingMode) -> Rounding {
+        Rounding {
+            interval: Duration::minutes(minutes),
+            mode,
+        }
+    }
+
+    #[test]
+    fn test_round_time() {
+        let time = ts("2024-05-01T09:07:00+09:00");
+        let round = |mode| rounding(15, mode).round_time(time).to_rfc3339();
+        assert_eq!(round(RoundingMode::Nearest), "2024-05-01T09:00:00+09:00");
+        assert_eq!(round(RoundingMode::Up), "2024-05-01T09:15:00+09:00");
+        assert_eq!(round(RoundingMode::Down), "2024-05-01T09:00:00+09:00");
+    }
+
+    #[test]
+    fn test_round_duration() {
+        let duration = Duration::minutes(52);
+        let round = |mode| rounding(15, mode).round_duration(duration);
+        assert_eq!(round(RoundingMode::Nearest), Duration::minutes(45));
+        assert_eq!(round(RoundingMode::Up), Duration::minutes(60));
+        assert_eq!(round(RoundingMode::Down), Duration::minutes(45));
+    }
+
+    // 09:07-09:58 と 10:05-10:20 の 2 セッションを、各ポリシーで集計する
+    fn total_under(storage: Option<Rounding>, report: Option<Rounding>) -> Duration {
+        let now = ts("2030-01-01T00:00:00Z");
+        let store = |t: &str| storage.map_or(ts(t), |r| r.round_time(ts(t)));
+        let mut sessions = vec![
+            Session::new(
+                "a",
+                store("2024-05-01T09:07:00Z"),
+                Some(store("2024-05-01T09:58:00Z")),
+            ),
+            Session::new(
+                "a",
+                store("2024-05-01T10:05:00Z"),
+                Some(store("2024-05-01T10:20:00Z")),
+            ),
+        ];
+        if let Some(report) = report {
+            round_sessions(&mut sessions, &report);
+        }
+        let period = Period::parse("2024-05").unwrap();
+        totals(&sessions, &period, GroupBy::Task, now)[0].1
+    }
+
+    #[test]
+    fn test_totals_under_each_policy() {
+        let quarter = Some(rounding(15, RoundingMode::Up));
+        assert_eq!(total_under(None, None), Duration::minutes(66));
+        assert_eq!(total_under(quarter, None), Duration::minutes(60));
+        assert_eq!(total_under(None, quarter), Duration::minutes(75));
+        let nearest = Some(rounding(15, RoundingMode::Nearest));
+        assert_eq!(total_under(nearest, None), Duration::minutes(75));
+        assert_eq!(total_under(None, nearest), Duration::minutes(60));
+    }
+}
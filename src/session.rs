@@ -0,0 +1,429 @@
+use crate::config::Config;
+use crate::interval::{gaps, Interval};
+use crate::record::{field, Event, Record};
+use chrono::{DateTime, Duration, FixedOffset};
+
+pub const BILLABLE_FIELD: &str = "billable";
+pub const NOTE_FIELD: &str = "note";
+pub const HOST_FIELD: &str = "host";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub task: String,
+    pub start: DateTime<FixedOffset>,
+    pub stop: Option<DateTime<FixedOffset>>,
+    pub tags: Vec<String>,
+    pub fields: Vec<(String, String)>,
+    pub laps: Vec<(DateTime<FixedOffset>, String)>,
+}
+
+impl Session {
+    pub fn new(
+        task: &str,
+        start: DateTime<FixedOffset>,
+        stop: Option<DateTime<FixedOffset>>,
+    ) -> Session {
+        Session {
+            task: task.to_string(),
+            start,
+            stop,
+            tags: Vec::new(),
+            fields: Vec::new(),
+            laps: Vec::new(),
+        }
+    }
+
+    pub fn field(&self, key: &str) -> Option<&str> {
+        field(&self.fields, key)
+    }
+
+    // billable=true のものだけ請求対象。記録が無ければ請求対象外。
+    pub fn billable(&self) -> bool {
+        self.field(BILLABLE_FIELD) == Some("true")
+    }
+
+    pub fn project(&self) -> Option<&str> {
+        project_of(&self.task)
+    }
+
+    pub fn end_or(&self, now: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        self.stop.unwrap_or(now)
+    }
+
+    // 開いているセッションは now までの区間
+    pub fn interval(&self, now: DateTime<FixedOffset>) -> Interval {
+        Interval::new(self.start, self.end_or(now))
+    }
+
+    // lap で区切った区間。各区間はそれを締めた lap のメモを持ち、最後の区間は None。
+    pub fn segments(
+        &self,
+        now: DateTime<FixedOffset>,
+    ) -> Vec<(DateTime<FixedOffset>, DateTime<FixedOffset>, Option<&str>)> {
+        let mut segments = Vec::new();
+        let mut from = self.start;
+        for (time, note) in &self.laps {
+            segments.push((from, *time, Some(note.as_str())));
+            from = *time;
+        }
+        segments.push((from, self.end_or(now), None));
+        segments
+    }
+
+    // 指定範囲と重なる部分の長さ
+    pub fn overlap(
+        &self,
+        from: DateTime<FixedOffset>,
+        to: DateTime<FixedOffset>,
+        now: DateTime<FixedOffset>,
+    ) -> Duration {
+        self.interval(now).overlap(&Interval::new(from, to))
+    }
+}
+
+// "project:task" 形式のタスク名からプロジェクト名を取り出す
+pub fn project_of(task: &str) -> Option<&str> {
+    task.split_once(':')
+        .map(|(project, _)| project.trim())
+        .filter(|project| !project.is_empty())
+}
+
+// start/stop を組にしてセッションにする。
+// 開いたままの start に続く start は、前のタスクをその時刻で閉じたものとみなす。
+// stop に付いたタグ (auto-stopped など) と、start に無いフィールドはセッションに含める。
+pub fn pair_sessions(records: &[Record]) -> Vec<Session> {
+    let mut sessions: Vec<Session> = Vec::new();
+    let mut open: Option<Session> = None;
+
+    for record in records {
+        match record.event {
+            Event::Start => {
+                if let Some(mut session) = open.take() {
+                    session.stop = Some(record.timestamp);
+                    sessions.push(session);
+                }
+                let mut session = Session::new(&record.task, record.timestamp, None);
+                session.tags = record.tags.clone();
+                session.fields = record.fields.clone();
+                open = Some(session);
+            }
+            Event::Stop => {
+                if let Some(mut session) = open.take() {
+                    session.stop = Some(record.timestamp);
+                    session.tags.extend(record.tags.iter().cloned());
+                    for (key, value) in &record.fields {
+                        if session.field(key).is_none() {
+                            session.fields.push((key.clone(), value.clone()));
+                        }
+                    }
+                    sessions.push(session);
+                }
+            }
+            Event::Lap => {
+                if let Some(session) = open.as_mut() {
+                    session.laps.push((record.timestamp, record.task.clone()));
+                }
+            }
+        }
+    }
+
+    sessions.extend(open);
+    sessions
+}
+
+// 直前の stop から gap 以内に同じタスクを同じタグで start し直すなら、中断ではなく継続とみなす。
+// auto-stopped などタグの付いた stop は継続の対象にしない。
+pub fn is_continuation(records: &[Record], start: &Record, gap: Duration) -> bool {
+    let mut events = records.iter().rev().filter(|r| r.event != Event::Lap);
+    let (Some(stop), Some(previous)) = (events.next(), events.next()) else {
+        return false;
+    };
+    if stop.event != Event::Stop || !stop.tags.is_empty() {
+        return false;
+    }
+    let since_stop = start.timestamp - stop.timestamp;
+    if since_stop < Duration::zero() || since_stop > gap {
+        return false;
+    }
+    previous.event == Event::Start && previous.task == start.task && previous.tags == start.tags
+}
+
+// 最近始めた順に、重複を除いたタスク名を limit 個まで
+pub fn recent_tasks(sessions: &[Session], limit: usize) -> Vec<&str> {
+    let mut tasks: Vec<&str> = Vec::new();
+    for session in sessions.iter().rev() {
+        if tasks.len() == limit {
+            break;
+        }
+        if !tasks.contains(&session.task.as_str()) {
+            tasks.push(&session.task);
+        }
+    }
+    tasks
+}
+
+// add や import で書く区間が記録済みのセッションと重なったときの扱い
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverlapMode {
+    #[default]
+    Reject,
+    // --allow-overlap: そのまま書く
+    Allow,
+    // --clip: 重ならない部分だけを書く
+    Clip,
+}
+
+// interval のうち書いてよい区間。Reject で重なればエラーにする。
+pub fn resolve_overlap(
+    sessions: &[Session],
+    task: &str,
+    interval: Interval,
+    mode: OverlapMode,
+    config: &Config,
+    now: DateTime<FixedOffset>,
+) -> Result<Vec<Interval>, String> {
+    let overlapping: Vec<&Session> = sessions
+        .iter()
+        .filter(|s| !s.interval(now).overlap(&interval).is_zero())
+        .collect();
+    match mode {
+        _ if overlapping.is_empty() => Ok(vec![interval]),
+        OverlapMode::Allow => Ok(vec![interval]),
+        OverlapMode::Clip => Ok(gaps(&interval, overlapping.iter().map(|s| s.interval(now)))),
+        OverlapMode::Reject => {
+            let existing: Vec<String> = overlapping
+                .iter()
+                .map(|s| {
+                    let interval = s.interval(now);
+                    format!(
+                        "'{}' {} - {}",
+                        s.task,
+                        config.format_datetime(interval.start),
+                        config.format_time(interval.end)
+                    )
+                })
+                .collect();
+            Err(format!(
+                "'{}' overlaps {}. Use --clip to record only the free time or --allow-overlap to record it anyway.",
+                task,
+                existing.join(", ")
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::parse_records;
+
+    fn ts(s: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(s).unwrap()
+    }
+
+    #[test]
+    fn test_pair_sessions() {
+        let records = parse_records(
+            "2024-05-01T09:00:00+09:00\tstart\ta\n\
+             2024-05-01T10:00:00+09:00\tstop\t\n\
+             2024-05-01T10:30:00+09:00\tstart\tb\n",
+        )
+        .unwrap();
+        let sessions = pair_sessions(&records);
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].task, "a");
+        assert_eq!(sessions[0].stop, Some(ts("2024-05-01T10:00:00+09:00")));
+        assert_eq!(sessions[1].task, "b");
+        assert_eq!(sessions[1].stop, None);
+    }
+
+    #[test]
+    fn test_pair_sessions_start_closes_previous() {
+        let records = parse_records(
+            "2024-05-01T09:00:00+09:00\tstart\ta\n\
+             2024-05-01T09:45:00+09:00\tstart\tb\n\
+             2024-05-01T10:00:00+09:00\tstop\t\n",
+        )
+        .unwrap();
+        let sessions = pair_sessions(&records);
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].stop, Some(ts("2024-05-01T09:45:00+09:00")));
+        assert_eq!(sessions[1].stop, Some(ts("2024-05-01T10:00:00+09:00")));
+    }
+
+    #[test]
+    fn test_pair_sessions_collects_stop_tags() {
+        let records = parse_records(
+            "2024-05-01T09:00:00+09:00\tstart\ta\t+x\n\
+             2024-05-01T19:00:00+09:00\tstop\t\t+auto-stopped\n",
+        )
+        .unwrap();
+        let sessions = pair_sessions(&records);
+        assert_eq!(sessions[0].tags, vec!["x", "auto-stopped"]);
+    }
+
+    #[test]
+    fn test_pair_sessions_prefers_start_fields() {
+        let records = parse_records(
+            "2024-05-01T09:00:00+09:00\tstart\ta\thost=laptop\n\
+             2024-05-01T10:00:00+09:00\tstop\t\thost=desktop\tnote=done\n",
+        )
+        .unwrap();
+        let sessions = pair_sessions(&records);
+        assert_eq!(sessions[0].field("host"), Some("laptop"));
+        assert_eq!(sessions[0].field("note"), Some("done"));
+    }
+
+    #[test]
+    fn test_pair_sessions_ignores_dangling_stop() {
+        let records = parse_records("2024-05-01T09:00:00+09:00\tstop\t\n").unwrap();
+        assert!(pair_sessions(&records).is_empty());
+    }
+
+    #[test]
+    fn test_project_of() {
+        assert_eq!(project_of("clientA:fix-login"), Some("clientA"));
+        assert_eq!(project_of("fix-login"), None);
+        assert_eq!(project_of(":fix-login"), None);
+    }
+
+    #[test]
+    fn test_overlap() {
+        let session = Session::new(
+            "a",
+            ts("2024-05-01T23:00:00+09:00"),
+            Some(ts("2024-05-02T01:00:00+09:00")),
+        );
+        let now = ts("2024-05-03T00:00:00+09:00");
+        let overlap = session.overlap(
+            ts("2024-05-02T00:00:00+09:00"),
+            ts("2024-05-03T00:00:00+09:00"),
+            now,
+        );
+        assert_eq!(overlap, Duration::hours(1));
+    }
+
+    #[test]
+    fn test_pair_sessions_with_laps() {
+        let records = parse_records(
+            "2024-05-01T09:00:00+09:00\tstart\ta\n\
+             2024-05-01T09:40:00+09:00\tlap\trepro found\n\
+             2024-05-01T11:00:00+09:00\tlap\tfix drafted\n\
+             2024-05-01T12:00:00+09:00\tstop\t\n\
+             2024-05-01T13:00:00+09:00\tlap\tignored\n",
+        )
+        .unwrap();
+        let sessions = pair_sessions(&records);
+        assert_eq!(sessions.len(), 1);
+        let segments = sessions[0].segments(ts("2030-01-01T00:00:00Z"));
+        let lengths: Vec<(i64, Option<&str>)> = segments
+            .iter()
+            .map(|(from, to, note)| ((*to - *from).num_minutes(), *note))
+            .collect();
+        assert_eq!(
+            lengths,
+            vec![
+                (40, Some("repro found")),
+                (80, Some("fix drafted")),
+                (60, None)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_continuation() {
+        let records = parse_records(
+            "2024-05-01T09:00:00+09:00\tstart\ta\n\
+             2024-05-01T10:00:00+09:00\tstop\t\n",
+        )
+        .unwrap();
+        let gap = Duration::minutes(2);
+        let start = |task: &str, time: &str| Record::new(ts(time), Event::Start, task);
+        assert!(is_continuation(
+            &records,
+            &start("a", "2024-05-01T10:01:30+09:00"),
+            gap
+        ));
+        assert!(!is_continuation(
+            &records,
+            &start("a", "2024-05-01T10:05:00+09:00"),
+            gap
+        ));
+        assert!(!is_continuation(
+            &records,
+            &start("b", "2024-05-01T10:01:00+09:00"),
+            gap
+        ));
+        assert!(!is_continuation(
+            &records,
+            &start("a", "2024-05-01T10:01:00+09:00").with_tag("x"),
+            gap
+        ));
+    }
+
+    #[test]
+    fn test_auto_stop_is_not_continued() {
+        let records = parse_records(
+            "2024-05-01T09:00:00+09:00\tstart\ta\n\
+             2024-05-01T10:00:00+09:00\tstop\t\t+auto-stopped\n",
+        )
+        .unwrap();
+        let start = Record::new(ts("2024-05-01T10:01:00+09:00"), Event::Start, "a");
+        assert!(!is_continuation(&records, &start, Duration::minutes(2)));
+    }
+
+    #[test]
+    fn test_recent_tasks() {
+        let sessions: Vec<Session> = ["a", "b", "a", "c", "b"]
+            .iter()
+            .map(|task| Session::new(task, ts("2024-05-01T09:00:00+09:00"), None))
+            .collect();
+        assert_eq!(recent_tasks(&sessions, 9), vec!["b", "c", "a"]);
+        assert_eq!(recent_tasks(&sessions, 2), vec!["b", "c"]);
+    }
+
+    #[test]
+    fn test_resolve_overlap() {
+        let sessions = vec![
+            Session::new(
+                "a",
+                ts("2024-05-01T09:00:00+09:00"),
+                Some(ts("2024-05-01T10:00:00+09:00")),
+            ),
+            Session::new("b", ts("2024-05-01T11:00:00+09:00"), None),
+        ];
+        let config = Config::default();
+        let now = ts("2024-05-01T12:00:00+09:00");
+        let interval = Interval::new(
+            ts("2024-05-01T08:00:00+09:00"),
+            ts("2024-05-01T11:30:00+09:00"),
+        );
+        let error = resolve_overlap(&sessions, "c", interval, OverlapMode::Reject, &config, now);
+        assert!(error.unwrap_err().contains("overlaps 'a' "));
+        assert_eq!(
+            resolve_overlap(&sessions, "c", interval, OverlapMode::Allow, &config, now).unwrap(),
+            vec![interval]
+        );
+        assert_eq!(
+            resolve_overlap(&sessions, "c", interval, OverlapMode::Clip, &config, now).unwrap(),
+            vec![
+                Interval::new(
+                    ts("2024-05-01T08:00:00+09:00"),
+                    ts("2024-05-01T09:00:00+09:00")
+                ),
+                Interval::new(
+                    ts("2024-05-01T10:00:00+09:00"),
+                    ts("2024-05-01T11:00:00+09:00")
+                ),
+            ]
+        );
+        // 接するだけなら重ならない
+        let adjacent = Interval::new(
+            ts("2024-05-01T10:00:00+09:00"),
+            ts("2024-05-01T11:00:00+09:00"),
+        );
+        assert!(
+            resolve_overlap(&sessions, "c", adjacent, OverlapMode::Reject, &config, now).is_ok()
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use crate::config::Config;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+pub const ORIGIN_FIELD: &str = "origin";
+const PRIMARY_ORIGIN: &str = "primary";
+
+// 読み込み時に合わせる記録ファイル。primary のものが書き込み先になる。
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Source {
+    pub name: String,
+    pub path: PathBuf,
+    #[serde(default)]
+    pub primary: bool,
+}
+
+pub fn primary_path(config: &Config) -> Option<&Path> {
+    config
+        .sources
+        .iter()
+        .find(|source| source.primary)
+        .map(|source| source.path.as_path())
+}
+
+// 読み込む (origin, パス) の一覧。書き込み先の file_path が先頭。
+// sources が無ければ origin は付けない。
+pub fn origins(file_path: &str, config: &Config) -> Vec<(Option<String>, PathBuf)> {
+    if config.sources.is_empty() {
+        return vec![(None, PathBuf::from(file_path))];
+    }
+    let file_path = Path::new(file_path);
+    let primary = config
+        .sources
+        .iter()
+        .find(|source| source.path == file_path)
+        .map_or(PRIMARY_ORIGIN, |source| source.name.as_str());
+    let mut origins = vec![(Some(primary.to_string()), file_path.to_path_buf())];
+    origins.extend(
+        config
+            .sources
+            .iter()
+            .filter(|source| source.path != file_path)
+            .map(|source| (Some(source.name.clone()), source.path.clone())),
+    );
+    origins
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config::parse(
+            r#"
+            [[sources]]
+            name = "personal"
+            path = "/data/personal.txt"
+            primary = true
+
+            [[sources]]
+            name = "team"
+            path = "/shared/team.txt"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_primary_path() {
+        assert_eq!(
+            primary_path(&config()),
+            Some(Path::new("/data/personal.txt"))
+        );
+        assert_eq!(primary_path(&Config::default()), None);
+    }
+
+    #[test]
+    fn test_origins() {
+        let origins = origins("/data/personal.txt", &config());
+        assert_eq!(
+            origins,
+            vec![
+                (
+                    Some("personal".to_string()),
+                    PathBuf::from("/data/personal.txt")
+                ),
+                (Some("team".to_string()), PathBuf::from("/shared/team.txt")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_origins_with_other_file() {
+        let origins = origins("other.txt", &config());
+        assert_eq!(origins.len(), 3);
+        assert_eq!(origins[0].0.as_deref(), Some(PRIMARY_ORIGIN));
+    }
+
+    #[test]
+    fn test_origins_without_sources() {
+        let origins = origins("a.txt", &Config::default());
+        assert_eq!(origins, vec![(None, PathBuf::from("a.txt"))]);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::invoice::Invoice;
+use crate::period::Period;
+use crate::plan::Plan;
+use crate::record::Record;
+use chrono::{DateTime, FixedOffset, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::ErrorKind;
+
+// 記録ファイルごとの状態 (ロックした期間など) を "<記録ファイル>.state" に保存する
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct State {
+    pub locks: Vec<Lock>,
+    pub plans: Vec<Plan>,
+    // 取り込み元ごとの、取り込み済みのレコードの content_hash
+    pub seen: BTreeMap<String, BTreeSet<String>>,
+    // push で中断したタスク。最後が pop で再開するもの。
+    pub stack: Vec<Suspended>,
+    // invoice --finalize で発行した請求書
+    pub invoices: Vec<Invoice>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Suspended {
+    pub task: String,
+    pub tags: Vec<String>,
+    pub suspended_at: DateTime<FixedOffset>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lock {
+    pub period: String,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub submitted_at: DateTime<FixedOffset>,
+}
+
+impl Lock {
+    pub fn contains(&self, time: DateTime<FixedOffset>) -> bool {
+        let date = time.with_timezone(&Local).date_naive();
+        self.start <= date && date <= self.end
+    }
+}
+
+impl State {
+    pub fn load(file_path: &str) -> Result<State, String> {
+        let path = state_path(file_path);
+        match fs::read_to_string(&path) {
+            Ok(content) => toml::from_str(&content).map_err(|e| format!("{}: {}", path, e)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(State::default()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    pub fn save(&self, file_path: &str) -> Result<(), String> {
+        let content = toml::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(state_path(file_path), content).map_err(|e| e.to_string())
+    }
+
+    pub fn lock(&mut self, label: &str, period: &Period, now: DateTime<FixedOffset>) {
+        self.locks.retain(|lock| lock.period != label);
+        self.locks.push(Lock {
+            period: label.to_string(),
+            start: period.start,
+            end: period.end,
+            submitted_at: now,
+        });
+    }
+
+    pub fn unlock(&mut self, label: &str) -> bool {
+        let before = self.locks.len();
+        self.locks.retain(|lock| lock.period != label);
+        self.locks.len() != before
+    }
+
+    pub fn locked_at(&self, time: DateTime<FixedOffset>) -> Option<&Lock> {
+        self.locks.iter().find(|lock| lock.contains(time))
+    }
+
+    pub fn is_seen(&self, source: &str, record: &Record) -> bool {
+        self.seen
+            .get(source)
+            .is_some_and(|hashes| hashes.contains(&record.content_hash()))
+    }
+
+    pub fn mark_seen<'a>(&mut self, source: &str, records: impl Iterator<Item = &'a Record>) {
+        self.seen
+            .entry(source.to_string())
+            .or_default()
+            .extend(records.map(Record::content_hash));
+    }
+
+    // ロック済みの期間に触れる時刻が含まれていればエラーにする
+    pub fn check_unlocked(
+        &self,
+        mut times: impl Iterator<Item = DateTime<FixedOffset>>,
+    ) -> Result<(), String> {
+        match times.find_map(|time| self.locked_at(time)) {
+            Some(lock) => Err(format!(
+                "{} is locked (submitted on {}). Use --force-unlock to modify it.",
+                lock.period,
+                lock.submitted_at.format("%Y-%m-%d")
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+pub fn state_path(file_path: &str) -> String {
+    format!("{}.state", file_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::local_midnight;
+    use chrono::Duration;
+
+    fn locked_state() -> State {
+        let mut state = State::default();
+        let period = Period::parse("2024-05").unwrap();
+        state.lock(
+            "2024-05",
+            &period,
+            local_midnight(period.end) + Duration::days(1),
+        );
+        state
+    }
+
+    #[test]
+    fn test_locked_at() {
+        let state = locked_state();
+        let inside = local_midnight(Period::parse("2024-05-31").unwrap().start);
+        let outside = local_midnight(Period::parse("2024-06-01").unwrap().start);
+        assert!(state.locked_at(inside).is_some());
+        assert!(state.locked_at(outside).is_none());
+    }
+
+    #[test]
+    fn test_check_unlocked() {
+        let state = locked_state();
+        let inside = local_midnight(Period::parse("2024-05-10").unwrap().start);
+        let err = state.check_unlocked([inside].into_iter()).unwrap_err();
+        assert!(err.starts_with("2024-05 is locked (submitted on 2024-06-01)."));
+        assert!(state.check_unlocked(std::iter::empty()).is_ok());
+    }
+
+    #[test]
+    fn test_lock_replaces_and_unlock_removes() {
+        let mut state = locked_state();
+        let period = Period::parse("2024-05").unwrap();
+        state.lock("2024-05", &period, local_midnight(period.end));
+        assert_eq!(state.locks.len(), 1);
+        assert!(state.unlock("2024-05"));
+        assert!(!state.unlock("2024-05"));
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let file_path = "test_state_round_trip.txt";
+        let state = locked_state();
+        state.save(file_path).unwrap();
+        assert_eq!(State::load(file_path).unwrap().locks, state.locks);
+        fs::remove_file(state_path(file_path)).unwrap();
+        assert!(State::load(file_path).unwrap().locks.is_empty());
+    }
+
+    #[test]
+    fn test_seen_round_trip() {
+        let file_path = "test_state_seen.txt";
+        let start = Record::new(
+            local_midnight(Period::parse("2024-05-01").unwrap().start),
+            crate::record::Event::Start,
+            "a",
+        );
+        let mut state = locked_state();
+        state.mark_seen("export.csv", [&start].into_iter());
+        state.save(file_path).unwrap();
+        let loaded = State::load(file_path).unwrap();
+        assert!(loaded.is_seen("export.csv", &start));
+        assert!(!loaded.is_seen("other.csv", &start));
+        assert!(!loaded.is_seen("export.csv", &start.clone().with_tag("x")));
+        fs::remove_file(state_path(file_path)).unwrap();
+    }
+}
@@ -0,0 +1,264 @@
+use crate::duration::format_duration;
+use crate::export::Locale;
+use crate::interval::Interval;
+use crate::names;
+use crate::period::{to_local, Period};
+use crate::report::{sum, total_between};
+use crate::session::Session;
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDate, NaiveTime, Weekday};
+
+const HEAT_LEVELS: [char; 5] = [' ', '░', '▒', '▓', '█'];
+
+// 曜日 (月曜始まり) × 時間帯ごとの合計
+pub type HeatTable = [[Duration; 24]; 7];
+
+pub fn per_hour(sessions: &[Session], period: &Period, now: DateTime<FixedOffset>) -> HeatTable {
+    let mut table = [[Duration::zero(); 24]; 7];
+    for day in period.days() {
+        let range = Interval::day(day);
+        let next_midnight = range.end;
+        let sessions: Vec<&Session> = sessions
+            .iter()
+            .filter(|s| s.interval(now).clip(&range).is_some())
+            .collect();
+        let row = &mut table[day.weekday().num_days_from_monday() as usize];
+        // 夏時間の切り替え日は 23 / 25 時間あるので、区切りは壁時計の時刻から求める
+        let hour_start = |hour: u32| match NaiveTime::from_hms_opt(hour, 0, 0) {
+            Some(time) => to_local(day.and_time(time)),
+            None => next_midnight,
+        };
+        for (hour, cell) in row.iter_mut().enumerate() {
+            let hour = hour as u32;
+            *cell += total_between(
+                sessions.iter().copied(),
+                hour_start(hour),
+                hour_start(hour + 1),
+                now,
+            );
+        }
+    }
+    table
+}
+
+pub fn render_per_hour(table: &HeatTable, locale: Locale) -> String {
+    let max = table
+        .iter()
+        .flatten()
+        .max()
+        .copied()
+        .unwrap_or_else(Duration::zero);
+    let heat = |cell: Duration| {
+        if max.is_zero() || cell.is_zero() {
+            HEAT_LEVELS[0]
+        } else {
+            let level = (cell.num_seconds() * (HEAT_LEVELS.len() - 1) as i64 + max.num_seconds()
+                - 1)
+                / max.num_seconds();
+            HEAT_LEVELS[level as usize]
+        }
+    };
+
+    // 曜日の列は地域設定の曜日名の幅に合わせる
+    let width = names::weekday_width(locale).max(3);
+    let mut output = " ".repeat(width + 1);
+    for hour in 0..24 {
+        output += &format!("{:02} ", hour);
+    }
+    output += "   Total\n";
+    let weekdays = std::iter::successors(Some(Weekday::Mon), |day| Some(day.succ()));
+    for (weekday, row) in weekdays.zip(table) {
+        output += &format!(
+            "{} ",
+            names::pad(names::weekday_short(weekday, locale), width)
+        );
+        for cell in row {
+            output += &format!(" {} ", heat(*cell));
+        }
+        output += &format!("{:>8}\n", format_duration(sum(row.iter().copied())));
+    }
+    output += &format!(
+        "\n{} up to {}/hour\n",
+        HEAT_LEVELS[HEAT_LEVELS.len() - 1],
+        format_duration(max)
+    );
+    output
+}
+
+// 1 日の集中の度合い。ブロックは同じタスクが途切れずに続いた区間。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FocusDay {
+    pub date: NaiveDate,
+    // タスクが変わった回数
+    pub switches: usize,
+    pub blocks: usize,
+    pub total: Duration,
+    pub longest: Duration,
+}
+
+impl FocusDay {
+    pub fn average_block(&self) -> Duration {
+        self.total / self.blocks.max(1) as i32
+    }
+}
+
+// 記録のある日ごとの切り替え回数とブロックの長さ
+pub fn focus(sessions: &[Session], period: &Period, now: DateTime<FixedOffset>) -> Vec<FocusDay> {
+    let mut days = Vec::new();
+    for date in period.days() {
+        let range = Interval::day(date);
+        let mut parts: Vec<(Interval, &str)> = sessions
+            .iter()
+            .filter_map(|s| s.interval(now).clip(&range).map(|i| (i, s.task.as_str())))
+            .collect();
+        if parts.is_empty() {
+            continue;
+        }
+        parts.sort();
+        let mut day = FocusDay {
+            date,
+            switches: 0,
+            blocks: 0,
+            total: Duration::zero(),
+            longest: Duration::zero(),
+        };
+        let mut block = Duration::zero();
+        let mut previous: Option<(Interval, &str)> = None;
+        for (interval, task) in parts {
+            match previous {
+                // 同じタスクが間を空けずに続けば同じブロック
+                Some((last, last_task)) if last_task == task && interval.start <= last.end => {}
+                Some((_, last_task)) => {
+                    day.switches += usize::from(last_task != task);
+                    day.longest = day.longest.max(block);
+                    day.blocks += 1;
+                    block = Duration::zero();
+                }
+                None => day.blocks += 1,
+            }
+            block += interval.duration();
+            day.total += interval.duration();
+            previous = Some((interval, task));
+        }
+        day.longest = day.longest.max(block);
+        days.push(day);
+    }
+    days
+}
+
+pub fn render_focus(days: &[FocusDay]) -> String {
+    let mut output = format!(
+        "{:<10}  {:>8}  {:>6}  {:>9}  {:>7}\n",
+        "Date", "Switches", "Blocks", "Avg block", "Longest"
+    );
+    for day in days {
+        output += &format!(
+            "{:<10}  {:>8}  {:>6}  {:>9}  {:>7}\n",
+            day.date,
+            day.switches,
+            day.blocks,
+            format_duration(day.average_block()),
+            format_duration(day.longest)
+        );
+    }
+    if !days.is_empty() {
+        let switches: usize = days.iter().map(|d| d.switches).sum();
+        let blocks: usize = days.iter().map(|d| d.blocks).sum();
+        let total = sum(days.iter().map(|d| d.total));
+        output += &format!(
+            "{:<10}  {:>8.1}  {:>6}  {:>9}\n",
+            "Average",
+            switches as f64 / days.len() as f64,
+            "",
+            format_duration(total / blocks.max(1) as i32)
+        );
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::{local_midnight, parse_date};
+
+    fn local(date: &str, minutes: i64) -> DateTime<FixedOffset> {
+        local_midnight(parse_date(date).unwrap()) + Duration::minutes(minutes)
+    }
+
+    #[test]
+    fn test_per_hour_splits_sessions_at_hour_boundaries() {
+        let now = local("2030-01-01", 0);
+        // 2024-05-01 は水曜。9:30-11:15
+        let sessions = vec![Session::new(
+            "a",
+            local("2024-05-01", 9 * 60 + 30),
+            Some(local("2024-05-01", 11 * 60 + 15)),
+        )];
+        let period = Period::parse("2024-05").unwrap();
+        let table = per_hour(&sessions, &period, now);
+        assert_eq!(table[2][9], Duration::minutes(30));
+        assert_eq!(table[2][10], Duration::minutes(60));
+        assert_eq!(table[2][11], Duration::minutes(15));
+        assert_eq!(sum(table.iter().flatten().copied()), Duration::minutes(105));
+    }
+
+    #[test]
+    fn test_per_hour_adds_up_same_weekday() {
+        let now = local("2030-01-01", 0);
+        let sessions = vec![
+            Session::new(
+                "a",
+                local("2024-05-06", 600),
+                Some(local("2024-05-06", 630)),
+            ),
+            Session::new(
+                "b",
+                local("2024-05-13", 600),
+                Some(local("2024-05-13", 660)),
+            ),
+        ];
+        let table = per_hour(&sessions, &Period::parse("2024-05").unwrap(), now);
+        assert_eq!(table[0][10], Duration::minutes(90));
+    }
+
+    #[test]
+    fn test_render_per_hour() {
+        let mut table = [[Duration::zero(); 24]; 7];
+        table[0][9] = Duration::hours(2);
+        table[0][10] = Duration::minutes(30);
+        let output = render_per_hour(&table, Locale::Default);
+        let monday = output.lines().nth(1).unwrap();
+        assert!(monday.starts_with("Mon "));
+        assert!(monday.contains(" █  ░ "));
+        assert!(monday.ends_with("2h30m"));
+        assert!(output.contains("█ up to 2h00m/hour"));
+    }
+
+    #[test]
+    fn test_focus() {
+        let now = local("2030-01-01", 0);
+        let session = |task: &str, from: i64, to: i64| {
+            Session::new(
+                task,
+                local("2024-05-01", from),
+                Some(local("2024-05-01", to)),
+            )
+        };
+        // a 9:00-10:00, a 10:00-10:30 (続き), b 10:30-11:00, a 13:00-13:30
+        let sessions = vec![
+            session("a", 540, 600),
+            session("a", 600, 630),
+            session("b", 630, 660),
+            session("a", 780, 810),
+        ];
+        let days = focus(&sessions, &Period::parse("2024-05").unwrap(), now);
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0].switches, 2);
+        assert_eq!(days[0].blocks, 3);
+        assert_eq!(days[0].longest, Duration::minutes(90));
+        assert_eq!(days[0].average_block(), Duration::minutes(50));
+
+        let output = render_focus(&days);
+        assert!(output.contains("2024-05-01         2       3        50m    1h30m"));
+        assert!(output.contains("Average          2.0                50m"));
+    }
+}
@@ -0,0 +1,160 @@
+use crate::duration::format_duration;
+use crate::export::Locale;
+use crate::hours::HoursProfile;
+use crate::names;
+use crate::period::Period;
+use crate::report::{totals, GroupBy};
+use crate::session::Session;
+use chrono::{DateTime, Datelike, Days, FixedOffset, NaiveDate};
+
+const NOTHING_LABEL: &str = "nothing";
+
+// 今日より前で所定時間のある最後の日。1 週間さかのぼっても無ければ昨日。
+pub fn previous_workday(today: NaiveDate, hours: &HoursProfile) -> NaiveDate {
+    let yesterday = today - Days::new(1);
+    (1..=7)
+        .map(|n| today - Days::new(n))
+        .find(|day| hours.is_workday(*day))
+        .unwrap_or(yesterday)
+}
+
+// 前の勤務日と今日の、タスクごとの合計。前の勤務日が昨日でなければ曜日で呼ぶ。
+pub struct Summary {
+    pub previous_label: String,
+    pub previous: Vec<(String, chrono::Duration)>,
+    pub today: Vec<(String, chrono::Duration)>,
+}
+
+impl Summary {
+    pub fn new(
+        sessions: &[Session],
+        today: NaiveDate,
+        hours: &HoursProfile,
+        locale: Locale,
+        now: DateTime<FixedOffset>,
+    ) -> Summary {
+        let previous = previous_workday(today, hours);
+        let previous_label = match previous == today - Days::new(1) {
+            true => "yesterday".to_string(),
+            false => names::weekday_long(previous.weekday(), locale).to_string(),
+        };
+        let day_totals = |day| totals(sessions, &Period::day(day), GroupBy::Task, now);
+        Summary {
+            previous_label,
+            previous: day_totals(previous),
+            today: day_totals(today),
+        }
+    }
+
+    // チャットに貼れる 1 行。"yesterday: a 2h10m, b 45m / today so far: c 1h00m"
+    pub fn to_standup(&self) -> String {
+        format!(
+            "{}: {} / today so far: {}\n",
+            self.previous_label,
+            inline(&self.previous),
+            inline(&self.today)
+        )
+    }
+
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        for (label, totals) in [
+            (self.previous_label.as_str(), &self.previous),
+            ("today so far", &self.today),
+        ] {
+            output += &format!("{}:\n", label);
+            if totals.is_empty() {
+                output += &format!("  {}\n", NOTHING_LABEL);
+            }
+            for (task, total) in totals {
+                output += &format!("  {:>6}  {}\n", format_duration(*total), task);
+            }
+        }
+        output
+    }
+}
+
+fn inline(totals: &[(String, chrono::Duration)]) -> String {
+    if totals.is_empty() {
+        return NOTHING_LABEL.to_string();
+    }
+    totals
+        .iter()
+        .map(|(task, total)| format!("{} {}", task, format_duration(*total)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::{parse_date, to_local};
+    use chrono::Duration;
+
+    fn at(date: &str, hour: u32, minute: u32) -> DateTime<FixedOffset> {
+        to_local(
+            parse_date(date)
+                .unwrap()
+                .and_hms_opt(hour, minute, 0)
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_previous_workday() {
+        let hours = HoursProfile::default();
+        // 2024-05-06 は月曜
+        let day = |s: &str| parse_date(s).unwrap();
+        assert_eq!(
+            previous_workday(day("2024-05-06"), &hours),
+            day("2024-05-03")
+        );
+        assert_eq!(
+            previous_workday(day("2024-05-08"), &hours),
+            day("2024-05-07")
+        );
+    }
+
+    #[test]
+    fn test_standup() {
+        let session = |task: &str, date: &str, hour: u32, minutes: i64| {
+            let start = at(date, hour, 0);
+            Session::new(task, start, Some(start + Duration::minutes(minutes)))
+        };
+        let sessions = vec![
+            session("b", "2024-05-07", 9, 45),
+            session("a", "2024-05-07", 10, 130),
+            session("c", "2024-05-08", 9, 60),
+        ];
+        let now = at("2024-05-08", 12, 0);
+        let summary = Summary::new(
+            &sessions,
+            now.date_naive(),
+            &HoursProfile::default(),
+            Locale::Default,
+            now,
+        );
+        assert_eq!(
+            summary.to_standup(),
+            "yesterday: a 2h10m, b 45m / today so far: c 1h00m\n"
+        );
+        assert_eq!(
+            summary.render(),
+            "yesterday:\n   2h10m  a\n     45m  b\ntoday so far:\n   1h00m  c\n"
+        );
+
+        // 月曜は金曜を振り返る
+        let monday = at("2024-05-06", 12, 0);
+        let summary = Summary::new(
+            &sessions,
+            monday.date_naive(),
+            &HoursProfile::default(),
+            Locale::De,
+            monday,
+        );
+        assert_eq!(
+            summary.to_standup(),
+            "Freitag: nothing / today so far: nothing\n"
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use crate::duration::format_duration;
+use crate::export::Locale;
+use crate::names;
+use crate::period::{local_midnight, to_local, Period};
+use crate::report::total_between;
+use crate::session::Session;
+use chrono::{DateTime, FixedOffset, Local, NaiveTime, Timelike};
+
+// 1 時間を 2 マス (30 分ずつ) で描く
+const CELLS_PER_HOUR: u32 = 2;
+const DEFAULT_HOURS: (u32, u32) = (9, 18);
+const EMPTY_CELL: char = '·';
+const BLOCK: char = '█';
+// ANSI の前景色。プロジェクトに順番に割り当てる。
+const COLORS: [u8; 6] = [34, 32, 33, 35, 36, 31];
+
+// プロジェクトがあればプロジェクト、無ければタスク名で色分けする
+fn label(session: &Session) -> &str {
+    session.project().unwrap_or(&session.task)
+}
+
+// 日ごとに 1 行、表示範囲の時間帯を横に並べる。
+// 色を使わないときはプロジェクトごとの英字で塗り、凡例で対応を示す。
+pub fn render(
+    sessions: &[Session],
+    period: &Period,
+    now: DateTime<FixedOffset>,
+    color: bool,
+    locale: Locale,
+) -> String {
+    let (from, to) = (period.start_time(), period.end_time());
+    let sessions: Vec<&Session> = sessions
+        .iter()
+        .filter(|s| !s.overlap(from, to, now).is_zero())
+        .collect();
+    let mut labels: Vec<&str> = Vec::new();
+    for session in &sessions {
+        if !labels.contains(&label(session)) {
+            labels.push(label(session));
+        }
+    }
+    let (first_hour, last_hour) = hour_range(&sessions, period, now);
+
+    // 時刻の見出しは 2 時間おき
+    let mut output = " ".repeat(10);
+    for hour in (first_hour..last_hour).step_by(2) {
+        output += &format!("{:<width$}", hour, width = 2 * CELLS_PER_HOUR as usize);
+    }
+    output = output.trim_end().to_string() + "\n";
+    for day in period.days() {
+        output += &format!("{} ", names::short_date(day, locale));
+        let next_midnight = local_midnight(day.succ_opt().unwrap());
+        let cell_start = |cell: u32| {
+            let minutes = cell * 60 / CELLS_PER_HOUR;
+            match NaiveTime::from_hms_opt(minutes / 60, minutes % 60, 0) {
+                Some(time) => to_local(day.and_time(time)),
+                None => next_midnight,
+            }
+        };
+        for cell in first_hour * CELLS_PER_HOUR..last_hour * CELLS_PER_HOUR {
+            let (start, end) = (cell_start(cell), cell_start(cell + 1));
+            let busiest = labels
+                .iter()
+                .enumerate()
+                .map(|(i, name)| {
+                    let matching = sessions.iter().copied().filter(|s| label(s) == *name);
+                    (i, total_between(matching, start, end, now))
+                })
+                .filter(|(_, total)| !total.is_zero())
+                .max_by_key(|(i, total)| (*total, std::cmp::Reverse(*i)));
+            output += &match busiest {
+                Some((i, _)) => paint(i, color),
+                None => EMPTY_CELL.to_string(),
+            };
+        }
+        let day_total = total_between(
+            sessions.iter().copied(),
+            local_midnight(day),
+            next_midnight,
+            now,
+        );
+        output += &format!("  {:>6}\n", format_duration(day_total));
+    }
+
+    if !labels.is_empty() {
+        output += "\n";
+        for (i, name) in labels.iter().enumerate() {
+            output += &format!("{} {}  ", paint(i, color), name);
+        }
+        output = output.trim_end().to_string() + "\n";
+    }
+    output
+}
+
+fn paint(index: usize, color: bool) -> String {
+    if color {
+        format!("\x1b[{}m{}\x1b[0m", COLORS[index % COLORS.len()], BLOCK)
+    } else {
+        char::from(b'A' + (index % 26) as u8).to_string()
+    }
+}
+
+// 記録のある時間帯を含むよう、既定の表示範囲 (9-18 時) を広げる
+fn hour_range(sessions: &[&Session], period: &Period, now: DateTime<FixedOffset>) -> (u32, u32) {
+    let (from, to) = (period.start_time(), period.end_time());
+    let mut range = DEFAULT_HOURS;
+    for session in sessions {
+        let start = session.start.max(from).with_timezone(&Local);
+        let end = session.end_or(now).min(to);
+        range.0 = range.0.min(start.hour());
+        // 終わりがちょうど 0 時 (翌日) のときは 24 時まで
+        let end_hour = if end == to || end.with_timezone(&Local).date_naive() != start.date_naive()
+        {
+            24
+        } else {
+            let end = end.with_timezone(&Local);
+            end.hour() + u32::from(end.minute() > 0 || end.second() > 0)
+        };
+        range.1 = range.1.max(end_hour);
+    }
+    (range.0, range.1.max(range.0 + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::parse_date;
+
+    fn local(date: &str, hour: u32, minute: u32) -> DateTime<FixedOffset> {
+        to_local(
+            parse_date(date)
+                .unwrap()
+                .and_hms_opt(hour, minute, 0)
+                .unwrap(),
+        )
+    }
+
+    fn session(task: &str, start: DateTime<FixedOffset>, stop: DateTime<FixedOffset>) -> Session {
+        Session::new(task, start, Some(stop))
+    }
+
+    #[test]
+    fn test_render_day() {
+        let sessions = vec![
+            session(
+                "clientA:fix",
+                local("2024-05-01", 9, 0),
+                local("2024-05-01", 10, 0),
+            ),
+            session(
+                "mail",
+                local("2024-05-01", 10, 0),
+                local("2024-05-01", 10, 15),
+            ),
+            session(
+                "clientA:review",
+                local("2024-05-01", 16, 30),
+                local("2024-05-01", 19, 0),
+            ),
+        ];
+        let period = Period::parse("2024-05-01").unwrap();
+        let output = render(
+            &sessions,
+            &period,
+            local("2030-01-01", 0, 0),
+            false,
+            Locale::Default,
+        );
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "          9   11  13  15  17");
+        assert_eq!(lines[1], "Wed 05-01 AAB············AAAAA   3h45m");
+        assert_eq!(lines[3], "A clientA  B mail");
+    }
+
+    #[test]
+    fn test_render_week_with_color() {
+        let sessions = vec![session(
+            "a",
+            local("2024-05-01", 7, 0),
+            local("2024-05-01", 8, 0),
+        )];
+        let period = Period::parse("2024-W18").unwrap();
+        let output = render(
+            &sessions,
+            &period,
+            local("2030-01-01", 0, 0),
+            true,
+            Locale::Ja,
+        );
+        assert_eq!(output.lines().count(), 1 + 7 + 2);
+        assert!(output
+            .lines()
+            .nth(3)
+            .unwrap()
+            .starts_with("水 05-01 \x1b[34m█\x1b[0m"));
+    }
+}
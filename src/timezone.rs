@@ -0,0 +1,96 @@
+use serde::Deserialize;
+use std::env;
+use std::path::Path;
+
+// chrono が tzdata を探す場所
+const ZONEINFO_DIRS: [&str; 4] = [
+    "/usr/share/zoneinfo",
+    "/share/zoneinfo",
+    "/etc/zoneinfo",
+    "/usr/share/lib/zoneinfo",
+];
+
+// 記録と表示に使うタイムゾーン。UTC か、Asia/Tokyo のような IANA の名前。
+// chrono の Local は TZ 環境変数に従うので、起動時に TZ を設定して記録にも report にも反映させる。
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TimeZone {
+    name: String,
+}
+
+impl TimeZone {
+    pub fn utc() -> TimeZone {
+        TimeZone {
+            name: "UTC".to_string(),
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<TimeZone, String> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("utc") {
+            return Ok(TimeZone::utc());
+        }
+        // ".." などでほかのファイルを指さないよう、名前に使える文字だけ受け付ける
+        let valid = !s.is_empty()
+            && s.split('/').all(|part| {
+                part.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+                    && part
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "_-+".contains(c))
+            });
+        if valid
+            && ZONEINFO_DIRS
+                .iter()
+                .any(|dir| Path::new(dir).join(s).is_file())
+        {
+            Ok(TimeZone {
+                name: s.to_string(),
+            })
+        } else {
+            Err(format!(
+                "Unknown timezone '{}'. Use UTC or an IANA name such as Asia/Tokyo.",
+                s
+            ))
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // 以降の Local をこのタイムゾーンにする。スレッドを立てる前の起動時にだけ呼ぶ。
+    pub fn apply(&self) {
+        // UTC は tzdata が無くても読める POSIX 形式で渡す
+        let value = match self.name.as_str() {
+            "UTC" => "UTC0",
+            name => name,
+        };
+        env::set_var("TZ", value);
+    }
+}
+
+impl TryFrom<String> for TimeZone {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<TimeZone, String> {
+        TimeZone::parse(&s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(TimeZone::parse("utc").unwrap(), TimeZone::utc());
+        assert_eq!(TimeZone::parse(" UTC ").unwrap().name(), "UTC");
+        for name in ["Mars/Olympus", "../etc/passwd", "Asia/../Tokyo", ""] {
+            assert!(TimeZone::parse(name).is_err(), "{}", name);
+        }
+        // tzdata のある環境でだけ名前を確かめる
+        if Path::new("/usr/share/zoneinfo/Asia/Tokyo").is_file() {
+            assert_eq!(TimeZone::parse("Asia/Tokyo").unwrap().name(), "Asia/Tokyo");
+        }
+    }
+}
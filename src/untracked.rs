@@ -0,0 +1,157 @@
+use crate::hours::HoursProfile;
+use crate::interval::{self, Interval};
+use crate::period::{parse_time, to_local, Period};
+use crate::session::Session;
+use chrono::{DateTime, Duration, FixedOffset, NaiveTime};
+use serde::Deserialize;
+
+// 勤務時間帯。"09:00-18:00" のように書く。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Workday {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl Default for Workday {
+    fn default() -> Workday {
+        Workday {
+            start: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+        }
+    }
+}
+
+impl Workday {
+    pub fn parse(s: &str) -> Result<Workday, String> {
+        let invalid = || format!("Invalid working hours '{}'.", s);
+        let (start, end) = s.split_once('-').ok_or_else(invalid)?;
+        let start = parse_time(start.trim())?;
+        let end = parse_time(end.trim())?;
+        if end <= start {
+            return Err(invalid());
+        }
+        Ok(Workday { start, end })
+    }
+}
+
+impl TryFrom<String> for Workday {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Workday, String> {
+        Workday::parse(&s)
+    }
+}
+
+// 所定時間のある日の勤務時間帯のうち、どのセッションとも重ならない min_gap 以上の区間。
+// まだ来ていない時間は数えない。
+pub fn gaps(
+    sessions: &[Session],
+    period: &Period,
+    workday: &Workday,
+    hours: &HoursProfile,
+    min_gap: Duration,
+    now: DateTime<FixedOffset>,
+) -> Vec<(DateTime<FixedOffset>, DateTime<FixedOffset>)> {
+    let mut gaps = Vec::new();
+    for day in period.days().filter(|d| hours.is_workday(*d)) {
+        let from = to_local(day.and_time(workday.start));
+        let to = to_local(day.and_time(workday.end)).min(now);
+        if to <= from {
+            continue;
+        }
+        let busy = sessions.iter().map(|s| s.interval(now));
+        gaps.extend(
+            interval::gaps(&Interval::new(from, to), busy)
+                .into_iter()
+                .filter(|gap| gap.duration() >= min_gap)
+                .map(|gap| (gap.start, gap.end)),
+        );
+    }
+    gaps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::parse_date;
+
+    fn local(date: &str, hour: u32, minute: u32) -> DateTime<FixedOffset> {
+        to_local(
+            parse_date(date)
+                .unwrap()
+                .and_hms_opt(hour, minute, 0)
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_parse_workday() {
+        let workday = Workday::parse("08:30-17:00").unwrap();
+        assert_eq!(workday.start, NaiveTime::from_hms_opt(8, 30, 0).unwrap());
+        assert_eq!(
+            Workday::parse("18:00-09:00").unwrap_err(),
+            "Invalid working hours '18:00-09:00'."
+        );
+        assert!(Workday::parse("9-18").is_err());
+    }
+
+    #[test]
+    fn test_gaps() {
+        let sessions = vec![
+            Session::new(
+                "a",
+                local("2024-05-03", 8, 0),
+                Some(local("2024-05-03", 12, 0)),
+            ),
+            Session::new(
+                "b",
+                local("2024-05-03", 11, 0),
+                Some(local("2024-05-03", 12, 3)),
+            ),
+            Session::new(
+                "c",
+                local("2024-05-03", 13, 0),
+                Some(local("2024-05-03", 17, 0)),
+            ),
+        ];
+        // 金曜から日曜まで。週末は数えない。
+        let period = Period::new(
+            parse_date("2024-05-03").unwrap(),
+            parse_date("2024-05-05").unwrap(),
+        )
+        .unwrap();
+        let gaps = gaps(
+            &sessions,
+            &period,
+            &Workday::default(),
+            &HoursProfile::default(),
+            Duration::minutes(5),
+            local("2030-01-01", 0, 0),
+        );
+        assert_eq!(
+            gaps,
+            vec![
+                (local("2024-05-03", 12, 3), local("2024-05-03", 13, 0)),
+                (local("2024-05-03", 17, 0), local("2024-05-03", 18, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gaps_stop_at_now() {
+        let period = Period::parse("2024-05-03").unwrap();
+        let gaps = gaps(
+            &[],
+            &period,
+            &Workday::default(),
+            &HoursProfile::default(),
+            Duration::minutes(5),
+            local("2024-05-03", 10, 0),
+        );
+        assert_eq!(
+            gaps,
+            vec![(local("2024-05-03", 9, 0), local("2024-05-03", 10, 0))]
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use crate::autostop::AUTO_STOPPED_TAG;
+use crate::config::deserialize_required_duration;
+use crate::duration::format_duration;
+use crate::record::{Event, Record};
+use chrono::{DateTime, Duration, FixedOffset};
+use serde::Deserialize;
+
+// [watch] の設定。計測中のセッションが threshold を超えたら通知し、
+// auto_stop なら threshold の時刻で止める。
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchConfig {
+    #[serde(deserialize_with = "deserialize_required_duration")]
+    pub threshold: Duration,
+    // 確かめる間隔
+    #[serde(deserialize_with = "deserialize_required_duration")]
+    pub interval: Duration,
+    pub auto_stop: bool,
+}
+
+impl Default for WatchConfig {
+    fn default() -> WatchConfig {
+        WatchConfig {
+            threshold: Duration::hours(10),
+            interval: Duration::minutes(1),
+            auto_stop: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub message: String,
+    // auto_stop のときに書く stop
+    pub stop: Option<Record>,
+}
+
+// 計測中の start が threshold を超えていれば知らせる内容を返す
+pub fn check(start: &Record, config: &WatchConfig, now: DateTime<FixedOffset>) -> Option<Alert> {
+    if start.event != Event::Start {
+        return None;
+    }
+    let elapsed = now - start.timestamp;
+    if elapsed <= config.threshold {
+        return None;
+    }
+    let limit = start.timestamp + config.threshold;
+    let stop = config
+        .auto_stop
+        .then(|| Record::new(limit, Event::Stop, "").with_tag(AUTO_STOPPED_TAG));
+    let message = match stop {
+        Some(_) => format!(
+            "Stopped '{}' after {} (still running at {}).",
+            start.task,
+            format_duration(config.threshold),
+            now.format("%H:%M")
+        ),
+        None => format!(
+            "'{}' has been running for {}.",
+            start.task,
+            format_duration(elapsed)
+        ),
+    };
+    Some(Alert { message, stop })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts(s: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(s).unwrap()
+    }
+
+    #[test]
+    fn test_check() {
+        let start = Record::new(ts("2024-05-01T09:00:00+09:00"), Event::Start, "a");
+        let mut config = WatchConfig::default();
+        assert_eq!(
+            check(&start, &config, ts("2024-05-01T18:00:00+09:00")),
+            None
+        );
+
+        let now = ts("2024-05-02T08:30:00+09:00");
+        let alert = check(&start, &config, now).unwrap();
+        assert_eq!(alert.message, "'a' has been running for 23h30m.");
+        assert_eq!(alert.stop, None);
+
+        config.auto_stop = true;
+        let alert = check(&start, &config, now).unwrap();
+        let stop = alert.stop.unwrap();
+        assert_eq!(stop.timestamp, ts("2024-05-01T19:00:00+09:00"));
+        assert_eq!(stop.tags, vec![AUTO_STOPPED_TAG]);
+        assert_eq!(
+            alert.message,
+            "Stopped 'a' after 10h00m (still running at 08:30)."
+        );
+    }
+}